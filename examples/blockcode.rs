@@ -2,7 +2,7 @@
 // This file is a part of HexoTK. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
-use hexodsp::wblockdsp::*;
+use hexotk::blocklang::*;
 use hexotk::*;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -71,6 +71,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 1,
         inputs: vec![Some("f".to_string())],
         outputs: vec![Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description:
@@ -85,6 +87,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 1,
         inputs: vec![],
         outputs: vec![Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "The 0.0 value".to_string(),
@@ -97,6 +101,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 1,
         inputs: vec![Some("".to_string())],
         outputs: vec![Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "Forwards the value one block".to_string(),
@@ -109,6 +115,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 2,
         inputs: vec![Some("".to_string())],
         outputs: vec![Some("".to_string()), Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "Forwards the value one block and sends it to multiple destinations"
@@ -122,6 +130,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 3,
         inputs: vec![Some("".to_string())],
         outputs: vec![Some("".to_string()), Some("".to_string()), Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "Forwards the value one block and sends it to multiple destinations"
@@ -135,6 +145,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 1,
         inputs: vec![Some("".to_string())],
         outputs: vec![],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::Identifier,
         description: "Stores into a variable".to_string(),
@@ -147,6 +159,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 1,
         inputs: vec![],
         outputs: vec![Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::Identifier,
         description: "Loads a variable".to_string(),
@@ -159,6 +173,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 1,
         inputs: vec![Some("".to_string())],
         outputs: vec![Some("".to_string())],
+        input_types: vec![BlockPortType::any().required()],
+        output_types: vec![],
         area_count: 2,
         user_input: BlockUserInput::None,
         description: "Divides the controlflow based on a true (>= 0.5) \
@@ -173,6 +189,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 2,
         inputs: vec![Some("in".to_string()), Some("f".to_string())],
         outputs: vec![Some("lp".to_string()), Some("hp".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "Runs a simple one pole filter on the input".to_string(),
@@ -185,6 +203,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 3,
         inputs: vec![Some("in".to_string()), Some("f".to_string()), Some("r".to_string())],
         outputs: vec![Some("lp".to_string()), Some("bp".to_string()), Some("hp".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "Runs a state variable filter on the input".to_string(),
@@ -197,6 +217,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 1,
         inputs: vec![Some("".to_string())],
         outputs: vec![Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "Calculates the sine of the input".to_string(),
@@ -209,6 +231,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
         rows: 2,
         inputs: vec![Some("in".to_string()), Some("t".to_string())],
         outputs: vec![Some("".to_string())],
+        input_types: vec![],
+        output_types: vec![],
         area_count: 0,
         user_input: BlockUserInput::None,
         description: "Runs a linearly interpolated delay on the input".to_string(),
@@ -226,6 +250,8 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
                 vec![Some("".to_string()), Some("".to_string())]
             },
             outputs: vec![Some("".to_string())],
+            input_types: vec![],
+            output_types: vec![],
             area_count: 0,
             user_input: BlockUserInput::None,
             description: "A binary arithmetics operation".to_string(),
@@ -243,6 +269,61 @@ fn make_block_lang() -> Rc<RefCell<BlockLanguage>> {
     Rc::new(RefCell::new(lang))
 }
 
+/// Undoes a change of the [BlockFun] by loading the program text
+/// saved before (or after) the change.
+struct BlockFunChange {
+    code: Arc<Mutex<BlockFun>>,
+    before: String,
+    after: String,
+    what: &'static str,
+}
+
+impl BlockFunChange {
+    fn restore(&self, text: &str) {
+        let mut code = self.code.lock().unwrap();
+        code.load_from_str(text).expect("saved program can be loaded");
+        code.update_diagnostics();
+    }
+}
+
+impl UndoCommand for BlockFunChange {
+    fn undo(&mut self, _ctx: &mut dyn std::any::Any) {
+        self.restore(&self.before);
+    }
+
+    fn redo(&mut self, _ctx: &mut dyn std::any::Any) {
+        self.restore(&self.after);
+    }
+
+    fn describe(&self) -> String {
+        self.what.to_string()
+    }
+}
+
+/// Applies `f` to the code and pushes the change onto the undo stack,
+/// if the program changed.
+fn edit_code<F: FnOnce(&mut BlockFun)>(
+    wid: &Widget,
+    code: &Arc<Mutex<BlockFun>>,
+    what: &'static str,
+    f: F,
+) {
+    let mut code_acc = code.lock().unwrap();
+    let before = code_acc.save_to_string();
+
+    f(&mut *code_acc);
+
+    code_acc.recalculate_area_sizes();
+    for diag in code_acc.update_diagnostics() {
+        println!("ERROR: {}", diag);
+    }
+
+    let after = code_acc.save_to_string();
+    if before != after {
+        wid.push_undo(BlockFunChange { code: code.clone(), before, after, what });
+    }
+}
+
 fn main() {
     let concurrent_data = Arc::new(Mutex::new(CloneMutable::new(("Count:".to_string(), 0))));
 
@@ -269,7 +350,7 @@ fn main() {
 
             let lang = make_block_lang();
 
-            let block_fun = Arc::new(Mutex::new(BlockFun::new(lang)));
+            let block_fun = Arc::new(Mutex::new(BlockFun::new(lang.clone())));
             {
                 let mut block_fun_acc = block_fun.lock().unwrap();
                 block_fun_acc.instanciate_at(0, 3, 3, "phse", None).unwrap();
@@ -309,35 +390,76 @@ fn main() {
                 block_fun_acc.instanciate_at(0, 5, 5, "phse", None).unwrap();
             }
 
+            let palette_popup = Widget::new(style_ref.with_style_clone(|style| {
+                style.bg_color = hexotk::style::UI_ACCENT_BG1_CLR;
+                style.border_style = BorderStyle::Rect;
+                style.font_size = 14.0;
+            }));
+            palette_popup.change_layout(|layout| {
+                layout.position_type = Some(PositionType::SelfDirected);
+                layout.width = Some(Units::Pixels(250.0));
+                layout.height = Some(Units::Pixels(350.0));
+                layout.visible = false;
+            });
+            palette_popup.auto_hide();
+
+            let palette_wid = Widget::new(palette_popup.style());
+            palette_wid
+                .set_ctrl(Control::BlockPalette { palette: Box::new(BlockPalette::new(lang)) });
+            palette_popup.add(palette_wid.clone());
+
             let code = block_fun.clone();
-            blockcode.reg("click", move |_ctx, _wid, ev| {
+            palette_wid.reg("instantiate", {
+                let palette_popup = palette_popup.clone();
+                move |_ctx, wid, ev| {
+                    if let EvPayload::BlockInstantiate { typ, user_input, at: Some(at) } = &ev.data
+                    {
+                        let (id, x, y) = at.pos();
+
+                        edit_code(&wid, &code, "Add Block", |code| {
+                            if let Err(e) = code.instanciate_at(id, x, y, typ, user_input.clone()) {
+                                println!("INSTANCIATE ERROR: {:?}", e);
+                            }
+                        });
+                    }
+
+                    palette_popup.hide();
+                }
+            });
+
+            let code = block_fun.clone();
+            let popup = palette_popup.clone();
+            blockcode.reg("click", move |_ctx, wid, ev| {
                 if let EvPayload::BlockPos { button, at, .. } = ev.data {
                     if let BlockPos::Block { row, col, .. } = at {
                         let (id, x, y) = at.pos();
 
-                        let mut code = code.lock().unwrap();
-
                         if button == MButton::Right {
                             println!("PORT CLICK {:?}", at);
-                            code.shift_port(id, x, y, row, col == 1);
+                            edit_code(&wid, &code, "Shift Port", |code| {
+                                code.shift_port(id, x, y, row, col == 1);
+                            });
                         } else {
-                            if col == 1 {
-                                let _ = code.split_block_chain_after(id, x, y, Some("->"));
-                            } else {
-                                let _ = code.split_block_chain_after(id, x - 1, y, None);
-                            }
+                            edit_code(&wid, &code, "Split Chain", |code| {
+                                if col == 1 {
+                                    let _ = code.split_block_chain_after(id, x, y, Some("->"));
+                                } else {
+                                    let _ = code.split_block_chain_after(id, x - 1, y, None);
+                                }
+                            });
                         }
 
-                        let tree = code.generate_tree::<ASTNodeRef>("zero").unwrap();
+                        let tree =
+                            code.lock().unwrap().generate_tree::<ASTNodeRef>("zero").unwrap();
                         println!("{}", tree.walk_dump("", "", 0));
-
-                        code.recalculate_area_sizes();
                     } else {
-                        println!("CLICK POPUP {:?}", at);
-                        //                    state.insert_event(
-                        //                        Event::new(PopupEvent::OpenAtCursor)
-                        //                        .target(pop)
-                        //                        .origin(Entity::root()));
+                        palette_wid.with_ctrl(|ctrl| {
+                            if let Control::BlockPalette { palette } = ctrl {
+                                palette.set_target(Some(at));
+                            }
+                        });
+                        popup.popup_at(PopupPos::MousePos);
+                        palette_wid.focus();
                     }
                     //                    (*on_change)(state, entity, code.clone());
                 }
@@ -345,41 +467,65 @@ fn main() {
             });
 
             let code = block_fun.clone();
-            blockcode.reg("drag", move |_ctx, _wid, ev| {
+            blockcode.reg("drag", move |_ctx, wid, ev| {
                 if let EvPayload::BlockPos { at, to: Some(to), button } = ev.data {
                     println!("CLICK: {:?}", ev);
                     let (id, x, y) = at.pos();
                     let (id2, x2, y2) = to.pos();
 
                     println!("P1={:?} P2={:?}", at, to);
-                    let mut code = code.lock().unwrap();
 
                     if let BlockPos::Cell { .. } = at {
                         if let BlockPos::Block { .. } = to {
-                            let _ = code.clone_block_from_to(id2, x2, y2, id, x, y);
-                            code.recalculate_area_sizes();
+                            edit_code(&wid, &code, "Clone Block", |code| {
+                                let _ = code.clone_block_from_to(id2, x2, y2, id, x, y);
+                            });
 
                             // (*ouagen_change)(state, entity, code.clone());
                         }
                     } else {
-                        if button == MButton::Right {
-                            let _ = code.move_block_from_to(id, x, y, id2, x2, y2);
-                        } else {
-                            if at.pos() == to.pos() {
-                                let _ = code.remove_at(id, x, y);
+                        edit_code(&wid, &code, "Move Block", |code| {
+                            if button == MButton::Right {
+                                let _ = code.move_block_from_to(id, x, y, id2, x2, y2);
                             } else {
-                                let _ = code.move_block_chain_from_to(id, x, y, id2, x2, y2);
+                                if at.pos() == to.pos() {
+                                    let _ = code.remove_at(id, x, y);
+                                } else {
+                                    let _ = code.move_block_chain_from_to(id, x, y, id2, x2, y2);
+                                }
                             }
-                        }
-
-                        code.recalculate_area_sizes();
+                        });
 
                         // (*on_change)(state, entity, code.clone());
                     }
                 }
             });
 
+            let code = block_fun.clone();
+            let paste_cb = move |_ctx: &mut dyn std::any::Any, wid: Widget, ev: &Event| {
+                if let EvPayload::BlockClip { at, chain, to: Some(to) } = ev.data {
+                    let (id, x, y) = if let Some(origin) = at.block_origin() {
+                        origin
+                    } else {
+                        return;
+                    };
+                    let (id2, x2, y2) = to.pos();
+
+                    edit_code(&wid, &code, "Paste Blocks", |code| {
+                        let origins =
+                            if chain { block_chain(&*code, id, x, y) } else { vec![(x, y)] };
+                        for (bx, by) in origins {
+                            let _ =
+                                code.clone_block_from_to(id, bx, by, id2, x2 + bx - x, y2 + by - y);
+                        }
+                    });
+                }
+            };
+            blockcode.reg("paste", paste_cb.clone());
+            blockcode.reg("duplicate", paste_cb);
+
             root.add(blockcode);
+            root.add(palette_popup);
 
             let mut ui = Box::new(UI::new(Rc::new(RefCell::new(1))));
 
@@ -1,4 +1,15 @@
-use crate::{BlockCodeView, BlockView};
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+//! A small engine for visual block languages, as displayed and edited
+//! by the [crate::BlockCode] widget.
+//!
+//! You define the available blocks with a [BlockLanguage], place them
+//! in the areas of a [BlockFun] and turn that into an AST of your own
+//! [BlockASTNode] type with [BlockFun::generate_tree].
+
+use crate::Validation;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -6,7 +17,106 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 
-#[derive(Debug, Clone)]
+mod eval;
+
+pub use eval::{BlockEvalCtx, BlockEvalError, BlockEvaluator};
+
+/// Read access to a single block for the [crate::BlockCode] widget.
+pub trait BlockView {
+    /// An ID, that stays the same when the block is moved.
+    fn id(&self) -> usize;
+    /// How many rows this block spans.
+    fn rows(&self) -> usize;
+    /// The ID of the sub area with the index `idx` (0 or 1).
+    fn contains(&self, idx: usize) -> Option<usize>;
+    /// Whether the sub areas are visible.
+    fn expanded(&self) -> bool;
+    /// Writes the label into `buf` and returns the number of bytes written.
+    fn label(&self, buf: &mut [u8]) -> usize;
+    fn has_input(&self, idx: usize) -> bool;
+    fn has_output(&self, idx: usize) -> bool;
+    /// Writes the label of the input port in row `idx` into `buf`.
+    fn input_label(&self, idx: usize, buf: &mut [u8]) -> usize;
+    /// Writes the label of the output port in row `idx` into `buf`.
+    fn output_label(&self, idx: usize, buf: &mut [u8]) -> usize;
+    /// A color index into the colors of the [crate::Style].
+    fn custom_color(&self) -> Option<usize>;
+}
+
+/// Read access to the areas of a block program for the [crate::BlockCode]
+/// widget. Area 0 is the root area, other areas are sub areas of blocks.
+pub trait BlockCodeView {
+    fn area_header(&self, id: usize) -> Option<&str>;
+    /// The size of the area in cells.
+    fn area_size(&self, id: usize) -> (usize, usize);
+    /// Returns the block that has its origin at the cell `x`/`y`.
+    fn block_at(&self, id: usize, x: i64, y: i64) -> Option<&dyn BlockView>;
+    /// Returns the origin cell of the block, that covers the cell `x`/`y`.
+    fn origin_at(&self, id: usize, x: i64, y: i64) -> Option<(i64, i64)>;
+    /// Increases with every change, so the widget knows when to redraw.
+    fn generation(&self) -> u64;
+    /// Whether the block with its origin at `x`/`y` is marked as erroneous.
+    fn block_has_error(&self, _id: usize, _x: i64, _y: i64) -> bool {
+        false
+    }
+}
+
+/// A position inside the areas of a [BlockFun], either a row of a block
+/// or an empty cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockPos {
+    Block { id: usize, x: i64, y: i64, row: usize, col: usize, rows: usize },
+    Cell { id: usize, x: i64, y: i64 },
+}
+
+#[allow(dead_code)]
+impl BlockPos {
+    pub fn area_id(&self) -> usize {
+        match self {
+            BlockPos::Block { id, .. } => *id,
+            BlockPos::Cell { id, .. } => *id,
+        }
+    }
+
+    pub fn x(&self) -> i64 {
+        match self {
+            BlockPos::Block { x, .. } => *x,
+            BlockPos::Cell { x, .. } => *x,
+        }
+    }
+
+    pub fn y(&self) -> i64 {
+        match self {
+            BlockPos::Block { y, .. } => *y,
+            BlockPos::Cell { y, .. } => *y,
+        }
+    }
+
+    pub fn row_info(&self) -> (usize, usize) {
+        match self {
+            BlockPos::Block { rows, row, .. } => (*rows, *row),
+            BlockPos::Cell { .. } => (1, 0),
+        }
+    }
+
+    pub fn pos(&self) -> (usize, i64, i64) {
+        match self {
+            BlockPos::Block { id, x, y, .. } => (*id, *x, *y),
+            BlockPos::Cell { id, x, y, .. } => (*id, *x, *y),
+        }
+    }
+
+    /// The area and the position of the top row of the block, or `None`
+    /// for an empty cell.
+    pub fn block_origin(&self) -> Option<(usize, i64, i64)> {
+        match self {
+            BlockPos::Block { id, x, y, row, .. } => Some((*id, *x, *y - *row as i64)),
+            BlockPos::Cell { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct BlockIDGenerator {
     counter: Rc<RefCell<usize>>,
 }
@@ -67,11 +177,16 @@ pub struct Block {
 }
 
 impl Block {
+    /// The ID of this block, it stays the same when the block is moved.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
     pub fn clone_with_new_id(&self, new_id: usize) -> Self {
         Self {
             id: new_id,
             rows: self.rows,
-            contains: self.contains.clone(),
+            contains: self.contains,
             expanded: self.expanded,
             typ: self.typ.clone(),
             lbl: self.lbl.clone(),
@@ -105,10 +220,8 @@ impl Block {
     /// `f` gets passed the row index.
     pub fn for_output_ports<F: FnMut(usize, &str)>(&self, mut f: F) {
         for i in 0..self.rows {
-            if let Some(p) = self.outputs.get(i) {
-                if let Some(p) = p {
-                    f(i, p);
-                }
+            if let Some(Some(p)) = self.outputs.get(i) {
+                f(i, p);
             }
         }
     }
@@ -132,10 +245,8 @@ impl Block {
     /// `f` gets passed the row index.
     pub fn for_input_ports<F: FnMut(usize, &str)>(&self, mut f: F) {
         for i in 0..self.rows {
-            if let Some(p) = self.inputs.get(i) {
-                if let Some(p) = p {
-                    f(i, p);
-                }
+            if let Some(Some(p)) = self.inputs.get(i) {
+                f(i, p);
             }
         }
     }
@@ -145,16 +256,17 @@ impl Block {
     pub fn for_input_ports_reverse<F: FnMut(usize, &str)>(&self, mut f: F) {
         for i in 1..=self.rows {
             let i = self.rows - i;
-            if let Some(p) = self.inputs.get(i) {
-                if let Some(p) = p {
-                    f(i, p);
-                }
+            if let Some(Some(p)) = self.inputs.get(i) {
+                f(i, p);
             }
         }
     }
 }
 
 impl BlockView for Block {
+    fn id(&self) -> usize {
+        self.id
+    }
     fn rows(&self) -> usize {
         self.rows
     }
@@ -184,15 +296,11 @@ impl BlockView for Block {
     }
     fn input_label(&self, idx: usize, buf: &mut [u8]) -> usize {
         use std::io::Write;
-        if let Some(lbl_opt) = self.inputs.get(idx) {
-            if let Some(lbl) = lbl_opt {
-                let mut bw = std::io::BufWriter::new(buf);
-                match write!(bw, "{}", lbl) {
-                    Ok(_) => bw.buffer().len(),
-                    _ => 0,
-                }
-            } else {
-                0
+        if let Some(Some(lbl)) = self.inputs.get(idx) {
+            let mut bw = std::io::BufWriter::new(buf);
+            match write!(bw, "{}", lbl) {
+                Ok(_) => bw.buffer().len(),
+                _ => 0,
             }
         } else {
             0
@@ -200,15 +308,11 @@ impl BlockView for Block {
     }
     fn output_label(&self, idx: usize, buf: &mut [u8]) -> usize {
         use std::io::Write;
-        if let Some(lbl_opt) = self.outputs.get(idx) {
-            if let Some(lbl) = lbl_opt {
-                let mut bw = std::io::BufWriter::new(buf);
-                match write!(bw, "{}", lbl) {
-                    Ok(_) => bw.buffer().len(),
-                    _ => 0,
-                }
-            } else {
-                0
+        if let Some(Some(lbl)) = self.outputs.get(idx) {
+            let mut bw = std::io::BufWriter::new(buf);
+            match write!(bw, "{}", lbl) {
+                Ok(_) => bw.buffer().len(),
+                _ => 0,
             }
         } else {
             0
@@ -377,7 +481,7 @@ impl BlockChain {
     }
 
     pub fn place_load(&mut self, area: &mut BlockArea) {
-        let load = std::mem::replace(&mut self.load, vec![]);
+        let load = std::mem::take(&mut self.load);
         area.set_blocks_from(load);
     }
 
@@ -405,7 +509,7 @@ impl BlockChain {
             (1, 2),
             (1, 3),
         ] {
-            println!("TRY {},{}", *xo, *yo);
+            //d// println!("TRY {},{}", *xo, *yo);
             if self.area_has_space_for_load(area, *xo, *yo) {
                 self.move_by_offs(*xo, *yo);
                 return true;
@@ -414,7 +518,7 @@ impl BlockChain {
             //d// println!("RETRY xo={}, yo={}", *xo, *yo);
         }
 
-        return false;
+        false
     }
 
     pub fn area_has_space_for_load(
@@ -445,7 +549,7 @@ impl BlockChain {
             }
         }
 
-        return false;
+        false
     }
 }
 
@@ -714,7 +818,7 @@ impl BlockArea {
         let mut min_w = 1;
         let mut min_h = 1;
 
-        for ((ox, oy), _) in &self.origin_map {
+        for (ox, oy) in self.origin_map.keys() {
             let (ox, oy) = ((*ox).max(0) as usize, (*oy).max(0) as usize);
 
             if min_w < (ox + 1) {
@@ -778,7 +882,7 @@ impl BlockArea {
         for i in 0..rows {
             let yo = y + (i as i64);
 
-            if self.origin_map.get(&(x, yo)).is_some() {
+            if self.origin_map.contains_key(&(x, yo)) {
                 return false;
             }
         }
@@ -787,8 +891,9 @@ impl BlockArea {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum BlockUserInput {
+    #[default]
     None,
     Float,
     Integer,
@@ -796,16 +901,67 @@ pub enum BlockUserInput {
     ClientDecision,
 }
 
-impl Default for BlockUserInput {
-    fn default() -> Self {
-        Self::None
-    }
-}
-
 impl BlockUserInput {
     pub fn needs_input(&self) -> bool {
         *self != BlockUserInput::None
     }
+
+    /// Checks the `text` a user entered for a block of this input kind.
+    /// Surrounding whitespace is trimmed off by normalization.
+    /// [BlockUserInput::None] and [BlockUserInput::ClientDecision]
+    /// accept anything.
+    pub fn validate(&self, text: &str) -> Validation {
+        let trimmed = text.trim();
+        let valid = match self {
+            BlockUserInput::Float => trimmed.parse::<f32>().map(|v| v.is_finite()).unwrap_or(false),
+            BlockUserInput::Integer => trimmed.parse::<i64>().is_ok(),
+            BlockUserInput::Identifier => {
+                !trimmed.is_empty() && !trimmed.chars().any(|c| c.is_whitespace() || c.is_control())
+            }
+            BlockUserInput::None | BlockUserInput::ClientDecision => return Validation::Valid,
+        };
+
+        if !valid {
+            Validation::Invalid
+        } else if trimmed != text {
+            Validation::Normalized(trimmed.to_string())
+        } else {
+            Validation::Valid
+        }
+    }
+}
+
+/// The data type of a port of a [BlockType], checked by [BlockFun::check].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BlockPortType {
+    /// The name of the data type. `None` connects to anything.
+    pub name: Option<String>,
+    /// Whether the input has to be connected. Otherwise an unconnected
+    /// input is filled in by [BlockFun::generate_tree].
+    pub required: bool,
+}
+
+impl BlockPortType {
+    pub fn any() -> Self {
+        Self { name: None, required: false }
+    }
+
+    pub fn typed(name: &str) -> Self {
+        Self { name: Some(name.to_string()), required: false }
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Whether an output of type `self` can be connected to an input of type `input`.
+    pub fn connects_to(&self, input: &BlockPortType) -> bool {
+        match (&self.name, &input.name) {
+            (Some(out), Some(inp)) => out == inp,
+            _ => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -815,6 +971,10 @@ pub struct BlockType {
     pub rows: usize,
     pub inputs: Vec<Option<String>>,
     pub outputs: Vec<Option<String>>,
+    /// The data types of the `inputs`, missing entries accept anything.
+    pub input_types: Vec<BlockPortType>,
+    /// The data types of the `outputs`, missing entries connect to anything.
+    pub output_types: Vec<BlockPortType>,
     pub area_count: usize,
     pub user_input: BlockUserInput,
     pub description: String,
@@ -822,6 +982,19 @@ pub struct BlockType {
 }
 
 impl BlockType {
+    /// Returns the data type of the input port labelled `lbl`. Ports are
+    /// identified by their label, because [BlockFun::shift_port] moves
+    /// them to other rows. [BlockLanguage::define] makes sure, that
+    /// ports sharing a label also share their type.
+    pub fn input_type(&self, lbl: &str) -> BlockPortType {
+        port_type(&self.inputs, &self.input_types, lbl)
+    }
+
+    /// Returns the data type of the output port labelled `lbl`.
+    pub fn output_type(&self, lbl: &str) -> BlockPortType {
+        port_type(&self.outputs, &self.output_types, lbl)
+    }
+
     fn touch_contains(&self, block: &mut Block) {
         block.contains = match self.area_count {
             0 => (None, None),
@@ -847,12 +1020,52 @@ impl BlockType {
             outputs: self.outputs.clone(),
             color: self.color,
         });
-        self.touch_contains(&mut *block);
+        self.touch_contains(&mut block);
         block
     }
 }
 
-#[derive(Debug, Clone)]
+#[cfg(test)]
+impl BlockType {
+    /// A block type for the tests, with one row per port and everything
+    /// else left at the defaults. Adjust the fields as needed.
+    pub(crate) fn for_test(name: &str, inputs: &[&str], outputs: &[&str]) -> Self {
+        BlockType {
+            category: "test".to_string(),
+            name: name.to_string(),
+            rows: inputs.len().max(outputs.len()).max(1),
+            inputs: inputs.iter().map(|s| Some(s.to_string())).collect(),
+            outputs: outputs.iter().map(|s| Some(s.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+fn port_type(ports: &[Option<String>], types: &[BlockPortType], lbl: &str) -> BlockPortType {
+    ports
+        .iter()
+        .position(|p| p.as_deref() == Some(lbl))
+        .and_then(|idx| types.get(idx))
+        .cloned()
+        .unwrap_or_else(BlockPortType::any)
+}
+
+/// Returns the label of two ports, that share it but differ in their data type.
+fn ambiguous_port<'a>(ports: &'a [Option<String>], types: &[BlockPortType]) -> Option<&'a str> {
+    let type_of = |idx: usize| types.get(idx).cloned().unwrap_or_else(BlockPortType::any);
+
+    for (i, a) in ports.iter().enumerate() {
+        for (j, b) in ports.iter().enumerate().skip(i + 1) {
+            if a.is_some() && a == b && type_of(i) != type_of(j) {
+                return a.as_deref();
+            }
+        }
+    }
+
+    None
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct BlockLanguage {
     types: HashMap<String, BlockType>,
     identifiers: HashMap<String, String>,
@@ -868,12 +1081,31 @@ impl BlockLanguage {
         self.identifiers.insert(id.to_string(), v);
     }
 
+    /// Adds the block type `typ` to the language.
+    ///
+    /// # Panics
+    ///
+    /// Ports are identified by their label, so if two input or two
+    /// output ports share a label, they also have to share the data type.
+    /// Otherwise [BlockFun::check] could not tell their types apart.
     pub fn define(&mut self, typ: BlockType) {
+        for (ports, types, kind) in [
+            (&typ.inputs, &typ.input_types, "inputs"),
+            (&typ.outputs, &typ.output_types, "outputs"),
+        ] {
+            if let Some(lbl) = ambiguous_port(ports, types) {
+                panic!(
+                    "block type '{}' has {} labelled '{}' with different data types",
+                    typ.name, kind, lbl
+                );
+            }
+        }
+
         self.types.insert(typ.name.clone(), typ);
     }
 
     pub fn is_identifier(&self, id: &str) -> bool {
-        self.identifiers.get(id).is_some()
+        self.identifiers.contains_key(id)
     }
 
     pub fn list_identifiers(&self) -> Vec<String> {
@@ -882,9 +1114,13 @@ impl BlockLanguage {
         identifiers
     }
 
+    pub fn get_type(&self, name: &str) -> Option<&BlockType> {
+        self.types.get(name)
+    }
+
     pub fn get_type_list(&self) -> Vec<(String, String, BlockUserInput)> {
         let mut out = vec![];
-        for (_, typ) in &self.types {
+        for typ in self.types.values() {
             out.push((typ.category.clone(), typ.name.clone(), typ.user_input));
         }
         out
@@ -908,20 +1144,74 @@ pub enum BlockDSPError {
     NoSpaceAvailable(usize, i64, i64, usize),
 }
 
+/// The version of the text format written by [BlockFun::save_to_string].
+pub const BLOCKFUN_FORMAT_VERSION: u32 = 1;
+
+/// An error from [BlockFun::load_from_str]. `line` is 1-based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockFunParseError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl std::fmt::Display for BlockFunParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "blockfun error in line {}: {}", self.line, self.msg)
+    }
+}
+
+/// A problem found by [BlockFun::check].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockDiagnostic {
+    /// The output at `from` has the type `got`, but the input at `pos`
+    /// expects `expected`.
+    TypeMismatch { pos: BlockPos, from: BlockPos, expected: String, got: String },
+    /// The required input at `pos` is not connected.
+    UnconnectedInput { pos: BlockPos, port: String },
+}
+
+impl BlockDiagnostic {
+    /// The position of the offending input port.
+    pub fn pos(&self) -> BlockPos {
+        match self {
+            BlockDiagnostic::TypeMismatch { pos, .. } => *pos,
+            BlockDiagnostic::UnconnectedInput { pos, .. } => *pos,
+        }
+    }
+}
+
+impl std::fmt::Display for BlockDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (id, x, y) = self.pos().pos();
+
+        match self {
+            BlockDiagnostic::TypeMismatch { expected, got, .. } => write!(
+                f,
+                "area {} at {},{}: expected input of type '{}', got '{}'",
+                id, x, y, expected, got
+            ),
+            BlockDiagnostic::UnconnectedInput { port, .. } => {
+                write!(f, "area {} at {},{}: input '{}' is not connected", id, x, y, port)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BlockFunSnapshot {
-    areas: Vec<Box<BlockArea>>,
+    areas: Vec<BlockArea>,
     cur_id: usize,
 }
 
 #[derive(Debug, Clone)]
 pub struct BlockFun {
     language: Rc<RefCell<BlockLanguage>>,
-    areas: Vec<Box<BlockArea>>,
+    areas: Vec<BlockArea>,
     size_work_dq: VecDeque<usize>,
     area_work_dq: VecDeque<usize>,
     id_gen: BlockIDGenerator,
     generation: u64,
+    diagnostics: Vec<BlockDiagnostic>,
 }
 
 #[derive(Debug)]
@@ -936,11 +1226,12 @@ impl BlockFun {
     pub fn new(lang: Rc<RefCell<BlockLanguage>>) -> Self {
         Self {
             language: lang,
-            areas: vec![Box::new(BlockArea::new(16, 16))],
+            areas: vec![BlockArea::new(16, 16)],
             size_work_dq: VecDeque::new(),
             area_work_dq: VecDeque::new(),
             id_gen: BlockIDGenerator::new(),
             generation: 0,
+            diagnostics: vec![],
         }
     }
 
@@ -962,19 +1253,409 @@ impl BlockFun {
     }
 
     pub fn save_snapshot(&self) -> BlockFunSnapshot {
-        BlockFunSnapshot {
-            areas: self.areas.iter().cloned().collect(),
-            cur_id: self.id_gen.current(),
-        }
+        BlockFunSnapshot { areas: self.areas.to_vec(), cur_id: self.id_gen.current() }
     }
 
     pub fn load_snapshot(&mut self, repr: &BlockFunSnapshot) {
-        self.areas = repr.areas.iter().cloned().collect();
+        self.areas = repr.areas.to_vec();
         self.id_gen = BlockIDGenerator::new_with_id(repr.cur_id);
         self.recalculate_area_sizes();
         self.generation += 1;
     }
 
+    /// Writes the program in a line based text format, that is stable
+    /// enough to be kept in version control:
+    ///
+    ///```text
+    ///    blockfun 1
+    ///    next_id 3
+    ///    area 0 16 16 fixed ""
+    ///    area 1 1 1 shrink ""
+    ///    area 2 1 1 shrink ""
+    ///    block 0 2 3 1 "if" "if" expanded 1 2
+    ///      in ""
+    ///      out ""
+    ///    block 1 0 0 2 "set" "x" expanded - -
+    ///      in ""
+    ///      out
+    ///```
+    ///
+    /// An `area` line holds the area ID, the size, whether it
+    /// shrinks to its contents and the header. A `block` line holds
+    /// the area ID, the position, the block ID, the [BlockType] name, the
+    /// label (the user input), `expanded` or `collapsed` and the IDs of
+    /// the sub areas. The optional `in` and `out` lines list the port
+    /// names by row, `-` stands for a row without port. If they are left
+    /// out, the ports of the [BlockType] are used.
+    pub fn save_to_string(&self) -> String {
+        let mut out = format!("blockfun {}\n", BLOCKFUN_FORMAT_VERSION);
+        out += &format!("next_id {}\n", self.id_gen.current());
+
+        for (id, area) in self.areas.iter().enumerate() {
+            out += &format!(
+                "area {} {} {} {} {}\n",
+                id,
+                area.size.0,
+                area.size.1,
+                if area.auto_shrink { "shrink" } else { "fixed" },
+                quote_str(&area.header)
+            );
+        }
+
+        let port_list = |ports: &Vec<Option<String>>| -> String {
+            let ports: Vec<String> = ports
+                .iter()
+                .map(|p| if let Some(p) = p { quote_str(p) } else { "-".to_string() })
+                .collect();
+            ports.join(" ")
+        };
+        let sub_area = |id: Option<usize>| -> String {
+            id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string())
+        };
+
+        for (id, area) in self.areas.iter().enumerate() {
+            let mut positions: Vec<&(i64, i64)> = area.blocks.keys().collect();
+            positions.sort();
+
+            for pos in positions {
+                let block = &area.blocks[pos];
+
+                out += &format!(
+                    "block {} {} {} {} {} {} {} {} {}\n",
+                    id,
+                    pos.0,
+                    pos.1,
+                    block.id,
+                    quote_str(&block.typ),
+                    quote_str(&block.lbl),
+                    if block.expanded { "expanded" } else { "collapsed" },
+                    sub_area(block.contains.0),
+                    sub_area(block.contains.1)
+                );
+                out += format!("  in {}", port_list(&block.inputs)).trim_end();
+                out += "\n";
+                out += format!("  out {}", port_list(&block.outputs)).trim_end();
+                out += "\n";
+            }
+        }
+
+        out
+    }
+
+    /// Replaces the program with one written by [BlockFun::save_to_string].
+    /// The [BlockType] of every block has to be defined in the
+    /// [BlockLanguage]. On error the program is left untouched.
+    pub fn load_from_str(&mut self, src: &str) -> Result<(), BlockFunParseError> {
+        let mut areas: Vec<BlockArea> = vec![];
+        let mut next_id = 0;
+        let mut max_id = 0;
+        let mut got_header = false;
+        let mut last_block: Option<(usize, i64, i64)> = None;
+        let mut sub_area_refs: Vec<(usize, usize)> = vec![];
+
+        let lang = self.language.borrow();
+
+        for (line_idx, line) in src.lines().enumerate() {
+            let line_no = line_idx + 1;
+            let err = |msg: String| BlockFunParseError { line: line_no, msg };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let toks = tokenize_line(line).map_err(err)?;
+            let (stmt, args) = match toks.split_first() {
+                Some((BlockFunTok::Word(stmt), args)) => (stmt.as_str(), args),
+                _ => return Err(err(format!("expected a statement, got '{}'", line))),
+            };
+
+            if !got_header {
+                if stmt != "blockfun" || args.len() != 1 {
+                    return Err(err("expected the 'blockfun <version>' header".to_string()));
+                }
+
+                let version: u32 = parse_tok(&args[0]).map_err(err)?;
+                if version > BLOCKFUN_FORMAT_VERSION {
+                    return Err(err(format!(
+                        "unsupported format version {}, expected {} or older",
+                        version, BLOCKFUN_FORMAT_VERSION
+                    )));
+                }
+
+                got_header = true;
+                continue;
+            }
+
+            match stmt {
+                "next_id" => {
+                    if args.len() != 1 {
+                        return Err(err("expected 'next_id <id>'".to_string()));
+                    }
+                    next_id = parse_tok(&args[0]).map_err(err)?;
+                }
+                "area" => {
+                    if args.len() != 5 {
+                        return Err(err(
+                            "expected 'area <id> <width> <height> fixed|shrink <header>'"
+                                .to_string(),
+                        ));
+                    }
+
+                    let id: usize = parse_tok(&args[0]).map_err(err)?;
+                    if id != areas.len() {
+                        return Err(err(format!(
+                            "areas must be numbered in order, expected area {}",
+                            areas.len()
+                        )));
+                    }
+
+                    let mut area = BlockArea::new(
+                        parse_tok(&args[1]).map_err(err)?,
+                        parse_tok(&args[2]).map_err(err)?,
+                    );
+                    area.set_auto_shrink(match word_tok(&args[3]) {
+                        Some("fixed") => false,
+                        Some("shrink") => true,
+                        _ => return Err(err("expected 'fixed' or 'shrink'".to_string())),
+                    });
+                    area.set_header(str_tok(&args[4]).map_err(err)?.to_string());
+
+                    areas.push(area);
+                }
+                "block" => {
+                    if args.len() != 9 {
+                        return Err(err("expected 'block <area> <x> <y> <id> <type> <label> \
+                                        expanded|collapsed <sub area> <sub area>'"
+                            .to_string()));
+                    }
+
+                    let area_id: usize = parse_tok(&args[0]).map_err(err)?;
+                    let x: i64 = parse_tok(&args[1]).map_err(err)?;
+                    let y: i64 = parse_tok(&args[2]).map_err(err)?;
+                    let id: usize = parse_tok(&args[3]).map_err(err)?;
+                    let typ_name = str_tok(&args[4]).map_err(err)?;
+                    let lbl = str_tok(&args[5]).map_err(err)?;
+
+                    let expanded = match word_tok(&args[6]) {
+                        Some("expanded") => true,
+                        Some("collapsed") => false,
+                        _ => return Err(err("expected 'expanded' or 'collapsed'".to_string())),
+                    };
+
+                    let mut contains = (None, None);
+                    for (i, arg) in args[7..9].iter().enumerate() {
+                        let sub = if word_tok(arg) == Some("-") {
+                            None
+                        } else {
+                            let sub: usize = parse_tok(arg).map_err(err)?;
+                            sub_area_refs.push((sub, line_no));
+                            Some(sub)
+                        };
+
+                        if i == 0 {
+                            contains.0 = sub;
+                        } else {
+                            contains.1 = sub;
+                        }
+                    }
+
+                    let typ = lang.types.get(typ_name).ok_or_else(|| {
+                        err(format!(
+                            "unknown block type '{}', it is not defined in the language",
+                            typ_name
+                        ))
+                    })?;
+
+                    let mut check = Block {
+                        id,
+                        rows: typ.rows,
+                        contains: (None, None),
+                        expanded,
+                        typ: typ.name.clone(),
+                        lbl: lbl.to_string(),
+                        inputs: typ.inputs.clone(),
+                        outputs: typ.outputs.clone(),
+                        color: typ.color,
+                    };
+                    typ.touch_contains(&mut check);
+                    if check.contains.0.is_some() != contains.0.is_some()
+                        || check.contains.1.is_some() != contains.1.is_some()
+                    {
+                        return Err(err(format!(
+                            "block type '{}' needs {} sub areas",
+                            typ_name, typ.area_count
+                        )));
+                    }
+                    check.contains = contains;
+
+                    let area = areas
+                        .get_mut(area_id)
+                        .ok_or_else(|| err(format!("unknown area {}", area_id)))?;
+                    if !area.check_space_at(x, y, check.rows) {
+                        return Err(err(format!("block at {},{} overlaps another block", x, y)));
+                    }
+
+                    area.set_block_at(x, y, Box::new(check));
+                    max_id = max_id.max(id);
+                    last_block = Some((area_id, x, y));
+                }
+                "in" | "out" => {
+                    let (area_id, x, y) = last_block
+                        .ok_or_else(|| err(format!("'{}' without a preceding block", stmt)))?;
+                    let block = areas[area_id].blocks.get_mut(&(x, y)).expect("block inserted");
+
+                    if args.len() > block.rows {
+                        return Err(err(format!(
+                            "{} ports given, but the block only has {} rows",
+                            args.len(),
+                            block.rows
+                        )));
+                    }
+
+                    let mut ports = vec![];
+                    for arg in args {
+                        if word_tok(arg) == Some("-") {
+                            ports.push(None);
+                        } else {
+                            ports.push(Some(str_tok(arg).map_err(err)?.to_string()));
+                        }
+                    }
+
+                    if stmt == "in" {
+                        block.inputs = ports;
+                    } else {
+                        block.outputs = ports;
+                    }
+                }
+                _ => return Err(err(format!("unknown statement '{}'", stmt))),
+            }
+        }
+
+        if !got_header {
+            return Err(BlockFunParseError {
+                line: 0,
+                msg: "expected the 'blockfun <version>' header".to_string(),
+            });
+        }
+
+        if areas.is_empty() {
+            return Err(BlockFunParseError { line: 0, msg: "no root area defined".to_string() });
+        }
+
+        let mut used = HashSet::new();
+        for (sub, line) in sub_area_refs {
+            if sub == 0 || sub >= areas.len() {
+                return Err(BlockFunParseError { line, msg: format!("unknown sub area {}", sub) });
+            }
+            if !used.insert(sub) {
+                return Err(BlockFunParseError {
+                    line,
+                    msg: format!("sub area {} is used by multiple blocks", sub),
+                });
+            }
+        }
+
+        drop(lang);
+
+        self.areas = areas;
+        self.id_gen = BlockIDGenerator::new_with_id(next_id.max(max_id));
+        self.recalculate_area_sizes();
+        self.generation += 1;
+
+        Ok(())
+    }
+
+    /// Checks the data types of all connections and whether all
+    /// required inputs are connected. Blocks of types, that are missing
+    /// from the [BlockLanguage], are skipped.
+    pub fn check(&self) -> Vec<BlockDiagnostic> {
+        let lang = self.language.borrow();
+        let mut diags = vec![];
+
+        for (area_id, area) in self.areas.iter().enumerate() {
+            let mut positions: Vec<&(i64, i64)> = area.blocks.keys().collect();
+            positions.sort();
+
+            for &(x, y) in positions {
+                let block = &area.blocks[&(x, y)];
+                let typ = if let Some(typ) = lang.types.get(&block.typ) {
+                    typ
+                } else {
+                    continue;
+                };
+
+                block.for_input_ports(|row, lbl| {
+                    let in_type = typ.input_type(lbl);
+                    let pos = BlockPos::Block {
+                        id: area_id,
+                        x,
+                        y: y + row as i64,
+                        row,
+                        col: 0,
+                        rows: block.rows,
+                    };
+
+                    let src = area.find_port_at(x - 1, y + row as i64, true);
+                    let (src_block, sx, sy, src_row) = if let Some(src) = src {
+                        src
+                    } else {
+                        if in_type.required {
+                            diags.push(BlockDiagnostic::UnconnectedInput {
+                                pos,
+                                port: lbl.to_string(),
+                            });
+                        }
+                        return;
+                    };
+
+                    let out_lbl = src_block.outputs.get(src_row).cloned().flatten();
+                    let out_type = lang
+                        .types
+                        .get(&src_block.typ)
+                        .map(|t| t.output_type(out_lbl.as_deref().unwrap_or("")))
+                        .unwrap_or_else(BlockPortType::any);
+
+                    if !out_type.connects_to(&in_type) {
+                        diags.push(BlockDiagnostic::TypeMismatch {
+                            pos,
+                            from: BlockPos::Block {
+                                id: area_id,
+                                x: sx,
+                                y: sy + src_row as i64,
+                                row: src_row,
+                                col: 1,
+                                rows: src_block.rows,
+                            },
+                            expected: in_type.name.unwrap_or_default(),
+                            got: out_type.name.unwrap_or_default(),
+                        });
+                    }
+                });
+            }
+        }
+
+        diags
+    }
+
+    /// Runs [BlockFun::check] and stores the result, so that the
+    /// [crate::BlockCode] widget marks the offending blocks.
+    /// Call this again after changing the program.
+    pub fn update_diagnostics(&mut self) -> &[BlockDiagnostic] {
+        let diags = self.check();
+        if diags != self.diagnostics {
+            self.diagnostics = diags;
+            self.generation += 1;
+        }
+
+        &self.diagnostics
+    }
+
+    /// The result of the last [BlockFun::update_diagnostics].
+    pub fn diagnostics(&self) -> &[BlockDiagnostic] {
+        &self.diagnostics
+    }
+
     pub fn generate_tree<Node: BlockASTNode>(&self, null_typ: &str) -> Result<Node, BlockDSPError> {
         // This is a type for filling in unfilled outputs:
         let lang = self.language.borrow();
@@ -1167,7 +1848,7 @@ impl BlockFun {
             let (w, h) = self.areas[area_idx].resolve_size(|_id| (0, 0));
             sizes[area_idx] = (w, h);
 
-            if cur_sub.len() == 0 {
+            if cur_sub.is_empty() {
                 size_work_list.push_front(area_idx);
             } else {
                 for sub_idx in &cur_sub {
@@ -1220,11 +1901,11 @@ impl BlockFun {
             }
         }
 
-        return false;
+        false
     }
 
     pub fn all_sub_areas_of(&mut self, block: &Block, areas: &mut Vec<usize>) {
-        let contains = block.contains.clone();
+        let contains = block.contains;
 
         let area_work_list = &mut self.area_work_dq;
         area_work_list.clear();
@@ -1236,7 +1917,7 @@ impl BlockFun {
             area_work_list.push_back(area_id);
         }
 
-        if area_work_list.len() <= 0 {
+        if area_work_list.is_empty() {
             return;
         }
 
@@ -1329,7 +2010,7 @@ impl BlockFun {
 
         let mut chain = area_clone.chain_at(x, y).ok_or(BlockDSPError::NoBlockAt(id, x, y))?;
 
-        chain.remove_load(area_clone.as_mut());
+        chain.remove_load(&mut area_clone);
 
         let lang = self.language.borrow();
         let typ: Option<&BlockType> = if let Some(filler_type) = filler_type {
@@ -1369,7 +2050,7 @@ impl BlockFun {
 
         let mut chain = area_clone.chain_at(x, y).ok_or(BlockDSPError::NoBlockAt(id, x, y))?;
 
-        chain.remove_load(area_clone.as_mut());
+        chain.remove_load(&mut area_clone);
         self.generation += 1;
 
         if id2 == id {
@@ -1467,14 +2148,14 @@ impl BlockFun {
 
     fn create_areas_for_block(&mut self, block: &mut Block) {
         if let Some(area_id) = &mut block.contains.0 {
-            let mut area = Box::new(BlockArea::new(1, 1));
+            let mut area = BlockArea::new(1, 1);
             area.set_auto_shrink(true);
             self.areas.push(area);
             *area_id = self.areas.len() - 1;
         }
 
         if let Some(area_id) = &mut block.contains.1 {
-            let mut area = Box::new(BlockArea::new(1, 1));
+            let mut area = BlockArea::new(1, 1);
             area.set_auto_shrink(true);
             self.areas.push(area);
             *area_id = self.areas.len() - 1;
@@ -1536,8 +2217,91 @@ impl BlockFun {
     }
 
     pub fn origin_at(&self, id: usize, x: i64, y: i64) -> Option<(i64, i64)> {
-        self.areas.get(id).map(|a| a.origin_map.get(&(x, y)).copied()).flatten()
+        self.areas.get(id).and_then(|a| a.origin_map.get(&(x, y)).copied())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlockFunTok {
+    Word(String),
+    Str(String),
+}
+
+fn quote_str(s: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            _ => out.push(c),
+        }
     }
+
+    out.push('"');
+    out
+}
+
+fn tokenize_line(line: &str) -> Result<Vec<BlockFunTok>, String> {
+    let mut toks = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        if c == '"' {
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => s.push('\n'),
+                        Some(c @ ('"' | '\\')) => s.push(c),
+                        _ => return Err("bad escape sequence in string".to_string()),
+                    },
+                    Some(c) => s.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            toks.push(BlockFunTok::Str(s));
+        } else {
+            let mut s = String::from(c);
+            while let Some(c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                s.push(*c);
+                chars.next();
+            }
+            toks.push(BlockFunTok::Word(s));
+        }
+    }
+
+    Ok(toks)
+}
+
+fn word_tok(tok: &BlockFunTok) -> Option<&str> {
+    if let BlockFunTok::Word(w) = tok {
+        Some(w)
+    } else {
+        None
+    }
+}
+
+fn str_tok(tok: &BlockFunTok) -> Result<&str, String> {
+    if let BlockFunTok::Str(s) = tok {
+        Ok(s)
+    } else {
+        Err(format!("expected a quoted string, got '{}'", word_tok(tok).unwrap_or("")))
+    }
+}
+
+fn parse_tok<T: std::str::FromStr>(tok: &BlockFunTok) -> Result<T, String> {
+    let w = word_tok(tok).ok_or_else(|| "expected a number, got a string".to_string())?;
+    w.parse::<T>().map_err(|_| format!("expected a number, got '{}'", w))
 }
 
 impl BlockCodeView for BlockFun {
@@ -1560,4 +2324,411 @@ impl BlockCodeView for BlockFun {
     fn generation(&self) -> u64 {
         self.generation
     }
+
+    fn block_has_error(&self, id: usize, x: i64, y: i64) -> bool {
+        self.diagnostics.iter().any(|d| d.pos().block_origin() == Some((id, x, y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestNodeData {
+        name: String,
+        nodes: Vec<(String, TestNode)>,
+    }
+
+    #[derive(Debug, Clone)]
+    struct TestNode(Rc<RefCell<TestNodeData>>);
+
+    impl TestNode {
+        fn dump(&self) -> String {
+            let node = self.0.borrow();
+            let mut s = node.name.clone();
+
+            if !node.nodes.is_empty() {
+                let childs: Vec<String> = node
+                    .nodes
+                    .iter()
+                    .map(|(in_port, n)| {
+                        if in_port.is_empty() {
+                            n.dump()
+                        } else {
+                            format!("{}={}", in_port, n.dump())
+                        }
+                    })
+                    .collect();
+                s += &format!("({})", childs.join(" "));
+            }
+
+            s
+        }
+    }
+
+    impl BlockASTNode for TestNode {
+        fn from(_id: usize, typ: &str, lbl: &str) -> Self {
+            let name = if lbl.is_empty() || lbl == typ {
+                typ.to_string()
+            } else {
+                format!("{}:{}", typ, lbl)
+            };
+            TestNode(Rc::new(RefCell::new(TestNodeData { name, nodes: vec![] })))
+        }
+
+        fn add_node(&self, in_port: String, _out_port: String, node: Self) {
+            self.0.borrow_mut().nodes.push((in_port, node));
+        }
+    }
+
+    fn new_fun() -> BlockFun {
+        let mut lang = BlockLanguage::new();
+
+        lang.define(BlockType::for_test("zero", &[], &[""]));
+        lang.define(BlockType::for_test("->", &[""], &[""]));
+        lang.define(BlockType::for_test("->2", &[""], &["", ""]));
+        lang.define(BlockType::for_test("+", &["a", "b"], &[""]));
+
+        let mut set = BlockType::for_test("set", &[""], &[]);
+        set.user_input = BlockUserInput::Identifier;
+        lang.define(set);
+
+        let mut cond = BlockType::for_test("if", &[""], &[""]);
+        cond.area_count = 2;
+        lang.define(cond);
+
+        let mut num = BlockType::for_test("num", &[], &[""]);
+        num.output_types = vec![BlockPortType::typed("num")];
+        lang.define(num);
+
+        let mut text = BlockType::for_test("text", &[], &[""]);
+        text.output_types = vec![BlockPortType::typed("str")];
+        lang.define(text);
+
+        let mut sqrt = BlockType::for_test("sqrt", &["x", "base"], &[""]);
+        sqrt.input_types = vec![BlockPortType::typed("num").required()];
+        sqrt.output_types = vec![BlockPortType::typed("num")];
+        lang.define(sqrt);
+
+        BlockFun::new(Rc::new(RefCell::new(lang)))
+    }
+
+    fn label_at(fun: &BlockFun, id: usize, x: i64, y: i64) -> Option<String> {
+        let block = fun.block_at(id, x, y)?;
+        let mut buf = [0; 64];
+        let len = block.label(&mut buf[..]);
+        Some(std::str::from_utf8(&buf[0..len]).unwrap().to_string())
+    }
+
+    #[test]
+    fn check_blockfun_instanciate() {
+        let mut fun = new_fun();
+        let gen = BlockCodeView::generation(&fun);
+
+        fun.instanciate_at(0, 1, 1, "zero", None).unwrap();
+        fun.instanciate_at(0, 3, 1, "+", None).unwrap();
+        assert!(BlockCodeView::generation(&fun) > gen);
+
+        assert_eq!(label_at(&fun, 0, 1, 1), Some("zero".to_string()));
+        assert_eq!(fun.block_at(0, 3, 1).map(|b| b.rows()), Some(2));
+        assert_eq!(fun.origin_at(0, 3, 2), Some((3, 1)));
+        assert!(fun.block_at(0, 3, 2).is_none());
+
+        assert_eq!(
+            fun.instanciate_at(0, 3, 2, "zero", None),
+            Err(BlockDSPError::NoSpaceAvailable(0, 3, 2, 1))
+        );
+        assert_eq!(
+            fun.instanciate_at(0, 5, 5, "foo", None),
+            Err(BlockDSPError::UnknownLanguageType("foo".to_string()))
+        );
+        assert_eq!(fun.instanciate_at(9, 0, 0, "zero", None), Err(BlockDSPError::UnknownArea(9)));
+
+        fun.instanciate_at(0, 5, 5, "set", Some("x".to_string())).unwrap();
+        assert_eq!(label_at(&fun, 0, 5, 5), Some("x".to_string()));
+
+        fun.instanciate_at(0, 7, 7, "if", None).unwrap();
+        let block = fun.block_at(0, 7, 7).unwrap();
+        assert_eq!((block.contains(0), block.contains(1)), (Some(1), Some(2)));
+
+        fun.instanciate_at(1, 0, 0, "zero", None).unwrap();
+        fun.recalculate_area_sizes();
+        assert_eq!(label_at(&fun, 1, 0, 0), Some("zero".to_string()));
+        assert_eq!(fun.area_size(1), (1, 1));
+    }
+
+    #[test]
+    fn check_blockfun_move() {
+        let mut fun = new_fun();
+
+        fun.instanciate_at(0, 0, 0, "zero", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "->", None).unwrap();
+        fun.instanciate_at(0, 2, 0, "set", Some("x".to_string())).unwrap();
+
+        fun.move_block_from_to(0, 2, 0, 0, 4, 4).unwrap();
+        assert!(fun.block_at(0, 2, 0).is_none());
+        assert_eq!(label_at(&fun, 0, 4, 4), Some("x".to_string()));
+
+        assert_eq!(
+            fun.move_block_from_to(0, 4, 4, 0, 1, 0),
+            Err(BlockDSPError::NoSpaceAvailable(0, 1, 0, 1))
+        );
+        assert_eq!(label_at(&fun, 0, 4, 4), Some("x".to_string()));
+
+        fun.move_block_chain_from_to(0, 1, 0, 0, 1, 2).unwrap();
+        assert!(fun.block_at(0, 0, 0).is_none());
+        assert!(fun.block_at(0, 1, 0).is_none());
+        assert_eq!(label_at(&fun, 0, 0, 2), Some("zero".to_string()));
+        assert_eq!(label_at(&fun, 0, 1, 2), Some("->".to_string()));
+        assert_eq!(label_at(&fun, 0, 4, 4), Some("x".to_string()));
+
+        fun.instanciate_at(0, 8, 8, "if", None).unwrap();
+        assert_eq!(
+            fun.move_block_from_to(0, 8, 8, 1, 0, 0),
+            Err(BlockDSPError::CircularAction(0, 1))
+        );
+
+        fun.move_block_chain_from_to(0, 0, 2, 1, 0, 0).unwrap();
+        assert!(fun.block_at(0, 0, 2).is_none());
+        assert_eq!(label_at(&fun, 1, 0, 0), Some("zero".to_string()));
+        assert_eq!(label_at(&fun, 1, 1, 0), Some("->".to_string()));
+    }
+
+    #[test]
+    fn check_blockfun_split() {
+        let mut fun = new_fun();
+
+        fun.instanciate_at(0, 0, 0, "->2", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "->", None).unwrap();
+        fun.instanciate_at(0, 1, 1, "->", None).unwrap();
+        fun.instanciate_at(0, 2, 0, "set", Some("x".to_string())).unwrap();
+
+        fun.split_block_chain_after(0, 0, 0, Some("->")).unwrap();
+
+        assert_eq!(label_at(&fun, 0, 0, 0), Some("->2".to_string()));
+        assert!(fun.block_at(0, 1, 0).is_none());
+        assert_eq!(label_at(&fun, 0, 1, 1), Some("->".to_string()));
+        assert_eq!(label_at(&fun, 0, 2, 0), Some("->".to_string()));
+        assert_eq!(label_at(&fun, 0, 2, 1), Some("->".to_string()));
+        assert_eq!(label_at(&fun, 0, 3, 0), Some("x".to_string()));
+
+        fun.split_block_chain_after(0, 2, 0, None).unwrap();
+        assert!(fun.block_at(0, 3, 0).is_none());
+        assert_eq!(label_at(&fun, 0, 4, 0), Some("x".to_string()));
+
+        assert_eq!(
+            fun.split_block_chain_after(0, 9, 9, None),
+            Err(BlockDSPError::NoBlockAt(0, 9, 9))
+        );
+    }
+
+    #[test]
+    fn check_blockfun_generate_tree() {
+        let mut fun = new_fun();
+
+        fun.instanciate_at(0, 0, 0, "zero", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "->", None).unwrap();
+        fun.instanciate_at(0, 2, 0, "set", Some("x".to_string())).unwrap();
+        fun.instanciate_at(0, 1, 3, "+", None).unwrap();
+        fun.instanciate_at(0, 0, 4, "zero", None).unwrap();
+
+        let tree = fun.generate_tree::<TestNode>("zero").unwrap();
+        assert_eq!(tree.dump(), "<r>(<a>(set:x(->(zero)) <res>(+(a=zero b=zero))))");
+
+        assert_eq!(
+            fun.generate_tree::<TestNode>("nop").unwrap_err(),
+            BlockDSPError::UnknownLanguageType("nop".to_string())
+        );
+    }
+
+    #[test]
+    fn check_blockfun_save_load() {
+        let mut fun = new_fun();
+
+        fun.instanciate_at(0, 0, 0, "zero", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "+", None).unwrap();
+        fun.shift_port(0, 1, 0, 0, false);
+        fun.instanciate_at(0, 2, 3, "if", None).unwrap();
+        fun.instanciate_at(1, 0, 0, "set", Some("say \"hi\"".to_string())).unwrap();
+        fun.recalculate_area_sizes();
+
+        let saved = fun.save_to_string();
+        assert!(saved.starts_with("blockfun 1\n"));
+        assert!(saved.contains("block 0 1 0 2 \"+\" \"+\" expanded - -\n  in \"b\" \"a\"\n"));
+        assert!(saved.contains("block 1 0 0 4 \"set\" \"say \\\"hi\\\"\" expanded - -\n"));
+
+        let mut fun2 = new_fun();
+        fun2.load_from_str(&saved).unwrap();
+        assert_eq!(fun2.save_to_string(), saved);
+        assert_eq!(label_at(&fun2, 1, 0, 0), Some("say \"hi\"".to_string()));
+        assert_eq!(
+            fun2.generate_tree::<TestNode>("zero").unwrap().dump(),
+            fun.generate_tree::<TestNode>("zero").unwrap().dump()
+        );
+
+        fun2.instanciate_at(0, 5, 5, "zero", None).unwrap();
+        assert!(fun2.save_to_string().contains("block 0 5 5 5 \"zero\""));
+    }
+
+    #[test]
+    fn check_blockfun_load_hand_written() {
+        let mut fun = new_fun();
+        fun.load_from_str(
+            "# A comment\n\
+             blockfun 1\n\
+             area 0 16 16 fixed \"\"\n\
+             block 0 0 0 1 \"zero\" \"zero\" expanded - -\n\
+             block 0 1 0 2 \"set\" \"x\" collapsed - -\n",
+        )
+        .unwrap();
+
+        assert_eq!(label_at(&fun, 0, 1, 0), Some("x".to_string()));
+        assert!(!fun.block_ref(0, 1, 0).unwrap().expanded);
+        assert!(fun.block_at(0, 1, 0).unwrap().has_input(0));
+        assert_eq!(fun.generate_tree::<TestNode>("zero").unwrap().dump(), "<r>(<a>(set:x(zero)))");
+    }
+
+    #[test]
+    fn check_blockfun_load_errors() {
+        let mut fun = new_fun();
+        fun.instanciate_at(0, 0, 0, "zero", None).unwrap();
+        let saved = fun.save_to_string();
+
+        let err = |src: &str| {
+            let mut fun = new_fun();
+            fun.load_from_str(src).unwrap_err()
+        };
+
+        let e = err("blockfun 2\n");
+        assert_eq!(e.line, 1);
+        assert!(e.msg.contains("unsupported format version 2"));
+
+        let e = err("area 0 16 16 fixed \"\"\n");
+        assert_eq!(e.line, 1);
+        assert!(e.msg.contains("header"));
+
+        let e = err(
+            "blockfun 1\narea 0 16 16 fixed \"\"\n\nblock 0 0 0 1 \"cbrt\" \"\" expanded - -\n",
+        );
+        assert_eq!(e.line, 4);
+        assert!(e.msg.contains("unknown block type 'cbrt'"));
+
+        let e =
+            err("blockfun 1\narea 0 16 16 fixed \"\"\nblock 0 0 0 1 \"if\" \"\" expanded - -\n");
+        assert_eq!(e.line, 3);
+        assert!(e.msg.contains("needs 2 sub areas"));
+
+        let e = err("blockfun 1\narea 0 16 16 fixed \"\nblock\n");
+        assert_eq!(e.line, 2);
+        assert_eq!(e.msg, "unterminated string");
+
+        let e = err("blockfun 1\narea 0 16 16 fixed \"\"\nblock 0 0 0 1 \"+\" \"\" expanded - -\n  in - - -\n");
+        assert_eq!(e.line, 4);
+        assert_eq!(
+            format!("{}", e),
+            "blockfun error in line 4: 3 ports given, but the block only has 2 rows"
+        );
+
+        let mut fun2 = new_fun();
+        fun2.instanciate_at(0, 3, 3, "zero", None).unwrap();
+        assert!(fun2.load_from_str("blockfun 1\nfoo\n").is_err());
+        assert_eq!(label_at(&fun2, 0, 3, 3), Some("zero".to_string()));
+        fun2.load_from_str(&saved).unwrap();
+        assert!(fun2.block_at(0, 3, 3).is_none());
+    }
+
+    #[test]
+    fn check_blockfun_check() {
+        let mut fun = new_fun();
+
+        fun.instanciate_at(0, 5, 0, "sqrt", None).unwrap();
+        let pos = BlockPos::Block { id: 0, x: 5, y: 0, row: 0, col: 0, rows: 2 };
+        assert_eq!(
+            fun.check(),
+            vec![BlockDiagnostic::UnconnectedInput { pos, port: "x".to_string() }]
+        );
+
+        fun.instanciate_at(0, 4, 0, "text", None).unwrap();
+        fun.instanciate_at(0, 4, 1, "num", None).unwrap();
+        let diags = fun.check();
+        assert_eq!(
+            diags,
+            vec![BlockDiagnostic::TypeMismatch {
+                pos,
+                from: BlockPos::Block { id: 0, x: 4, y: 0, row: 0, col: 1, rows: 1 },
+                expected: "num".to_string(),
+                got: "str".to_string(),
+            }]
+        );
+        assert_eq!(
+            format!("{}", diags[0]),
+            "area 0 at 5,0: expected input of type 'num', got 'str'"
+        );
+
+        // Shifting the ports moves the types along with the labels:
+        fun.shift_port(0, 5, 0, 0, false);
+        assert!(fun.check().is_empty());
+        fun.shift_port(0, 5, 0, 0, false);
+
+        let gen = BlockCodeView::generation(&fun);
+        assert_eq!(fun.update_diagnostics().len(), 1);
+        assert!(BlockCodeView::generation(&fun) > gen);
+        assert!(fun.block_has_error(0, 5, 0));
+        assert!(!fun.block_has_error(0, 4, 0));
+
+        fun.remove_at(0, 4, 0).unwrap();
+        fun.instanciate_at(0, 4, 0, "zero", None).unwrap();
+        assert!(fun.update_diagnostics().is_empty());
+        assert!(!fun.block_has_error(0, 5, 0));
+    }
+
+    #[test]
+    fn check_blockfun_check_unlabeled_typed_outputs() {
+        let mut fun = new_fun();
+
+        let mut nums = BlockType::for_test("nums", &[], &["", ""]);
+        nums.output_types = vec![BlockPortType::typed("num"), BlockPortType::typed("num")];
+        fun.language.borrow_mut().define(nums);
+
+        fun.instanciate_at(0, 4, 0, "nums", None).unwrap();
+        fun.instanciate_at(0, 5, 0, "sqrt", None).unwrap();
+        assert!(fun.check().is_empty());
+
+        fun.shift_port(0, 4, 0, 0, true);
+        assert!(fun.check().is_empty());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "block type 'mixed' has outputs labelled '' with different data types"
+    )]
+    fn check_define_rejects_ambiguous_unlabeled_outputs() {
+        let mut lang = BlockLanguage::new();
+
+        let mut mixed = BlockType::for_test("mixed", &[], &["", ""]);
+        mixed.output_types = vec![BlockPortType::typed("num"), BlockPortType::typed("str")];
+        lang.define(mixed);
+    }
+
+    #[test]
+    fn check_user_input_validate() {
+        assert_eq!(BlockUserInput::Float.validate("0.5"), Validation::Valid);
+        assert_eq!(BlockUserInput::Float.validate("-1e3"), Validation::Valid);
+        assert_eq!(
+            BlockUserInput::Float.validate(" 2.0 "),
+            Validation::Normalized("2.0".to_string())
+        );
+        assert_eq!(BlockUserInput::Float.validate("inf"), Validation::Invalid);
+        assert_eq!(BlockUserInput::Float.validate("x"), Validation::Invalid);
+
+        assert_eq!(BlockUserInput::Integer.validate("42"), Validation::Valid);
+        assert_eq!(BlockUserInput::Integer.validate("4.2"), Validation::Invalid);
+
+        assert_eq!(BlockUserInput::Identifier.validate("&sig1"), Validation::Valid);
+        assert_eq!(BlockUserInput::Identifier.validate(""), Validation::Invalid);
+        assert_eq!(BlockUserInput::Identifier.validate("a b"), Validation::Invalid);
+
+        assert_eq!(BlockUserInput::None.validate(""), Validation::Valid);
+    }
 }
@@ -0,0 +1,429 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use super::{BlockASTNode, BlockDSPError, BlockFun, BlockLanguage};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+type BlockEvalFun = Rc<dyn Fn(&mut BlockEvalCtx) -> Vec<f32>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockEvalError {
+    /// No function was registered with [BlockEvaluator::define] for this [super::BlockType].
+    UnknownFunction(String),
+    /// The AST could not be generated, see [BlockFun::generate_tree].
+    Tree(BlockDSPError),
+}
+
+impl From<BlockDSPError> for BlockEvalError {
+    fn from(err: BlockDSPError) -> Self {
+        BlockEvalError::Tree(err)
+    }
+}
+
+#[derive(Debug)]
+struct EvalNodeData {
+    id: usize,
+    typ: String,
+    lbl: String,
+    nodes: Vec<(String, String, EvalNode)>,
+}
+
+#[derive(Debug, Clone)]
+struct EvalNode(Rc<RefCell<EvalNodeData>>);
+
+impl BlockASTNode for EvalNode {
+    fn from(id: usize, typ: &str, lbl: &str) -> Self {
+        EvalNode(Rc::new(RefCell::new(EvalNodeData {
+            id,
+            typ: typ.to_string(),
+            lbl: lbl.to_string(),
+            nodes: vec![],
+        })))
+    }
+
+    fn add_node(&self, in_port: String, out_port: String, node: Self) {
+        self.0.borrow_mut().nodes.push((in_port, out_port, node));
+    }
+}
+
+impl EvalNode {
+    fn key(&self) -> usize {
+        Rc::as_ptr(&self.0) as usize
+    }
+
+    fn is_area(&self) -> bool {
+        self.0.borrow().typ == "<a>"
+    }
+}
+
+/// Evaluates a [BlockFun] with a Rust function per [super::BlockType],
+/// for previews and unit tests of block programs without a DSP backend.
+///
+///```ignore
+///    let mut ev = BlockEvaluator::new();
+///    ev.define("zero", |_ctx| vec![0.0]);
+///    ev.define("+", |ctx| vec![ctx.input(0) + ctx.input(1)]);
+///    ev.define("if", |ctx| {
+///        let area = if ctx.input(0) >= 0.5 { 0 } else { 1 };
+///        vec![ctx.area(area).unwrap_or(0.0)]
+///    });
+///
+///    let result = ev.eval(&fun, "zero")?;
+///```
+///
+/// The functions return the values of the outputs of the block, in the
+/// order of [super::BlockType::outputs]. Ports are identified by their
+/// label, ports with the same label by their row.
+///
+/// The value of an area is the bottom right most unconnected output in it.
+#[derive(Default)]
+pub struct BlockEvaluator {
+    funs: HashMap<String, BlockEvalFun>,
+    vars: HashMap<String, f32>,
+    values: HashMap<usize, Vec<f32>>,
+    caches: Vec<HashMap<usize, Vec<f32>>>,
+    lang: Option<Rc<RefCell<BlockLanguage>>>,
+    error: Option<BlockEvalError>,
+}
+
+impl BlockEvaluator {
+    pub fn new() -> Self {
+        Self {
+            funs: HashMap::new(),
+            vars: HashMap::new(),
+            values: HashMap::new(),
+            caches: vec![],
+            lang: None,
+            error: None,
+        }
+    }
+
+    /// Registers the function for the blocks of the type `typ`.
+    pub fn define<F: 'static + Fn(&mut BlockEvalCtx) -> Vec<f32>>(&mut self, typ: &str, f: F) {
+        self.funs.insert(typ.to_string(), Rc::new(f));
+    }
+
+    /// Evaluates the program and returns the value of the root area.
+    /// `null_typ` is the type for unconnected inputs, as for
+    /// [BlockFun::generate_tree]. The variables are kept between calls.
+    pub fn eval(&mut self, fun: &BlockFun, null_typ: &str) -> Result<Option<f32>, BlockEvalError> {
+        let tree = fun.generate_tree::<EvalNode>(null_typ)?;
+
+        self.lang = Some(fun.language.clone());
+        self.values.clear();
+        self.error = None;
+
+        let root_area = tree.0.borrow().nodes.first().map(|(_, _, n)| n.clone());
+        let res = root_area.and_then(|area| self.eval_area(&area));
+
+        self.lang = None;
+
+        if let Some(err) = self.error.take() {
+            Err(err)
+        } else {
+            Ok(res)
+        }
+    }
+
+    /// The output values of the block with the `id` from the last
+    /// [BlockEvaluator::eval], for displaying them next to the blocks.
+    /// See also [super::Block::id].
+    pub fn block_outputs(&self, id: usize) -> Option<&[f32]> {
+        self.values.get(&id).map(|v| &v[..])
+    }
+
+    pub fn var(&self, name: &str) -> f32 {
+        self.vars.get(name).copied().unwrap_or(0.0)
+    }
+
+    pub fn set_var(&mut self, name: &str, value: f32) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    pub fn clear_vars(&mut self) {
+        self.vars.clear();
+    }
+
+    fn eval_area(&mut self, area: &EvalNode) -> Option<f32> {
+        let childs: Vec<EvalNode> =
+            area.0.borrow().nodes.iter().map(|(_, _, n)| n.clone()).collect();
+
+        // Each area gets its own cache, so that a loop evaluating a
+        // sub area again computes the blocks inside it again.
+        self.caches.push(HashMap::new());
+
+        let mut res = None;
+        for child in childs {
+            if child.0.borrow().typ == "<res>" {
+                let out = child.0.borrow().nodes.first().cloned();
+                if let Some((_, out_port, node)) = out {
+                    res = Some(self.eval_output(&node, &out_port));
+                }
+            } else {
+                self.eval_node(&child);
+            }
+        }
+
+        self.caches.pop();
+
+        res
+    }
+
+    fn eval_output(&mut self, node: &EvalNode, out_port: &str) -> f32 {
+        let values = self.eval_node(node);
+
+        let typ = node.0.borrow().typ.clone();
+        let idx = self
+            .lang
+            .as_ref()
+            .and_then(|lang| {
+                lang.borrow()
+                    .types
+                    .get(&typ)
+                    .and_then(|t| t.outputs.iter().position(|o| o.as_deref() == Some(out_port)))
+            })
+            .unwrap_or(0);
+
+        values.get(idx).copied().unwrap_or(0.0)
+    }
+
+    fn eval_node(&mut self, node: &EvalNode) -> Vec<f32> {
+        if let Some(values) = self.caches.last().and_then(|c| c.get(&node.key())) {
+            return values.clone();
+        }
+
+        let (id, typ, lbl) = {
+            let n = node.0.borrow();
+            (n.id, n.typ.clone(), n.lbl.clone())
+        };
+
+        let f = if let Some(f) = self.funs.get(&typ) {
+            f.clone()
+        } else {
+            if self.error.is_none() {
+                self.error = Some(BlockEvalError::UnknownFunction(typ));
+            }
+            return vec![];
+        };
+
+        let type_inputs = self
+            .lang
+            .as_ref()
+            .and_then(|lang| lang.borrow().types.get(&typ).map(|t| t.inputs.clone()))
+            .unwrap_or_default();
+
+        let mut inputs = vec![None; type_inputs.len()];
+        let mut areas = vec![];
+
+        for (in_port, out_port, child) in node.0.borrow().nodes.iter() {
+            if child.is_area() {
+                areas.push(child.clone());
+                continue;
+            }
+
+            // Ports with the same label are assigned in row order:
+            let idx = type_inputs
+                .iter()
+                .enumerate()
+                .position(|(i, p)| p.as_deref() == Some(in_port) && inputs[i].is_none());
+            if let Some(idx) = idx {
+                inputs[idx] = Some((child.clone(), out_port.clone()));
+            }
+        }
+
+        let mut ctx = BlockEvalCtx { eval: self, lbl, inputs, areas };
+        let values = f(&mut ctx);
+
+        if let Some(cache) = self.caches.last_mut() {
+            cache.insert(node.key(), values.clone());
+        }
+        if id > 0 {
+            self.values.insert(id, values.clone());
+        }
+
+        values
+    }
+}
+
+/// Passed to the functions of a [BlockEvaluator] for accessing the
+/// block that is evaluated.
+pub struct BlockEvalCtx<'a> {
+    eval: &'a mut BlockEvaluator,
+    lbl: String,
+    inputs: Vec<Option<(EvalNode, String)>>,
+    areas: Vec<EvalNode>,
+}
+
+impl BlockEvalCtx<'_> {
+    /// The label of the block, which holds the user input, like the
+    /// name of a variable.
+    pub fn label(&self) -> &str {
+        &self.lbl
+    }
+
+    /// Evaluates the input `idx`, in the order of [super::BlockType::inputs].
+    pub fn input(&mut self, idx: usize) -> f32 {
+        if let Some(Some((node, out_port))) = self.inputs.get(idx).cloned() {
+            self.eval.eval_output(&node, &out_port)
+        } else {
+            0.0
+        }
+    }
+
+    /// Evaluates the sub area `idx` (0 or 1) of the block and returns its
+    /// value. Call it repeatedly to implement loops.
+    pub fn area(&mut self, idx: usize) -> Option<f32> {
+        let area = self.areas.get(idx)?.clone();
+        self.eval.eval_area(&area)
+    }
+
+    pub fn var(&self, name: &str) -> f32 {
+        self.eval.var(name)
+    }
+
+    pub fn set_var(&mut self, name: &str, value: f32) {
+        self.eval.set_var(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BlockType;
+    use super::*;
+
+    fn new_fun() -> BlockFun {
+        let mut lang = BlockLanguage::new();
+
+        lang.define(BlockType::for_test("zero", &[], &[""]));
+        lang.define(BlockType::for_test("one", &[], &[""]));
+        lang.define(BlockType::for_test("-", &["a", "b"], &[""]));
+        lang.define(BlockType::for_test("+", &["", ""], &[""]));
+        lang.define(BlockType::for_test("split", &[""], &["lo", "hi"]));
+        lang.define(BlockType::for_test("set", &[""], &[]));
+        lang.define(BlockType::for_test("get", &[], &[""]));
+        lang.define(BlockType { area_count: 2, ..BlockType::for_test("if", &[""], &[""]) });
+        lang.define(BlockType { area_count: 1, ..BlockType::for_test("repeat", &[""], &[""]) });
+
+        BlockFun::new(Rc::new(RefCell::new(lang)))
+    }
+
+    fn new_evaluator() -> BlockEvaluator {
+        let mut ev = BlockEvaluator::new();
+        ev.define("zero", |_ctx| vec![0.0]);
+        ev.define("one", |_ctx| vec![1.0]);
+        ev.define("-", |ctx| vec![ctx.input(0) - ctx.input(1)]);
+        ev.define("+", |ctx| vec![ctx.input(0) + ctx.input(1)]);
+        ev.define("split", |ctx| {
+            let v = ctx.input(0);
+            vec![v - 0.5, v + 0.5]
+        });
+        ev.define("set", |ctx| {
+            let v = ctx.input(0);
+            let name = ctx.label().to_string();
+            ctx.set_var(&name, v);
+            vec![]
+        });
+        ev.define("get", |ctx| vec![ctx.var(ctx.label())]);
+        ev.define("if", |ctx| {
+            let area = if ctx.input(0) >= 0.5 { 0 } else { 1 };
+            vec![ctx.area(area).unwrap_or(0.0)]
+        });
+        ev.define("repeat", |ctx| {
+            let mut res = 0.0;
+            for _ in 0..(ctx.input(0) as usize) {
+                res = ctx.area(0).unwrap_or(0.0);
+            }
+            vec![res]
+        });
+        ev
+    }
+
+    #[test]
+    fn check_eval_arithmetics() {
+        let mut fun = new_fun();
+        let mut ev = new_evaluator();
+
+        assert_eq!(ev.eval(&fun, "zero"), Ok(None));
+
+        fun.instanciate_at(0, 0, 0, "one", None).unwrap();
+        fun.instanciate_at(0, 0, 1, "one", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "+", None).unwrap();
+        assert_eq!(ev.eval(&fun, "zero"), Ok(Some(2.0)));
+
+        // Unconnected inputs get the null type:
+        fun.instanciate_at(0, 2, 0, "-", None).unwrap();
+        assert_eq!(ev.eval(&fun, "zero"), Ok(Some(2.0)));
+
+        // The labelled inputs keep their meaning after shifting the ports:
+        fun.shift_port(0, 2, 0, 0, false);
+        assert_eq!(ev.eval(&fun, "zero"), Ok(Some(-2.0)));
+
+        let id = fun.block_ref(0, 1, 0).unwrap().id();
+        assert_eq!(ev.block_outputs(id), Some(&[2.0][..]));
+    }
+
+    #[test]
+    fn check_eval_multi_outputs() {
+        let mut fun = new_fun();
+        let mut ev = new_evaluator();
+
+        fun.instanciate_at(0, 0, 0, "one", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "split", None).unwrap();
+        fun.instanciate_at(0, 2, 0, "-", None).unwrap();
+        fun.shift_port(0, 1, 0, 0, true);
+
+        // "hi" goes to the "a" input and "lo" to "b": 1.5 - 0.5
+        assert_eq!(ev.eval(&fun, "zero"), Ok(Some(1.0)));
+    }
+
+    #[test]
+    fn check_eval_areas_and_vars() {
+        let mut fun = new_fun();
+        let mut ev = new_evaluator();
+
+        fun.instanciate_at(0, 0, 0, "one", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "if", None).unwrap();
+        fun.instanciate_at(1, 0, 0, "one", None).unwrap();
+        fun.instanciate_at(1, 0, 1, "one", None).unwrap();
+        fun.instanciate_at(1, 1, 0, "+", None).unwrap();
+        fun.instanciate_at(2, 0, 0, "zero", None).unwrap();
+
+        assert_eq!(ev.eval(&fun, "zero"), Ok(Some(2.0)));
+
+        fun.remove_at(0, 0, 0).unwrap();
+        assert_eq!(ev.eval(&fun, "zero"), Ok(Some(0.0)));
+
+        // A counter in a loop:
+        let mut fun = new_fun();
+        fun.instanciate_at(0, 0, 0, "one", None).unwrap();
+        fun.instanciate_at(0, 0, 1, "one", None).unwrap();
+        fun.instanciate_at(0, 1, 0, "+", None).unwrap();
+        fun.instanciate_at(0, 2, 0, "repeat", None).unwrap();
+        fun.instanciate_at(1, 0, 0, "get", Some("i".to_string())).unwrap();
+        fun.instanciate_at(1, 0, 1, "one", None).unwrap();
+        fun.instanciate_at(1, 1, 0, "+", None).unwrap();
+        fun.instanciate_at(1, 2, 0, "set", Some("i".to_string())).unwrap();
+        fun.instanciate_at(1, 0, 3, "get", Some("i".to_string())).unwrap();
+
+        assert_eq!(ev.eval(&fun, "zero"), Ok(Some(2.0)));
+        assert_eq!(ev.var("i"), 2.0);
+    }
+
+    #[test]
+    fn check_eval_unknown_function() {
+        let mut fun = new_fun();
+        let mut ev = BlockEvaluator::new();
+        ev.define("zero", |_ctx| vec![0.0]);
+
+        fun.instanciate_at(0, 1, 0, "-", None).unwrap();
+        assert_eq!(ev.eval(&fun, "zero"), Err(BlockEvalError::UnknownFunction("-".to_string())));
+        assert_eq!(
+            ev.eval(&fun, "nop"),
+            Err(BlockEvalError::Tree(BlockDSPError::UnknownLanguageType("nop".to_string())))
+        );
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Access to a text clipboard. Applications can connect the system
+/// clipboard with [crate::UI::set_clipboard], otherwise a
+/// [MemoryClipboard] is used, which also works in headless tests.
+pub trait Clipboard {
+    fn get_text(&mut self) -> Option<String>;
+    fn set_text(&mut self, text: &str);
+}
+
+/// A clipboard that only lives inside the [crate::UI].
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    text: Option<String>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self { text: None }
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn get_text(&mut self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.text = Some(text.to_string());
+    }
+}
+
+/// A shared handle to the [Clipboard] of the [crate::UI].
+#[derive(Clone)]
+pub struct ClipboardRef(Rc<RefCell<Box<dyn Clipboard>>>);
+
+impl ClipboardRef {
+    pub fn new() -> Self {
+        Self(Rc::new(RefCell::new(Box::new(MemoryClipboard::new()))))
+    }
+
+    /// Replaces the clipboard implementation for all holders of this handle.
+    pub fn set_clipboard(&self, clipboard: Box<dyn Clipboard>) {
+        *self.0.borrow_mut() = clipboard;
+    }
+
+    pub fn get_text(&self) -> Option<String> {
+        self.0.borrow_mut().get_text()
+    }
+
+    pub fn set_text(&self, text: &str) {
+        self.0.borrow_mut().set_text(text)
+    }
+}
+
+impl Default for ClipboardRef {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for ClipboardRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClipboardRef").finish()
+    }
+}
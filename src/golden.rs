@@ -0,0 +1,274 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use crate::Rect;
+
+/// If this environment variable is set, [FrameCapture::compare_with_png]
+/// (over)writes the reference images instead of comparing against them.
+pub const GOLDEN_UPDATE_ENV: &str = "HEXOTK_GOLDEN_UPDATE";
+
+/// The RGBA pixels of a rendered frame, or a part of it.
+#[derive(Debug, Clone)]
+pub struct FrameCapture {
+    pub w: usize,
+    pub h: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    /// The reference image does not exist, the capture was written
+    /// next to it as `*.actual.png`.
+    MissingReference(String),
+    /// Reference (w, h) and captured (w, h) differ.
+    SizeMismatch((usize, usize), (usize, usize)),
+    /// The number of pixels that differ more than the tolerance, and the
+    /// path of the written diff image.
+    Mismatch { pixels: usize, diff_path: String },
+    /// No frame was captured, or the backend does not support it.
+    MissingCapture,
+    Image(image::ImageError),
+}
+
+impl std::fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GoldenError::MissingReference(path) => write!(
+                f,
+                "reference image '{}' missing (set {}=1 to create it)",
+                path, GOLDEN_UPDATE_ENV
+            ),
+            GoldenError::SizeMismatch(r, c) => {
+                write!(f, "size mismatch: reference {}x{}, captured {}x{}", r.0, r.1, c.0, c.1)
+            }
+            GoldenError::Mismatch { pixels, diff_path } => {
+                write!(f, "{} pixels differ, see '{}'", pixels, diff_path)
+            }
+            GoldenError::MissingCapture => write!(f, "no frame captured"),
+            GoldenError::Image(e) => write!(f, "image error: {}", e),
+        }
+    }
+}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(e: image::ImageError) -> Self {
+        GoldenError::Image(e)
+    }
+}
+
+fn path_with_suffix(path: &str, suffix: &str) -> String {
+    let base = path.strip_suffix(".png").unwrap_or(path);
+    format!("{}.{}.png", base, suffix)
+}
+
+impl FrameCapture {
+    pub fn new(w: usize, h: usize, data: Vec<u8>) -> Self {
+        Self { w, h, data }
+    }
+
+    /// Copies out the pixels inside `rect`, which is clipped to the frame.
+    pub fn crop(&self, rect: Rect) -> FrameCapture {
+        let rect = rect.round();
+        let x0 = (rect.x.max(0.0) as usize).min(self.w);
+        let y0 = (rect.y.max(0.0) as usize).min(self.h);
+        let x1 = ((rect.x + rect.w).max(0.0) as usize).min(self.w);
+        let y1 = ((rect.y + rect.h).max(0.0) as usize).min(self.h);
+
+        let (w, h) = (x1.saturating_sub(x0), y1.saturating_sub(y0));
+        let mut data = Vec::with_capacity(w * h * 4);
+        for y in y0..y1 {
+            let row = (y * self.w + x0) * 4;
+            data.extend_from_slice(&self.data[row..(row + w * 4)]);
+        }
+
+        FrameCapture { w, h, data }
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), image::ImageError> {
+        image::save_buffer(
+            path,
+            &self.data[..],
+            self.w as u32,
+            self.h as u32,
+            image::ColorType::Rgba8,
+        )
+    }
+
+    /// Compares the capture with the reference PNG at `ref_path`.
+    /// A pixel counts as different if any channel deviates by more
+    /// than `tolerance`. On a mismatch the capture is written to
+    /// `*.actual.png` and a diff image, that marks the differing
+    /// pixels in red, to `*.diff.png` next to the reference.
+    pub fn compare_with_png(&self, ref_path: &str, tolerance: u8) -> Result<(), GoldenError> {
+        if std::env::var(GOLDEN_UPDATE_ENV).is_ok() {
+            self.save_png(ref_path)?;
+            return Ok(());
+        }
+
+        let reference = match image::open(ref_path) {
+            Ok(img) => img.to_rgba8(),
+            Err(_) => {
+                self.save_png(&path_with_suffix(ref_path, "actual"))?;
+                return Err(GoldenError::MissingReference(ref_path.to_string()));
+            }
+        };
+
+        let ref_size = (reference.width() as usize, reference.height() as usize);
+        if ref_size != (self.w, self.h) {
+            self.save_png(&path_with_suffix(ref_path, "actual"))?;
+            return Err(GoldenError::SizeMismatch(ref_size, (self.w, self.h)));
+        }
+
+        let mut diff = Vec::with_capacity(self.data.len());
+        let mut diff_pixels = 0;
+
+        for (cap, rf) in self.data.chunks_exact(4).zip(reference.as_raw().chunks_exact(4)) {
+            let differs = cap.iter().zip(rf.iter()).any(|(a, b)| a.abs_diff(*b) > tolerance);
+
+            if differs {
+                diff_pixels += 1;
+                diff.extend_from_slice(&[255, 0, 0, 255]);
+            } else {
+                let gray = ((cap[0] as u32 + cap[1] as u32 + cap[2] as u32) / 9) as u8;
+                diff.extend_from_slice(&[gray, gray, gray, 255]);
+            }
+        }
+
+        if diff_pixels > 0 {
+            let diff_path = path_with_suffix(ref_path, "diff");
+            let diff_img = FrameCapture { w: self.w, h: self.h, data: diff };
+            diff_img.save_png(&diff_path)?;
+            self.save_png(&path_with_suffix(ref_path, "actual"))?;
+
+            return Err(GoldenError::Mismatch { pixels: diff_pixels, diff_path });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Serializes the tests that depend on [GOLDEN_UPDATE_ENV].
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// A `w` x `h` frame, the red channel holds the pixel index.
+    fn test_frame(w: usize, h: usize) -> FrameCapture {
+        let mut data = vec![];
+        for i in 0..(w * h) {
+            data.extend_from_slice(&[i as u8, 10, 20, 255]);
+        }
+        FrameCapture::new(w, h, data)
+    }
+
+    /// An empty directory for the images of the test `name`.
+    fn test_dir(name: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("hexotk_golden_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("creates test dir");
+        dir.to_string_lossy().to_string()
+    }
+
+    fn reds(cap: &FrameCapture) -> Vec<u8> {
+        cap.data.chunks_exact(4).map(|px| px[0]).collect()
+    }
+
+    #[test]
+    fn check_frame_capture_crop() {
+        let frame = test_frame(4, 3);
+
+        let crop = frame.crop(Rect::from(1.0, 1.0, 2.0, 2.0));
+        assert_eq!((crop.w, crop.h), (2, 2));
+        assert_eq!(reds(&crop), vec![5, 6, 9, 10]);
+        assert_eq!(&crop.data[..4], &[5, 10, 20, 255]);
+
+        // Clipped to the frame:
+        let crop = frame.crop(Rect::from(-1.0, -1.0, 3.0, 3.0));
+        assert_eq!((crop.w, crop.h), (2, 2));
+        assert_eq!(reds(&crop), vec![0, 1, 4, 5]);
+
+        let crop = frame.crop(Rect::from(3.0, 2.0, 10.0, 10.0));
+        assert_eq!((crop.w, crop.h), (1, 1));
+        assert_eq!(reds(&crop), vec![11]);
+
+        let crop = frame.crop(Rect::from(10.0, 10.0, 5.0, 5.0));
+        assert_eq!((crop.w, crop.h), (0, 0));
+        assert!(crop.data.is_empty());
+    }
+
+    #[test]
+    fn check_compare_with_png_tolerance() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        std::env::remove_var(GOLDEN_UPDATE_ENV);
+
+        let dir = test_dir("tolerance");
+        let ref_path = format!("{}/frame.png", dir);
+        let frame = test_frame(4, 3);
+        frame.save_png(&ref_path).expect("writes reference");
+
+        let mut changed = frame.clone();
+        changed.data[4 * 5 + 1] += 3;
+
+        assert!(frame.compare_with_png(&ref_path, 0).is_ok());
+        assert!(changed.compare_with_png(&ref_path, 3).is_ok());
+        assert!(!std::path::Path::new(&format!("{}/frame.diff.png", dir)).exists());
+
+        match changed.compare_with_png(&ref_path, 2) {
+            Err(GoldenError::Mismatch { pixels, diff_path }) => {
+                assert_eq!(pixels, 1);
+                assert_eq!(diff_path, format!("{}/frame.diff.png", dir));
+            }
+            res => panic!("expected a mismatch, got {:?}", res),
+        }
+        assert!(std::path::Path::new(&format!("{}/frame.diff.png", dir)).exists());
+        assert!(std::path::Path::new(&format!("{}/frame.actual.png", dir)).exists());
+
+        match test_frame(3, 3).compare_with_png(&ref_path, 255) {
+            Err(GoldenError::SizeMismatch(r, c)) => assert_eq!((r, c), ((4, 3), (3, 3))),
+            res => panic!("expected a size mismatch, got {:?}", res),
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn check_golden_update_writes_reference() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        std::env::remove_var(GOLDEN_UPDATE_ENV);
+
+        let dir = test_dir("update");
+        let ref_path = format!("{}/frame.png", dir);
+        let frame = test_frame(4, 3);
+
+        match frame.compare_with_png(&ref_path, 0) {
+            Err(GoldenError::MissingReference(path)) => assert_eq!(path, ref_path),
+            res => panic!("expected a missing reference, got {:?}", res),
+        }
+        assert!(!std::path::Path::new(&ref_path).exists());
+        assert!(std::path::Path::new(&format!("{}/frame.actual.png", dir)).exists());
+
+        std::env::set_var(GOLDEN_UPDATE_ENV, "1");
+        let res = frame.compare_with_png(&ref_path, 0);
+        std::env::remove_var(GOLDEN_UPDATE_ENV);
+        assert!(res.is_ok());
+
+        let reference = image::open(&ref_path).expect("reference written").to_rgba8();
+        assert_eq!((reference.width(), reference.height()), (4, 3));
+        assert_eq!(reference.as_raw(), &frame.data);
+
+        // Overwrites an outdated reference:
+        test_frame(2, 2).save_png(&ref_path).expect("writes reference");
+        std::env::set_var(GOLDEN_UPDATE_ENV, "1");
+        let res = frame.compare_with_png(&ref_path, 0);
+        std::env::remove_var(GOLDEN_UPDATE_ENV);
+        assert!(res.is_ok());
+        assert!(frame.compare_with_png(&ref_path, 0).is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use crate::painter::{Painter, PersistPainterData, SoftwareBackend};
+use crate::{InputEvent, WindowUI};
+
+/// Renders a [WindowUI] without a window and without a GPU.
+///
+/// The frames are drawn by the [SoftwareBackend] into an RGBA buffer,
+/// which can be inspected with [HeadlessRenderer::pixels] or written
+/// out with [HeadlessRenderer::save_png]. This is useful for running
+/// the whole widget tree in CI:
+///
+///```ignore
+///    let mut hr = HeadlessRenderer::new(Box::new(ui), 800, 600, 1.0);
+///    hr.handle_input_event(InputEvent::MousePosition(100.0, 100.0));
+///    hr.render_frame();
+///    hr.save_png("/tmp/ui.png").expect("writes png");
+///```
+pub struct HeadlessRenderer {
+    ui: Box<dyn WindowUI>,
+    backend: SoftwareBackend,
+    painter_data: PersistPainterData,
+    dpi_factor: f32,
+    bg_color: (f32, f32, f32),
+}
+
+impl HeadlessRenderer {
+    pub fn new(mut ui: Box<dyn WindowUI>, width: usize, height: usize, dpi_factor: f32) -> Self {
+        let mut painter_data = PersistPainterData::new();
+
+        for (file, data) in ui.get_image_data().iter() {
+            painter_data.preload_image(file, data.clone());
+        }
+
+        ui.set_window_size(width as f32, height as f32, dpi_factor);
+
+        Self {
+            ui,
+            backend: SoftwareBackend::new(width, height),
+            painter_data,
+            dpi_factor,
+            bg_color: (0.3, 0.1, 0.3),
+        }
+    }
+
+    pub fn set_bg_color(&mut self, color: (f32, f32, f32)) {
+        self.bg_color = color;
+    }
+
+    pub fn set_window_size(&mut self, width: usize, height: usize, dpi_factor: f32) {
+        self.dpi_factor = dpi_factor;
+        self.backend.resize(width, height);
+        self.ui.set_window_size(width as f32, height as f32, dpi_factor);
+    }
+
+    pub fn ui(&mut self) -> &mut dyn WindowUI {
+        &mut *self.ui
+    }
+
+    pub fn handle_input_event(&mut self, event: InputEvent) {
+        self.ui.handle_input_event(event);
+    }
+
+    /// Runs one frame of the UI, like the window would do it.
+    pub fn render_frame(&mut self) {
+        self.ui.pre_frame();
+
+        self.backend.begin_frame(self.bg_color);
+
+        {
+            let painter =
+                &mut Painter::new(&mut self.backend, &mut self.painter_data, self.dpi_factor);
+            self.ui.draw(painter);
+        }
+
+        self.painter_data.cleanup(&mut self.backend);
+
+        self.ui.post_frame();
+    }
+
+    pub fn width(&self) -> usize {
+        self.backend.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.backend.height()
+    }
+
+    /// The RGBA pixels of the last rendered frame, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        self.backend.pixels()
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), image::ImageError> {
+        self.backend.save_png(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Control, Style, Widget, UI};
+    use morphorm::{PositionType, Units};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn placed(ctrl: Control, bg: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) -> Widget {
+        let mut style = Style::new();
+        style.bg_color = bg;
+        style.border = 0.0;
+        let wid = Widget::new(Rc::new(style));
+        wid.set_ctrl(ctrl);
+        wid.change_layout(|l| {
+            l.position_type = Some(PositionType::SelfDirected);
+            l.left = Some(Units::Pixels(x));
+            l.top = Some(Units::Pixels(y));
+            l.width = Some(Units::Pixels(w));
+            l.height = Some(Units::Pixels(h));
+        });
+        wid
+    }
+
+    fn pixel(data: &[u8], w: usize, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * w + x) * 4;
+        [data[i], data[i + 1], data[i + 2], data[i + 3]]
+    }
+
+    #[test]
+    fn check_render_ui_to_png() {
+        let black = (0.0, 0.0, 0.0);
+        let root = placed(Control::Rect, black, 0.0, 0.0, 200.0, 100.0);
+        root.add(placed(
+            Control::Label { label: Box::new("HexoTK".to_string()) },
+            black,
+            0.0,
+            0.0,
+            100.0,
+            50.0,
+        ));
+        root.add(placed(Control::Rect, (1.0, 0.0, 0.0), 120.0, 20.0, 40.0, 40.0));
+
+        let mut ui = UI::new(Rc::new(RefCell::new(1)));
+        ui.add_layer_root(root);
+
+        let mut hr = HeadlessRenderer::new(Box::new(ui), 200, 100, 1.0);
+        hr.render_frame();
+        hr.render_frame();
+
+        let path = std::env::temp_dir().join(format!("hexotk_headless_{}.png", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        hr.save_png(&path).expect("writes png");
+        let img = image::open(&path).expect("reads png").to_rgba8();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!((img.width(), img.height()), (200, 100));
+        let (w, data) = (img.width() as usize, img.as_raw());
+        assert_eq!(data, hr.pixels());
+
+        assert_eq!(pixel(data, w, 140, 40), [255, 0, 0, 255]);
+        assert_eq!(pixel(data, w, 121, 21), [255, 0, 0, 255]);
+        assert_eq!(pixel(data, w, 190, 90), [0, 0, 0, 255]);
+        assert_eq!(pixel(data, w, 118, 40), [0, 0, 0, 255]);
+
+        // The label text is drawn in the top left:
+        let text_px = (0..50)
+            .flat_map(|y| (0..100).map(move |x| (x, y)))
+            .filter(|(x, y)| pixel(data, w, *x, *y) != [0, 0, 0, 255])
+            .count();
+        assert!(text_px > 20, "only {} text pixels", text_px);
+        assert!((50..100).all(|y| pixel(data, w, 50, y) == [0, 0, 0, 255]));
+    }
+}
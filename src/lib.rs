@@ -2,41 +2,67 @@
 // This file is a part of HexoTK. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
+pub mod blocklang;
+mod clipboard;
+mod golden;
+mod headless;
 mod layout;
 mod markdown;
 mod painter;
 mod rect;
+mod stylesheet;
+mod svg_export;
 #[allow(unused)]
 pub mod style;
 mod ui;
+mod undo;
+mod validation;
 mod widget;
 mod widget_store;
 mod widgets;
 mod window;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
 use keyboard_types::KeyboardEvent; // Key
 pub use markdown::*;
-use painter::Painter;
+pub use blocklang::{BlockCodeView, BlockPos, BlockView};
+pub use clipboard::{Clipboard, ClipboardRef, MemoryClipboard};
+pub use golden::{FrameCapture, GoldenError, GOLDEN_UPDATE_ENV};
+pub use headless::HeadlessRenderer;
+pub use painter::{
+    FemtovgBackend, LblDebugTag, Painter, PainterBackend, PainterFont, SoftwareBackend,
+    SvgBackend,
+};
 pub use rect::Rect;
 pub use style::{Align, BorderStyle, DPIStyle, Style, StyleExt, VAlign};
+pub use stylesheet::{StyleSheet, StyleSheetError};
+pub use svg_export::{blockcode_to_svg, hexgrid_to_svg};
 pub use ui::UI;
 pub use ui::{TestDriver, TestScript};
+pub use undo::{UndoCommand, UndoStack, UndoStackRef};
+pub use validation::Validation;
 pub use widget::Layout;
 pub use widget::Widget;
-use widget::{widget_annotate_drop_event, widget_draw, widget_draw_frame, widget_draw_shallow};
+use widget::{
+    widget_annotate_drop_event, widget_draw, widget_draw_frame, widget_draw_shallow,
+    widget_walk_parents,
+};
 pub use window::{open_window, open_window_ext, HexoTKWindowHandle};
 
 pub use widgets::EditableText;
 pub use widgets::Entry;
 pub use widgets::TextField;
+pub use widgets::NumericRange;
+pub use widgets::{TextBuffer, TextEdit, TextPos};
 pub use widgets::WichText;
-pub use widgets::{BlockCode, BlockPos};
-pub use widgets::{ChangeRes, DummyParamModel, HexKnob, ParamModel};
+pub use widgets::{block_chain, BlockCode};
+pub use widgets::{fuzzy_score, BlockPalette};
+pub use widgets::{AutomationState, ChangeRes, DummyParamModel, HexKnob, ParamModel};
 pub use widgets::{Connector, ConnectorData};
+pub use widgets::CustomWidget;
 pub use widgets::{DummyOctaveKeysData, OctaveKeys, OctaveKeysModel};
 pub use widgets::{Graph, GraphModel, StaticGraphData};
 pub use widgets::{GraphMinMax, GraphMinMaxModel, StaticGraphMinMaxData};
@@ -238,16 +264,19 @@ pub enum Control {
     Label { label: Box<dyn TextMutable> },
     WichText { wt: Box<WichText> },
     Entry { entry: Box<Entry> },
+    TextEdit { edit: Box<TextEdit> },
     HexKnob { knob: Box<HexKnob> },
     HexGrid { grid: Box<HexGrid> },
     Connector { con: Box<Connector> },
     BlockCode { code: Box<BlockCode> },
+    BlockPalette { palette: Box<BlockPalette> },
     OctaveKeys { keys: Box<OctaveKeys> },
     Graph { graph: Box<Graph> },
     Scope { scope: Box<Scope> },
     GraphMinMax { graph: Box<GraphMinMax> },
     PatternEditor { edit: Box<PatternEditor> },
     List { list: Box<List> },
+    Custom { widget: Box<dyn CustomWidget> },
 }
 
 impl std::fmt::Debug for Control {
@@ -259,16 +288,19 @@ impl std::fmt::Debug for Control {
             Control::Label { .. } => write!(f, "Ctrl::Label"),
             Control::WichText { .. } => write!(f, "Ctrl::WichText"),
             Control::Entry { .. } => write!(f, "Ctrl::Entry"),
+            Control::TextEdit { .. } => write!(f, "Ctrl::TextEdit"),
             Control::HexKnob { .. } => write!(f, "Ctrl::HexKnob"),
             Control::HexGrid { .. } => write!(f, "Ctrl::HexGrid"),
             Control::Connector { .. } => write!(f, "Ctrl::Connector"),
             Control::BlockCode { .. } => write!(f, "Ctrl::BlockCode"),
+            Control::BlockPalette { .. } => write!(f, "Ctrl::BlockPalette"),
             Control::OctaveKeys { .. } => write!(f, "Ctrl::OctaveKeys"),
             Control::Graph { .. } => write!(f, "Ctrl::Graph"),
             Control::Scope { .. } => write!(f, "Ctrl::Scope"),
             Control::GraphMinMax { .. } => write!(f, "Ctrl::GraphMinMax"),
             Control::PatternEditor { .. } => write!(f, "Ctrl::PatternEditor"),
             Control::List { .. } => write!(f, "Ctrl::List"),
+            Control::Custom { widget } => write!(f, "Ctrl::Custom({})", widget.type_name()),
         }
     }
 }
@@ -375,16 +407,19 @@ impl Control {
             Control::Button { .. } => true,
             Control::WichText { .. } => true,
             Control::Entry { .. } => true,
+            Control::TextEdit { .. } => true,
             Control::HexKnob { .. } => true,
             Control::HexGrid { .. } => true,
             Control::Connector { .. } => true,
             Control::BlockCode { .. } => true,
+            Control::BlockPalette { .. } => true,
             Control::OctaveKeys { .. } => true,
             Control::Graph { .. } => true,
             Control::Scope { .. } => true,
             Control::GraphMinMax { .. } => true,
             Control::PatternEditor { .. } => true,
             Control::List { .. } => true,
+            Control::Custom { widget } => widget.has_default_style(),
             Control::None => false,
         }
     }
@@ -402,13 +437,17 @@ impl Control {
             Control::Label { .. } => {}
             Control::WichText { .. } => {}
             Control::Entry { .. } => {}
+            Control::TextEdit { .. } => {}
             Control::HexKnob { .. } => {}
             Control::List { .. } => {}
+            Control::BlockPalette { .. } => {}
             Control::HexGrid { grid } => {
                 grid.draw_frame(w, &dpi_style, painter);
             }
             Control::Connector { .. } => {}
-            Control::BlockCode { .. } => {}
+            Control::BlockCode { code } => {
+                code.draw_frame(w, &dpi_style, painter);
+            }
             Control::OctaveKeys { keys } => {
                 keys.draw_frame(w, &dpi_style, painter);
             }
@@ -424,6 +463,9 @@ impl Control {
             Control::PatternEditor { edit } => {
                 edit.draw_frame(w, &dpi_style, painter);
             }
+            Control::Custom { widget } => {
+                widget.draw_frame(w, &dpi_style, painter);
+            }
         }
     }
 
@@ -435,16 +477,19 @@ impl Control {
             Control::Button { .. } => true,
             Control::WichText { .. } => true,
             Control::Entry { .. } => true,
+            Control::TextEdit { .. } => true,
             Control::HexKnob { .. } => true,
             Control::HexGrid { .. } => true,
             Control::Connector { .. } => true,
             Control::BlockCode { .. } => true,
+            Control::BlockPalette { .. } => true,
             Control::OctaveKeys { .. } => true,
             Control::Graph { .. } => false,
             Control::Scope { .. } => false,
             Control::GraphMinMax { .. } => false,
             Control::PatternEditor { .. } => true,
             Control::List { .. } => true,
+            Control::Custom { widget } => widget.can_show_hover(),
         }
     }
 
@@ -456,30 +501,135 @@ impl Control {
             Control::Button { .. } => true,
             Control::WichText { .. } => true,
             Control::Entry { .. } => true,
+            Control::TextEdit { .. } => true,
             Control::HexKnob { .. } => true,
             Control::HexGrid { .. } => true,
             Control::Connector { .. } => true,
             Control::BlockCode { .. } => true,
+            Control::BlockPalette { .. } => true,
             Control::OctaveKeys { .. } => true,
             Control::Graph { .. } => false,
             Control::Scope { .. } => false,
             Control::GraphMinMax { .. } => false,
             Control::PatternEditor { .. } => true,
             Control::List { .. } => true,
+            Control::Custom { widget } => widget.can_hover(),
+        }
+    }
+
+    /// The names [Control::type_name] returns for the builtin controls,
+    /// including the default name of a [CustomWidget].
+    pub const TYPE_NAMES: &'static [&'static str] = &[
+        "None",
+        "Rect",
+        "Label",
+        "Button",
+        "WichText",
+        "Entry",
+        "TextEdit",
+        "HexKnob",
+        "HexGrid",
+        "Connector",
+        "BlockCode",
+        "BlockPalette",
+        "OctaveKeys",
+        "Graph",
+        "Scope",
+        "GraphMinMax",
+        "PatternEditor",
+        "List",
+        "Custom",
+    ];
+
+    /// The name of the control type, as used by the [StyleSheet] selectors.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Control::None => "None",
+            Control::Rect => "Rect",
+            Control::Label { .. } => "Label",
+            Control::Button { .. } => "Button",
+            Control::WichText { .. } => "WichText",
+            Control::Entry { .. } => "Entry",
+            Control::TextEdit { .. } => "TextEdit",
+            Control::HexKnob { .. } => "HexKnob",
+            Control::HexGrid { .. } => "HexGrid",
+            Control::Connector { .. } => "Connector",
+            Control::BlockCode { .. } => "BlockCode",
+            Control::BlockPalette { .. } => "BlockPalette",
+            Control::OctaveKeys { .. } => "OctaveKeys",
+            Control::Graph { .. } => "Graph",
+            Control::Scope { .. } => "Scope",
+            Control::GraphMinMax { .. } => "GraphMinMax",
+            Control::PatternEditor { .. } => "PatternEditor",
+            Control::List { .. } => "List",
+            Control::Custom { widget } => widget.type_name(),
+        }
+    }
+
+    /// Whether the control can receive the keyboard focus, either
+    /// by clicking on it or by Tab navigation.
+    pub fn can_focus(&self) -> bool {
+        match self {
+            Control::None => false,
+            Control::Rect => false,
+            Control::Label { .. } => false,
+            Control::Button { .. } => false,
+            Control::WichText { .. } => false,
+            Control::Entry { .. } => true,
+            Control::TextEdit { .. } => true,
+            Control::HexKnob { .. } => true,
+            Control::HexGrid { .. } => true,
+            Control::Connector { .. } => false,
+            Control::BlockCode { .. } => true,
+            Control::BlockPalette { .. } => true,
+            Control::OctaveKeys { .. } => false,
+            Control::Graph { .. } => false,
+            Control::Scope { .. } => false,
+            Control::GraphMinMax { .. } => false,
+            Control::PatternEditor { .. } => true,
+            Control::List { .. } => true,
+            Control::Custom { widget } => widget.can_focus(),
+        }
+    }
+
+    /// Whether the control keeps its own undo history, that Ctrl+Z and
+    /// Ctrl+Y should act on while it is focused, instead of the
+    /// [UndoStack] of the [UI]. A [HexKnob] only does while its inline
+    /// value editor is open.
+    pub fn has_local_undo(&self) -> bool {
+        match self {
+            Control::Entry { .. } | Control::TextEdit { .. } => true,
+            Control::HexKnob { knob } => knob.is_editing(),
+            _ => false,
+        }
+    }
+
+    /// Whether the control handles the Tab key itself while it is focused.
+    /// Otherwise Tab and Shift+Tab move the focus to the next or previous
+    /// focusable widget. A single line [Entry] leaves Tab to the focus
+    /// navigation, like in most forms.
+    pub fn wants_tab(&self) -> bool {
+        match self {
+            Control::TextEdit { .. } | Control::BlockPalette { .. } => true,
+            Control::Custom { widget } => widget.wants_tab(),
+            _ => false,
         }
     }
 
     pub fn annotate_drop_event(&mut self, mouse_pos: (f32, f32), ev: Event) -> Event {
         match self {
             Control::HexGrid { grid } => grid.annotate_drop_event(mouse_pos, ev),
+            Control::Custom { widget } => widget.annotate_drop_event(mouse_pos, ev),
             Control::Rect
             | Control::None
             | Control::Label { .. }
             | Control::Button { .. }
             | Control::WichText { .. }
             | Control::Entry { .. }
+            | Control::TextEdit { .. }
             | Control::Connector { .. }
             | Control::BlockCode { .. }
+            | Control::BlockPalette { .. }
             | Control::OctaveKeys { .. }
             | Control::Graph { .. }
             | Control::Scope { .. }
@@ -572,6 +722,30 @@ impl Control {
         }
     }
 
+    fn draw_focus_ring(&mut self, style: &DPIStyle, pos: Rect, painter: &mut Painter) {
+        let ring = style.focus_ring();
+        if ring < 0.1 {
+            return;
+        }
+
+        let pos = pos.shrink(ring * 0.5, ring * 0.5);
+
+        match style.border_style() {
+            BorderStyle::Rect => {
+                painter.rect_stroke_r(ring, style.focus_color(), pos);
+            }
+            BorderStyle::Bevel { corner_offsets } => {
+                let mut pt_buf = [(0.0, 0.0); 8];
+                let points = bevel_points(pos, corner_offsets, &mut pt_buf);
+                painter.path_stroke(ring, style.focus_color(), &mut points.iter().copied(), true);
+            }
+            BorderStyle::Hex { offset } => {
+                let points = hex_points(pos, offset);
+                painter.path_stroke(ring, style.focus_color(), &mut points.iter().copied(), true);
+            }
+        }
+    }
+
     fn dispatch_draw_control(
         &mut self,
         w: &Widget,
@@ -633,6 +807,9 @@ impl Control {
             Control::Entry { entry } => {
                 entry.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
             }
+            Control::TextEdit { edit } => {
+                edit.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
+            }
             Control::WichText { wt } => {
                 wt.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
             }
@@ -648,6 +825,9 @@ impl Control {
             Control::BlockCode { code } => {
                 code.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
             }
+            Control::BlockPalette { palette } => {
+                palette.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
+            }
             Control::OctaveKeys { keys } => {
                 keys.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
             }
@@ -666,6 +846,9 @@ impl Control {
             Control::List { list } => {
                 list.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
             }
+            Control::Custom { widget } => {
+                widget.draw(w, &style, draw_widget_pos, real_widget_pos, painter);
+            }
         }
     }
 
@@ -784,6 +967,10 @@ impl Control {
             //d// println!("DISP DRAW CTRL wid={} cached={} {:?}", w.unique_id(), is_cached, draw_widget_pos);
             self.dispatch_draw_control(w, &logic_style, draw_widget_pos, real_widget_pos, painter);
 
+            if w.is_focused() {
+                self.draw_focus_ring(&style, draw_border_pos, painter);
+            }
+
             if let Some(redraw_widgets) = redraw_widgets {
                 if let Some(childs) = childs {
                     for c in childs.iter() {
@@ -821,16 +1008,19 @@ impl Control {
             Control::Label { label } => label.get_generation(),
             Control::WichText { wt } => wt.data().get_generation(),
             Control::Entry { entry } => entry.get_generation(),
+            Control::TextEdit { edit } => edit.get_generation(),
             Control::HexKnob { knob } => knob.get_generation(),
             Control::HexGrid { grid } => grid.get_generation(),
             Control::Connector { con } => con.get_generation(),
             Control::BlockCode { code } => code.get_generation(),
+            Control::BlockPalette { palette } => palette.get_generation(),
             Control::OctaveKeys { keys } => keys.get_generation(),
             Control::Graph { graph } => graph.get_generation(),
             Control::Scope { scope } => scope.get_generation(),
             Control::GraphMinMax { graph } => graph.get_generation(),
             Control::PatternEditor { edit } => edit.get_generation(),
             Control::List { list } => list.get_generation(),
+            Control::Custom { widget } => widget.get_generation(),
         }
     }
 
@@ -847,7 +1037,7 @@ impl Control {
                         w.activate();
                         out_events.push((
                             w.unique_id(),
-                            Event { name: "press".to_string(), data: EvPayload::Button(*button) },
+                            Event::new(EvKind::Press, EvPayload::Button(*button)),
                         ));
                     }
                 }
@@ -855,11 +1045,11 @@ impl Control {
                     if w.is_active() && is_hovered {
                         out_events.push((
                             w.unique_id(),
-                            Event { name: "click".to_string(), data: EvPayload::Button(*button) },
+                            Event::new(EvKind::Click, EvPayload::Button(*button)),
                         ));
                         out_events.push((
                             w.unique_id(),
-                            Event { name: "release".to_string(), data: EvPayload::Button(*button) },
+                            Event::new(EvKind::Release, EvPayload::Button(*button)),
                         ));
                     }
                     if w.is_active() {
@@ -871,6 +1061,9 @@ impl Control {
             Control::Entry { entry } => {
                 entry.handle(w, event, out_events);
             }
+            Control::TextEdit { edit } => {
+                edit.handle(w, event, out_events);
+            }
             Control::HexKnob { knob } => {
                 knob.handle(w, event, out_events);
             }
@@ -886,6 +1079,9 @@ impl Control {
             Control::BlockCode { code } => {
                 code.handle(w, event, out_events);
             }
+            Control::BlockPalette { palette } => {
+                palette.handle(w, event, out_events);
+            }
             Control::OctaveKeys { keys } => {
                 keys.handle(w, event, out_events);
             }
@@ -898,6 +1094,9 @@ impl Control {
             Control::List { list } => {
                 list.handle(w, event, out_events);
             }
+            Control::Custom { widget } => {
+                widget.handle(w, event, out_events);
+            }
         }
     }
 }
@@ -916,7 +1115,12 @@ pub struct UINotifier {
     pub mouse_pos: (f32, f32),
     pub redraw: HashSet<usize>,
     pub active: Option<usize>,
+    pub focus: Option<usize>,
     pub popups: Vec<(usize, PopupPos)>,
+    pub undo: UndoStackRef,
+    pub clipboard: ClipboardRef,
+    pub learn_mode: bool,
+    pub learn_armed: Option<usize>,
 }
 
 impl UINotifier {
@@ -928,7 +1132,12 @@ impl UINotifier {
             mouse_pos: (0.0, 0.0),
             redraw: HashSet::new(),
             active: None,
+            focus: None,
             popups: vec![],
+            undo: UndoStackRef::new(),
+            clipboard: ClipboardRef::new(),
+            learn_mode: false,
+            learn_armed: None,
         }))
     }
 }
@@ -1045,6 +1254,78 @@ impl UINotifierRef {
         r.active
     }
 
+    /// Moves the keyboard focus to the widget with the `unique_id`.
+    /// The "focus" and "blur" events are sent by the [crate::UI]
+    /// after the current input event has been handled.
+    pub fn focus(&self, unique_id: usize) {
+        let old_focus = self.0.borrow_mut().focus.replace(unique_id);
+
+        if let Some(old_focus_id) = old_focus {
+            if old_focus_id != unique_id {
+                self.redraw(old_focus_id);
+            }
+        }
+
+        self.redraw(unique_id);
+    }
+
+    pub fn blur(&self) {
+        let old_focus = self.0.borrow_mut().focus.take();
+
+        if let Some(old_focus_id) = old_focus {
+            self.redraw(old_focus_id);
+        }
+    }
+
+    pub fn focused(&self) -> Option<usize> {
+        let r = self.0.borrow_mut();
+        r.focus
+    }
+
+    pub fn undo_stack(&self) -> UndoStackRef {
+        self.0.borrow().undo.clone()
+    }
+
+    pub fn clipboard(&self) -> ClipboardRef {
+        self.0.borrow().clipboard.clone()
+    }
+
+    /// Leaving the learn mode also disarms the armed widget.
+    pub fn set_learn_mode(&self, enabled: bool) {
+        let old_armed = {
+            let mut r = self.0.borrow_mut();
+            r.learn_mode = enabled;
+            if enabled {
+                None
+            } else {
+                r.learn_armed.take()
+            }
+        };
+
+        if let Some(old_armed_id) = old_armed {
+            self.redraw(old_armed_id);
+        }
+    }
+
+    pub fn is_learn_mode(&self) -> bool {
+        self.0.borrow().learn_mode
+    }
+
+    pub fn arm_learn(&self, unique_id: Option<usize>) {
+        let old_armed = std::mem::replace(&mut self.0.borrow_mut().learn_armed, unique_id);
+
+        if let Some(old_armed_id) = old_armed {
+            self.redraw(old_armed_id);
+        }
+        if let Some(unique_id) = unique_id {
+            self.redraw(unique_id);
+        }
+    }
+
+    pub fn learn_armed(&self) -> Option<usize> {
+        self.0.borrow().learn_armed
+    }
+
     pub fn popup(&self, widget_id: usize, pos: PopupPos) {
         self.0.borrow_mut().popups.push((widget_id, pos));
     }
@@ -1054,10 +1335,230 @@ impl UINotifierRef {
     }
 }
 
+/// The kinds of events the built-in widgets and the [UI] send.
+/// Registering callbacks with an [EvKind] instead of a string catches
+/// typos at compile time. Any other name is carried by [EvKind::Custom].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EvKind {
+    Click,
+    Press,
+    Release,
+    Hover,
+    Drag,
+    HexDrag,
+    Drop,
+    DropQuery,
+    Change,
+    Changed,
+    ConnectionHover,
+    Enter,
+    Select,
+    CenterPos,
+    ContextMenuOpen,
+    Focus,
+    Blur,
+    Undo,
+    Redo,
+    LearnArmed,
+    Selection,
+    Copy,
+    Paste,
+    Duplicate,
+    Instantiate,
+    Custom(String),
+}
+
+impl EvKind {
+    /// All kinds except [EvKind::Custom].
+    pub const BUILTIN: &'static [EvKind] = &[
+        EvKind::Click,
+        EvKind::Press,
+        EvKind::Release,
+        EvKind::Hover,
+        EvKind::Drag,
+        EvKind::HexDrag,
+        EvKind::Drop,
+        EvKind::DropQuery,
+        EvKind::Change,
+        EvKind::Changed,
+        EvKind::ConnectionHover,
+        EvKind::Enter,
+        EvKind::Select,
+        EvKind::CenterPos,
+        EvKind::ContextMenuOpen,
+        EvKind::Focus,
+        EvKind::Blur,
+        EvKind::Undo,
+        EvKind::Redo,
+        EvKind::LearnArmed,
+        EvKind::Selection,
+        EvKind::Copy,
+        EvKind::Paste,
+        EvKind::Duplicate,
+        EvKind::Instantiate,
+    ];
+
+    /// For a [EvKind::Custom] name, that only differs by case or one
+    /// character from a builtin kind, returns that kind. Such names
+    /// might be typos, callbacks for them would never be called by the
+    /// builtin widgets. Nothing checks this on its own, as custom names
+    /// like "clicks" are legitimate. Applications can assert on it for
+    /// their own event names, for instance in a test.
+    pub fn likely_typo_of(&self) -> Option<EvKind> {
+        let name = if let EvKind::Custom(name) = self { name } else { return None };
+
+        EvKind::BUILTIN
+            .iter()
+            .find(|kind| {
+                name.eq_ignore_ascii_case(kind.as_str()) || one_edit_apart(name, kind.as_str())
+            })
+            .cloned()
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            EvKind::Click => "click",
+            EvKind::Press => "press",
+            EvKind::Release => "release",
+            EvKind::Hover => "hover",
+            EvKind::Drag => "drag",
+            EvKind::HexDrag => "hex_drag",
+            EvKind::Drop => "drop",
+            EvKind::DropQuery => "drop_query",
+            EvKind::Change => "change",
+            EvKind::Changed => "changed",
+            EvKind::ConnectionHover => "connection_hover",
+            EvKind::Enter => "enter",
+            EvKind::Select => "select",
+            EvKind::CenterPos => "center_pos",
+            EvKind::ContextMenuOpen => "context_menu_open",
+            EvKind::Focus => "focus",
+            EvKind::Blur => "blur",
+            EvKind::Undo => "undo",
+            EvKind::Redo => "redo",
+            EvKind::LearnArmed => "learn_armed",
+            EvKind::Selection => "selection",
+            EvKind::Copy => "copy",
+            EvKind::Paste => "paste",
+            EvKind::Duplicate => "duplicate",
+            EvKind::Instantiate => "instantiate",
+            EvKind::Custom(name) => &name[..],
+        }
+    }
+}
+
+impl From<&str> for EvKind {
+    fn from(name: &str) -> Self {
+        match name {
+            "click" => EvKind::Click,
+            "press" => EvKind::Press,
+            "release" => EvKind::Release,
+            "hover" => EvKind::Hover,
+            "drag" => EvKind::Drag,
+            "hex_drag" => EvKind::HexDrag,
+            "drop" => EvKind::Drop,
+            "drop_query" => EvKind::DropQuery,
+            "change" => EvKind::Change,
+            "changed" => EvKind::Changed,
+            "connection_hover" => EvKind::ConnectionHover,
+            "enter" => EvKind::Enter,
+            "select" => EvKind::Select,
+            "center_pos" => EvKind::CenterPos,
+            "context_menu_open" => EvKind::ContextMenuOpen,
+            "focus" => EvKind::Focus,
+            "blur" => EvKind::Blur,
+            "undo" => EvKind::Undo,
+            "redo" => EvKind::Redo,
+            "learn_armed" => EvKind::LearnArmed,
+            "selection" => EvKind::Selection,
+            "copy" => EvKind::Copy,
+            "paste" => EvKind::Paste,
+            "duplicate" => EvKind::Duplicate,
+            "instantiate" => EvKind::Instantiate,
+            _ => EvKind::Custom(name.to_string()),
+        }
+    }
+}
+
+/// Whether `a` turns into `b` by inserting, removing or replacing one character.
+fn one_edit_apart(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let (short, long) = if a.len() <= b.len() { (&a, &b) } else { (&b, &a) };
+
+    if long.len() - short.len() > 1 {
+        return false;
+    }
+
+    let prefix = short.iter().zip(long.iter()).take_while(|(x, y)| x == y).count();
+    if short.len() == long.len() {
+        prefix < short.len() && short[(prefix + 1)..] == long[(prefix + 1)..]
+    } else {
+        short[prefix..] == long[(prefix + 1)..]
+    }
+}
+
+impl std::fmt::Display for EvKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The phase an [Event] is currently dispatched in. The event first
+/// travels from the root down to the parent of the target widget
+/// ([EvPhase::Capture]), then is handled by the target itself and
+/// finally travels back up to the root ([EvPhase::Bubble]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvPhase {
+    Capture,
+    Target,
+    Bubble,
+}
+
+/// An event emitted by a widget, see [Widget::reg].
+///
+/// The struct is `non_exhaustive`, because it carries the propagation
+/// state of the dispatch. Construct it with [Event::new].
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct Event {
     pub name: String,
     pub data: EvPayload,
+    phase: Cell<EvPhase>,
+    stopped: Cell<bool>,
+}
+
+impl Event {
+    pub fn new<K: Into<EvKind>>(kind: K, data: EvPayload) -> Self {
+        Self {
+            name: kind.into().as_str().to_string(),
+            data,
+            phase: Cell::new(EvPhase::Target),
+            stopped: Cell::new(false),
+        }
+    }
+
+    pub fn kind(&self) -> EvKind {
+        EvKind::from(&self.name[..])
+    }
+
+    pub fn is(&self, kind: EvKind) -> bool {
+        self.name == kind.as_str()
+    }
+
+    pub fn phase(&self) -> EvPhase {
+        self.phase.get()
+    }
+
+    /// Prevents the event from being passed on to further widgets
+    /// in the capture or bubble phase. The remaining callbacks on the
+    /// current widget are still called.
+    pub fn stop_propagation(&self) {
+        self.stopped.set(true);
+    }
+
+    pub fn is_propagation_stopped(&self) -> bool {
+        self.stopped.get()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1068,6 +1569,7 @@ pub enum EvPayload {
     HexGridClick { x: usize, y: usize, button: MButton },
     HexGridDrag { x_src: usize, y_src: usize, x_dst: usize, y_dst: usize, button: MButton },
     HexGridDropData { x: usize, y: usize, data: Rc<RefCell<Box<dyn std::any::Any>>> },
+    HexGridSelection { cells: Vec<(usize, usize)> },
     Click { x: f32, y: f32, button: MButton },
     ItemSelect { index: i32 },
     SetConnection(Option<(usize, usize)>),
@@ -1078,41 +1580,96 @@ pub enum EvPayload {
     Button(MButton),
     Text(String),
     BlockPos { button: MButton, at: BlockPos, to: Option<BlockPos> },
+    /// The block at `at` (with all blocks connected to its outputs
+    /// if `chain` is set) for the clipboard events of the [BlockCode].
+    BlockClip { at: BlockPos, chain: bool, to: Option<BlockPos> },
+    /// A block type picked in the [BlockPalette], to be placed at the
+    /// target `at` with [blocklang::BlockFun::instanciate_at].
+    BlockInstantiate { typ: String, user_input: Option<String>, at: Option<BlockPos> },
     Pos { x: f32, y: f32 },
+    ParamLearn { param_id: u64 },
+}
+
+type EventCallbacks = std::collections::HashMap<
+    String,
+    Option<Vec<Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>>>,
+>;
+
+fn event_callbacks_reg(
+    callbacks: &mut EventCallbacks,
+    kind: EvKind,
+    cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
+) {
+    if let Some(cbs) = callbacks.get_mut(kind.as_str()) {
+        if let Some(cbs) = cbs {
+            cbs.push(cb);
+        }
+    } else {
+        callbacks.insert(kind.as_str().to_string(), Some(vec![cb]));
+    }
 }
 
 pub struct EventCore {
-    callbacks: std::collections::HashMap<
-        String,
-        Option<Vec<Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>>>,
-    >,
+    callbacks: EventCallbacks,
+    capture_callbacks: EventCallbacks,
+    bubble_callbacks: EventCallbacks,
 }
 
 impl EventCore {
     pub fn new() -> Self {
-        Self { callbacks: std::collections::HashMap::new() }
+        Self {
+            callbacks: std::collections::HashMap::new(),
+            capture_callbacks: std::collections::HashMap::new(),
+            bubble_callbacks: std::collections::HashMap::new(),
+        }
     }
 
     pub fn clear(&mut self) {
         self.callbacks.clear();
+        self.capture_callbacks.clear();
+        self.bubble_callbacks.clear();
     }
 
-    pub fn reg(
+    /// Registers a callback for events emitted by the widget itself.
+    pub fn reg<K: Into<EvKind>>(
         &mut self,
-        ev_name: &str,
+        kind: K,
         cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
     ) {
-        if let Some(cbs) = self.callbacks.get_mut(ev_name) {
-            if let Some(cbs) = cbs {
-                cbs.push(cb);
-            }
-        } else {
-            self.callbacks.insert(ev_name.to_string(), Some(vec![cb]));
-        }
+        event_callbacks_reg(&mut self.callbacks, kind.into(), cb);
+    }
+
+    /// Registers a callback for events emitted by any child widget.
+    /// It is called on the way down to the child, before the child
+    /// handles the event itself. The callback receives the child widget.
+    pub fn reg_capture<K: Into<EvKind>>(
+        &mut self,
+        kind: K,
+        cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
+    ) {
+        event_callbacks_reg(&mut self.capture_callbacks, kind.into(), cb);
     }
 
+    /// Registers a callback for events emitted by any child widget.
+    /// It is called on the way back up, after the child handled the
+    /// event itself. The callback receives the child widget.
+    pub fn reg_bubble<K: Into<EvKind>>(
+        &mut self,
+        kind: K,
+        cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
+    ) {
+        event_callbacks_reg(&mut self.bubble_callbacks, kind.into(), cb);
+    }
+
+    /// Calls the callbacks registered for the current [EvPhase] of `ev`.
     pub fn call(&mut self, ctx: &mut dyn std::any::Any, ev: &Event, widget: &Widget) {
-        if let Some(cbs) = self.callbacks.get_mut(&ev.name) {
+        let callbacks = match ev.phase() {
+            EvPhase::Capture => &mut self.capture_callbacks,
+            EvPhase::Target => &mut self.callbacks,
+            EvPhase::Bubble => &mut self.bubble_callbacks,
+        };
+
+        if let Some(cbs) = callbacks.get_mut(&ev.name) {
             if let Some(cbs) = cbs {
                 for cb in cbs {
                     (*cb)(ctx, widget.clone(), ev);
@@ -1122,15 +1679,55 @@ impl EventCore {
     }
 }
 
-pub(crate) fn widget_handle_event(widget: &Widget, ctx: &mut dyn std::any::Any, ev: &Event) {
-    let evc = widget.take_event_core();
+fn widget_call_event_core(
+    cur_widget: &Widget,
+    ctx: &mut dyn std::any::Any,
+    ev: &Event,
+    target: &Widget,
+) {
+    let evc = cur_widget.take_event_core();
 
     if let Some(mut evc) = evc {
-        evc.call(ctx, ev, widget);
-        widget.give_back_event_core(evc);
+        evc.call(ctx, ev, target);
+        cur_widget.give_back_event_core(evc);
     }
 }
 
+/// Dispatches `ev` emitted by `widget`: First the capture callbacks of all
+/// parents are called from the root downwards, then the callbacks of
+/// `widget` and finally the bubble callbacks of the parents upwards.
+/// [Event::stop_propagation] ends the dispatch after the current widget.
+pub(crate) fn widget_handle_event(widget: &Widget, ctx: &mut dyn std::any::Any, ev: &Event) {
+    ev.stopped.set(false);
+
+    let mut parents = vec![];
+    widget_walk_parents(widget, |parent| parents.push(parent.clone()));
+
+    ev.phase.set(EvPhase::Capture);
+    for parent in parents.iter().rev() {
+        widget_call_event_core(parent, ctx, ev, widget);
+        if ev.is_propagation_stopped() {
+            ev.phase.set(EvPhase::Target);
+            return;
+        }
+    }
+
+    ev.phase.set(EvPhase::Target);
+    widget_call_event_core(widget, ctx, ev, widget);
+
+    if !ev.is_propagation_stopped() {
+        ev.phase.set(EvPhase::Bubble);
+        for parent in parents.iter() {
+            widget_call_event_core(parent, ctx, ev, widget);
+            if ev.is_propagation_stopped() {
+                break;
+            }
+        }
+    }
+
+    ev.phase.set(EvPhase::Target);
+}
+
 pub trait WindowUI {
     fn pre_frame(&mut self);
     fn post_frame(&mut self);
@@ -1160,3 +1757,97 @@ pub enum MButton {
     Right,
     Middle,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    fn log_cb(
+        log: &Log,
+        name: &'static str,
+        stop: bool,
+    ) -> impl FnMut(&mut dyn std::any::Any, Widget, &Event) {
+        let log = log.clone();
+        move |_ctx, _wid, ev| {
+            log.borrow_mut().push(format!("{}:{:?}", name, ev.phase()));
+            if stop {
+                ev.stop_propagation();
+            }
+        }
+    }
+
+    /// Builds root -> mid -> child, with `stop_at` stopping the propagation.
+    fn dispatch_click(stop_at: &str) -> Vec<String> {
+        let style = Rc::new(Style::new());
+        let root = Widget::new(style.clone());
+        let mid = Widget::new(style.clone());
+        let child = Widget::new(style);
+        root.add(mid.clone());
+        mid.add(child.clone());
+
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        root.reg_capture("click", log_cb(&log, "root", stop_at == "root:Capture"));
+        mid.reg_capture("click", log_cb(&log, "mid", stop_at == "mid:Capture"));
+        child.reg("click", log_cb(&log, "child", stop_at == "child:Target"));
+        mid.reg_bubble("click", log_cb(&log, "mid", stop_at == "mid:Bubble"));
+        root.reg_bubble("click", log_cb(&log, "root", stop_at == "root:Bubble"));
+
+        let ev = Event::new(EvKind::Click, EvPayload::None);
+        widget_handle_event(&child, &mut (), &ev);
+        assert_eq!(ev.phase(), EvPhase::Target);
+
+        let res = log.borrow().clone();
+        res
+    }
+
+    #[test]
+    fn check_event_capture_target_bubble_order() {
+        assert_eq!(
+            dispatch_click(""),
+            vec!["root:Capture", "mid:Capture", "child:Target", "mid:Bubble", "root:Bubble"]
+        );
+    }
+
+    #[test]
+    fn check_event_stop_propagation() {
+        assert_eq!(dispatch_click("root:Capture"), vec!["root:Capture"]);
+        assert_eq!(dispatch_click("mid:Capture"), vec!["root:Capture", "mid:Capture"]);
+        assert_eq!(
+            dispatch_click("child:Target"),
+            vec!["root:Capture", "mid:Capture", "child:Target"]
+        );
+        assert_eq!(
+            dispatch_click("mid:Bubble"),
+            vec!["root:Capture", "mid:Capture", "child:Target", "mid:Bubble"]
+        );
+    }
+
+    #[test]
+    fn check_event_kind_typos() {
+        assert_eq!(EvKind::from("clik").likely_typo_of(), Some(EvKind::Click));
+        assert_eq!(EvKind::from("Click").likely_typo_of(), Some(EvKind::Click));
+        assert_eq!(EvKind::from("changes").likely_typo_of(), Some(EvKind::Change));
+        assert_eq!(EvKind::from("my_event").likely_typo_of(), None);
+        assert_eq!(EvKind::Click.likely_typo_of(), None);
+
+        for kind in EvKind::BUILTIN {
+            assert_eq!(&EvKind::from(kind.as_str()), kind);
+        }
+        assert_eq!(EvKind::from("hex_drag"), EvKind::HexDrag);
+        assert_eq!(EvKind::from("connection_hover"), EvKind::ConnectionHover);
+    }
+
+    #[test]
+    fn check_event_custom_kind_near_builtin() {
+        let wid = Widget::new(Rc::new(Style::new()));
+
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        wid.reg("clicks", log_cb(&log, "clicks", false));
+        wid.reg("click", log_cb(&log, "click", false));
+
+        widget_handle_event(&wid, &mut (), &Event::new("clicks", EvPayload::None));
+        assert_eq!(log.borrow().clone(), vec!["clicks:Target"]);
+    }
+}
@@ -9,7 +9,13 @@ use std::rc::Rc;
 
 use std::collections::HashMap;
 
-use femtovg::{renderer::OpenGl, Canvas, Color, FontId, ImageFlags, ImageId};
+mod femtovg_backend;
+mod software;
+mod svg;
+
+pub use femtovg_backend::FemtovgBackend;
+pub use software::SoftwareBackend;
+pub use svg::SvgBackend;
 
 #[macro_export]
 macro_rules! hxclr {
@@ -53,13 +59,96 @@ pub fn lighten_clr(depth: u32, clr: (f32, f32, f32)) -> (f32, f32, f32) {
 //        (clr.2 * 255.0) as u8)
 //}
 
+/// Selects one of the two fonts every [PainterBackend] provides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PainterFont {
+    Default,
+    Mono,
+}
+
+/// The drawing primitives the [Painter] is built on.
+///
+/// HexoTK comes with a [FemtovgBackend] for drawing into an OpenGL window
+/// and a [SoftwareBackend], that rasterizes into an RGBA buffer on the CPU
+/// for headless rendering.
+///
+/// Images are referenced by plain `usize` handles, which are handed out by
+/// [PainterBackend::create_image] and [PainterBackend::load_image].
+pub trait PainterBackend {
+    /// Saves the current transformation and clipping state.
+    fn save(&mut self);
+    /// Restores the state saved with [PainterBackend::save].
+    fn restore(&mut self);
+    fn translate(&mut self, x: f32, y: f32);
+    /// Rotates the coordinate system, `rad` is in radians.
+    fn rotate(&mut self, rad: f32);
+    /// Sets the clipping rectangle, in the current coordinate system.
+    fn scissor(&mut self, x: f32, y: f32, w: f32, h: f32);
+    fn reset_scissor(&mut self);
+
+    fn fill_path(&mut self, color: (f32, f32, f32), path: &[(f32, f32)], closed: bool);
+    fn stroke_path(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        path: &[(f32, f32)],
+        closed: bool,
+    );
+    /// Strokes a clockwise arc from `from_rad` to `to_rad` around `x`/`y`.
+    fn stroke_arc(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        radius: f32,
+        from_rad: f32,
+        to_rad: f32,
+        x: f32,
+        y: f32,
+    );
+    fn fill_rect(&mut self, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32);
+    fn stroke_rect(&mut self, width: f32, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32);
+
+    /// Draws a single line of text. `x` is the anchor for the `align`ment
+    /// (-1 left, 0 center, 1 right) and `y` is the vertical middle of the text.
+    fn fill_text(
+        &mut self,
+        font: PainterFont,
+        size: f32,
+        align: i8,
+        color: (f32, f32, f32),
+        x: f32,
+        y: f32,
+        text: &str,
+    );
+    /// Returns the width and height of `text`.
+    fn measure_text(&mut self, font: PainterFont, size: f32, text: &str) -> Option<(f32, f32)>;
+    fn font_height(&mut self, font: PainterFont, size: f32) -> Option<f32>;
+
+    /// Creates an empty, transparent RGBA image to render into.
+    fn create_image(&mut self, w: usize, h: usize) -> Option<usize>;
+    /// Loads an image from encoded file data, like PNG or JPEG.
+    fn load_image(&mut self, data: &[u8]) -> Option<usize>;
+    fn image_size(&mut self, image: usize) -> Option<(usize, usize)>;
+    fn delete_image(&mut self, image: usize);
+    /// Saves the state and redirects all drawing into `image`, which is cleared.
+    fn push_render_image(&mut self, image: usize);
+    /// Finishes drawing into the current image and returns to the previous target.
+    fn pop_render_image(&mut self);
+    fn draw_image(&mut self, image: usize, x: f32, y: f32, w: f32, h: f32);
+
+    /// Reads back the pixels of the current render target as
+    /// (width, height, RGBA data row by row), if the backend supports it.
+    fn screenshot(&mut self) -> Option<(usize, usize, Vec<u8>)> {
+        None
+    }
+}
+
 pub struct ImageStore {
-    // TODO: FREE THESE IMAGES OR WE HAVE A MEMORY LEAK!
-    freed_images: Vec<ImageId>,
+    freed_images: Vec<usize>,
 }
 pub struct ImgRef {
     store: Rc<RefCell<ImageStore>>,
-    image_id: ImageId,
+    image_id: usize,
     w: f32,
     h: f32,
 }
@@ -80,18 +169,20 @@ impl Drop for ImgRef {
 }
 
 pub struct PersistPainterData {
-    render_targets: Vec<femtovg::RenderTarget>,
     store: Rc<RefCell<ImageStore>>,
-    image_files: HashMap<String, ImageId>,
+    image_files: HashMap<String, usize>,
     image_data: HashMap<String, Vec<u8>>,
+    /// Reused by [Painter::path_fill] and [Painter::path_stroke] for
+    /// collecting the points, so that drawing does not allocate.
+    path_buf: Vec<(f32, f32)>,
 }
 impl PersistPainterData {
     pub fn new() -> Self {
         Self {
-            render_targets: vec![],
             store: Rc::new(RefCell::new(ImageStore { freed_images: vec![] })),
             image_files: HashMap::new(),
             image_data: HashMap::new(),
+            path_buf: vec![],
         }
     }
 
@@ -99,35 +190,31 @@ impl PersistPainterData {
         self.image_data.insert(file.to_string(), data);
     }
 
-    pub fn get_image_file(&mut self, file: &str, canvas: &mut Canvas<OpenGl>) -> Option<ImageId> {
+    pub fn get_image_file(
+        &mut self,
+        file: &str,
+        backend: &mut dyn PainterBackend,
+    ) -> Option<usize> {
         if let Some(image_id) = self.image_files.get(file) {
             Some(*image_id)
         } else {
             let image_data = self.image_data.get(file)?;
 
-            match canvas.load_image_mem(&image_data[..], ImageFlags::empty()) {
-                Ok(img_id) => {
-                    self.image_files.insert(file.to_string(), img_id);
-                    Some(img_id)
-                }
-                Err(e) => {
-                    eprintln!("Error loading image: {}", e);
-                    None
-                }
+            if let Some(img_id) = backend.load_image(&image_data[..]) {
+                self.image_files.insert(file.to_string(), img_id);
+                Some(img_id)
+            } else {
+                eprintln!("Error loading image: {}", file);
+                None
             }
         }
     }
 
-    pub fn init_render_targets(&mut self, target: femtovg::RenderTarget) {
-        self.render_targets.clear();
-        self.render_targets.push(target);
-    }
-
-    pub fn cleanup(&self, canvas: &mut Canvas<OpenGl>) {
+    pub fn cleanup(&self, backend: &mut dyn PainterBackend) {
         let mut store = self.store.borrow_mut();
         if !store.freed_images.is_empty() {
             for img in store.freed_images.iter() {
-                canvas.delete_image(*img);
+                backend.delete_image(*img);
                 //d// println!("CLEANUP IMAGE!");
             }
 
@@ -183,19 +270,21 @@ impl LblDebugTag {
 }
 
 pub struct Painter<'a, 'b> {
-    pub canvas: &'a mut Canvas<OpenGl>,
+    pub backend: &'a mut dyn PainterBackend,
     pub data: &'b mut PersistPainterData,
     pub lbl_collect: Option<Vec<(LblDebugTag, (f32, f32, f32, f32, String))>>,
-    pub font: FontId,
-    pub font_mono: FontId,
     pub dpi_factor: f32,
 }
 
-fn color_paint(color: (f32, f32, f32)) -> femtovg::Paint {
-    femtovg::Paint::color(Color::rgbf(color.0 as f32, color.1 as f32, color.2 as f32))
-}
-
 impl<'a, 'b> Painter<'a, 'b> {
+    pub fn new(
+        backend: &'a mut dyn PainterBackend,
+        data: &'b mut PersistPainterData,
+        dpi_factor: f32,
+    ) -> Self {
+        Self { backend, data, lbl_collect: None, dpi_factor }
+    }
+
     pub fn start_label_collector(&mut self) {
         self.lbl_collect = Some(vec![]);
     }
@@ -211,15 +300,8 @@ impl<'a, 'b> Painter<'a, 'b> {
     }
 
     pub fn new_image(&mut self, w: f32, h: f32) -> ImgRef {
-        let image_id = self
-            .canvas
-            .create_image_empty(
-                w as usize,
-                h as usize,
-                femtovg::PixelFormat::Rgba8,
-                femtovg::ImageFlags::FLIP_Y,
-            )
-            .expect("making image buffer");
+        let image_id =
+            self.backend.create_image(w as usize, h as usize).expect("making image buffer");
 
         //d// println!("new_image w={}, h={} id={:?}", w, h, image_id);
 
@@ -228,26 +310,12 @@ impl<'a, 'b> Painter<'a, 'b> {
 
     pub fn start_image(&mut self, image: &ImgRef) {
         //d// println!("start_image {:?}", image.image_id);
-        self.canvas.save();
-        self.canvas.set_render_target(femtovg::RenderTarget::Image(image.image_id));
-        self.data.render_targets.push(femtovg::RenderTarget::Image(image.image_id));
-        self.canvas.clear_rect(
-            0,
-            0,
-            image.w as u32,
-            image.h as u32,
-            Color::rgbaf(0.0, 0.0, 0.0, 0.0),
-        );
+        self.backend.push_render_image(image.image_id);
     }
 
     pub fn finish_image(&mut self) {
         //d// println!("finish_image");
-        self.canvas.flush();
-        self.canvas.restore();
-        self.data.render_targets.pop();
-        if let Some(rt) = self.data.render_targets.last() {
-            self.canvas.set_render_target(*rt);
-        }
+        self.backend.pop_render_image();
     }
 
     pub fn draw_image(&mut self, image: &ImgRef, screen_x: f32, screen_y: f32) {
@@ -257,31 +325,16 @@ impl<'a, 'b> Painter<'a, 'b> {
         //d//     screen_y,
         //d//     image.w,
         //d//     image.h);
-        let img_paint = femtovg::Paint::image(
-            image.image_id,
-            screen_x,
-            screen_y,
-            image.w as f32,
-            image.h as f32,
-            0.0,
-            1.0,
-        );
-        let mut path = femtovg::Path::new();
-        path.rect(screen_x as f32, screen_y as f32, image.w as f32, image.h as f32);
-        self.canvas.fill_path(&mut path, &img_paint);
+        self.backend.draw_image(image.image_id, screen_x, screen_y, image.w, image.h);
     }
 
     pub fn draw_image_file(&mut self, file: &str, x: f32, y: f32, _w: f32, h: f32) {
-        if let Some(img_id) = self.data.get_image_file(file, self.canvas) {
-            if let Ok((imgw, imgh)) = self.canvas.image_size(img_id) {
+        if let Some(img_id) = self.data.get_image_file(file, self.backend) {
+            if let Some((imgw, imgh)) = self.backend.image_size(img_id) {
                 if imgh as f32 > 0.0 && h > 0.0 {
                     let w = (imgw as f32 * h) / imgh as f32;
 
-                    let img_paint =
-                        femtovg::Paint::image(img_id, x, y, w as f32, h as f32, 0.0, 1.0);
-                    let mut path = femtovg::Path::new();
-                    path.rect(x, y, w, h);
-                    self.canvas.fill_path(&mut path, &img_paint);
+                    self.backend.draw_image(img_id, x, y, w, h);
                 }
             }
         }
@@ -301,17 +354,13 @@ impl<'a, 'b> Painter<'a, 'b> {
         w: f32,
         h: f32,
         text: &str,
-        font: FontId,
+        font: PainterFont,
         dbg: &LblDebugTag,
     ) {
-        let mut paint = color_paint(color);
-        paint.set_font(&[font]);
-        paint.set_font_size(size as f32);
-        paint.set_text_baseline(femtovg::Baseline::Middle);
         let x = x.round();
 
         let (x, y) = if rot > 0.0 {
-            self.canvas.save();
+            self.backend.save();
             let x = x as f32;
             let y = y as f32;
             let wh = (w / 2.0) as f32;
@@ -319,9 +368,9 @@ impl<'a, 'b> Painter<'a, 'b> {
 
             let rot = rot.to_radians() as f32;
 
-            self.canvas.translate(x + wh, y + hh);
-            self.canvas.rotate(rot);
-            self.canvas.translate(xoi as f32, yoi as f32);
+            self.backend.translate(x + wh, y + hh);
+            self.backend.rotate(rot);
+            self.backend.translate(xoi as f32, yoi as f32);
 
             (-wh, -hh)
         } else {
@@ -332,34 +381,17 @@ impl<'a, 'b> Painter<'a, 'b> {
         //        p.rect(x as f32, y as f32, w as f32, h as f32);
         //        self.canvas.stroke_path(&mut p, paint);
         let (rx, ry) = match align {
-            -1 => {
-                paint.set_text_align(femtovg::Align::Left);
-                (x as f32, (y + h / 2.0).round() as f32)
-            }
-            0 => {
-                paint.set_text_align(femtovg::Align::Center);
-                ((x + (w / 2.0)) as f32, (y + h / 2.0).round() as f32)
-            }
-            _ => {
-                paint.set_text_align(femtovg::Align::Right);
-                ((x + w) as f32, (y + h / 2.0).round() as f32)
-            }
+            -1 => (x as f32, (y + h / 2.0).round() as f32),
+            0 => ((x + (w / 2.0)) as f32, (y + h / 2.0).round() as f32),
+            _ => ((x + w) as f32, (y + h / 2.0).round() as f32),
         };
 
-        let _ = self.canvas.fill_text(rx, ry, text, &paint);
+        self.backend.fill_text(font, size, align, color, rx, ry, text);
 
         if let Some(collector) = &mut self.lbl_collect {
-            if let Ok(metr) = self.canvas.measure_text(rx, ry, text, &paint) {
-                collector.push((
-                    *dbg,
-                    (
-                        rx + dbg.offs_x,
-                        ry + dbg.offs_y,
-                        metr.width(),
-                        metr.height(),
-                        text.to_string(),
-                    ),
-                ));
+            if let Some((mw, mh)) = self.backend.measure_text(font, size, text) {
+                collector
+                    .push((*dbg, (rx + dbg.offs_x, ry + dbg.offs_y, mw, mh, text.to_string())));
             }
         }
 
@@ -370,21 +402,20 @@ impl<'a, 'b> Painter<'a, 'b> {
         //        self.canvas.stroke_path(&mut p, paint2);
 
         if rot > 0.0 {
-            //            self.canvas.translate(-(0.5 * w) as f32, 0.0);
-            self.canvas.restore();
+            self.backend.restore();
         }
     }
 }
 
 impl<'a, 'b> Painter<'a, 'b> {
     pub fn clip_region(&mut self, x: f32, y: f32, w: f32, h: f32) {
-        self.canvas.save();
-        self.canvas.scissor(x as f32, y as f32, w as f32, h as f32);
+        self.backend.save();
+        self.backend.scissor(x as f32, y as f32, w as f32, h as f32);
     }
 
     pub fn reset_clip_region(&mut self) {
-        self.canvas.reset_scissor();
-        self.canvas.restore();
+        self.backend.reset_scissor();
+        self.backend.restore();
     }
 
     pub fn path_fill_rot(
@@ -398,16 +429,16 @@ impl<'a, 'b> Painter<'a, 'b> {
         segments: &mut dyn std::iter::Iterator<Item = (f32, f32)>,
         closed: bool,
     ) {
-        self.canvas.save();
+        self.backend.save();
         let rot = rot.to_radians();
 
-        self.canvas.translate(x as f32, y as f32);
-        self.canvas.rotate(rot as f32);
-        self.canvas.translate(xo as f32, yo as f32);
+        self.backend.translate(x as f32, y as f32);
+        self.backend.rotate(rot as f32);
+        self.backend.translate(xo as f32, yo as f32);
 
         self.path_fill(color, segments, closed);
 
-        self.canvas.restore();
+        self.backend.restore();
     }
 
     #[allow(dead_code)]
@@ -423,16 +454,16 @@ impl<'a, 'b> Painter<'a, 'b> {
         segments: &mut dyn std::iter::Iterator<Item = (f32, f32)>,
         closed: bool,
     ) {
-        self.canvas.save();
+        self.backend.save();
         let rot = rot.to_radians();
 
-        self.canvas.translate(x as f32, y as f32);
-        self.canvas.rotate(rot as f32);
-        self.canvas.translate(xo as f32, yo as f32);
+        self.backend.translate(x as f32, y as f32);
+        self.backend.rotate(rot as f32);
+        self.backend.translate(xo as f32, yo as f32);
 
         self.path_stroke(width, color, segments, closed);
 
-        self.canvas.restore();
+        self.backend.restore();
     }
 
     pub fn path_fill(
@@ -441,24 +472,9 @@ impl<'a, 'b> Painter<'a, 'b> {
         segments: &mut dyn std::iter::Iterator<Item = (f32, f32)>,
         closed: bool,
     ) {
-        let mut p = femtovg::Path::new();
-        let paint = color_paint(color);
-
-        let mut first = true;
-        for s in segments {
-            if first {
-                p.move_to(s.0 as f32, s.1 as f32);
-                first = false;
-            } else {
-                p.line_to(s.0 as f32, s.1 as f32);
-            }
-        }
-
-        if closed {
-            p.close();
-        }
-
-        self.canvas.fill_path(&mut p, &paint);
+        self.data.path_buf.clear();
+        self.data.path_buf.extend(segments);
+        self.backend.fill_path(color, &self.data.path_buf[..], closed);
     }
 
     pub fn stroke(
@@ -468,7 +484,7 @@ impl<'a, 'b> Painter<'a, 'b> {
         segments: &[(f32, f32)],
         closed: bool,
     ) {
-        self.path_stroke(width, color, &mut segments.iter().copied(), closed);
+        self.backend.stroke_path(width, color, segments, closed);
     }
 
     pub fn path_stroke(
@@ -478,27 +494,9 @@ impl<'a, 'b> Painter<'a, 'b> {
         segments: &mut dyn std::iter::Iterator<Item = (f32, f32)>,
         closed: bool,
     ) {
-        let mut p = femtovg::Path::new();
-        let mut paint = color_paint(color);
-        paint.set_line_join(femtovg::LineJoin::Round);
-        // paint.set_line_cap(femtovg::LineCap::Round);
-        paint.set_line_width(width as f32);
-
-        let mut first = true;
-        for s in segments {
-            if first {
-                p.move_to(s.0 as f32, s.1 as f32);
-                first = false;
-            } else {
-                p.line_to(s.0 as f32, s.1 as f32);
-            }
-        }
-
-        if closed {
-            p.close();
-        }
-
-        self.canvas.stroke_path(&mut p, &paint);
+        self.data.path_buf.clear();
+        self.data.path_buf.extend(segments);
+        self.backend.stroke_path(width, color, &self.data.path_buf[..], closed);
     }
 
     pub fn arc_stroke(
@@ -511,18 +509,7 @@ impl<'a, 'b> Painter<'a, 'b> {
         x: f32,
         y: f32,
     ) {
-        let mut p = femtovg::Path::new();
-        let mut paint = color_paint(color);
-        paint.set_line_width(width as f32);
-        p.arc(
-            x as f32,
-            y as f32,
-            radius as f32,
-            from_rad as f32,
-            to_rad as f32,
-            femtovg::Solidity::Hole,
-        );
-        self.canvas.stroke_path(&mut p, &paint);
+        self.backend.stroke_arc(width, color, radius, from_rad, to_rad, x, y);
     }
 
     pub fn rect_border_fill_r(
@@ -542,9 +529,7 @@ impl<'a, 'b> Painter<'a, 'b> {
     }
 
     pub fn rect_fill(&mut self, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) {
-        let mut pth = femtovg::Path::new();
-        pth.rect(x as f32, y as f32, w as f32, h as f32);
-        self.canvas.fill_path(&mut pth, &color_paint(color));
+        self.backend.fill_rect(color, x, y, w, h);
     }
 
     pub fn rect_stroke_r(&mut self, width: f32, color: (f32, f32, f32), r: Rect) {
@@ -560,11 +545,7 @@ impl<'a, 'b> Painter<'a, 'b> {
         w: f32,
         h: f32,
     ) {
-        let mut pth = femtovg::Path::new();
-        pth.rect(x as f32, y as f32, w as f32, h as f32);
-        let mut paint = color_paint(color);
-        paint.set_line_width(width as f32);
-        self.canvas.stroke_path(&mut pth, &paint);
+        self.backend.stroke_rect(width, color, x, y, w, h);
     }
 
     pub fn label(
@@ -579,7 +560,21 @@ impl<'a, 'b> Painter<'a, 'b> {
         text: &str,
         dbg: &LblDebugTag,
     ) {
-        self.label_with_font(size, align, 0.0, color, x, y, 0.0, 0.0, w, h, text, self.font, dbg);
+        self.label_with_font(
+            size,
+            align,
+            0.0,
+            color,
+            x,
+            y,
+            0.0,
+            0.0,
+            w,
+            h,
+            text,
+            PainterFont::Default,
+            dbg,
+        );
     }
 
     pub fn label_rot(
@@ -597,7 +592,21 @@ impl<'a, 'b> Painter<'a, 'b> {
         text: &str,
         dbg: &LblDebugTag,
     ) {
-        self.label_with_font(size, align, rot, color, x, y, xo, yo, w, h, text, self.font, dbg);
+        self.label_with_font(
+            size,
+            align,
+            rot,
+            color,
+            x,
+            y,
+            xo,
+            yo,
+            w,
+            h,
+            text,
+            PainterFont::Default,
+            dbg,
+        );
     }
 
     pub fn label_mono(
@@ -624,36 +633,24 @@ impl<'a, 'b> Painter<'a, 'b> {
             w,
             h,
             text,
-            self.font_mono,
+            PainterFont::Mono,
             dbg,
         );
     }
 
     pub fn text_width(&mut self, size: f32, mono: bool, text: &str) -> f32 {
-        let mut paint = color_paint((1.0, 0.0, 1.0));
-        if mono {
-            paint.set_font(&[self.font_mono]);
-        } else {
-            paint.set_font(&[self.font]);
-        }
-        paint.set_font_size(size);
-        if let Ok(metr) = self.canvas.measure_text(0.0, 0.0, text, &paint) {
-            metr.width()
+        let font = if mono { PainterFont::Mono } else { PainterFont::Default };
+        if let Some((w, _h)) = self.backend.measure_text(font, size, text) {
+            w
         } else {
             20.0
         }
     }
 
     pub fn font_height(&mut self, size: f32, mono: bool) -> f32 {
-        let mut paint = color_paint((1.0, 0.0, 1.0));
-        if mono {
-            paint.set_font(&[self.font_mono]);
-        } else {
-            paint.set_font(&[self.font]);
-        }
-        paint.set_font_size(size);
-        if let Ok(metr) = self.canvas.measure_font(&paint) {
-            metr.height()
+        let font = if mono { PainterFont::Mono } else { PainterFont::Default };
+        if let Some(h) = self.backend.font_height(font, size) {
+            h
         } else {
             UI_ELEM_TXT_DEFAULT_H as f32
         }
@@ -668,18 +665,18 @@ impl<'a, 'b> Painter<'a, 'b> {
 
     pub fn translate(&mut self, x: f32, y: f32) {
         // , x2: f64, y2: f64, factor: f64) {
-        self.canvas.save();
+        self.backend.save();
         //        self.cur_scale = factor as f32;
         //        let factor = self.cur_scale;
         //        self.canvas.translate(x as f32, y as f32);
-        self.canvas.translate(x, y);
+        self.backend.translate(x, y);
         //        self.canvas.scale(factor, factor);
         //        self.canvas.translate(x2 as f32, y2 as f32);
         //        self.canvas.translate(-x as f32 / factor, -y as f32 / factor);
     }
 
     pub fn restore(&mut self) {
-        self.canvas.restore();
+        self.backend.restore();
     }
 }
 
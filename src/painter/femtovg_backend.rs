@@ -0,0 +1,285 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use super::{PainterBackend, PainterFont};
+
+use std::collections::HashMap;
+
+use femtovg::{renderer::OpenGl, Canvas, Color, FontId, ImageFlags, ImageId, RenderTarget};
+
+fn color_paint(color: (f32, f32, f32)) -> femtovg::Paint {
+    femtovg::Paint::color(Color::rgbf(color.0 as f32, color.1 as f32, color.2 as f32))
+}
+
+fn build_path(path: &[(f32, f32)], closed: bool) -> femtovg::Path {
+    let mut p = femtovg::Path::new();
+
+    let mut first = true;
+    for s in path.iter() {
+        if first {
+            p.move_to(s.0 as f32, s.1 as f32);
+            first = false;
+        } else {
+            p.line_to(s.0 as f32, s.1 as f32);
+        }
+    }
+
+    if closed {
+        p.close();
+    }
+
+    p
+}
+
+/// The [PainterBackend] that draws with femtovg into an OpenGL context.
+/// This is what the window opened by [crate::open_window] uses.
+pub struct FemtovgBackend {
+    pub canvas: Canvas<OpenGl>,
+    font: FontId,
+    font_mono: FontId,
+    images: HashMap<usize, ImageId>,
+    next_image_id: usize,
+    render_targets: Vec<RenderTarget>,
+}
+
+impl FemtovgBackend {
+    pub fn new(mut canvas: Canvas<OpenGl>) -> Self {
+        let font = canvas.add_font_mem(std::include_bytes!("../font.ttf")).expect("can load font");
+        let font_mono =
+            canvas.add_font_mem(std::include_bytes!("../font_mono.ttf")).expect("can load font");
+
+        Self {
+            canvas,
+            font,
+            font_mono,
+            images: HashMap::new(),
+            next_image_id: 1,
+            render_targets: vec![],
+        }
+    }
+
+    /// Sets the render target that is returned to when all
+    /// images started with [PainterBackend::push_render_image] are finished.
+    pub fn init_render_targets(&mut self, target: RenderTarget) {
+        self.render_targets.clear();
+        self.render_targets.push(target);
+    }
+
+    fn font_paint(&self, font: PainterFont, size: f32, color: (f32, f32, f32)) -> femtovg::Paint {
+        let mut paint = color_paint(color);
+        match font {
+            PainterFont::Default => paint.set_font(&[self.font]),
+            PainterFont::Mono => paint.set_font(&[self.font_mono]),
+        }
+        paint.set_font_size(size as f32);
+        paint.set_text_baseline(femtovg::Baseline::Middle);
+        paint
+    }
+
+    fn add_image(&mut self, image_id: ImageId) -> usize {
+        let id = self.next_image_id;
+        self.next_image_id += 1;
+        self.images.insert(id, image_id);
+        id
+    }
+}
+
+impl PainterBackend for FemtovgBackend {
+    fn save(&mut self) {
+        self.canvas.save();
+    }
+
+    fn restore(&mut self) {
+        self.canvas.restore();
+    }
+
+    fn translate(&mut self, x: f32, y: f32) {
+        self.canvas.translate(x, y);
+    }
+
+    fn rotate(&mut self, rad: f32) {
+        self.canvas.rotate(rad);
+    }
+
+    fn scissor(&mut self, x: f32, y: f32, w: f32, h: f32) {
+        self.canvas.scissor(x, y, w, h);
+    }
+
+    fn reset_scissor(&mut self) {
+        self.canvas.reset_scissor();
+    }
+
+    fn fill_path(&mut self, color: (f32, f32, f32), path: &[(f32, f32)], closed: bool) {
+        let mut p = build_path(path, closed);
+        self.canvas.fill_path(&mut p, &color_paint(color));
+    }
+
+    fn stroke_path(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        path: &[(f32, f32)],
+        closed: bool,
+    ) {
+        let mut p = build_path(path, closed);
+        let mut paint = color_paint(color);
+        paint.set_line_join(femtovg::LineJoin::Round);
+        // paint.set_line_cap(femtovg::LineCap::Round);
+        paint.set_line_width(width as f32);
+        self.canvas.stroke_path(&mut p, &paint);
+    }
+
+    fn stroke_arc(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        radius: f32,
+        from_rad: f32,
+        to_rad: f32,
+        x: f32,
+        y: f32,
+    ) {
+        let mut p = femtovg::Path::new();
+        let mut paint = color_paint(color);
+        paint.set_line_width(width as f32);
+        p.arc(
+            x as f32,
+            y as f32,
+            radius as f32,
+            from_rad as f32,
+            to_rad as f32,
+            femtovg::Solidity::Hole,
+        );
+        self.canvas.stroke_path(&mut p, &paint);
+    }
+
+    fn fill_rect(&mut self, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) {
+        let mut pth = femtovg::Path::new();
+        pth.rect(x as f32, y as f32, w as f32, h as f32);
+        self.canvas.fill_path(&mut pth, &color_paint(color));
+    }
+
+    fn stroke_rect(&mut self, width: f32, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) {
+        let mut pth = femtovg::Path::new();
+        pth.rect(x as f32, y as f32, w as f32, h as f32);
+        let mut paint = color_paint(color);
+        paint.set_line_width(width as f32);
+        self.canvas.stroke_path(&mut pth, &paint);
+    }
+
+    fn fill_text(
+        &mut self,
+        font: PainterFont,
+        size: f32,
+        align: i8,
+        color: (f32, f32, f32),
+        x: f32,
+        y: f32,
+        text: &str,
+    ) {
+        let mut paint = self.font_paint(font, size, color);
+        match align {
+            -1 => paint.set_text_align(femtovg::Align::Left),
+            0 => paint.set_text_align(femtovg::Align::Center),
+            _ => paint.set_text_align(femtovg::Align::Right),
+        }
+
+        let _ = self.canvas.fill_text(x, y, text, &paint);
+    }
+
+    fn measure_text(&mut self, font: PainterFont, size: f32, text: &str) -> Option<(f32, f32)> {
+        let paint = self.font_paint(font, size, (1.0, 0.0, 1.0));
+        if let Ok(metr) = self.canvas.measure_text(0.0, 0.0, text, &paint) {
+            Some((metr.width(), metr.height()))
+        } else {
+            None
+        }
+    }
+
+    fn font_height(&mut self, font: PainterFont, size: f32) -> Option<f32> {
+        let paint = self.font_paint(font, size, (1.0, 0.0, 1.0));
+        if let Ok(metr) = self.canvas.measure_font(&paint) {
+            Some(metr.height())
+        } else {
+            None
+        }
+    }
+
+    fn create_image(&mut self, w: usize, h: usize) -> Option<usize> {
+        let image_id = self
+            .canvas
+            .create_image_empty(w, h, femtovg::PixelFormat::Rgba8, femtovg::ImageFlags::FLIP_Y)
+            .ok()?;
+        Some(self.add_image(image_id))
+    }
+
+    fn load_image(&mut self, data: &[u8]) -> Option<usize> {
+        match self.canvas.load_image_mem(data, ImageFlags::empty()) {
+            Ok(image_id) => Some(self.add_image(image_id)),
+            Err(e) => {
+                eprintln!("Error loading image: {}", e);
+                None
+            }
+        }
+    }
+
+    fn image_size(&mut self, image: usize) -> Option<(usize, usize)> {
+        let image_id = self.images.get(&image)?;
+        self.canvas.image_size(*image_id).ok()
+    }
+
+    fn delete_image(&mut self, image: usize) {
+        if let Some(image_id) = self.images.remove(&image) {
+            self.canvas.delete_image(image_id);
+        }
+    }
+
+    fn push_render_image(&mut self, image: usize) {
+        let image_id = if let Some(image_id) = self.images.get(&image) {
+            *image_id
+        } else {
+            return;
+        };
+        let (w, h) = self.canvas.image_size(image_id).unwrap_or((0, 0));
+
+        self.canvas.save();
+        self.canvas.set_render_target(RenderTarget::Image(image_id));
+        self.render_targets.push(RenderTarget::Image(image_id));
+        self.canvas.clear_rect(0, 0, w as u32, h as u32, Color::rgbaf(0.0, 0.0, 0.0, 0.0));
+    }
+
+    fn pop_render_image(&mut self) {
+        self.canvas.flush();
+        self.canvas.restore();
+        self.render_targets.pop();
+        if let Some(rt) = self.render_targets.last() {
+            self.canvas.set_render_target(*rt);
+        }
+    }
+
+    fn draw_image(&mut self, image: usize, x: f32, y: f32, w: f32, h: f32) {
+        let image_id = if let Some(image_id) = self.images.get(&image) {
+            *image_id
+        } else {
+            return;
+        };
+
+        let img_paint = femtovg::Paint::image(image_id, x, y, w, h, 0.0, 1.0);
+        let mut path = femtovg::Path::new();
+        path.rect(x, y, w, h);
+        self.canvas.fill_path(&mut path, &img_paint);
+    }
+
+    fn screenshot(&mut self) -> Option<(usize, usize, Vec<u8>)> {
+        self.canvas.flush();
+        let img = self.canvas.screenshot().ok()?;
+
+        let mut data = Vec::with_capacity(img.width() * img.height() * 4);
+        for px in img.pixels() {
+            data.extend_from_slice(&[px.r, px.g, px.b, px.a]);
+        }
+
+        Some((img.width(), img.height(), data))
+    }
+}
@@ -0,0 +1,819 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use super::{PainterBackend, PainterFont};
+
+use std::collections::HashMap;
+
+const SUBSAMPLES: usize = 4;
+const CURVE_SEGMENTS: usize = 8;
+const JOIN_SEGMENTS: usize = 12;
+
+#[derive(Debug, Clone)]
+struct SwImage {
+    w: usize,
+    h: usize,
+    data: Vec<u8>,
+}
+
+impl SwImage {
+    fn new(w: usize, h: usize) -> Self {
+        Self { w, h, data: vec![0; w * h * 4] }
+    }
+
+    fn clear(&mut self, color: (f32, f32, f32, f32)) {
+        let c = [
+            (color.0 * 255.0).round() as u8,
+            (color.1 * 255.0).round() as u8,
+            (color.2 * 255.0).round() as u8,
+            (color.3 * 255.0).round() as u8,
+        ];
+        for px in self.data.chunks_exact_mut(4) {
+            px.copy_from_slice(&c[..]);
+        }
+    }
+
+    /// Blends `color` with the `alpha` over the pixel at `x`/`y`.
+    fn blend(&mut self, x: usize, y: usize, color: (f32, f32, f32), alpha: f32) {
+        let idx = (y * self.w + x) * 4;
+        let px = &mut self.data[idx..(idx + 4)];
+
+        let da = px[3] as f32 / 255.0;
+        let out_a = alpha + da * (1.0 - alpha);
+        if out_a <= 0.0 {
+            px.copy_from_slice(&[0, 0, 0, 0]);
+            return;
+        }
+
+        let mix = |src: f32, dst: u8| {
+            let dst = dst as f32 / 255.0;
+            let v = (src * alpha + dst * da * (1.0 - alpha)) / out_a;
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+
+        px[0] = mix(color.0, px[0]);
+        px[1] = mix(color.1, px[1]);
+        px[2] = mix(color.2, px[2]);
+        px[3] = (out_a.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SwState {
+    /// Affine transformation: x' = a*x + c*y + e, y' = b*x + d*y + f
+    xform: [f32; 6],
+    /// Clipping rectangle in device coordinates: x0, y0, x1, y1
+    clip: Option<[f32; 4]>,
+}
+
+impl SwState {
+    fn new() -> Self {
+        Self { xform: [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], clip: None }
+    }
+
+    fn apply(&self, p: (f32, f32)) -> (f32, f32) {
+        let m = &self.xform;
+        (m[0] * p.0 + m[2] * p.1 + m[4], m[1] * p.0 + m[3] * p.1 + m[5])
+    }
+}
+
+pub(super) struct SwFont {
+    face: ttf_parser::Face<'static>,
+}
+
+impl SwFont {
+    pub(super) fn new(data: &'static [u8]) -> Self {
+        Self { face: ttf_parser::Face::from_slice(data, 0).expect("can load font") }
+    }
+
+    fn scale(&self, size: f32) -> f32 {
+        size / (self.face.units_per_em() as f32).max(1.0)
+    }
+
+    fn glyph(&self, c: char) -> ttf_parser::GlyphId {
+        self.face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0))
+    }
+
+    pub(super) fn text_width(&self, size: f32, text: &str) -> f32 {
+        let scale = self.scale(size);
+        text.chars()
+            .map(|c| self.face.glyph_hor_advance(self.glyph(c)).unwrap_or(0) as f32 * scale)
+            .sum()
+    }
+
+    pub(super) fn height(&self, size: f32) -> f32 {
+        (self.face.ascender() as f32 - self.face.descender() as f32) * self.scale(size)
+    }
+
+    /// Offset of the baseline from the vertical middle of the text.
+    pub(super) fn middle_baseline_offs(&self, size: f32) -> f32 {
+        (self.face.ascender() as f32 + self.face.descender() as f32) * 0.5 * self.scale(size)
+    }
+
+    /// Appends the flattened glyph outlines of `text` to `polys`.
+    pub(super) fn outline(
+        &self,
+        size: f32,
+        x: f32,
+        baseline: f32,
+        text: &str,
+        polys: &mut Vec<Vec<(f32, f32)>>,
+    ) {
+        let scale = self.scale(size);
+        let mut builder =
+            OutlineFlattener { polys: vec![], cur: vec![], ox: x, oy: baseline, scale };
+
+        for c in text.chars() {
+            let gid = self.glyph(c);
+            self.face.outline_glyph(gid, &mut builder);
+            builder.flush();
+            builder.ox += self.face.glyph_hor_advance(gid).unwrap_or(0) as f32 * scale;
+        }
+
+        polys.append(&mut builder.polys);
+    }
+}
+
+struct OutlineFlattener {
+    polys: Vec<Vec<(f32, f32)>>,
+    cur: Vec<(f32, f32)>,
+    ox: f32,
+    oy: f32,
+    scale: f32,
+}
+
+impl OutlineFlattener {
+    fn pt(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.ox + x * self.scale, self.oy - y * self.scale)
+    }
+
+    fn last(&self) -> (f32, f32) {
+        self.cur.last().copied().unwrap_or((self.ox, self.oy))
+    }
+
+    fn flush(&mut self) {
+        if self.cur.len() > 2 {
+            self.polys.push(std::mem::take(&mut self.cur));
+        } else {
+            self.cur.clear();
+        }
+    }
+}
+
+impl ttf_parser::OutlineBuilder for OutlineFlattener {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.flush();
+        let p = self.pt(x, y);
+        self.cur.push(p);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.pt(x, y);
+        self.cur.push(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let p0 = self.last();
+        let p1 = self.pt(x1, y1);
+        let p2 = self.pt(x, y);
+
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let it = 1.0 - t;
+            self.cur.push((
+                it * it * p0.0 + 2.0 * it * t * p1.0 + t * t * p2.0,
+                it * it * p0.1 + 2.0 * it * t * p1.1 + t * t * p2.1,
+            ));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let p0 = self.last();
+        let p1 = self.pt(x1, y1);
+        let p2 = self.pt(x2, y2);
+        let p3 = self.pt(x, y);
+
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let it = 1.0 - t;
+            let (a, b, c, d) = (it * it * it, 3.0 * it * it * t, 3.0 * it * t * t, t * t * t);
+            self.cur.push((
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            ));
+        }
+    }
+
+    fn close(&mut self) {
+        self.flush();
+    }
+}
+
+/// Makes all polygons wind in the same direction, so that overlapping
+/// stroke pieces don't cancel each other out with the non-zero fill rule.
+fn normalize_winding(poly: &mut Vec<(f32, f32)>) {
+    let mut area = 0.0;
+    for i in 0..poly.len() {
+        let a = poly[i];
+        let b = poly[(i + 1) % poly.len()];
+        area += a.0 * b.1 - b.0 * a.1;
+    }
+
+    if area > 0.0 {
+        poly.reverse();
+    }
+}
+
+fn circle_poly(x: f32, y: f32, radius: f32) -> Vec<(f32, f32)> {
+    let mut poly = Vec::with_capacity(JOIN_SEGMENTS);
+    for i in 0..JOIN_SEGMENTS {
+        let phi = (i as f32 / JOIN_SEGMENTS as f32) * std::f32::consts::TAU;
+        poly.push((x + radius * phi.cos(), y + radius * phi.sin()));
+    }
+    poly
+}
+
+fn rect_poly(x: f32, y: f32, w: f32, h: f32) -> Vec<(f32, f32)> {
+    vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)]
+}
+
+/// Converts a stroked path into polygons, with butt caps and round joins.
+fn stroke_polys(path: &[(f32, f32)], closed: bool, width: f32) -> Vec<Vec<(f32, f32)>> {
+    let hw = width * 0.5;
+    let mut polys = vec![];
+
+    if path.len() < 2 {
+        return polys;
+    }
+
+    let seg_count = if closed { path.len() } else { path.len() - 1 };
+    for i in 0..seg_count {
+        let a = path[i];
+        let b = path[(i + 1) % path.len()];
+
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len < 0.0001 {
+            continue;
+        }
+
+        let (nx, ny) = ((-dy / len) * hw, (dx / len) * hw);
+        polys.push(vec![
+            (a.0 + nx, a.1 + ny),
+            (b.0 + nx, b.1 + ny),
+            (b.0 - nx, b.1 - ny),
+            (a.0 - nx, a.1 - ny),
+        ]);
+    }
+
+    let (from, to) = if closed { (0, path.len()) } else { (1, path.len() - 1) };
+    for p in path[from..to].iter() {
+        polys.push(circle_poly(p.0, p.1, hw));
+    }
+
+    for poly in polys.iter_mut() {
+        normalize_winding(poly);
+    }
+
+    polys
+}
+
+fn add_span(cov: &mut [f32], xs: f32, xe: f32, weight: f32) {
+    let len = cov.len() as f32;
+    let xs = xs.clamp(0.0, len);
+    let xe = xe.clamp(0.0, len);
+    if xs >= xe {
+        return;
+    }
+
+    let ixs = xs.floor() as usize;
+    let ixe = xe.floor() as usize;
+
+    if ixs == ixe {
+        cov[ixs] += (xe - xs) * weight;
+        return;
+    }
+
+    cov[ixs] += (ixs as f32 + 1.0 - xs) * weight;
+    for c in cov[(ixs + 1)..ixe].iter_mut() {
+        *c += weight;
+    }
+    if ixe < cov.len() {
+        cov[ixe] += (xe - ixe as f32) * weight;
+    }
+}
+
+/// Scanline rasterizer for polygons in device coordinates, using the
+/// non-zero winding rule. Anti-aliasing is done with vertical subsamples
+/// and exact horizontal coverage.
+fn fill_polys(
+    img: &mut SwImage,
+    clip: Option<[f32; 4]>,
+    polys: &[Vec<(f32, f32)>],
+    color: (f32, f32, f32),
+) {
+    let mut bounds = [std::f32::MAX, std::f32::MAX, std::f32::MIN, std::f32::MIN];
+    let mut edges = vec![];
+
+    for poly in polys.iter() {
+        for i in 0..poly.len() {
+            let a = poly[i];
+            let b = poly[(i + 1) % poly.len()];
+
+            bounds[0] = bounds[0].min(a.0);
+            bounds[1] = bounds[1].min(a.1);
+            bounds[2] = bounds[2].max(a.0);
+            bounds[3] = bounds[3].max(a.1);
+
+            if a.1 != b.1 {
+                edges.push((a, b));
+            }
+        }
+    }
+
+    if let Some(clip) = clip {
+        bounds[0] = bounds[0].max(clip[0]);
+        bounds[1] = bounds[1].max(clip[1]);
+        bounds[2] = bounds[2].min(clip[2]);
+        bounds[3] = bounds[3].min(clip[3]);
+    }
+
+    let x0 = bounds[0].floor().max(0.0) as usize;
+    let y0 = bounds[1].floor().max(0.0) as usize;
+    let x1 = (bounds[2].ceil().max(0.0) as usize).min(img.w);
+    let y1 = (bounds[3].ceil().max(0.0) as usize).min(img.h);
+    if x0 >= x1 || y0 >= y1 || edges.is_empty() {
+        return;
+    }
+
+    let (cx0, cx1) = if let Some(clip) = clip { (clip[0], clip[2]) } else { (0.0, img.w as f32) };
+
+    let mut cov = vec![0.0; x1 - x0];
+    let mut crossings: Vec<(f32, i32)> = vec![];
+    let weight = 1.0 / SUBSAMPLES as f32;
+
+    for row in y0..y1 {
+        for c in cov.iter_mut() {
+            *c = 0.0;
+        }
+
+        for s in 0..SUBSAMPLES {
+            let sy = row as f32 + (s as f32 + 0.5) * weight;
+
+            crossings.clear();
+            for (a, b) in edges.iter() {
+                if (a.1 <= sy && b.1 > sy) || (b.1 <= sy && a.1 > sy) {
+                    let t = (sy - a.1) / (b.1 - a.1);
+                    let dir = if b.1 > a.1 { 1 } else { -1 };
+                    crossings.push((a.0 + t * (b.0 - a.0), dir));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            let mut winding = 0;
+            let mut start = 0.0;
+            for (x, dir) in crossings.iter() {
+                let prev = winding;
+                winding += dir;
+
+                if prev == 0 && winding != 0 {
+                    start = *x;
+                } else if prev != 0 && winding == 0 {
+                    let xs = start.max(cx0) - x0 as f32;
+                    let xe = x.min(cx1) - x0 as f32;
+                    add_span(&mut cov[..], xs, xe, weight);
+                }
+            }
+        }
+
+        for (i, c) in cov.iter().enumerate() {
+            if *c > 0.001 {
+                img.blend(x0 + i, row, color, c.min(1.0));
+            }
+        }
+    }
+}
+
+/// A [PainterBackend] that rasterizes on the CPU into an RGBA buffer.
+///
+/// This is used for headless rendering, for instance in automated tests
+/// that compare the rendered UI against reference images. See also
+/// [crate::HeadlessRenderer].
+pub struct SoftwareBackend {
+    screen: SwImage,
+    images: HashMap<usize, SwImage>,
+    next_image_id: usize,
+    targets: Vec<usize>,
+    state: SwState,
+    states: Vec<SwState>,
+    font: SwFont,
+    font_mono: SwFont,
+}
+
+impl SoftwareBackend {
+    pub fn new(w: usize, h: usize) -> Self {
+        Self {
+            screen: SwImage::new(w, h),
+            images: HashMap::new(),
+            next_image_id: 1,
+            targets: vec![],
+            state: SwState::new(),
+            states: vec![],
+            font: SwFont::new(std::include_bytes!("../font.ttf")),
+            font_mono: SwFont::new(std::include_bytes!("../font_mono.ttf")),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.screen.w
+    }
+
+    pub fn height(&self) -> usize {
+        self.screen.h
+    }
+
+    /// The RGBA pixels of the screen buffer, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.screen.data[..]
+    }
+
+    pub fn resize(&mut self, w: usize, h: usize) {
+        self.screen = SwImage::new(w, h);
+    }
+
+    /// Starts a new frame: Resets the transformation and clipping
+    /// state and fills the screen buffer with `color`.
+    pub fn begin_frame(&mut self, color: (f32, f32, f32)) {
+        self.targets.clear();
+        self.states.clear();
+        self.state = SwState::new();
+        self.screen.clear((color.0, color.1, color.2, 1.0));
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), image::ImageError> {
+        image::save_buffer(
+            path,
+            &self.screen.data[..],
+            self.screen.w as u32,
+            self.screen.h as u32,
+            image::ColorType::Rgba8,
+        )
+    }
+
+    fn font(&self, font: PainterFont) -> &SwFont {
+        match font {
+            PainterFont::Default => &self.font,
+            PainterFont::Mono => &self.font_mono,
+        }
+    }
+
+    fn fill_device_polys(&mut self, polys: &[Vec<(f32, f32)>], color: (f32, f32, f32)) {
+        let clip = self.state.clip;
+        let target = self.targets.last().copied();
+
+        let img = match target {
+            Some(id) => {
+                if let Some(img) = self.images.get_mut(&id) {
+                    img
+                } else {
+                    return;
+                }
+            }
+            None => &mut self.screen,
+        };
+
+        fill_polys(img, clip, polys, color);
+    }
+
+    fn fill_user_polys(&mut self, mut polys: Vec<Vec<(f32, f32)>>, color: (f32, f32, f32)) {
+        for poly in polys.iter_mut() {
+            for p in poly.iter_mut() {
+                *p = self.state.apply(*p);
+            }
+        }
+
+        self.fill_device_polys(&polys[..], color);
+    }
+}
+
+impl PainterBackend for SoftwareBackend {
+    fn save(&mut self) {
+        self.states.push(self.state);
+    }
+
+    fn restore(&mut self) {
+        self.state = self.states.pop().unwrap_or_else(SwState::new);
+    }
+
+    fn translate(&mut self, x: f32, y: f32) {
+        let m = &mut self.state.xform;
+        m[4] += m[0] * x + m[2] * y;
+        m[5] += m[1] * x + m[3] * y;
+    }
+
+    fn rotate(&mut self, rad: f32) {
+        let (s, c) = rad.sin_cos();
+        let m = self.state.xform;
+        self.state.xform = [
+            m[0] * c + m[2] * s,
+            m[1] * c + m[3] * s,
+            m[2] * c - m[0] * s,
+            m[3] * c - m[1] * s,
+            m[4],
+            m[5],
+        ];
+    }
+
+    fn scissor(&mut self, x: f32, y: f32, w: f32, h: f32) {
+        let corners = [
+            self.state.apply((x, y)),
+            self.state.apply((x + w, y)),
+            self.state.apply((x + w, y + h)),
+            self.state.apply((x, y + h)),
+        ];
+
+        let mut clip = [std::f32::MAX, std::f32::MAX, std::f32::MIN, std::f32::MIN];
+        for c in corners.iter() {
+            clip[0] = clip[0].min(c.0);
+            clip[1] = clip[1].min(c.1);
+            clip[2] = clip[2].max(c.0);
+            clip[3] = clip[3].max(c.1);
+        }
+
+        self.state.clip = Some(clip);
+    }
+
+    fn reset_scissor(&mut self) {
+        self.state.clip = None;
+    }
+
+    fn fill_path(&mut self, color: (f32, f32, f32), path: &[(f32, f32)], _closed: bool) {
+        if path.len() < 3 {
+            return;
+        }
+        self.fill_user_polys(vec![path.to_vec()], color);
+    }
+
+    fn stroke_path(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        path: &[(f32, f32)],
+        closed: bool,
+    ) {
+        let polys = stroke_polys(path, closed, width);
+        self.fill_user_polys(polys, color);
+    }
+
+    fn stroke_arc(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        radius: f32,
+        from_rad: f32,
+        to_rad: f32,
+        x: f32,
+        y: f32,
+    ) {
+        let tau = std::f32::consts::TAU;
+        let mut da = to_rad - from_rad;
+        if da.abs() >= tau {
+            da = tau;
+        } else {
+            while da < 0.0 {
+                da += tau;
+            }
+        }
+
+        let segs = ((da / (std::f32::consts::PI / 16.0)).ceil() as usize).max(4);
+        let mut path = Vec::with_capacity(segs + 1);
+        for i in 0..=segs {
+            let phi = from_rad + da * (i as f32 / segs as f32);
+            path.push((x + radius * phi.cos(), y + radius * phi.sin()));
+        }
+
+        let polys = stroke_polys(&path[..], false, width);
+        self.fill_user_polys(polys, color);
+    }
+
+    fn fill_rect(&mut self, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) {
+        self.fill_user_polys(vec![rect_poly(x, y, w, h)], color);
+    }
+
+    fn stroke_rect(&mut self, width: f32, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) {
+        let hw = width * 0.5;
+        let mut polys = vec![
+            rect_poly(x - hw, y - hw, w + width, width),
+            rect_poly(x - hw, y + h - hw, w + width, width),
+            rect_poly(x - hw, y + hw, width, h - width),
+            rect_poly(x + w - hw, y + hw, width, h - width),
+        ];
+        for poly in polys.iter_mut() {
+            normalize_winding(poly);
+        }
+        self.fill_user_polys(polys, color);
+    }
+
+    fn fill_text(
+        &mut self,
+        font: PainterFont,
+        size: f32,
+        align: i8,
+        color: (f32, f32, f32),
+        x: f32,
+        y: f32,
+        text: &str,
+    ) {
+        let fnt = self.font(font);
+        let w = fnt.text_width(size, text);
+        let x = match align {
+            -1 => x,
+            0 => x - w * 0.5,
+            _ => x - w,
+        };
+
+        let mut polys = vec![];
+        fnt.outline(size, x, y + fnt.middle_baseline_offs(size), text, &mut polys);
+        self.fill_user_polys(polys, color);
+    }
+
+    fn measure_text(&mut self, font: PainterFont, size: f32, text: &str) -> Option<(f32, f32)> {
+        let fnt = self.font(font);
+        Some((fnt.text_width(size, text), fnt.height(size)))
+    }
+
+    fn font_height(&mut self, font: PainterFont, size: f32) -> Option<f32> {
+        Some(self.font(font).height(size))
+    }
+
+    fn create_image(&mut self, w: usize, h: usize) -> Option<usize> {
+        let id = self.next_image_id;
+        self.next_image_id += 1;
+        self.images.insert(id, SwImage::new(w, h));
+        Some(id)
+    }
+
+    fn load_image(&mut self, data: &[u8]) -> Option<usize> {
+        match image::load_from_memory(data) {
+            Ok(img) => {
+                let img = img.to_rgba8();
+                let (w, h) = (img.width() as usize, img.height() as usize);
+
+                let id = self.next_image_id;
+                self.next_image_id += 1;
+                self.images.insert(id, SwImage { w, h, data: img.into_raw() });
+                Some(id)
+            }
+            Err(e) => {
+                eprintln!("Error loading image: {}", e);
+                None
+            }
+        }
+    }
+
+    fn image_size(&mut self, image: usize) -> Option<(usize, usize)> {
+        self.images.get(&image).map(|img| (img.w, img.h))
+    }
+
+    fn delete_image(&mut self, image: usize) {
+        self.images.remove(&image);
+    }
+
+    fn push_render_image(&mut self, image: usize) {
+        self.save();
+        self.targets.push(image);
+        if let Some(img) = self.images.get_mut(&image) {
+            img.clear((0.0, 0.0, 0.0, 0.0));
+        }
+    }
+
+    fn pop_render_image(&mut self) {
+        self.targets.pop();
+        self.restore();
+    }
+
+    fn draw_image(&mut self, image: usize, x: f32, y: f32, w: f32, h: f32) {
+        let src = if let Some(src) = self.images.remove(&image) { src } else { return };
+
+        // Only translation and scaling are honored for images:
+        let (dx, dy) = self.state.apply((x, y));
+        let dw = w * self.state.xform[0];
+        let dh = h * self.state.xform[3];
+
+        if dw > 0.0 && dh > 0.0 && src.w > 0 && src.h > 0 {
+            let clip = self.state.clip;
+            let target = self.targets.last().copied();
+            let dst = match target {
+                Some(id) => self.images.get_mut(&id),
+                None => Some(&mut self.screen),
+            };
+
+            if let Some(dst) = dst {
+                let mut bounds = [dx, dy, dx + dw, dy + dh];
+                if let Some(clip) = clip {
+                    bounds[0] = bounds[0].max(clip[0]);
+                    bounds[1] = bounds[1].max(clip[1]);
+                    bounds[2] = bounds[2].min(clip[2]);
+                    bounds[3] = bounds[3].min(clip[3]);
+                }
+
+                let x0 = bounds[0].round().max(0.0) as usize;
+                let y0 = bounds[1].round().max(0.0) as usize;
+                let x1 = (bounds[2].round().max(0.0) as usize).min(dst.w);
+                let y1 = (bounds[3].round().max(0.0) as usize).min(dst.h);
+
+                for py in y0..y1 {
+                    let sy = (((py as f32 + 0.5 - dy) / dh) * src.h as f32) as usize;
+                    let sy = sy.min(src.h - 1);
+
+                    for px in x0..x1 {
+                        let sx = (((px as f32 + 0.5 - dx) / dw) * src.w as f32) as usize;
+                        let sx = sx.min(src.w - 1);
+
+                        let si = (sy * src.w + sx) * 4;
+                        let alpha = src.data[si + 3] as f32 / 255.0;
+                        if alpha > 0.0 {
+                            dst.blend(
+                                px,
+                                py,
+                                (
+                                    src.data[si] as f32 / 255.0,
+                                    src.data[si + 1] as f32 / 255.0,
+                                    src.data[si + 2] as f32 / 255.0,
+                                ),
+                                alpha,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        self.images.insert(image, src);
+    }
+
+    fn screenshot(&mut self) -> Option<(usize, usize, Vec<u8>)> {
+        let img = match self.targets.last() {
+            Some(id) => self.images.get(id)?,
+            None => &self.screen,
+        };
+        Some((img.w, img.h, img.data.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(sb: &SoftwareBackend, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * sb.width() + x) * 4;
+        let p = sb.pixels();
+        [p[i], p[i + 1], p[i + 2], p[i + 3]]
+    }
+
+    #[test]
+    fn check_sw_fill_rect() {
+        let mut sb = SoftwareBackend::new(20, 20);
+        sb.begin_frame((0.0, 0.0, 0.0));
+        sb.translate(2.0, 2.0);
+        sb.fill_rect((1.0, 0.0, 0.0), 2.0, 2.0, 4.0, 4.0);
+
+        assert_eq!(pixel(&sb, 5, 5), [255, 0, 0, 255]);
+        assert_eq!(pixel(&sb, 3, 3), [0, 0, 0, 255]);
+        assert_eq!(pixel(&sb, 8, 8), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn check_sw_clip_and_images() {
+        let mut sb = SoftwareBackend::new(20, 20);
+        sb.begin_frame((0.0, 0.0, 0.0));
+
+        let img = sb.create_image(10, 10).unwrap();
+        sb.push_render_image(img);
+        sb.fill_rect((0.0, 1.0, 0.0), 0.0, 0.0, 10.0, 10.0);
+        sb.pop_render_image();
+
+        sb.save();
+        sb.scissor(0.0, 0.0, 15.0, 15.0);
+        sb.draw_image(img, 10.0, 10.0, 10.0, 10.0);
+        sb.restore();
+
+        assert_eq!(pixel(&sb, 12, 12), [0, 255, 0, 255]);
+        assert_eq!(pixel(&sb, 17, 17), [0, 0, 0, 255]);
+        assert_eq!(pixel(&sb, 5, 5), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn check_sw_text() {
+        let mut sb = SoftwareBackend::new(100, 30);
+        sb.begin_frame((0.0, 0.0, 0.0));
+
+        let (w, h) = sb.measure_text(PainterFont::Default, 14.0, "Test").unwrap();
+        assert!(w > 10.0 && w < 60.0);
+        assert!(h > 10.0 && h < 30.0);
+
+        sb.fill_text(PainterFont::Default, 14.0, -1, (1.0, 1.0, 1.0), 5.0, 15.0, "Test");
+        assert!(sb.pixels().chunks(4).any(|p| p[0] > 128));
+    }
+}
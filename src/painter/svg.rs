@@ -0,0 +1,454 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use super::software::SwFont;
+use super::{PainterBackend, PainterFont};
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone)]
+struct SvgImage {
+    w: usize,
+    h: usize,
+    content: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SvgState {
+    /// Affine transformation: x' = a*x + c*y + e, y' = b*x + d*y + f
+    xform: [f32; 6],
+    /// Index of the current `<clipPath>` in the definitions.
+    clip: Option<usize>,
+}
+
+impl SvgState {
+    fn new() -> Self {
+        Self { xform: [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], clip: None }
+    }
+
+    fn apply(&self, p: (f32, f32)) -> (f32, f32) {
+        let m = &self.xform;
+        (m[0] * p.0 + m[2] * p.1 + m[4], m[1] * p.0 + m[3] * p.1 + m[5])
+    }
+
+    /// The factor the transformation scales lengths with.
+    fn scale(&self) -> f32 {
+        let m = &self.xform;
+        (m[0] * m[3] - m[1] * m[2]).abs().sqrt()
+    }
+}
+
+fn svg_color(color: (f32, f32, f32)) -> String {
+    let c = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", c(color.0), c(color.1), c(color.2))
+}
+
+/// A [PainterBackend] that records everything as SVG document.
+///
+/// All coordinates are transformed before they are written out, and the
+/// text is converted to glyph outlines with the same fonts the other backends
+/// use. That way the SVG looks like the rendered widget, even on systems
+/// without the fonts. Images loaded from files are not exported.
+///
+///```ignore
+///    let mut svg = SvgBackend::new(800.0, 600.0);
+///    let mut data = PersistPainterData::new();
+///    let p = &mut Painter::new(&mut svg, &mut data, 1.0);
+///    // ... draw with p ...
+///    std::fs::write("/tmp/out.svg", svg.to_svg()).expect("writes svg");
+///```
+pub struct SvgBackend {
+    w: f32,
+    h: f32,
+    defs: String,
+    clip_count: usize,
+    /// The output of the screen and of all images that are currently
+    /// rendered into with [PainterBackend::push_render_image].
+    outputs: Vec<String>,
+    targets: Vec<usize>,
+    images: HashMap<usize, SvgImage>,
+    next_image_id: usize,
+    state: SvgState,
+    states: Vec<SvgState>,
+    font: SwFont,
+    font_mono: SwFont,
+}
+
+impl SvgBackend {
+    pub fn new(w: f32, h: f32) -> Self {
+        Self {
+            w,
+            h,
+            defs: String::new(),
+            clip_count: 0,
+            outputs: vec![String::new()],
+            targets: vec![],
+            images: HashMap::new(),
+            next_image_id: 1,
+            state: SvgState::new(),
+            states: vec![],
+            font: SwFont::new(std::include_bytes!("../font.ttf")),
+            font_mono: SwFont::new(std::include_bytes!("../font_mono.ttf")),
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.w
+    }
+
+    pub fn height(&self) -> f32 {
+        self.h
+    }
+
+    /// Returns the complete SVG document of everything drawn so far.
+    pub fn to_svg(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        let _ = writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0:.0}\" height=\"{1:.0}\" \
+             viewBox=\"0 0 {0:.2} {1:.2}\">",
+            self.w, self.h
+        );
+        let _ = writeln!(out, "<defs>\n{}</defs>", self.defs);
+        out += &self.outputs[0];
+        out += "</svg>\n";
+
+        out
+    }
+
+    fn font(&self, font: PainterFont) -> &SwFont {
+        match font {
+            PainterFont::Default => &self.font,
+            PainterFont::Mono => &self.font_mono,
+        }
+    }
+
+    fn clip_attr(&self) -> String {
+        if let Some(clip) = self.state.clip {
+            format!(" clip-path=\"url(#clip{})\"", clip)
+        } else {
+            String::new()
+        }
+    }
+
+    fn path_data(&self, polys: &[Vec<(f32, f32)>], closed: bool) -> String {
+        let mut d = String::new();
+
+        for poly in polys.iter() {
+            for (i, p) in poly.iter().enumerate() {
+                let (x, y) = self.state.apply(*p);
+                let cmd = if i == 0 { 'M' } else { 'L' };
+                let _ = write!(d, "{}{:.2} {:.2} ", cmd, x, y);
+            }
+
+            if closed {
+                d += "Z ";
+            }
+        }
+
+        d.trim_end().to_string()
+    }
+
+    fn emit(&mut self, element: String) {
+        if let Some(out) = self.outputs.last_mut() {
+            *out += &element;
+            out.push('\n');
+        }
+    }
+
+    fn emit_fill(&mut self, color: (f32, f32, f32), polys: &[Vec<(f32, f32)>]) {
+        let d = self.path_data(polys, true);
+        if d.is_empty() {
+            return;
+        }
+
+        let element =
+            format!("<path d=\"{}\" fill=\"{}\"{}/>", d, svg_color(color), self.clip_attr());
+        self.emit(element);
+    }
+
+    fn emit_stroke(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        path: &[(f32, f32)],
+        closed: bool,
+        join: &str,
+    ) {
+        if path.len() < 2 {
+            return;
+        }
+
+        let d = self.path_data(&[path.to_vec()], closed);
+        let element = format!(
+            "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.2}\" \
+             stroke-linejoin=\"{}\"{}/>",
+            d,
+            svg_color(color),
+            width * self.state.scale(),
+            join,
+            self.clip_attr()
+        );
+        self.emit(element);
+    }
+}
+
+impl PainterBackend for SvgBackend {
+    fn save(&mut self) {
+        self.states.push(self.state);
+    }
+
+    fn restore(&mut self) {
+        self.state = self.states.pop().unwrap_or_else(SvgState::new);
+    }
+
+    fn translate(&mut self, x: f32, y: f32) {
+        let m = &mut self.state.xform;
+        m[4] += m[0] * x + m[2] * y;
+        m[5] += m[1] * x + m[3] * y;
+    }
+
+    fn rotate(&mut self, rad: f32) {
+        let (s, c) = rad.sin_cos();
+        let m = self.state.xform;
+        self.state.xform = [
+            m[0] * c + m[2] * s,
+            m[1] * c + m[3] * s,
+            m[2] * c - m[0] * s,
+            m[3] * c - m[1] * s,
+            m[4],
+            m[5],
+        ];
+    }
+
+    fn scissor(&mut self, x: f32, y: f32, w: f32, h: f32) {
+        let corners = [
+            self.state.apply((x, y)),
+            self.state.apply((x + w, y)),
+            self.state.apply((x + w, y + h)),
+            self.state.apply((x, y + h)),
+        ];
+
+        let mut clip = [std::f32::MAX, std::f32::MAX, std::f32::MIN, std::f32::MIN];
+        for c in corners.iter() {
+            clip[0] = clip[0].min(c.0);
+            clip[1] = clip[1].min(c.1);
+            clip[2] = clip[2].max(c.0);
+            clip[3] = clip[3].max(c.1);
+        }
+
+        let id = self.clip_count;
+        self.clip_count += 1;
+
+        let _ = writeln!(
+            self.defs,
+            "<clipPath id=\"clip{}\"><rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" \
+             height=\"{:.2}\"/></clipPath>",
+            id,
+            clip[0],
+            clip[1],
+            clip[2] - clip[0],
+            clip[3] - clip[1]
+        );
+
+        self.state.clip = Some(id);
+    }
+
+    fn reset_scissor(&mut self) {
+        self.state.clip = None;
+    }
+
+    fn fill_path(&mut self, color: (f32, f32, f32), path: &[(f32, f32)], _closed: bool) {
+        if path.len() < 3 {
+            return;
+        }
+        self.emit_fill(color, &[path.to_vec()]);
+    }
+
+    fn stroke_path(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        path: &[(f32, f32)],
+        closed: bool,
+    ) {
+        self.emit_stroke(width, color, path, closed, "round");
+    }
+
+    fn stroke_arc(
+        &mut self,
+        width: f32,
+        color: (f32, f32, f32),
+        radius: f32,
+        from_rad: f32,
+        to_rad: f32,
+        x: f32,
+        y: f32,
+    ) {
+        let tau = std::f32::consts::TAU;
+        let mut da = to_rad - from_rad;
+        if da.abs() >= tau {
+            da = tau;
+        } else {
+            while da < 0.0 {
+                da += tau;
+            }
+        }
+
+        let segs = ((da / (std::f32::consts::PI / 16.0)).ceil() as usize).max(4);
+        let mut path = Vec::with_capacity(segs + 1);
+        for i in 0..=segs {
+            let phi = from_rad + da * (i as f32 / segs as f32);
+            path.push((x + radius * phi.cos(), y + radius * phi.sin()));
+        }
+
+        self.emit_stroke(width, color, &path[..], false, "round");
+    }
+
+    fn fill_rect(&mut self, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) {
+        self.emit_fill(color, &[vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)]]);
+    }
+
+    fn stroke_rect(&mut self, width: f32, color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32) {
+        let path = [(x, y), (x + w, y), (x + w, y + h), (x, y + h)];
+        self.emit_stroke(width, color, &path[..], true, "miter");
+    }
+
+    fn fill_text(
+        &mut self,
+        font: PainterFont,
+        size: f32,
+        align: i8,
+        color: (f32, f32, f32),
+        x: f32,
+        y: f32,
+        text: &str,
+    ) {
+        let fnt = self.font(font);
+        let w = fnt.text_width(size, text);
+        let x = match align {
+            -1 => x,
+            0 => x - w * 0.5,
+            _ => x - w,
+        };
+
+        let mut polys = vec![];
+        fnt.outline(size, x, y + fnt.middle_baseline_offs(size), text, &mut polys);
+        self.emit_fill(color, &polys[..]);
+    }
+
+    fn measure_text(&mut self, font: PainterFont, size: f32, text: &str) -> Option<(f32, f32)> {
+        let fnt = self.font(font);
+        Some((fnt.text_width(size, text), fnt.height(size)))
+    }
+
+    fn font_height(&mut self, font: PainterFont, size: f32) -> Option<f32> {
+        Some(self.font(font).height(size))
+    }
+
+    fn create_image(&mut self, w: usize, h: usize) -> Option<usize> {
+        let id = self.next_image_id;
+        self.next_image_id += 1;
+        self.images.insert(id, SvgImage { w, h, content: String::new() });
+        Some(id)
+    }
+
+    fn load_image(&mut self, _data: &[u8]) -> Option<usize> {
+        None
+    }
+
+    fn image_size(&mut self, image: usize) -> Option<(usize, usize)> {
+        self.images.get(&image).map(|img| (img.w, img.h))
+    }
+
+    fn delete_image(&mut self, image: usize) {
+        self.images.remove(&image);
+    }
+
+    fn push_render_image(&mut self, image: usize) {
+        self.save();
+        self.targets.push(image);
+        self.outputs.push(String::new());
+    }
+
+    fn pop_render_image(&mut self) {
+        if let Some(image) = self.targets.pop() {
+            let content = self.outputs.pop().unwrap_or_default();
+            if let Some(img) = self.images.get_mut(&image) {
+                img.content = content;
+            }
+        }
+
+        self.restore();
+    }
+
+    fn draw_image(&mut self, image: usize, x: f32, y: f32, w: f32, h: f32) {
+        let (iw, ih, content) = if let Some(img) = self.images.get(&image) {
+            (img.w, img.h, img.content.clone())
+        } else {
+            return;
+        };
+
+        if iw == 0 || ih == 0 {
+            return;
+        }
+
+        let (sx, sy) = (w / iw as f32, h / ih as f32);
+        let m = &self.state.xform;
+        let (ox, oy) = self.state.apply((x, y));
+
+        // The clip-path is applied outside of the transformation of the image:
+        let element = format!(
+            "<g{}><g transform=\"matrix({:.4} {:.4} {:.4} {:.4} {:.2} {:.2})\">\n{}</g></g>",
+            self.clip_attr(),
+            m[0] * sx,
+            m[1] * sx,
+            m[2] * sy,
+            m[3] * sy,
+            ox,
+            oy,
+            content
+        );
+        self.emit(element);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_svg_backend_elements() {
+        let mut svg = SvgBackend::new(100.0, 50.0);
+
+        svg.save();
+        svg.translate(10.0, 5.0);
+        svg.scissor(0.0, 0.0, 20.0, 20.0);
+        svg.fill_rect((1.0, 0.0, 0.0), 0.0, 0.0, 10.0, 10.0);
+        svg.restore();
+        svg.stroke_path(2.0, (0.0, 1.0, 0.0), &[(0.0, 0.0), (5.0, 5.0)], false);
+
+        let out = svg.to_svg();
+        assert!(out.contains("width=\"100\" height=\"50\""));
+        assert!(out.contains("<clipPath id=\"clip0\"><rect x=\"10.00\" y=\"5.00\""));
+        assert!(out.contains(
+            "<path d=\"M10.00 5.00 L20.00 5.00 L20.00 15.00 L10.00 15.00 Z\" \
+             fill=\"#ff0000\" clip-path=\"url(#clip0)\"/>"
+        ));
+        assert!(out.contains("<path d=\"M0.00 0.00 L5.00 5.00\" fill=\"none\" stroke=\"#00ff00\""));
+    }
+
+    #[test]
+    fn check_svg_backend_text_outlines() {
+        let mut svg = SvgBackend::new(100.0, 50.0);
+        svg.fill_text(PainterFont::Default, 14.0, -1, (1.0, 1.0, 1.0), 0.0, 10.0, "Hx");
+
+        let out = svg.to_svg();
+        assert!(out.contains("fill=\"#ffffff\""));
+        assert!(!out.contains("<text"));
+    }
+}
@@ -32,6 +32,7 @@ pub const UI_HLIGHT2_CLR: (f32, f32, f32) = hxclr!(0xbcf9cd); // b5c412
 pub const UI_SELECT_CLR: (f32, f32, f32) = hxclr!(0xd73988); // 0xdc1821);
 pub const UI_INACTIVE_CLR: (f32, f32, f32) = hxclr!(0x6f8782);
 pub const UI_INACTIVE2_CLR: (f32, f32, f32) = hxclr!(0xa6dbd0);
+pub const UI_ERROR_CLR: (f32, f32, f32) = hxclr!(0xf2545b);
 
 pub fn get_ui_colors() -> Vec<(&'static str, (f32, f32, f32))> {
     vec![
@@ -53,6 +54,7 @@ pub fn get_ui_colors() -> Vec<(&'static str, (f32, f32, f32))> {
         ("UI_SELECT_CLR", UI_SELECT_CLR),
         ("UI_INACTIVE_CLR", UI_INACTIVE_CLR),
         ("UI_INACTIVE2_CLR", UI_INACTIVE2_CLR),
+        ("UI_ERROR_CLR", UI_ERROR_CLR),
     ]
 }
 
@@ -153,6 +155,9 @@ pub struct Style {
     pub active_color: (f32, f32, f32),
     pub inactive_color: (f32, f32, f32),
     pub selected_color: (f32, f32, f32),
+    pub focus_color: (f32, f32, f32),
+    pub invalid_color: (f32, f32, f32),
+    pub focus_ring: f32,
     pub text_align: Align,
     pub text_valign: VAlign,
     pub font_size: f32,
@@ -189,6 +194,9 @@ impl Style {
             active_color: UI_HLIGHT2_CLR,
             inactive_color: UI_INACTIVE_CLR,
             selected_color: UI_SELECT_CLR,
+            focus_color: UI_PRIM_CLR,
+            invalid_color: UI_ERROR_CLR,
+            focus_ring: 1.0,
             text_align: Align::Center,
             text_valign: VAlign::Middle,
             font_size: 14.0,
@@ -365,6 +373,7 @@ impl<'a> DPIStyle<'a> {
     dpi_accessor! {pad_bottom}
     dpi_accessor! {pad_item}
     dpi_accessor! {font_size}
+    dpi_accessor! {focus_ring}
 
     dpi_ext_accessor! {StyleExt::Graph, graph_line, 0.9}
     dpi_ext_accessor! {StyleExt::Graph, vline1, 1.0}
@@ -389,6 +398,8 @@ impl<'a> DPIStyle<'a> {
     color_accessor! {active_color}
     color_accessor! {inactive_color}
     color_accessor! {selected_color}
+    color_accessor! {focus_color}
+    color_accessor! {invalid_color}
 
     color_ext_accessor! {StyleExt::Graph, hline_color, UI_ACCENT_CLR}
     color_ext_accessor! {StyleExt::Graph, vline1_color, UI_PRIM2_CLR}
@@ -0,0 +1,622 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use crate::style::*;
+
+/// A parsed stylesheet, that is resolved into [Style] values per widget.
+///
+/// The format is a small subset of CSS:
+///
+///```text
+///    /* Applies to all widgets: */
+///    * { font_size: 14; }
+///
+///    HexKnob, Button { bg_color: #414a51; pad: 2; }
+///    Button:hover { color: UI_HLIGHT_CLR; }
+///    #main_grid { border_style: hex(10); }
+///    Graph#env_graph { graph_line: 1.5; vline1_color: #ffffff; }
+///```
+///
+/// A selector consists of an optional control type (one of
+/// [crate::Control::TYPE_NAMES], for instance `Button` or `HexGrid`,
+/// or a custom widget type passed to [StyleSheet::parse_with_types]),
+/// an optional `#tag` (see [crate::Widget::set_tag]) and an optional
+/// state `:hover` or `:active`.
+/// The states write the `hover_*` and `active_*` fields of the [Style],
+/// so `Button:hover { color: ... }` sets `hover_color`.
+///
+/// Rules with a tag win over rules with only a type, later rules win over
+/// earlier ones. The property names are the field names of [Style] and
+/// [StyleExt]. Colors are written as `#rrggbb`, `#rgb`, `rgb(r, g, b)`
+/// with values 0-255 or by the name of a constant like `UI_PRIM_CLR`.
+#[derive(Debug, Clone)]
+pub struct StyleSheet {
+    rules: Vec<StyleRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StyleSheetError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl std::fmt::Display for StyleSheetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stylesheet error in line {}: {}", self.line, self.msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StyleState {
+    Normal,
+    Hover,
+    Active,
+}
+
+#[derive(Debug, Clone)]
+struct Selector {
+    typ: Option<String>,
+    tag: Option<String>,
+    state: StyleState,
+}
+
+impl Selector {
+    fn parse(s: &str, custom_types: &[&str]) -> Result<Self, String> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            return Err("empty selector".to_string());
+        }
+
+        let (s, state) = match s.split_once(':') {
+            Some((s, "hover")) => (s, StyleState::Hover),
+            Some((s, "active")) => (s, StyleState::Active),
+            Some((_, state)) => return Err(format!("unknown state ':{}'", state)),
+            None => (s, StyleState::Normal),
+        };
+
+        let (typ, tag) = match s.split_once('#') {
+            Some((typ, tag)) => (typ, Some(tag)),
+            None => (s, None),
+        };
+
+        let typ = match typ {
+            "" | "*" => None,
+            typ => {
+                if !typ.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(format!("bad control type '{}'", typ));
+                }
+                if !crate::Control::TYPE_NAMES.contains(&typ) && !custom_types.contains(&typ) {
+                    return Err(format!("unknown control type '{}'", typ));
+                }
+                Some(typ.to_string())
+            }
+        };
+
+        let tag = match tag {
+            Some("") => return Err("empty tag after '#'".to_string()),
+            Some(tag) => {
+                if tag.contains(char::is_whitespace) {
+                    return Err(format!("bad tag '{}'", tag));
+                }
+                Some(tag.to_string())
+            }
+            None => None,
+        };
+
+        Ok(Self { typ, tag, state })
+    }
+
+    fn specificity(&self) -> usize {
+        (if self.typ.is_some() { 1 } else { 0 }) + (if self.tag.is_some() { 2 } else { 0 })
+    }
+
+    fn matches(&self, type_name: &str, tag: &str) -> bool {
+        self.typ.as_ref().map(|t| t == type_name).unwrap_or(true)
+            && self.tag.as_ref().map(|t| t == tag).unwrap_or(true)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct StyleDecl {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Clone)]
+struct StyleRule {
+    selector: Selector,
+    decls: Vec<StyleDecl>,
+}
+
+fn count_lines(s: &str) -> usize {
+    s.chars().filter(|c| *c == '\n').count()
+}
+
+/// Replaces comments by spaces, keeping the newlines for the line numbers.
+fn strip_comments(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut chars = src.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek().copied()) {
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                out.push(' ');
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+impl StyleSheet {
+    pub fn new() -> Self {
+        Self { rules: vec![] }
+    }
+
+    pub fn parse(src: &str) -> Result<Self, StyleSheetError> {
+        Self::parse_with_types(src, &[])
+    }
+
+    /// Like [StyleSheet::parse], but also accepts the `custom_types`
+    /// in the selectors, see [crate::CustomWidget::type_name].
+    pub fn parse_with_types(src: &str, custom_types: &[&str]) -> Result<Self, StyleSheetError> {
+        let src = strip_comments(src);
+        let mut rules = vec![];
+
+        let mut rest = &src[..];
+        let mut line = 1;
+
+        while !rest.trim().is_empty() {
+            let err_line = line + count_lines(&rest[..(rest.len() - rest.trim_start().len())]);
+            let err = |msg: &str| StyleSheetError { line: err_line, msg: msg.to_string() };
+
+            let open = rest.find('{').ok_or_else(|| err("expected '{'"))?;
+            let close = rest.find('}').ok_or_else(|| err("missing '}'"))?;
+            if close < open {
+                return Err(err("unexpected '}'"));
+            }
+
+            let body = &rest[(open + 1)..close];
+            if body.contains('{') {
+                return Err(err("missing '}'"));
+            }
+
+            let mut selectors = vec![];
+            for sel in rest[..open].split(',') {
+                selectors.push(Selector::parse(sel, custom_types).map_err(|msg| err(&msg))?);
+            }
+
+            let mut decls = vec![];
+            let mut decl_line = line + count_lines(&rest[..(open + 1)]);
+
+            for decl in body.split(';') {
+                let this_line =
+                    decl_line + count_lines(&decl[..(decl.len() - decl.trim_start().len())]);
+                decl_line += count_lines(decl);
+
+                let decl = decl.trim();
+                if decl.is_empty() {
+                    continue;
+                }
+
+                let (name, value) = decl.split_once(':').ok_or_else(|| StyleSheetError {
+                    line: this_line,
+                    msg: format!("expected 'property: value', got '{}'", decl),
+                })?;
+                let decl =
+                    StyleDecl { name: name.trim().to_string(), value: value.trim().to_string() };
+
+                // Check the declaration early, so that errors come with a line number:
+                let mut scratch = Style::new();
+                for sel in selectors.iter() {
+                    apply_decl(&mut scratch, sel.state, &decl.name, &decl.value)
+                        .map_err(|msg| StyleSheetError { line: this_line, msg })?;
+                }
+
+                decls.push(decl);
+            }
+
+            for selector in selectors {
+                rules.push(StyleRule { selector, decls: decls.clone() });
+            }
+
+            line += count_lines(&rest[..(close + 1)]);
+            rest = &rest[(close + 1)..];
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: &str) -> Result<Self, StyleSheetError> {
+        Self::load_with_types(path, &[])
+    }
+
+    /// Like [StyleSheet::load], see also [StyleSheet::parse_with_types].
+    pub fn load_with_types(path: &str, custom_types: &[&str]) -> Result<Self, StyleSheetError> {
+        let src = std::fs::read_to_string(path).map_err(|e| StyleSheetError {
+            line: 0,
+            msg: format!("can't read '{}': {}", path, e),
+        })?;
+
+        Self::parse_with_types(&src, custom_types)
+    }
+
+    /// Applies all rules matching the control `type_name` and the widget
+    /// `tag` on top of `base`. Returns `None` if no rule matches.
+    pub fn resolve(&self, base: &Style, type_name: &str, tag: &str) -> Option<Style> {
+        let mut matching: Vec<&StyleRule> =
+            self.rules.iter().filter(|r| r.selector.matches(type_name, tag)).collect();
+
+        if matching.is_empty() {
+            return None;
+        }
+
+        // The sort is stable, so the source order is kept for equal specificity:
+        matching.sort_by_key(|r| r.selector.specificity());
+
+        let mut style = base.clone();
+        for rule in matching {
+            for decl in rule.decls.iter() {
+                let _ = apply_decl(&mut style, rule.selector.state, &decl.name, &decl.value);
+            }
+        }
+
+        Some(style)
+    }
+}
+
+fn parse_num(value: &str) -> Result<f32, String> {
+    value.parse::<f32>().map_err(|_| format!("expected a number, got '{}'", value))
+}
+
+fn parse_nums(value: &str, count: usize) -> Result<Vec<f32>, String> {
+    let nums = value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(parse_num)
+        .collect::<Result<Vec<f32>, String>>()?;
+
+    if nums.len() != count {
+        return Err(format!("expected {} numbers, got '{}'", count, value));
+    }
+
+    Ok(nums)
+}
+
+/// Splits `name(args)` into `name` and `args`.
+fn parse_call(value: &str) -> Option<(&str, &str)> {
+    let (name, args) = value.split_once('(')?;
+    Some((name.trim(), args.strip_suffix(')')?))
+}
+
+fn parse_color(value: &str) -> Result<(f32, f32, f32), String> {
+    let err = || format!("expected a color, got '{}'", value);
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let hex = if hex.len() == 3 {
+            hex.chars().flat_map(|c| [c, c]).collect::<String>()
+        } else {
+            hex.to_string()
+        };
+
+        if hex.len() != 6 {
+            return Err(err());
+        }
+
+        let i = u32::from_str_radix(&hex, 16).map_err(|_| err())?;
+        return Ok(crate::hxclr!(i));
+    }
+
+    if let Some(("rgb", args)) = parse_call(value) {
+        let c = parse_nums(args, 3).map_err(|_| err())?;
+        return Ok((c[0] / 255.0, c[1] / 255.0, c[2] / 255.0));
+    }
+
+    get_ui_colors().iter().find(|(name, _)| *name == value).map(|(_, clr)| *clr).ok_or_else(err)
+}
+
+fn parse_border_style(value: &str) -> Result<BorderStyle, String> {
+    match value {
+        "rect" => return Ok(BorderStyle::Rect),
+        "hex" => return Ok(BorderStyle::Hex { offset: 0.0 }),
+        _ => (),
+    }
+
+    match parse_call(value) {
+        Some(("hex", args)) => Ok(BorderStyle::Hex { offset: parse_num(args.trim())? }),
+        Some(("bevel", args)) => {
+            let o = parse_nums(args, 4)?;
+            Ok(BorderStyle::Bevel { corner_offsets: (o[0], o[1], o[2], o[3]) })
+        }
+        _ => Err(format!("expected rect, hex(offs) or bevel(a b c d), got '{}'", value)),
+    }
+}
+
+fn ensure_graph_ext(ext: &mut StyleExt) {
+    if !matches!(ext, StyleExt::Graph { .. }) {
+        *ext = StyleExt::Graph {
+            graph_line: 0.9,
+            vline1: 1.0,
+            vline2: 1.0,
+            vline1_color: UI_PRIM2_CLR,
+            vline2_color: UI_PRIM_CLR,
+            hline: 0.0,
+            hline_color: UI_ACCENT_CLR,
+        };
+    }
+}
+
+fn ensure_pattern_editor_ext(ext: &mut StyleExt) {
+    if !matches!(ext, StyleExt::PatternEditor { .. }) {
+        *ext = StyleExt::PatternEditor { row_height: 14.0, col_width: 38.0, col_div_pad: 3.0 };
+    }
+}
+
+fn ensure_block_code_ext(ext: &mut StyleExt) {
+    if !matches!(ext, StyleExt::BlockCode { .. }) {
+        *ext = StyleExt::BlockCode {
+            with_markers: false,
+            grid_marker_color: UI_ACCENT_DARK_CLR,
+            block_bg_hover_color: UI_ACCENT_CLR,
+            block_bg_color: UI_ACCENT_BG2_CLR,
+            port_select_color: UI_SELECT_CLR,
+        };
+    }
+}
+
+fn apply_decl(style: &mut Style, state: StyleState, name: &str, value: &str) -> Result<(), String> {
+    let state_name;
+    let name = match state {
+        StyleState::Normal => name,
+        StyleState::Hover | StyleState::Active => {
+            let prefix = if state == StyleState::Hover { "hover" } else { "active" };
+            match name {
+                "color" | "border_color" | "shadow_color" => {
+                    state_name = format!("{}_{}", prefix, name);
+                    &state_name[..]
+                }
+                _ => return Err(format!("property '{}' can't be used with :{}", name, prefix)),
+            }
+        }
+    };
+
+    match name {
+        "bg_color" => style.bg_color = parse_color(value)?,
+        "border_color" => style.border_color = parse_color(value)?,
+        "border2_color" => style.border2_color = parse_color(value)?,
+        "color" => style.color = parse_color(value)?,
+        "color2" => style.color2 = parse_color(value)?,
+        "shadow_color" => style.shadow_color = parse_color(value)?,
+        "hover_shadow_color" => style.hover_shadow_color = parse_color(value)?,
+        "hover_border_color" => style.hover_border_color = parse_color(value)?,
+        "hover_color" => style.hover_color = parse_color(value)?,
+        "active_shadow_color" => style.active_shadow_color = parse_color(value)?,
+        "active_border_color" => style.active_border_color = parse_color(value)?,
+        "active_color" => style.active_color = parse_color(value)?,
+        "inactive_color" => style.inactive_color = parse_color(value)?,
+        "selected_color" => style.selected_color = parse_color(value)?,
+        "focus_color" => style.focus_color = parse_color(value)?,
+        "invalid_color" => style.invalid_color = parse_color(value)?,
+        "border" => style.border = parse_num(value)?,
+        "border2" => style.border2 = parse_num(value)?,
+        "line" => style.line = parse_num(value)?,
+        "pad_left" => style.pad_left = parse_num(value)?,
+        "pad_right" => style.pad_right = parse_num(value)?,
+        "pad_top" => style.pad_top = parse_num(value)?,
+        "pad_bottom" => style.pad_bottom = parse_num(value)?,
+        "pad_item" => style.pad_item = parse_num(value)?,
+        "pad" => {
+            let pad = parse_num(value)?;
+            style.pad_left = pad;
+            style.pad_right = pad;
+            style.pad_top = pad;
+            style.pad_bottom = pad;
+        }
+        "font_size" => style.font_size = parse_num(value)?,
+        "focus_ring" => style.focus_ring = parse_num(value)?,
+        "shadow_offs" => {
+            let offs = parse_nums(value, 2)?;
+            style.shadow_offs = (offs[0], offs[1]);
+        }
+        "border_style" => style.border_style = parse_border_style(value)?,
+        "text_align" => {
+            style.text_align = match value {
+                "center" => Align::Center,
+                "left" => Align::Left,
+                "right" => Align::Right,
+                _ => return Err(format!("expected center, left or right, got '{}'", value)),
+            }
+        }
+        "text_valign" => {
+            style.text_valign = match value {
+                "middle" => VAlign::Middle,
+                "top" => VAlign::Top,
+                "bottom" => VAlign::Bottom,
+                _ => return Err(format!("expected middle, top or bottom, got '{}'", value)),
+            }
+        }
+        "colors" => {
+            let colors = value
+                .split_whitespace()
+                .map(parse_color)
+                .collect::<Result<Vec<(f32, f32, f32)>, String>>()?;
+            if colors.is_empty() {
+                return Err("expected at least one color".to_string());
+            }
+            style.colors = colors;
+        }
+        "graph_line" | "vline1" | "vline2" | "hline" => {
+            let v = parse_num(value)?;
+            ensure_graph_ext(&mut style.ext);
+            if let StyleExt::Graph { graph_line, vline1, vline2, hline, .. } = &mut style.ext {
+                let field = match name {
+                    "graph_line" => graph_line,
+                    "vline1" => vline1,
+                    "vline2" => vline2,
+                    _ => hline,
+                };
+                *field = v;
+            }
+        }
+        "vline1_color" | "vline2_color" | "hline_color" => {
+            let clr = parse_color(value)?;
+            ensure_graph_ext(&mut style.ext);
+            if let StyleExt::Graph { vline1_color, vline2_color, hline_color, .. } = &mut style.ext
+            {
+                let field = match name {
+                    "vline1_color" => vline1_color,
+                    "vline2_color" => vline2_color,
+                    _ => hline_color,
+                };
+                *field = clr;
+            }
+        }
+        "row_height" | "col_width" | "col_div_pad" => {
+            let v = parse_num(value)?;
+            ensure_pattern_editor_ext(&mut style.ext);
+            if let StyleExt::PatternEditor { row_height, col_width, col_div_pad } = &mut style.ext {
+                let field = match name {
+                    "row_height" => row_height,
+                    "col_width" => col_width,
+                    _ => col_div_pad,
+                };
+                *field = v;
+            }
+        }
+        "with_markers" => {
+            let v = match value {
+                "true" => true,
+                "false" => false,
+                _ => return Err(format!("expected true or false, got '{}'", value)),
+            };
+            ensure_block_code_ext(&mut style.ext);
+            if let StyleExt::BlockCode { with_markers, .. } = &mut style.ext {
+                *with_markers = v;
+            }
+        }
+        "grid_marker_color" | "block_bg_hover_color" | "block_bg_color" | "port_select_color" => {
+            let clr = parse_color(value)?;
+            ensure_block_code_ext(&mut style.ext);
+            if let StyleExt::BlockCode {
+                grid_marker_color,
+                block_bg_hover_color,
+                block_bg_color,
+                port_select_color,
+                ..
+            } = &mut style.ext
+            {
+                let field = match name {
+                    "grid_marker_color" => grid_marker_color,
+                    "block_bg_hover_color" => block_bg_hover_color,
+                    "block_bg_color" => block_bg_color,
+                    _ => port_select_color,
+                };
+                *field = clr;
+            }
+        }
+        _ => return Err(format!("unknown property '{}'", name)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_stylesheet_resolve() {
+        let sheet = StyleSheet::parse(
+            r#"
+            /* base */
+            * { font_size: 10; }
+            #knob1 { font_size: 20; }
+            HexKnob { font_size: 15; color: #ff0000; }
+            HexKnob:hover { color: #0f0; }
+            Button, Label { pad: 3; text_align: left; }
+            "#,
+        )
+        .expect("parses");
+
+        let base = Style::new();
+
+        let knob = sheet.resolve(&base, "HexKnob", "knob1").unwrap();
+        assert_eq!(knob.font_size, 20.0);
+        assert_eq!(knob.color, (1.0, 0.0, 0.0));
+        assert_eq!(knob.hover_color, (0.0, 1.0, 0.0));
+
+        let knob = sheet.resolve(&base, "HexKnob", "").unwrap();
+        assert_eq!(knob.font_size, 15.0);
+
+        let lbl = sheet.resolve(&base, "Label", "").unwrap();
+        assert_eq!(lbl.pad_left, 3.0);
+        assert_eq!(lbl.pad_bottom, 3.0);
+        assert!(matches!(lbl.text_align, Align::Left));
+
+        let sheet = StyleSheet::parse("Entry { color: #fff; }").unwrap();
+        assert!(sheet.resolve(&base, "Button", "").is_none());
+    }
+
+    #[test]
+    fn check_stylesheet_ext() {
+        let sheet = StyleSheet::parse(
+            "Graph#env { graph_line: 2.5; hline_color: UI_PRIM_CLR; border_style: bevel(1 2 3 4); }",
+        )
+        .unwrap();
+
+        let graph = sheet.resolve(&Style::new(), "Graph", "env").unwrap();
+        assert!(matches!(graph.ext, StyleExt::Graph { graph_line, .. } if graph_line == 2.5));
+        assert!(matches!(
+            graph.border_style,
+            BorderStyle::Bevel { corner_offsets: (_, _, _, d) } if d == 4.0
+        ));
+    }
+
+    #[test]
+    fn check_stylesheet_errors() {
+        let err = StyleSheet::parse("Button {\n  color: #fff;\n  colr: #000;\n}").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.msg.contains("colr"));
+
+        let err = StyleSheet::parse("\n\nButton:hover { font_size: 10; }").unwrap_err();
+        assert_eq!(err.line, 3);
+
+        let err = StyleSheet::parse("Button:pressed { color: #fff; }").unwrap_err();
+        assert!(err.msg.contains("pressed"));
+
+        let err = StyleSheet::parse("\nButon { color: #fff; }").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.msg.contains("Buton"));
+
+        assert!(StyleSheet::parse("MyWidget { color: #fff; }").is_err());
+        let sheet =
+            StyleSheet::parse_with_types("MyWidget { color: #fff; }", &["MyWidget"]).unwrap();
+        assert!(sheet.resolve(&Style::new(), "MyWidget", "").is_some());
+        assert!(StyleSheet::parse("Custom#foo { color: #fff; }").is_ok());
+
+        assert!(StyleSheet::parse("Button { color: #ff; }").is_err());
+        assert!(StyleSheet::parse("Button { color: #fff; ").is_err());
+    }
+}
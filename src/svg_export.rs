@@ -0,0 +1,163 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use crate::painter::{Painter, PersistPainterData, SvgBackend};
+use crate::style::{DPIStyle, Style};
+use crate::widgets::{BlockCode, HexGrid, HexGridModel};
+use crate::{Rect, Widget};
+
+use crate::blocklang::BlockCodeView;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// Draws the `draw` function into an SVG document of the given size.
+/// Works without a window or GL context, see also [SvgBackend].
+fn render_svg<F: FnOnce(&Widget, &DPIStyle, Rect, &mut Painter)>(
+    style: &Style,
+    w: f32,
+    h: f32,
+    draw: F,
+) -> String {
+    let mut backend = SvgBackend::new(w.ceil(), h.ceil());
+    let mut painter_data = PersistPainterData::new();
+
+    let style = Rc::new(style.clone());
+    let widget = Widget::new(style.clone());
+    let dpi_style = DPIStyle::new_from(1.0, &style);
+
+    {
+        let painter = &mut Painter::new(&mut backend, &mut painter_data, 1.0);
+        draw(&widget, &dpi_style, Rect::from(0.0, 0.0, w.ceil(), h.ceil()), painter);
+    }
+
+    backend.to_svg()
+}
+
+/// Exports all cells of the `model` as SVG document, with the same
+/// layout, labels, edges and colors the [HexGrid] draws at the default zoom.
+///
+///```ignore
+///    let svg = hexgrid_to_svg(model, &Style::new());
+///    std::fs::write("patch.svg", svg).expect("writes svg");
+///```
+pub fn hexgrid_to_svg(model: Rc<RefCell<dyn HexGridModel>>, style: &Style) -> String {
+    let mut grid = HexGrid::new(model);
+    grid.stop_flow_animation();
+    let ext = grid.grid_extent();
+
+    render_svg(style, ext.w, ext.h, |w, style, pos, p| {
+        grid.draw(w, style, pos, pos, p);
+        // The edge arrows and LEDs are drawn per frame:
+        grid.draw_frame(w, style, p);
+    })
+}
+
+/// Exports the code as SVG document, with the same layout the
+/// [BlockCode] widget draws.
+pub fn blockcode_to_svg(code: Arc<Mutex<dyn BlockCodeView>>, style: &Style) -> String {
+    let mut block_code = BlockCode::new(code);
+    let (w, h) = block_code.export_size();
+
+    render_svg(style, w, h, |widget, style, pos, p| {
+        block_code.draw(widget, style, pos, pos, p);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocklang::{BlockFun, BlockLanguage, BlockType};
+    use crate::widgets::{HexCell, HexDir, HexEdge, HexHLight};
+    use std::time::{Duration, Instant};
+
+    struct FlowGrid;
+
+    impl HexGridModel for FlowGrid {
+        fn width(&self) -> usize {
+            2
+        }
+        fn height(&self) -> usize {
+            2
+        }
+        fn cell_visible(&self, _x: usize, _y: usize) -> bool {
+            true
+        }
+        fn cell_empty(&self, x: usize, y: usize) -> bool {
+            (x, y) != (0, 0)
+        }
+        fn cell_led(&self, _x: usize, _y: usize) -> Option<(f32, f32)> {
+            None
+        }
+        fn cell_label<'a>(&self, x: usize, y: usize, _out: &'a mut [u8]) -> Option<HexCell<'a>> {
+            if (x, y) != (0, 0) {
+                return None;
+            }
+
+            Some(HexCell { label: "Sin", hlight: HexHLight::Normal })
+        }
+        fn cell_edge(&self, _x: usize, _y: usize, _edge: HexDir) -> HexEdge {
+            HexEdge::NoArrow
+        }
+        fn cell_edge_activity(&self, x: usize, y: usize, _edge: HexDir) -> Option<f32> {
+            if (x, y) == (0, 0) {
+                Some(0.5)
+            } else {
+                None
+            }
+        }
+        fn cell_edge_label<'a>(
+            &self,
+            _x: usize,
+            _y: usize,
+            _edge: HexDir,
+            _buf: &'a mut [u8],
+        ) -> Option<&'a str> {
+            None
+        }
+        fn get_generation(&self) -> u64 {
+            1
+        }
+    }
+
+    #[test]
+    fn check_hexgrid_to_svg_stable() {
+        let model = Rc::new(RefCell::new(FlowGrid));
+        let style = Style::new();
+
+        // The flow dashes of the export don't move with the time:
+        let mut grid = HexGrid::new(model.clone());
+        grid.stop_flow_animation();
+        let later = Instant::now() + Duration::from_secs(1);
+        assert_eq!(grid.flow_phase(later, 1.0, 6.0), 0.0);
+
+        let first = hexgrid_to_svg(model.clone(), &style);
+        let second = hexgrid_to_svg(model, &style);
+
+        assert!(first.contains("<svg"));
+        assert!(first.contains("<path"));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn check_blockcode_to_svg_stable() {
+        let mut lang = BlockLanguage::new();
+        lang.define(BlockType::for_test("num", &[], &[""]));
+        lang.define(BlockType::for_test("sqrt", &[""], &[""]));
+
+        let mut fun = BlockFun::new(Rc::new(RefCell::new(lang)));
+        fun.instanciate_at(0, 1, 1, "num", None).unwrap();
+        fun.instanciate_at(0, 2, 1, "sqrt", None).unwrap();
+        let fun = Arc::new(Mutex::new(fun));
+        let style = Style::new();
+
+        let first = blockcode_to_svg(fun.clone(), &style);
+        let second = blockcode_to_svg(fun, &style);
+
+        assert!(first.contains("<svg"));
+        assert!(first.contains("<path"));
+        assert_eq!(first, second);
+    }
+}
@@ -2,24 +2,27 @@
 // This file is a part of HexoTK. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
+use crate::golden::{FrameCapture, GoldenError};
 use crate::painter::LblDebugTag;
 use crate::Widget;
 use crate::WindowUI;
 use crate::{
     widget_annotate_drop_event, widget_draw, widget_draw_frame, widget_draw_shallow,
-    widget_handle_event, EvPayload, Event, EventCore, InputEvent, MButton, Painter, PopupPos, Rect,
-    UINotifierRef,
+    widget_handle_event, Clipboard, ClipboardRef, EvKind, EvPayload, Event, EventCore, InputEvent,
+    MButton, Painter, PopupPos, Rect, Style, StyleSheet, StyleSheetError, UINotifierRef,
+    UndoStackRef,
 };
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::layout::{LayoutCache, LayoutTree};
 use crate::widget::{widget_walk, widget_walk_parents};
 use crate::widget_store::{WidgetStore, WidgetTree};
 
-use keyboard_types::{Key, KeyboardEvent};
+use keyboard_types::{Key, KeyboardEvent, Modifiers};
 use morphorm::{PositionType, Units};
 
 struct Layer {
@@ -89,6 +92,8 @@ pub struct TestDriver {
     widgets: HashMap<usize, WidgetFeedback>,
     //  tag,   tag_path, ctrl, widget pos
     widget_tags: HashMap<usize, (String, String, String, Rect)>,
+    capture_frame: bool,
+    frame: Option<FrameCapture>,
 }
 
 #[derive(Debug, Clone)]
@@ -106,7 +111,60 @@ pub struct LabelInfo {
 
 impl TestDriver {
     pub fn new() -> Self {
-        Self { injected_events: vec![], widgets: HashMap::new(), widget_tags: HashMap::new() }
+        Self {
+            injected_events: vec![],
+            widgets: HashMap::new(),
+            widget_tags: HashMap::new(),
+            capture_frame: false,
+            frame: None,
+        }
+    }
+
+    /// Requests that the next drawn frame is captured.
+    /// It can be retrieved with [TestDriver::frame] in a following step.
+    pub fn request_frame_capture(&mut self) {
+        self.capture_frame = true;
+    }
+
+    pub fn frame(&self) -> Option<&FrameCapture> {
+        self.frame.as_ref()
+    }
+
+    pub fn set_frame(&mut self, frame: Option<FrameCapture>) {
+        self.frame = frame;
+    }
+
+    pub fn wants_frame_capture(&self) -> bool {
+        self.capture_frame
+    }
+
+    /// Returns the position of the first widget with the given tag.
+    /// Like the widget positions after the layout, it is in physical pixels,
+    /// the coordinates of the [FrameCapture].
+    pub fn widget_rect_by_tag(&self, tag: &str) -> Option<Rect> {
+        self.widget_tags.values().find(|(t, _, _, _)| t == tag).map(|(_, _, _, pos)| *pos)
+    }
+
+    /// Compares the last captured frame, or the part of it in `rect`,
+    /// with the reference PNG at `ref_path`. See also
+    /// [FrameCapture::compare_with_png].
+    pub fn compare_frame_to_png(
+        &self,
+        ref_path: &str,
+        rect: Option<Rect>,
+        tolerance: u8,
+    ) -> Result<(), GoldenError> {
+        let frame = if let Some(frame) = &self.frame {
+            frame
+        } else {
+            return Err(GoldenError::MissingCapture);
+        };
+
+        if let Some(rect) = rect {
+            frame.crop(rect).compare_with_png(ref_path, tolerance)
+        } else {
+            frame.compare_with_png(ref_path, tolerance)
+        }
     }
 
     pub fn get_all_labels(&self) -> Vec<LabelInfo> {
@@ -237,6 +295,61 @@ impl TestScript {
     ) {
         self.queue.push((step_name, FScriptStep::Callback(cb)));
     }
+
+    /// Pushes two steps, that capture the next frame and compare it
+    /// with the reference PNG at `ref_path`. If `tag` is given, only the
+    /// rect of the widget with that tag is compared.
+    /// `tolerance` is the allowed deviation per color channel.
+    pub fn push_golden(
+        &mut self,
+        step_name: String,
+        ref_path: String,
+        tag: Option<String>,
+        tolerance: u8,
+    ) {
+        self.push_cb(
+            format!("{} (capture)", step_name),
+            Rc::new(|_ctx: &mut dyn std::any::Any, mut td: Box<TestDriver>| {
+                td.request_frame_capture();
+                (true, td)
+            }),
+        );
+
+        self.push_cb(
+            step_name,
+            Rc::new(move |_ctx: &mut dyn std::any::Any, td: Box<TestDriver>| {
+                let rect = if let Some(tag) = &tag {
+                    if let Some(rect) = td.widget_rect_by_tag(tag) {
+                        Some(rect)
+                    } else {
+                        eprintln!("golden: no widget with tag '{}'", tag);
+                        return (false, td);
+                    }
+                } else {
+                    None
+                };
+
+                match td.compare_frame_to_png(&ref_path, rect, tolerance) {
+                    Ok(()) => (true, td),
+                    Err(e) => {
+                        eprintln!("golden: {}: {}", ref_path, e);
+                        (false, td)
+                    }
+                }
+            }),
+        );
+    }
+}
+
+struct StyleSheetWatch {
+    path: String,
+    custom_types: Vec<String>,
+    mtime: Option<SystemTime>,
+    last_check: Instant,
+}
+
+fn file_mtime(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
 }
 
 #[derive(Debug, Clone)]
@@ -298,6 +411,12 @@ pub struct UI {
     driver_handle_cb: Option<Box<dyn FnMut(&mut dyn std::any::Any, Box<TestDriver>) -> Box<TestDriver>>>,
 
     image_data: HashMap<String, Vec<u8>>,
+
+    stylesheet: Option<StyleSheet>,
+    stylesheet_watch: Option<StyleSheetWatch>,
+    /// The base style of each styled widget and the style the
+    /// stylesheet made of it.
+    base_styles: HashMap<usize, (Rc<Style>, Rc<Style>)>,
 }
 
 impl UI {
@@ -329,16 +448,19 @@ impl UI {
             auto_hide_queue: vec![],
             frame_cb: None,
 
-            drop_query_ev: Event {
-                name: "drop_query".to_string(),
-                data: EvPayload::DropAccept(Rc::new(RefCell::new((
+            drop_query_ev: Event::new(
+                EvKind::DropQuery,
+                EvPayload::DropAccept(Rc::new(RefCell::new((
                     Rc::new(RefCell::new(Box::new(0))),
                     false,
                 )))),
-            },
+            ),
             last_hover_id: usize::MAX,
-            hover_ev: Event { name: "hover".to_string(), data: EvPayload::None },
+            hover_ev: Event::new(EvKind::Hover, EvPayload::None),
             image_data: HashMap::new(),
+            stylesheet: None,
+            stylesheet_watch: None,
+            base_styles: HashMap::new(),
             ctx,
         }
     }
@@ -361,8 +483,12 @@ impl UI {
         self.on_tree_changed();
     }
 
-    pub fn reg(&mut self, event: &str, cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>) {
-        self.global_event_core.reg(event, cb);
+    pub fn reg<K: Into<EvKind>>(
+        &mut self,
+        kind: K,
+        cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
+    ) {
+        self.global_event_core.reg(kind, cb);
     }
 
     pub fn reg_driver_cb(&mut self, cb: Box<dyn FnMut(&mut dyn std::any::Any, Box<TestDriver>) -> Box<TestDriver>>) {
@@ -477,6 +603,18 @@ impl UI {
 
         self.auto_hide_queue = auto_hide_queue;
 
+        if let Some(focus_id) = self.notifier.focused() {
+            if self.widgets.borrow().get(focus_id).is_none() {
+                self.notifier.blur();
+            }
+        }
+
+        if self.stylesheet.is_some() {
+            let widgets = self.widgets.clone();
+            self.base_styles.retain(|id, _| widgets.borrow().get(*id).is_some());
+            self.restyle();
+        }
+
         for layer in &mut self.layers {
             layer.tree = None;
         }
@@ -523,6 +661,150 @@ impl UI {
         }
     }
 
+    /// Moves the keyboard focus to `widget`, if its control can be focused.
+    /// The previously focused widget receives a "blur" event and `widget`
+    /// a "focus" event.
+    pub fn focus(&mut self, widget: &Widget) {
+        if !widget.can_focus() {
+            return;
+        }
+
+        let old_focus = self.notifier.focused();
+        self.notifier.focus(widget.unique_id());
+        self.emit_focus_change(old_focus);
+    }
+
+    /// Removes the keyboard focus from the currently focused widget.
+    pub fn blur(&mut self) {
+        let old_focus = self.notifier.focused();
+        self.notifier.blur();
+        self.emit_focus_change(old_focus);
+    }
+
+    pub fn focused(&self) -> Option<Widget> {
+        self.widgets.borrow().get(self.notifier.focused()?)
+    }
+
+    /// Whether the focused control handles Ctrl+Z and Ctrl+Y itself.
+    fn focus_has_local_undo(&self) -> bool {
+        self.focused().and_then(|w| w.with_ctrl(|ctrl| ctrl.has_local_undo())).unwrap_or(false)
+    }
+
+    /// Whether the focused control takes the Tab key, instead of moving
+    /// the focus on.
+    fn focus_wants_tab(&self) -> bool {
+        self.focused().and_then(|w| w.with_ctrl(|ctrl| ctrl.wants_tab())).unwrap_or(false)
+    }
+
+    /// Restyles all widgets with the rules of `sheet`. The style a widget
+    /// had when the first stylesheet was applied to it is kept as base,
+    /// on top of which the matching rules are applied. A style set with
+    /// [Widget::set_style] afterwards becomes the new base. Widgets that
+    /// are added later are styled when the tree changes.
+    pub fn apply_stylesheet(&mut self, sheet: StyleSheet) {
+        self.stylesheet = Some(sheet);
+        self.restyle();
+    }
+
+    /// Removes the stylesheet and gives all widgets their base style back.
+    pub fn clear_stylesheet(&mut self) {
+        self.stylesheet = None;
+        self.stylesheet_watch = None;
+
+        let base_styles = std::mem::take(&mut self.base_styles);
+        for (id, (base, applied)) in base_styles {
+            if let Some(wid) = self.widgets.borrow().get(id) {
+                // Keep styles the application set in the meantime:
+                if Rc::ptr_eq(&wid.style(), &applied) {
+                    wid.set_style(base);
+                }
+            }
+        }
+
+        self.notifier.set_layout_changed();
+    }
+
+    /// Loads and applies the stylesheet at `path`. The file is checked
+    /// for modifications in [WindowUI::pre_frame] and reapplied, so the
+    /// theme can be edited while the UI is running.
+    pub fn watch_stylesheet(&mut self, path: &str) -> Result<(), StyleSheetError> {
+        self.watch_stylesheet_with_types(path, &[])
+    }
+
+    /// Like [UI::watch_stylesheet], for stylesheets that also style the
+    /// `custom_types`, see [StyleSheet::parse_with_types].
+    pub fn watch_stylesheet_with_types(
+        &mut self,
+        path: &str,
+        custom_types: &[&str],
+    ) -> Result<(), StyleSheetError> {
+        let sheet = StyleSheet::load_with_types(path, custom_types)?;
+
+        self.stylesheet_watch = Some(StyleSheetWatch {
+            path: path.to_string(),
+            custom_types: custom_types.iter().map(|t| t.to_string()).collect(),
+            mtime: file_mtime(path),
+            last_check: Instant::now(),
+        });
+        self.apply_stylesheet(sheet);
+
+        Ok(())
+    }
+
+    /// The undo history, that widgets push their changes to.
+    /// Application callbacks can push their own [crate::UndoCommand]s here.
+    pub fn undo_stack(&self) -> UndoStackRef {
+        self.notifier.undo_stack()
+    }
+
+    /// Reverts the last undo step and sends the global "undo" event.
+    pub fn undo(&mut self) -> bool {
+        let ctx = self.ctx.clone();
+        let done = self.notifier.undo_stack().undo(&mut *(ctx.borrow_mut()));
+        if done {
+            self.emit_global_event(Event::new(EvKind::Undo, EvPayload::None));
+        }
+        done
+    }
+
+    /// Applies the last undone step again and sends the global "redo" event.
+    pub fn redo(&mut self) -> bool {
+        let ctx = self.ctx.clone();
+        let done = self.notifier.undo_stack().redo(&mut *(ctx.borrow_mut()));
+        if done {
+            self.emit_global_event(Event::new(EvKind::Redo, EvPayload::None));
+        }
+        done
+    }
+
+    /// In learn mode, clicking a [crate::HexKnob] arms its parameter for
+    /// learning and sends a "learn_armed" event with [EvPayload::ParamLearn].
+    /// The application maps the next incoming MIDI controller to it and
+    /// leaves the learn mode or arms the next parameter.
+    pub fn set_learn_mode(&mut self, enabled: bool) {
+        self.notifier.set_learn_mode(enabled);
+        self.widgets.borrow().for_each_widget(|wid| wid.emit_redraw_required());
+    }
+
+    pub fn is_learn_mode(&self) -> bool {
+        self.notifier.is_learn_mode()
+    }
+
+    /// The widget that is currently armed for learning.
+    pub fn learn_armed(&self) -> Option<Widget> {
+        self.widgets.borrow().get(self.notifier.learn_armed()?)
+    }
+
+    /// Replaces the in-memory clipboard, for example with one that
+    /// accesses the clipboard of the operating system.
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn Clipboard>) {
+        self.notifier.clipboard().set_clipboard(clipboard);
+    }
+
+    pub fn clipboard(&self) -> ClipboardRef {
+        self.notifier.clipboard()
+    }
+
     fn find_layer_by_root_id(&mut self, root_widget_id: usize) -> Option<&mut Layer> {
         for layer in &mut self.layers {
             if layer.root.unique_id() == root_widget_id {
@@ -592,8 +874,7 @@ impl UI {
             //d// println!("DROP! {} on {}", self.drag.hover_id, hov_id);
             if let Some(ud) = &self.drag.userdata {
                 if let Some(widget) = self.widgets.borrow().get(hov_id) {
-                    let ev =
-                        Event { name: "drop".to_string(), data: EvPayload::UserData(ud.clone()) };
+                    let ev = Event::new(EvKind::Drop, EvPayload::UserData(ud.clone()));
                     let ev = widget_annotate_drop_event(&widget, self.drag.pos, ev);
 
                     if let Some(widget) = self.widgets.borrow().get(hov_id) {
@@ -624,10 +905,7 @@ impl UI {
                 widget_handle_event(
                     &widget,
                     &mut *(self.ctx.borrow_mut()),
-                    &Event {
-                        name: "drag".to_string(),
-                        data: EvPayload::UserData(userdata.clone()),
-                    },
+                    &Event::new(EvKind::Drag, EvPayload::UserData(userdata.clone())),
                 );
             }
 
@@ -724,6 +1002,122 @@ impl UI {
         }
     }
 
+    fn restyle(&mut self) {
+        let sheet = if let Some(sheet) = &self.stylesheet { sheet } else { return };
+        let base_styles = &mut self.base_styles;
+
+        self.widgets.borrow().for_each_widget(|wid| {
+            let cur = wid.style();
+            let (base, applied) =
+                base_styles.entry(wid.unique_id()).or_insert_with(|| (cur.clone(), cur.clone()));
+
+            // The application set a new style since the last restyle:
+            if !Rc::ptr_eq(&cur, applied) {
+                *base = cur;
+            }
+
+            let style = sheet.resolve(base, &wid.type_name(), &wid.tag()).map(Rc::new);
+            *applied = style.unwrap_or_else(|| base.clone());
+            wid.set_style(applied.clone());
+        });
+
+        // Paddings are not covered by the layout change detection of set_style():
+        self.notifier.set_layout_changed();
+    }
+
+    fn check_stylesheet_change(&mut self) {
+        let (path, custom_types) = if let Some(watch) = &mut self.stylesheet_watch {
+            if watch.last_check.elapsed() < Duration::from_millis(500) {
+                return;
+            }
+            watch.last_check = Instant::now();
+
+            let mtime = file_mtime(&watch.path);
+            if mtime == watch.mtime {
+                return;
+            }
+            watch.mtime = mtime;
+
+            (watch.path.clone(), watch.custom_types.clone())
+        } else {
+            return;
+        };
+
+        let custom_types: Vec<&str> = custom_types.iter().map(|t| &t[..]).collect();
+        match StyleSheet::load_with_types(&path, &custom_types) {
+            Ok(sheet) => self.apply_stylesheet(sheet),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    fn emit_global_event(&mut self, ev: Event) {
+        let ctx = self.ctx.clone();
+
+        if let Some(layer) = self.layers.get(0) {
+            self.global_event_core.call(&mut *(ctx.borrow_mut()), &ev, &layer.root);
+        }
+    }
+
+    fn emit_focus_change(&mut self, old_focus: Option<usize>) {
+        let new_focus = self.notifier.focused();
+        if old_focus == new_focus {
+            return;
+        }
+
+        let ctx = self.ctx.clone();
+
+        if let Some(old_id) = old_focus {
+            if let Some(widget) = self.widgets.borrow().get(old_id) {
+                let ev = Event::new(EvKind::Blur, EvPayload::None);
+                widget_handle_event(&widget, &mut *(ctx.borrow_mut()), &ev);
+            }
+        }
+
+        if let Some(new_id) = new_focus {
+            if let Some(widget) = self.widgets.borrow().get(new_id) {
+                let ev = Event::new(EvKind::Focus, EvPayload::None);
+                widget_handle_event(&widget, &mut *(ctx.borrow_mut()), &ev);
+            }
+        }
+    }
+
+    /// Collects all visible focusable widgets, layer by layer in tree order.
+    fn focus_chain(&self) -> Vec<Widget> {
+        let mut chain = vec![];
+
+        for layer in self.layers.iter() {
+            widget_walk(&layer.root, |wid, _parent, _is_first, _is_last, _depth| {
+                if wid.is_visible() && wid.can_focus() {
+                    chain.push(wid.clone());
+                }
+            });
+        }
+
+        chain
+    }
+
+    fn focus_next(&mut self, backward: bool) {
+        let chain = self.focus_chain();
+        if chain.is_empty() {
+            return;
+        }
+
+        let cur_idx = self
+            .notifier
+            .focused()
+            .and_then(|focus_id| chain.iter().position(|wid| wid.unique_id() == focus_id));
+
+        let len = chain.len();
+        let next_idx = match cur_idx {
+            Some(idx) if backward => (idx + len - 1) % len,
+            Some(idx) => (idx + 1) % len,
+            None if backward => len - 1,
+            None => 0,
+        };
+
+        self.focus(&chain[next_idx]);
+    }
+
     fn deposit_popups_in_layers(&mut self) {
         while let Some((wid_id, pos)) = self.notifier.pop_popup() {
             let mut root_wid = None;
@@ -799,6 +1193,8 @@ impl WindowUI for UI {
 
         let notifier = self.notifier.clone();
 
+        self.check_stylesheet_change();
+
         self.deposit_popups_in_layers();
 
         if notifier.is_tree_changed() {
@@ -902,6 +1298,7 @@ impl WindowUI for UI {
 
         let old_hover = notifier.hover();
         let old_active = notifier.active();
+        let old_focus = notifier.focused();
 
         let mut sent_events: Vec<(usize, Event)> = vec![];
 
@@ -914,6 +1311,12 @@ impl WindowUI for UI {
                 if self.do_auto_hide_if_not_inside(notifier.mouse_pos()) {
                     return;
                 }
+
+                let hover_wid = self.widgets.borrow().get(notifier.hover());
+                match hover_wid {
+                    Some(wid) if wid.can_focus() => notifier.focus(wid.unique_id()),
+                    _ => notifier.blur(),
+                }
             }
             InputEvent::MouseButtonReleased(btn) => {
                 if *btn == MButton::Left {
@@ -926,10 +1329,7 @@ impl WindowUI for UI {
                 if let Some(layer) = self.layers.get(0) {
                     self.global_event_core.call(
                         &mut *(ctx.borrow_mut()),
-                        &Event {
-                            name: "click".to_string(),
-                            data: EvPayload::Click { x, y, button: *btn },
-                        },
+                        &Event::new(EvKind::Click, EvPayload::Click { x, y, button: *btn }),
                         &layer.root,
                     );
                 }
@@ -976,6 +1376,24 @@ impl WindowUI for UI {
                 Key::Escape => {
                     self.do_auto_hide(None);
                 }
+                Key::Tab if !self.focus_wants_tab() => {
+                    self.focus_next(key.modifiers.contains(Modifiers::SHIFT));
+                    return;
+                }
+                Key::Character(c)
+                    if key.modifiers.contains(Modifiers::CONTROL)
+                        && !self.focus_has_local_undo() =>
+                {
+                    let shift = key.modifiers.contains(Modifiers::SHIFT);
+
+                    if c.eq_ignore_ascii_case("z") && !shift {
+                        self.undo();
+                        return;
+                    } else if c.eq_ignore_ascii_case("y") || c.eq_ignore_ascii_case("z") {
+                        self.redo();
+                        return;
+                    }
+                }
                 _ => {}
             },
             _ => {}
@@ -1019,6 +1437,8 @@ impl WindowUI for UI {
                 self.do_auto_hide(notifier.active());
             }
         }
+
+        self.emit_focus_change(old_focus);
     }
 
     fn draw(&mut self, painter: &mut Painter) {
@@ -1081,6 +1501,14 @@ impl WindowUI for UI {
                 }
             }
         }
+
+        if let Some(fb) = &mut self.fb {
+            if fb.wants_frame_capture() {
+                fb.capture_frame = false;
+                fb.frame =
+                    painter.backend.screenshot().map(|(w, h, data)| FrameCapture::new(w, h, data));
+            }
+        }
     }
 
     fn set_window_size(&mut self, w: f32, h: f32, dpi_factor: f32) {
@@ -1096,3 +1524,309 @@ impl WindowUI for UI {
         &self.image_data
     }
 }
+
+#[cfg(test)]
+impl UI {
+    /// A 400x300 [UI] for the tests, with the `ctrls` below one root,
+    /// each 100x50 in size, top to bottom.
+    pub(crate) fn for_test(ctrls: Vec<crate::Control>) -> (UI, Vec<Widget>) {
+        let style = Rc::new(Style::new());
+        let root = Widget::new(style.clone());
+
+        let mut wids = vec![];
+        for ctrl in ctrls {
+            let wid = Widget::new(style.clone());
+            wid.set_ctrl(ctrl);
+            wid.change_layout(|l| {
+                l.width = Some(Units::Pixels(100.0));
+                l.height = Some(Units::Pixels(50.0));
+            });
+            root.add(wid.clone());
+            wids.push(wid);
+        }
+
+        let mut ui = UI::new(Rc::new(RefCell::new(1)));
+        ui.add_layer_root(root);
+        ui.set_window_size(400.0, 300.0, 1.0);
+        ui.pre_frame();
+        ui.on_layout_changed();
+
+        (ui, wids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Control, DummyParamModel, EditableText, Entry, HeadlessRenderer, HexKnob, TextEdit,
+        TextField, UndoCommand,
+    };
+
+    struct NopCmd;
+
+    impl UndoCommand for NopCmd {
+        fn undo(&mut self, _ctx: &mut dyn std::any::Any) {}
+        fn redo(&mut self, _ctx: &mut dyn std::any::Any) {}
+
+        fn describe(&self) -> String {
+            "nop".to_string()
+        }
+    }
+
+    fn ctrl_z() -> InputEvent {
+        InputEvent::KeyPressed(KeyboardEvent {
+            key: Key::Character("z".to_string()),
+            modifiers: Modifiers::CONTROL,
+            ..Default::default()
+        })
+    }
+
+    fn tab(shift: bool) -> InputEvent {
+        InputEvent::KeyPressed(KeyboardEvent {
+            key: Key::Tab,
+            modifiers: if shift { Modifiers::SHIFT } else { Modifiers::empty() },
+            ..Default::default()
+        })
+    }
+
+    fn ui_with(ctrl: Control) -> (UI, Widget) {
+        let (ui, mut wids) = UI::for_test(vec![ctrl]);
+        (ui, wids.remove(0))
+    }
+
+    fn entry() -> Control {
+        Control::Entry { entry: Box::new(Entry::new(Box::new(TextField::new()))) }
+    }
+
+    fn focused_id(ui: &UI) -> Option<usize> {
+        ui.focused().map(|w| w.unique_id())
+    }
+
+    /// Logs the "focus" and "blur" events of `wid` as "<name>:<kind>".
+    fn log_focus(wid: &Widget, name: &'static str, log: &Rc<RefCell<Vec<String>>>) {
+        for kind in [EvKind::Focus, EvKind::Blur] {
+            let log = log.clone();
+            wid.reg(kind, move |_ctx, _wid, ev| {
+                log.borrow_mut().push(format!("{}:{}", name, ev.kind()));
+            });
+        }
+    }
+
+    #[test]
+    fn check_ctrl_z_keeps_undo_stack_with_focused_text_edit() {
+        let (mut ui, edit) = ui_with(Control::TextEdit {
+            edit: Box::new(TextEdit::new(Box::new(TextField::new()))),
+        });
+
+        ui.undo_stack().push(NopCmd);
+        ui.focus(&edit);
+        assert!(ui.focused().is_some());
+
+        ui.handle_input_event(ctrl_z());
+        assert!(ui.undo_stack().can_undo());
+
+        // Without the focus on the TextEdit, the UI undoes:
+        ui.blur();
+        ui.handle_input_event(ctrl_z());
+        assert!(!ui.undo_stack().can_undo());
+    }
+
+    #[test]
+    fn check_tab_focus_order() {
+        let knob = HexKnob::new(Rc::new(RefCell::new(DummyParamModel::new())));
+        let (mut ui, wids) = UI::for_test(vec![
+            entry(),
+            Control::Rect,
+            Control::HexKnob { knob: Box::new(knob) },
+            entry(),
+        ]);
+        let ids: Vec<usize> = wids.iter().map(|w| w.unique_id()).collect();
+
+        assert_eq!(focused_id(&ui), None);
+
+        // The Rect can not be focused and is skipped:
+        ui.handle_input_event(tab(false));
+        assert_eq!(focused_id(&ui), Some(ids[0]));
+        ui.handle_input_event(tab(false));
+        assert_eq!(focused_id(&ui), Some(ids[2]));
+        ui.handle_input_event(tab(false));
+        assert_eq!(focused_id(&ui), Some(ids[3]));
+        ui.handle_input_event(tab(false));
+        assert_eq!(focused_id(&ui), Some(ids[0]), "wraps around");
+
+        // Shift+Tab goes backwards:
+        ui.handle_input_event(tab(true));
+        assert_eq!(focused_id(&ui), Some(ids[3]), "wraps around");
+        ui.handle_input_event(tab(true));
+        assert_eq!(focused_id(&ui), Some(ids[2]));
+
+        // Without a focus, Shift+Tab starts at the end:
+        ui.blur();
+        ui.handle_input_event(tab(true));
+        assert_eq!(focused_id(&ui), Some(ids[3]));
+    }
+
+    #[test]
+    fn check_tab_focus_events() {
+        let (mut ui, wids) = UI::for_test(vec![entry(), entry()]);
+
+        let log = Rc::new(RefCell::new(vec![]));
+        log_focus(&wids[0], "a", &log);
+        log_focus(&wids[1], "b", &log);
+
+        ui.handle_input_event(tab(false));
+        ui.handle_input_event(tab(false));
+        assert_eq!(log.borrow().clone(), vec!["a:focus", "a:blur", "b:focus"]);
+    }
+
+    #[test]
+    fn check_tab_goes_to_text_edit() {
+        let text = TextField::new();
+        let (mut ui, wids) = UI::for_test(vec![
+            entry(),
+            Control::TextEdit { edit: Box::new(TextEdit::new(Box::new(text.clone()))) },
+        ]);
+
+        ui.focus(&wids[1]);
+        ui.handle_input_event(tab(false));
+        assert_eq!(focused_id(&ui), Some(wids[1].unique_id()));
+        assert_eq!(text.get(), "    ");
+
+        // An Entry leaves Tab to the focus navigation:
+        ui.focus(&wids[0]);
+        ui.handle_input_event(tab(false));
+        assert_eq!(focused_id(&ui), Some(wids[1].unique_id()));
+    }
+
+    #[test]
+    fn check_click_focus_and_blur() {
+        let (mut ui, wids) = UI::for_test(vec![entry(), entry()]);
+
+        let log = Rc::new(RefCell::new(vec![]));
+        log_focus(&wids[1], "b", &log);
+
+        let pos = wids[1].pos();
+        assert!(pos.w > 0.0 && pos.h > 0.0);
+
+        ui.handle_input_event(InputEvent::MousePosition(pos.x + 5.0, pos.y + 5.0));
+        ui.handle_input_event(InputEvent::MouseButtonPressed(MButton::Left));
+        ui.handle_input_event(InputEvent::MouseButtonReleased(MButton::Left));
+        assert_eq!(focused_id(&ui), Some(wids[1].unique_id()));
+
+        // Clicking outside of any focusable widget blurs:
+        ui.handle_input_event(InputEvent::MousePosition(390.0, 290.0));
+        ui.handle_input_event(InputEvent::MouseButtonPressed(MButton::Left));
+        ui.handle_input_event(InputEvent::MouseButtonReleased(MButton::Left));
+        assert_eq!(focused_id(&ui), None);
+        assert_eq!(log.borrow().clone(), vec!["b:focus", "b:blur"]);
+    }
+
+    #[test]
+    fn check_stylesheet_keeps_app_styles() {
+        let (mut ui, wids) = UI::for_test(vec![entry(), entry()]);
+        ui.apply_stylesheet(StyleSheet::parse("Entry { font_size: 20; }").unwrap());
+        assert_eq!(wids[0].style().font_size, 20.0);
+
+        let mut style = Style::new();
+        style.color = (1.0, 0.0, 0.0);
+        wids[0].set_style(Rc::new(style));
+
+        // A tree change restyles, on top of the style the application set:
+        let root = wids[0].parent().unwrap();
+        root.add(Widget::new(Rc::new(Style::new())));
+        ui.pre_frame();
+        assert_eq!(wids[0].style().color, (1.0, 0.0, 0.0));
+        assert_eq!(wids[0].style().font_size, 20.0);
+
+        // Overwritten after the last restyle, it is kept as is:
+        let mut style = Style::new();
+        style.font_size = 5.0;
+        let style = Rc::new(style);
+        wids[1].set_style(style.clone());
+
+        ui.clear_stylesheet();
+        assert_eq!(wids[0].style().color, (1.0, 0.0, 0.0));
+        assert_eq!(wids[0].style().font_size, Style::new().font_size);
+        assert!(Rc::ptr_eq(&wids[1].style(), &style));
+    }
+
+    /// A box at logical (10, 5) of 30x20, blue with a red top half.
+    fn golden_box_ui() -> UI {
+        let rect = |color: (f32, f32, f32), x: f32, y: f32, w: f32, h: f32| {
+            let mut style = Style::new();
+            style.bg_color = color;
+            style.border = 0.0;
+            let wid = Widget::new(Rc::new(style));
+            wid.set_ctrl(Control::Rect);
+            wid.change_layout(|l| {
+                l.position_type = Some(PositionType::SelfDirected);
+                l.left = Some(Units::Pixels(x));
+                l.top = Some(Units::Pixels(y));
+                l.width = Some(Units::Pixels(w));
+                l.height = Some(Units::Pixels(h));
+            });
+            wid
+        };
+
+        let root = Widget::new(Rc::new(Style::new()));
+        root.set_ctrl(Control::None);
+
+        let bx = rect((0.0, 0.0, 1.0), 10.0, 5.0, 30.0, 20.0);
+        bx.set_tag("box".to_string());
+        bx.add(rect((1.0, 0.0, 0.0), 0.0, 0.0, 30.0, 10.0));
+        root.add(bx);
+
+        let mut ui = UI::new(Rc::new(RefCell::new(1)));
+        ui.add_layer_root(root);
+        ui
+    }
+
+    #[test]
+    fn check_push_golden_end_to_end() {
+        let dir = std::env::temp_dir().join(format!("hexotk_ui_golden_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("creates test dir");
+        let ref_path = dir.join("box.png").to_string_lossy().to_string();
+
+        // At a DPI factor of 2 the box covers (20, 10) to (80, 50) of the
+        // frame. The red half must end up on top:
+        let mut data = vec![];
+        for y in 0..40 {
+            let px = if y < 20 { [255, 0, 0, 255] } else { [0, 0, 255, 255] };
+            for _ in 0..60 {
+                data.extend_from_slice(&px);
+            }
+        }
+        FrameCapture::new(60, 40, data).save_png(&ref_path).expect("writes reference");
+
+        let mut ui = golden_box_ui();
+        let done = Rc::new(std::cell::Cell::new(false));
+
+        let mut script = TestScript::new("golden".to_string());
+        script.push_cb(
+            "rect".to_string(),
+            Rc::new(|_ctx: &mut dyn std::any::Any, td: Box<TestDriver>| {
+                let rect = td.widget_rect_by_tag("box");
+                (rect == Some(Rect::from(20.0, 10.0, 60.0, 40.0)), td)
+            }),
+        );
+        script.push_golden("box".to_string(), ref_path.clone(), Some("box".to_string()), 2);
+        let d = done.clone();
+        script.push_cb(
+            "done".to_string(),
+            Rc::new(move |_ctx: &mut dyn std::any::Any, td: Box<TestDriver>| {
+                d.set(true);
+                (true, td)
+            }),
+        );
+        ui.install_test_script(script);
+
+        let mut hr = HeadlessRenderer::new(Box::new(ui), 200, 120, 2.0);
+        for _ in 0..8 {
+            hr.render_frame();
+        }
+
+        assert!(done.get(), "golden script failed, see {}", dir.display());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
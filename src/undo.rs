@@ -0,0 +1,349 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A reversible change, that can be pushed onto the [UndoStack].
+///
+/// The command is pushed _after_ the change was applied, so the
+/// first call on it will be [UndoCommand::undo].
+pub trait UndoCommand {
+    fn undo(&mut self, ctx: &mut dyn std::any::Any);
+    fn redo(&mut self, ctx: &mut dyn std::any::Any);
+
+    /// A short human readable description, like "Change Frequency".
+    fn describe(&self) -> String;
+}
+
+struct UndoGroup {
+    desc: String,
+    cmds: Vec<Box<dyn UndoCommand>>,
+}
+
+impl UndoCommand for UndoGroup {
+    fn undo(&mut self, ctx: &mut dyn std::any::Any) {
+        for cmd in self.cmds.iter_mut().rev() {
+            cmd.undo(ctx);
+        }
+    }
+
+    fn redo(&mut self, ctx: &mut dyn std::any::Any) {
+        for cmd in self.cmds.iter_mut() {
+            cmd.redo(ctx);
+        }
+    }
+
+    fn describe(&self) -> String {
+        self.desc.clone()
+    }
+}
+
+/// The history of [UndoCommand]s. The [crate::UI] owns one, which
+/// widgets reach with [crate::Widget::push_undo] and applications with
+/// [crate::UI::undo_stack]. Ctrl+Z undoes, Ctrl+Y or Ctrl+Shift+Z redoes.
+pub struct UndoStack {
+    undo: Vec<Box<dyn UndoCommand>>,
+    redo: Vec<Box<dyn UndoCommand>>,
+    group: Option<UndoGroup>,
+    group_depth: usize,
+    limit: usize,
+    generation: u64,
+}
+
+impl UndoStack {
+    pub fn new() -> Self {
+        Self {
+            undo: vec![],
+            redo: vec![],
+            group: None,
+            group_depth: 0,
+            limit: 100,
+            generation: 0,
+        }
+    }
+
+    /// Sets the maximum number of undo steps that are kept.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(1);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        if self.undo.len() > self.limit {
+            let excess = self.undo.len() - self.limit;
+            self.undo.drain(0..excess);
+        }
+    }
+
+    /// Pushes a command for a change that was already applied.
+    /// This discards everything that could be redone.
+    pub fn push(&mut self, cmd: Box<dyn UndoCommand>) {
+        if let Some(group) = &mut self.group {
+            group.cmds.push(cmd);
+            return;
+        }
+
+        self.undo.push(cmd);
+        self.redo.clear();
+        self.trim();
+        self.generation += 1;
+    }
+
+    /// Starts collecting all pushed commands into one undo step, until
+    /// the matching [UndoStack::end_group]. Groups can be nested, only
+    /// the outermost group becomes an undo step.
+    pub fn begin_group(&mut self, desc: &str) {
+        if self.group_depth == 0 {
+            self.group = Some(UndoGroup { desc: desc.to_string(), cmds: vec![] });
+        }
+
+        self.group_depth += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        if self.group_depth == 0 {
+            return;
+        }
+
+        self.group_depth -= 1;
+
+        if self.group_depth == 0 {
+            if let Some(group) = self.group.take() {
+                if !group.cmds.is_empty() {
+                    self.push(Box::new(group));
+                }
+            }
+        }
+    }
+
+    pub fn is_grouping(&self) -> bool {
+        self.group_depth > 0
+    }
+
+    fn close_groups(&mut self) {
+        while self.is_grouping() {
+            self.end_group();
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo_description(&self) -> Option<String> {
+        self.undo.last().map(|cmd| cmd.describe())
+    }
+
+    pub fn redo_description(&self) -> Option<String> {
+        self.redo.last().map(|cmd| cmd.describe())
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.group = None;
+        self.group_depth = 0;
+        self.generation += 1;
+    }
+
+    /// Increases with every change of the history, useful for
+    /// updating menu entries or buttons.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn take_undo(&mut self) -> Option<Box<dyn UndoCommand>> {
+        self.close_groups();
+        self.undo.pop()
+    }
+
+    fn take_redo(&mut self) -> Option<Box<dyn UndoCommand>> {
+        self.close_groups();
+        self.redo.pop()
+    }
+
+    fn undone(&mut self, cmd: Box<dyn UndoCommand>) {
+        self.redo.push(cmd);
+        self.generation += 1;
+    }
+
+    fn redone(&mut self, cmd: Box<dyn UndoCommand>) {
+        self.undo.push(cmd);
+        self.generation += 1;
+    }
+}
+
+/// A shared handle to an [UndoStack].
+///
+/// The commands are executed while the stack is not borrowed, so
+/// they may trigger callbacks that look at the stack again.
+#[derive(Clone)]
+pub struct UndoStackRef(Rc<RefCell<UndoStack>>);
+
+impl UndoStackRef {
+    pub fn new() -> Self {
+        Self(Rc::new(RefCell::new(UndoStack::new())))
+    }
+
+    pub fn push<C: 'static + UndoCommand>(&self, cmd: C) {
+        self.0.borrow_mut().push(Box::new(cmd));
+    }
+
+    pub fn begin_group(&self, desc: &str) {
+        self.0.borrow_mut().begin_group(desc);
+    }
+
+    pub fn end_group(&self) {
+        self.0.borrow_mut().end_group();
+    }
+
+    /// Reverts the last undo step. Returns false if there was none.
+    pub fn undo(&self, ctx: &mut dyn std::any::Any) -> bool {
+        let cmd = self.0.borrow_mut().take_undo();
+
+        if let Some(mut cmd) = cmd {
+            cmd.undo(ctx);
+            self.0.borrow_mut().undone(cmd);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Applies the last undone step again. Returns false if there was none.
+    pub fn redo(&self, ctx: &mut dyn std::any::Any) -> bool {
+        let cmd = self.0.borrow_mut().take_redo();
+
+        if let Some(mut cmd) = cmd {
+            cmd.redo(ctx);
+            self.0.borrow_mut().redone(cmd);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.0.borrow().can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.0.borrow().can_redo()
+    }
+
+    pub fn with<R, F: FnOnce(&mut UndoStack) -> R>(&self, f: F) -> R {
+        f(&mut *self.0.borrow_mut())
+    }
+}
+
+impl std::fmt::Debug for UndoStackRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stack = self.0.borrow();
+        f.debug_struct("UndoStackRef")
+            .field("undo", &stack.undo.len())
+            .field("redo", &stack.redo.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct SetVal {
+        val: Rc<RefCell<i32>>,
+        old: i32,
+        new: i32,
+    }
+
+    impl UndoCommand for SetVal {
+        fn undo(&mut self, _ctx: &mut dyn std::any::Any) {
+            *self.val.borrow_mut() = self.old;
+        }
+
+        fn redo(&mut self, _ctx: &mut dyn std::any::Any) {
+            *self.val.borrow_mut() = self.new;
+        }
+
+        fn describe(&self) -> String {
+            format!("set {}", self.new)
+        }
+    }
+
+    fn set(stack: &UndoStackRef, val: &Rc<RefCell<i32>>, new: i32) {
+        let old = *val.borrow();
+        *val.borrow_mut() = new;
+        stack.push(SetVal { val: val.clone(), old, new });
+    }
+
+    #[test]
+    fn check_undo_redo() {
+        let stack = UndoStackRef::new();
+        let val = Rc::new(RefCell::new(0));
+        let mut ctx = ();
+
+        set(&stack, &val, 1);
+        set(&stack, &val, 2);
+
+        assert!(stack.undo(&mut ctx));
+        assert_eq!(*val.borrow(), 1);
+        assert!(stack.undo(&mut ctx));
+        assert_eq!(*val.borrow(), 0);
+        assert!(!stack.undo(&mut ctx));
+
+        assert!(stack.redo(&mut ctx));
+        assert_eq!(*val.borrow(), 1);
+        assert_eq!(stack.with(|s| s.redo_description()), Some("set 2".to_string()));
+
+        set(&stack, &val, 5);
+        assert!(!stack.can_redo());
+        assert!(stack.undo(&mut ctx));
+        assert_eq!(*val.borrow(), 1);
+    }
+
+    #[test]
+    fn check_undo_group() {
+        let stack = UndoStackRef::new();
+        let val = Rc::new(RefCell::new(0));
+        let mut ctx = ();
+
+        stack.begin_group("drag");
+        set(&stack, &val, 1);
+        stack.begin_group("inner");
+        set(&stack, &val, 2);
+        stack.end_group();
+        set(&stack, &val, 3);
+        stack.end_group();
+
+        assert_eq!(stack.with(|s| s.undo_description()), Some("drag".to_string()));
+        assert!(stack.undo(&mut ctx));
+        assert_eq!(*val.borrow(), 0);
+        assert!(!stack.can_undo());
+
+        assert!(stack.redo(&mut ctx));
+        assert_eq!(*val.borrow(), 3);
+    }
+
+    #[test]
+    fn check_undo_limit() {
+        let stack = UndoStackRef::new();
+        let val = Rc::new(RefCell::new(0));
+        let mut ctx = ();
+
+        stack.with(|s| s.set_limit(2));
+        set(&stack, &val, 1);
+        set(&stack, &val, 2);
+        set(&stack, &val, 3);
+
+        assert!(stack.undo(&mut ctx));
+        assert!(stack.undo(&mut ctx));
+        assert!(!stack.undo(&mut ctx));
+        assert_eq!(*val.borrow(), 1);
+    }
+}
@@ -0,0 +1,13 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+/// The result of validating a text, see [crate::EditableText::validate]
+/// and [crate::blocklang::BlockUserInput::validate].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Validation {
+    Valid,
+    /// The text is accepted, but replaced by the contained one.
+    Normalized(String),
+    Invalid,
+}
@@ -4,7 +4,10 @@
 
 use crate::painter::{ImgRef, LblDebugTag};
 use crate::style::Style;
-use crate::{Control, EvPayload, Event, EventCore, Painter, PopupPos, Rect, UINotifierRef};
+use crate::{
+    ClipboardRef, Control, EvKind, EvPayload, Event, EventCore, Painter, PopupPos, Rect,
+    UINotifierRef, UndoCommand, UndoStackRef,
+};
 use std::cell::RefCell;
 use std::rc::{Rc, Weak};
 
@@ -112,8 +115,8 @@ impl Widget {
         LblDebugTag::from_id(self.unique_id())
     }
 
-    pub fn event(&self, name: &str, data: EvPayload) -> (usize, Event) {
-        (self.unique_id(), Event { name: name.to_string(), data })
+    pub fn event<K: Into<EvKind>>(&self, kind: K, data: EvPayload) -> (usize, Event) {
+        (self.unique_id(), Event::new(kind, data))
     }
 
     pub fn set_tree_pos(&self, idx: usize, depth: usize) {
@@ -140,12 +143,38 @@ impl Widget {
         Rc::downgrade(&self.0)
     }
 
-    pub fn reg<F: 'static + FnMut(&mut dyn std::any::Any, Widget, &Event)>(
+    pub fn reg<K: Into<EvKind>, F: 'static + FnMut(&mut dyn std::any::Any, Widget, &Event)>(
         &self,
-        ev_name: &str,
+        kind: K,
         cb: F,
     ) {
-        self.0.borrow_mut().reg(ev_name, Box::new(cb));
+        self.0.borrow_mut().reg(kind, Box::new(cb));
+    }
+
+    /// Registers a callback for events of the child widgets, that is
+    /// called before the child handles them. See also [EventCore::reg_capture].
+    pub fn reg_capture<
+        K: Into<EvKind>,
+        F: 'static + FnMut(&mut dyn std::any::Any, Widget, &Event),
+    >(
+        &self,
+        kind: K,
+        cb: F,
+    ) {
+        self.0.borrow_mut().reg_capture(kind, Box::new(cb));
+    }
+
+    /// Registers a callback for events of the child widgets, that is
+    /// called after the child handled them. See also [EventCore::reg_bubble].
+    pub fn reg_bubble<
+        K: Into<EvKind>,
+        F: 'static + FnMut(&mut dyn std::any::Any, Widget, &Event),
+    >(
+        &self,
+        kind: K,
+        cb: F,
+    ) {
+        self.0.borrow_mut().reg_bubble(kind, Box::new(cb));
     }
 
     pub fn take_event_core(&self) -> Option<EventCore> {
@@ -202,6 +231,52 @@ impl Widget {
         self.0.borrow_mut().is_active()
     }
 
+    pub fn focus(&self) {
+        self.0.borrow_mut().focus();
+    }
+
+    pub fn blur(&self) {
+        self.0.borrow_mut().blur();
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.0.borrow().is_focused()
+    }
+
+    /// Records an already applied change in the undo history of the [crate::UI].
+    pub fn push_undo<C: 'static + UndoCommand>(&self, cmd: C) {
+        if let Some(undo) = self.undo_stack() {
+            undo.push(cmd);
+        }
+    }
+
+    pub fn undo_stack(&self) -> Option<UndoStackRef> {
+        self.0.borrow().notifier.as_ref().map(|n| n.undo_stack())
+    }
+
+    /// Whether the [crate::UI] is in learn mode, see [crate::UI::set_learn_mode].
+    pub fn is_learn_mode(&self) -> bool {
+        self.0.borrow().notifier.as_ref().map(|n| n.is_learn_mode()).unwrap_or(false)
+    }
+
+    /// Arms this widget for learning, disarming any other widget.
+    pub fn arm_learn(&self) {
+        let id = self.unique_id();
+        if let Some(n) = self.0.borrow().notifier.as_ref() {
+            n.arm_learn(Some(id));
+        }
+    }
+
+    pub fn is_learn_armed(&self) -> bool {
+        let id = self.unique_id();
+        self.0.borrow().notifier.as_ref().and_then(|n| n.learn_armed()) == Some(id)
+    }
+
+    /// The clipboard of the [crate::UI], for copy and paste.
+    pub fn clipboard(&self) -> Option<ClipboardRef> {
+        self.0.borrow().notifier.as_ref().map(|n| n.clipboard())
+    }
+
     pub fn set_notifier(&self, not: UINotifierRef) {
         self.0.borrow_mut().set_notifier(not)
     }
@@ -253,6 +328,16 @@ impl Widget {
         self.0.borrow_mut().can_hover()
     }
 
+    pub fn can_focus(&self) -> bool {
+        self.0.borrow_mut().can_focus()
+    }
+
+    /// The [Control::type_name] of the widget, or an empty string
+    /// if it has no control.
+    pub fn type_name(&self) -> String {
+        self.0.borrow().ctrl.as_ref().map(|c| c.type_name().to_string()).unwrap_or_default()
+    }
+
     pub fn set_pos(&self, pos: Rect) {
         self.emit_redraw_required();
         self.0.borrow_mut().pos = pos;
@@ -429,13 +514,33 @@ impl WidgetImpl {
         }
     }
 
-    pub fn reg(
+    pub fn reg<K: Into<EvKind>>(
+        &mut self,
+        kind: K,
+        cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
+    ) {
+        if let Some(evc) = &mut self.evc {
+            evc.reg(kind, cb);
+        }
+    }
+
+    pub fn reg_capture<K: Into<EvKind>>(
+        &mut self,
+        kind: K,
+        cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
+    ) {
+        if let Some(evc) = &mut self.evc {
+            evc.reg_capture(kind, cb);
+        }
+    }
+
+    pub fn reg_bubble<K: Into<EvKind>>(
         &mut self,
-        ev_name: &str,
+        kind: K,
         cb: Box<dyn FnMut(&mut dyn std::any::Any, Widget, &Event)>,
     ) {
         if let Some(evc) = &mut self.evc {
-            evc.reg(ev_name, cb);
+            evc.reg_bubble(kind, cb);
         }
     }
 
@@ -510,6 +615,21 @@ impl WidgetImpl {
         self.unique_id == self.notifier.as_ref().map(|n| n.active()).flatten().unwrap_or(0)
     }
 
+    pub fn focus(&self) {
+        self.notifier.as_ref().map(|n| n.focus(self.unique_id));
+    }
+
+    /// Only removes the focus if this widget actually has it.
+    pub fn blur(&self) {
+        if self.is_focused() {
+            self.notifier.as_ref().map(|n| n.blur());
+        }
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.unique_id == self.notifier.as_ref().map(|n| n.focused()).flatten().unwrap_or(0)
+    }
+
     pub fn set_notifier(&mut self, not: UINotifierRef) {
         self.notifier = Some(not);
     }
@@ -557,6 +677,10 @@ impl WidgetImpl {
         self.ctrl.as_ref().map(|c| c.can_hover()).unwrap_or(false)
     }
 
+    pub fn can_focus(&self) -> bool {
+        self.ctrl.as_ref().map(|c| c.can_focus()).unwrap_or(false)
+    }
+
     pub fn with_ctrl<R, F: FnOnce(&mut Control) -> R>(&mut self, f: F) -> Option<R> {
         if let Some(ctrl) = self.ctrl.as_mut() {
             Some(f(ctrl))
@@ -0,0 +1,984 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use super::ModifierTracker;
+use crate::blocklang::{BlockLanguage, BlockUserInput};
+use crate::{BlockPos, EvPayload, Event, InputEvent, MButton, Validation, Widget};
+
+use keyboard_types::{Key, Modifiers};
+
+use crate::style::*;
+
+use crate::painter::*;
+use crate::rect::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// How many lines of the [crate::blocklang::BlockType] description
+/// are shown below the list.
+const HELP_LINES: usize = 3;
+
+/// Scores how well `pattern` matches `text`. All characters of the
+/// pattern have to appear in the text in the same order, ignoring case
+/// and whitespace in the pattern. Matches at the start of the text and
+/// consecutive matches score higher, unmatched characters lower.
+/// Returns `None` if the pattern does not match.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i32> {
+    let mut text_chars = text.chars().flat_map(char::to_lowercase).enumerate();
+    let mut score = 0;
+    let mut matched = 0;
+    let mut last_match: Option<usize> = None;
+
+    for pc in pattern.chars().flat_map(char::to_lowercase) {
+        if pc.is_whitespace() {
+            continue;
+        }
+
+        loop {
+            let (idx, tc) = text_chars.next()?;
+            if tc == pc {
+                score += 1;
+                if idx == 0 {
+                    score += 3;
+                } else if last_match == Some(idx - 1) {
+                    score += 2;
+                }
+
+                matched += 1;
+                last_match = Some(idx);
+                break;
+            }
+        }
+    }
+
+    let unmatched = text.chars().flat_map(char::to_lowercase).count() - matched;
+    Some(score - unmatched as i32)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PaletteRow {
+    Category(String),
+    Type(String),
+    Identifier(String),
+}
+
+impl PaletteRow {
+    fn is_selectable(&self) -> bool {
+        !matches!(self, PaletteRow::Category(_))
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            PaletteRow::Category(s) => s,
+            PaletteRow::Type(s) => s,
+            PaletteRow::Identifier(s) => s,
+        }
+    }
+}
+
+/// Groups the block types of `lang` that match `query` by their category.
+/// The categories are sorted by name, or by their best match if there
+/// is a query.
+fn type_rows(lang: &BlockLanguage, query: &str) -> Vec<PaletteRow> {
+    // The best score, name and the (score, name) of the types of each category:
+    type Category = (i32, String, Vec<(i32, String)>);
+    let mut categories: Vec<Category> = vec![];
+
+    for (category, name, _) in lang.get_type_list() {
+        let score = if let Some(score) = fuzzy_score(query, &name) { score } else { continue };
+
+        if let Some(cat) = categories.iter_mut().find(|c| c.1 == category) {
+            cat.0 = cat.0.max(score);
+            cat.2.push((score, name));
+        } else {
+            categories.push((score, category, vec![(score, name)]));
+        }
+    }
+
+    let by_score = !query.trim().is_empty();
+    let order = |a: i32, b: i32| if by_score { b.cmp(&a) } else { std::cmp::Ordering::Equal };
+
+    for cat in categories.iter_mut() {
+        cat.2.sort_by(|a, b| order(a.0, b.0).then(a.1.cmp(&b.1)));
+    }
+    categories.sort_by(|a, b| order(a.0, b.0).then(a.1.cmp(&b.1)));
+
+    let mut rows = vec![];
+    for (_, category, types) in categories {
+        rows.push(PaletteRow::Category(category));
+        for (_, name) in types {
+            rows.push(PaletteRow::Type(name));
+        }
+    }
+
+    rows
+}
+
+/// The identifiers of `lang` that match `query`, best matches first.
+fn identifier_rows(lang: &BlockLanguage, query: &str) -> Vec<PaletteRow> {
+    let mut ids: Vec<(i32, String)> = lang
+        .list_identifiers()
+        .into_iter()
+        .filter_map(|id| fuzzy_score(query, &id).map(|score| (score, id)))
+        .collect();
+    if !query.trim().is_empty() {
+        // The list is already sorted by name, which is kept for equal scores:
+        ids.sort_by_key(|id| std::cmp::Reverse(id.0));
+    }
+
+    ids.into_iter().map(|(_, id)| PaletteRow::Identifier(id)).collect()
+}
+
+fn user_input_name(input: BlockUserInput) -> &'static str {
+    match input {
+        BlockUserInput::Float => "float",
+        BlockUserInput::Integer => "integer",
+        BlockUserInput::Identifier => "identifier",
+        BlockUserInput::ClientDecision => "...",
+        BlockUserInput::None => "",
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PalettePrompt {
+    typ: String,
+    input: BlockUserInput,
+    text: String,
+    invalid: bool,
+}
+
+/// A searchable palette of the block types of a [BlockLanguage],
+/// usually shown in a popup next to a [crate::BlockCode].
+///
+/// Typing filters the types with [fuzzy_score], the arrow keys and
+/// Enter or a click pick one. Types that need a [BlockUserInput] prompt
+/// for the value first, identifiers are suggested from
+/// [BlockLanguage::list_identifiers]. The picked type is emitted as
+/// "instantiate" event with [EvPayload::BlockInstantiate] and the
+/// target set by [BlockPalette::set_target].
+pub struct BlockPalette {
+    lang: Rc<RefCell<BlockLanguage>>,
+    target: Option<BlockPos>,
+    query: String,
+    prompt: Option<PalettePrompt>,
+    rows: Vec<PaletteRow>,
+    selected: Option<usize>,
+    scroll: usize,
+    shown_rows: usize,
+    modkeys: ModifierTracker,
+    hover: Option<usize>,
+    item_areas: Vec<(usize, Rect)>,
+    generation: u64,
+}
+
+impl BlockPalette {
+    pub fn new(lang: Rc<RefCell<BlockLanguage>>) -> Self {
+        let mut palette = Self {
+            lang,
+            target: None,
+            query: String::new(),
+            prompt: None,
+            rows: vec![],
+            selected: None,
+            scroll: 0,
+            shown_rows: 0,
+            modkeys: ModifierTracker::new(),
+            hover: None,
+            item_areas: vec![],
+            generation: 0,
+        };
+        palette.update_rows();
+        palette
+    }
+
+    /// Starts a new search for a block to be placed at `at`. Also
+    /// picks up types that were defined in the language in the meantime.
+    pub fn set_target(&mut self, at: Option<BlockPos>) {
+        self.target = at;
+        self.query.clear();
+        self.prompt = None;
+        self.update_rows();
+    }
+
+    pub fn target(&self) -> Option<BlockPos> {
+        self.target
+    }
+
+    pub fn get_generation(&mut self) -> u64 {
+        self.generation
+    }
+
+    fn update_rows(&mut self) {
+        let lang = self.lang.borrow();
+
+        if let Some(prompt) = &self.prompt {
+            self.rows = if prompt.input == BlockUserInput::Identifier {
+                identifier_rows(&lang, &prompt.text)
+            } else {
+                vec![]
+            };
+            // The typed text is taken unless a suggestion is picked:
+            self.selected = None;
+        } else {
+            self.rows = type_rows(&lang, &self.query);
+            self.selected = self.rows.iter().position(|r| r.is_selectable());
+        }
+
+        self.scroll = 0;
+        self.hover = None;
+        self.generation += 1;
+    }
+
+    fn select_offs(&mut self, offs: i32) {
+        let selectable: Vec<usize> =
+            (0..self.rows.len()).filter(|i| self.rows[*i].is_selectable()).collect();
+        if selectable.is_empty() {
+            return;
+        }
+
+        let next = match self.selected.and_then(|s| selectable.iter().position(|i| *i == s)) {
+            Some(cur) => (cur as i32 + offs).max(0).min(selectable.len() as i32 - 1) as usize,
+            None if offs < 0 => selectable.len() - 1,
+            None => 0,
+        };
+        let sel = selectable[next];
+        self.selected = Some(sel);
+
+        // Keep the category header above the first type of a group in view:
+        let top = if sel > 0 && !self.rows[sel - 1].is_selectable() { sel - 1 } else { sel };
+        if top < self.scroll {
+            self.scroll = top;
+        } else if self.shown_rows > 0 && sel >= self.scroll + self.shown_rows {
+            self.scroll = sel + 1 - self.shown_rows;
+        }
+
+        self.generation += 1;
+    }
+
+    fn scroll_by(&mut self, offs: i32) {
+        let max = self.rows.len().saturating_sub(self.shown_rows) as i32;
+        self.scroll = (self.scroll as i32 + offs).max(0).min(max) as usize;
+        self.generation += 1;
+    }
+
+    fn edit_text(&mut self, f: impl FnOnce(&mut String)) {
+        if let Some(prompt) = &mut self.prompt {
+            f(&mut prompt.text);
+            prompt.invalid = false;
+        } else {
+            f(&mut self.query);
+        }
+
+        self.update_rows();
+    }
+
+    /// The name of the type the help text is shown for.
+    fn current_type(&self) -> Option<String> {
+        if let Some(prompt) = &self.prompt {
+            return Some(prompt.typ.clone());
+        }
+
+        match self.selected.and_then(|i| self.rows.get(i)) {
+            Some(PaletteRow::Type(name)) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    fn emit_instantiate(
+        &mut self,
+        w: &Widget,
+        typ: String,
+        user_input: Option<String>,
+        out_events: &mut Vec<(usize, Event)>,
+    ) {
+        out_events.push(w.event(
+            "instantiate",
+            EvPayload::BlockInstantiate { typ, user_input, at: self.target },
+        ));
+
+        self.query.clear();
+        self.prompt = None;
+        self.update_rows();
+    }
+
+    fn pick(&mut self, w: &Widget, out_events: &mut Vec<(usize, Event)>) {
+        let row = self.selected.and_then(|i| self.rows.get(i)).cloned();
+
+        if let Some(prompt) = self.prompt.clone() {
+            let text = match row {
+                Some(PaletteRow::Identifier(id)) => id,
+                _ => prompt.text,
+            };
+
+            let text = match prompt.input.validate(&text) {
+                Validation::Valid => text,
+                Validation::Normalized(text) => text,
+                Validation::Invalid => {
+                    if let Some(prompt) = &mut self.prompt {
+                        prompt.invalid = true;
+                    }
+                    self.generation += 1;
+                    return;
+                }
+            };
+
+            self.emit_instantiate(w, prompt.typ, Some(text), out_events);
+            return;
+        }
+
+        let name = if let Some(PaletteRow::Type(name)) = row { name } else { return };
+        let input = self.lang.borrow().get_type(&name).map(|t| t.user_input);
+
+        match input {
+            Some(
+                input @ (BlockUserInput::Float
+                | BlockUserInput::Integer
+                | BlockUserInput::Identifier),
+            ) => {
+                self.prompt =
+                    Some(PalettePrompt { typ: name, input, text: String::new(), invalid: false });
+                self.update_rows();
+            }
+            _ => {
+                self.emit_instantiate(w, name, None, out_events);
+            }
+        }
+    }
+
+    /// Tab completes the search or the prompt with the selected row.
+    fn complete(&mut self) {
+        let label = match self.selected.and_then(|i| self.rows.get(i)) {
+            Some(row) => row.label().to_string(),
+            None => return,
+        };
+
+        if let Some(prompt) = &mut self.prompt {
+            prompt.text = label;
+            prompt.invalid = false;
+        } else {
+            self.query = label;
+        }
+
+        self.update_rows();
+    }
+
+    /// Escape leaves the value prompt or clears the search.
+    fn cancel(&mut self) {
+        if self.prompt.is_some() {
+            self.prompt = None;
+        } else {
+            self.query.clear();
+        }
+
+        self.update_rows();
+    }
+
+    fn mouse_zone(&self) -> Option<usize> {
+        let (x, y) = (self.modkeys.mouse.x, self.modkeys.mouse.y);
+        self.item_areas.iter().find(|ia| ia.1.is_inside(x, y)).map(|ia| ia.0)
+    }
+
+    pub fn handle(&mut self, w: &Widget, event: &InputEvent, out_events: &mut Vec<(usize, Event)>) {
+        let is_hovered = w.is_hovered();
+
+        self.modkeys.handle(event);
+
+        match event {
+            InputEvent::MouseButtonPressed(MButton::Left) => {
+                if !is_hovered {
+                    return;
+                }
+                if self.mouse_zone().is_some() {
+                    w.activate();
+                    w.emit_redraw_required();
+                }
+            }
+            InputEvent::MouseButtonReleased(MButton::Left) if w.is_active() => {
+                if let Some(zone) = self.mouse_zone() {
+                    self.selected = Some(zone);
+                    self.pick(w, out_events);
+                }
+
+                w.emit_redraw_required();
+                w.deactivate();
+            }
+            InputEvent::MouseWheel(y) => {
+                if !is_hovered {
+                    return;
+                }
+                self.scroll_by(if *y < 0.0 { 3 } else { -3 });
+                self.hover = None;
+                w.emit_redraw_required();
+            }
+            InputEvent::KeyPressed(key) => {
+                if !w.is_focused() || !w.is_visible() {
+                    return;
+                }
+
+                if key.modifiers.contains(Modifiers::CONTROL) {
+                    return;
+                }
+
+                let page = self.shown_rows.max(2) as i32 - 1;
+
+                match &key.key {
+                    Key::Character(s) => {
+                        let s: String = s.chars().filter(|c| !c.is_control()).collect();
+                        if s.is_empty() {
+                            return;
+                        }
+                        self.edit_text(|text| text.push_str(&s));
+                    }
+                    Key::Backspace => {
+                        self.edit_text(|text| {
+                            text.pop();
+                        });
+                    }
+                    Key::ArrowUp => self.select_offs(-1),
+                    Key::ArrowDown => self.select_offs(1),
+                    Key::PageUp => self.select_offs(-page),
+                    Key::PageDown => self.select_offs(page),
+                    Key::Enter => self.pick(w, out_events),
+                    Key::Tab => self.complete(),
+                    Key::Escape => self.cancel(),
+                    _ => return,
+                }
+
+                w.emit_redraw_required();
+            }
+            InputEvent::MousePosition(_x, _y) => {
+                if !is_hovered {
+                    if self.hover.is_some() {
+                        self.hover = None;
+                        w.emit_redraw_required();
+                    }
+                    return;
+                }
+
+                let old_hover = self.hover;
+                self.hover = self.mouse_zone();
+
+                if old_hover != self.hover {
+                    w.emit_redraw_required();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Breaks `text` into lines that fit into `width`.
+    fn wrap_text(p: &mut Painter, size: f32, text: &str, width: f32) -> Vec<String> {
+        let mut lines = vec![];
+        let mut line = String::new();
+
+        for word in text.split_whitespace() {
+            let candidate =
+                if line.is_empty() { word.to_string() } else { line.clone() + " " + word };
+
+            if !line.is_empty() && p.text_width(size, false, &candidate) > width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+
+        if !line.is_empty() {
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    pub fn draw(
+        &mut self,
+        w: &Widget,
+        style: &DPIStyle,
+        pos: Rect,
+        real_pos: Rect,
+        p: &mut Painter,
+    ) {
+        let is_hovered = w.is_hovered();
+        let is_active = w.is_active();
+        let is_focused = w.is_focused();
+
+        let mut dbg = w.debug_tag();
+        let (dx, dy) = (real_pos.x - pos.x, real_pos.y - pos.y);
+        dbg.set_offs((dx, dy));
+
+        let size = style.font_size();
+        let fh = p.font_height(size, false);
+        let pad = style.pad_item();
+        let line_h = (2.0 * pad + style.border2() + fh).round();
+
+        let search_pos = Rect::from(pos.x, pos.y, pos.w, line_h);
+        let help_pos = Rect::from(
+            pos.x,
+            pos.y + pos.h - line_h * (HELP_LINES as f32),
+            pos.w,
+            line_h * (HELP_LINES as f32),
+        );
+        let list_pos = pos.crop_top(line_h).crop_bottom(help_pos.h);
+
+        // The search line, or the prompt for the user input:
+        let border_clr = if is_focused { style.focus_color() } else { style.border_color() };
+        p.rect_stroke(
+            style.border2(),
+            border_clr,
+            search_pos.x,
+            search_pos.y,
+            search_pos.w,
+            search_pos.h,
+        );
+
+        let cursor = if is_focused { "|" } else { "" };
+        let (search_txt, search_clr) = if let Some(prompt) = &self.prompt {
+            let clr = if prompt.invalid { style.invalid_color() } else { style.color() };
+            (
+                format!(
+                    "{} {}: {}{}",
+                    prompt.typ,
+                    user_input_name(prompt.input),
+                    prompt.text,
+                    cursor
+                ),
+                clr,
+            )
+        } else if self.query.is_empty() {
+            (format!("{}type to search...", cursor), style.inactive_color())
+        } else {
+            (format!("{}{}", self.query, cursor), style.color())
+        };
+
+        let search_inner = search_pos.shrink(pad, pad);
+        p.label(
+            size,
+            -1,
+            search_clr,
+            search_inner.x,
+            search_inner.y,
+            search_inner.w,
+            search_inner.h,
+            &search_txt,
+            dbg.source("search"),
+        );
+
+        // The list of categories and types, or identifier suggestions:
+        self.shown_rows = (list_pos.h / line_h).floor().max(0.0) as usize;
+        self.scroll = self.scroll.min(self.rows.len().saturating_sub(self.shown_rows));
+        self.item_areas.clear();
+
+        p.clip_region(list_pos.x, list_pos.y, list_pos.w, list_pos.h);
+
+        if self.rows.is_empty() {
+            let hint = match &self.prompt {
+                Some(_) => "Enter: confirm, Escape: back",
+                None => "no matching block",
+            };
+            p.label(
+                size,
+                0,
+                style.inactive_color(),
+                list_pos.x,
+                list_pos.y,
+                list_pos.w,
+                line_h,
+                hint,
+                dbg.source("hint"),
+            );
+        }
+
+        let lang = self.lang.borrow();
+
+        for (i, row) in self.rows.iter().enumerate().skip(self.scroll).take(self.shown_rows) {
+            let y = ((i - self.scroll) as f32 * line_h).round();
+            let item_outer = Rect::from(list_pos.x, list_pos.y + y, list_pos.w, line_h);
+            let item_pos = item_outer.shrink(pad, pad);
+
+            if let PaletteRow::Category(category) = row {
+                p.label(
+                    size,
+                    -1,
+                    style.color2(),
+                    item_pos.x,
+                    item_pos.y,
+                    item_pos.w,
+                    item_pos.h,
+                    category,
+                    dbg.source("category"),
+                );
+                p.stroke(
+                    style.border2(),
+                    style.color2(),
+                    &[
+                        (item_outer.x, item_outer.y + item_outer.h - 1.0),
+                        (item_outer.x + item_outer.w, item_outer.y + item_outer.h - 1.0),
+                    ],
+                    false,
+                );
+                continue;
+            }
+
+            let mut color = style.color();
+            if Some(i) == self.selected {
+                color = style.selected_color();
+                p.rect_stroke(
+                    style.border2(),
+                    color,
+                    item_outer.x,
+                    item_outer.y,
+                    item_outer.w,
+                    item_outer.h,
+                );
+            }
+            if is_hovered && self.hover == Some(i) {
+                color = if is_active { style.active_color() } else { style.hover_color() };
+            }
+
+            let indent = if let PaletteRow::Type(_) = row { fh } else { 0.0 };
+            p.label(
+                size,
+                -1,
+                color,
+                item_pos.x + indent,
+                item_pos.y,
+                item_pos.w - indent,
+                item_pos.h,
+                row.label(),
+                dbg.source("item"),
+            );
+
+            if let PaletteRow::Type(name) = row {
+                if let Some(typ) = lang.get_type(name) {
+                    p.label(
+                        size,
+                        1,
+                        style.inactive_color(),
+                        item_pos.x,
+                        item_pos.y,
+                        item_pos.w,
+                        item_pos.h,
+                        user_input_name(typ.user_input),
+                        dbg.source("input"),
+                    );
+                }
+            }
+
+            self.item_areas.push((i, item_outer.offs(dx, dy)));
+        }
+
+        p.reset_clip_region();
+
+        // The description of the selected type as help text:
+        p.stroke(
+            style.border2(),
+            style.border_color(),
+            &[(help_pos.x, help_pos.y), (help_pos.x + help_pos.w, help_pos.y)],
+            false,
+        );
+
+        let description = self
+            .current_type()
+            .and_then(|name| lang.get_type(&name).map(|t| t.description.clone()))
+            .unwrap_or_default();
+
+        let help_inner = help_pos.shrink(pad, pad);
+        let lines = Self::wrap_text(p, size, &description, help_inner.w);
+        for (i, line) in lines.iter().take(HELP_LINES).enumerate() {
+            p.label(
+                size,
+                -1,
+                style.color2(),
+                help_inner.x,
+                help_inner.y + (i as f32) * line_h,
+                help_inner.w,
+                line_h,
+                line,
+                dbg.source("help"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocklang::BlockType;
+    use crate::{Control, EvKind, UI};
+    use keyboard_types::KeyboardEvent;
+
+    fn test_lang() -> BlockLanguage {
+        let mut lang = BlockLanguage::new();
+
+        for (category, name, user_input) in [
+            ("literals", "value", BlockUserInput::Float),
+            ("literals", "zero", BlockUserInput::None),
+            ("variables", "get", BlockUserInput::Identifier),
+            ("variables", "set", BlockUserInput::Identifier),
+            ("functions", "sin", BlockUserInput::None),
+            ("functions", "sqrt", BlockUserInput::None),
+        ] {
+            lang.define(BlockType {
+                category: category.to_string(),
+                user_input,
+                ..BlockType::for_test(name, &[], &[""])
+            });
+        }
+
+        lang.define_identifier("alpha");
+        lang.define_identifier("beta");
+        lang.define_identifier("gamma");
+
+        lang
+    }
+
+    fn labels(rows: &[PaletteRow]) -> Vec<String> {
+        rows.iter()
+            .map(|r| match r {
+                PaletteRow::Category(s) => format!("[{}]", s),
+                _ => r.label().to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn check_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "sin"), Some(-3));
+        assert!(fuzzy_score("sn", "sin").is_some());
+        assert!(fuzzy_score("SIN", "sin").is_some());
+        assert!(fuzzy_score("ns", "sin").is_none());
+        assert!(fuzzy_score("sinx", "sin").is_none());
+
+        // Exact and prefix matches rank above scattered ones:
+        assert!(fuzzy_score("sin", "sin") > fuzzy_score("sin", "sinc"));
+        assert!(fuzzy_score("de", "delay") > fuzzy_score("de", "node"));
+        assert!(fuzzy_score("sq", "sqrt") > fuzzy_score("sq", "setq"));
+    }
+
+    #[test]
+    fn check_palette_type_rows() {
+        let lang = test_lang();
+
+        assert_eq!(
+            labels(&type_rows(&lang, "")),
+            vec![
+                "[functions]",
+                "sin",
+                "sqrt",
+                "[literals]",
+                "value",
+                "zero",
+                "[variables]",
+                "get",
+                "set",
+            ]
+        );
+
+        assert_eq!(
+            labels(&type_rows(&lang, "s")),
+            vec!["[functions]", "sin", "sqrt", "[variables]", "set",]
+        );
+
+        assert_eq!(labels(&type_rows(&lang, "et")), vec!["[variables]", "get", "set"]);
+        assert!(type_rows(&lang, "xyz").is_empty());
+    }
+
+    #[test]
+    fn check_palette_identifier_rows() {
+        let lang = test_lang();
+
+        assert_eq!(labels(&identifier_rows(&lang, "")), vec!["alpha", "beta", "gamma"]);
+        assert_eq!(labels(&identifier_rows(&lang, "a")), vec!["alpha", "beta", "gamma"]);
+        assert_eq!(labels(&identifier_rows(&lang, "ga")), vec!["gamma"]);
+    }
+
+    type InstantiateLog = Rc<RefCell<Vec<(String, Option<String>, Option<BlockPos>)>>>;
+
+    const TARGET: BlockPos = BlockPos::Cell { id: 0, x: 1, y: 2 };
+
+    fn palette_ui(lang: BlockLanguage) -> (UI, Widget, InstantiateLog) {
+        let mut palette = BlockPalette::new(Rc::new(RefCell::new(lang)));
+        palette.set_target(Some(TARGET));
+
+        let (mut ui, mut wids) =
+            UI::for_test(vec![Control::BlockPalette { palette: Box::new(palette) }]);
+        let wid = wids.remove(0);
+        ui.focus(&wid);
+
+        let log: InstantiateLog = Rc::new(RefCell::new(vec![]));
+        let cb_log = log.clone();
+        wid.reg(EvKind::Instantiate, move |_ctx, _wid, ev| {
+            if let EvPayload::BlockInstantiate { typ, user_input, at } = &ev.data {
+                cb_log.borrow_mut().push((typ.clone(), user_input.clone(), *at));
+            }
+        });
+
+        (ui, wid, log)
+    }
+
+    fn with_palette<R>(wid: &Widget, f: impl FnOnce(&BlockPalette) -> R) -> R {
+        wid.with_ctrl(|ctrl| match ctrl {
+            Control::BlockPalette { palette } => f(palette),
+            _ => panic!("no block palette"),
+        })
+        .expect("widget has a control")
+    }
+
+    fn key(key: Key) -> InputEvent {
+        InputEvent::KeyPressed(KeyboardEvent { key, ..Default::default() })
+    }
+
+    fn type_text(ui: &mut UI, text: &str) {
+        for c in text.chars() {
+            ui.handle_input_event(key(Key::Character(c.to_string())));
+        }
+    }
+
+    fn erase(ui: &mut UI, count: usize) {
+        for _ in 0..count {
+            ui.handle_input_event(key(Key::Backspace));
+        }
+    }
+
+    fn prompt_state(wid: &Widget) -> Option<(String, String, bool)> {
+        with_palette(wid, |p| {
+            p.prompt.as_ref().map(|pr| (pr.typ.clone(), pr.text.clone(), pr.invalid))
+        })
+    }
+
+    fn selected_label(wid: &Widget) -> Option<String> {
+        with_palette(wid, |p| p.selected.map(|i| p.rows[i].label().to_string()))
+    }
+
+    #[test]
+    fn check_palette_typing_filters() {
+        let (mut ui, wid, log) = palette_ui(test_lang());
+
+        type_text(&mut ui, "sq");
+        assert_eq!(with_palette(&wid, |p| p.query.clone()), "sq");
+        assert_eq!(with_palette(&wid, |p| labels(&p.rows)), vec!["[functions]", "sqrt"]);
+        assert_eq!(selected_label(&wid).as_deref(), Some("sqrt"));
+
+        erase(&mut ui, 1);
+        assert_eq!(
+            with_palette(&wid, |p| labels(&p.rows)),
+            vec!["[functions]", "sin", "sqrt", "[variables]", "set"]
+        );
+        assert_eq!(selected_label(&wid).as_deref(), Some("sin"));
+
+        // The arrow keys skip the category headers:
+        ui.handle_input_event(key(Key::ArrowDown));
+        ui.handle_input_event(key(Key::ArrowDown));
+        assert_eq!(selected_label(&wid).as_deref(), Some("set"));
+
+        ui.handle_input_event(key(Key::Escape));
+        assert_eq!(with_palette(&wid, |p| p.query.clone()), "");
+        assert_eq!(with_palette(&wid, |p| p.rows.len()), 9);
+
+        // An unfocused palette ignores the keyboard:
+        ui.blur();
+        type_text(&mut ui, "sq");
+        assert_eq!(with_palette(&wid, |p| p.query.clone()), "");
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn check_palette_enter_instantiates() {
+        let (mut ui, wid, log) = palette_ui(test_lang());
+
+        type_text(&mut ui, "sqrt");
+        ui.handle_input_event(key(Key::Enter));
+        assert_eq!(*log.borrow(), vec![("sqrt".to_string(), None, Some(TARGET))]);
+
+        // The search starts over for the next block:
+        assert_eq!(with_palette(&wid, |p| p.query.clone()), "");
+        assert!(prompt_state(&wid).is_none());
+
+        // Nothing is emitted without a matching type:
+        type_text(&mut ui, "xyz");
+        ui.handle_input_event(key(Key::Enter));
+        assert_eq!(log.borrow().len(), 1);
+    }
+
+    #[test]
+    fn check_palette_prompt_rejects_invalid() {
+        let mut lang = test_lang();
+        lang.define(BlockType {
+            category: "literals".to_string(),
+            user_input: BlockUserInput::Integer,
+            ..BlockType::for_test("count", &[], &[""])
+        });
+        let (mut ui, wid, log) = palette_ui(lang);
+
+        for (typ, invalid, valid, expected) in [
+            ("value", "1.5x", " 1.5", "1.5"),
+            ("count", "1.5", "-3", "-3"),
+            ("get", "a b", "delta", "delta"),
+        ] {
+            type_text(&mut ui, typ);
+            ui.handle_input_event(key(Key::Enter));
+            assert_eq!(prompt_state(&wid), Some((typ.to_string(), String::new(), false)));
+            assert!(log.borrow().is_empty());
+
+            type_text(&mut ui, invalid);
+            ui.handle_input_event(key(Key::Enter));
+            assert_eq!(prompt_state(&wid), Some((typ.to_string(), invalid.to_string(), true)));
+            assert!(log.borrow().is_empty());
+
+            // Editing the text clears the error:
+            erase(&mut ui, invalid.len());
+            assert_eq!(prompt_state(&wid), Some((typ.to_string(), String::new(), false)));
+
+            type_text(&mut ui, valid);
+            ui.handle_input_event(key(Key::Enter));
+            assert!(prompt_state(&wid).is_none());
+            assert_eq!(
+                log.borrow_mut().pop(),
+                Some((typ.to_string(), Some(expected.to_string()), Some(TARGET)))
+            );
+        }
+
+        // Escape leaves the prompt without emitting anything:
+        type_text(&mut ui, "value");
+        ui.handle_input_event(key(Key::Enter));
+        assert!(prompt_state(&wid).is_some());
+        ui.handle_input_event(key(Key::Escape));
+        assert!(prompt_state(&wid).is_none());
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn check_palette_pick_identifier() {
+        let (mut ui, wid, log) = palette_ui(test_lang());
+
+        type_text(&mut ui, "set");
+        ui.handle_input_event(key(Key::Enter));
+        assert_eq!(with_palette(&wid, |p| labels(&p.rows)), vec!["alpha", "beta", "gamma"]);
+        // The typed text is taken until a suggestion is selected:
+        assert_eq!(selected_label(&wid), None);
+
+        type_text(&mut ui, "a");
+        ui.handle_input_event(key(Key::ArrowDown));
+        ui.handle_input_event(key(Key::ArrowDown));
+        assert_eq!(selected_label(&wid).as_deref(), Some("beta"));
+        ui.handle_input_event(key(Key::Enter));
+        assert_eq!(
+            *log.borrow(),
+            vec![("set".to_string(), Some("beta".to_string()), Some(TARGET))]
+        );
+
+        // Tab completes the prompt with the selected suggestion:
+        type_text(&mut ui, "get");
+        ui.handle_input_event(key(Key::Enter));
+        type_text(&mut ui, "ga");
+        ui.handle_input_event(key(Key::ArrowDown));
+        ui.handle_input_event(key(Key::Tab));
+        assert_eq!(prompt_state(&wid), Some(("get".to_string(), "gamma".to_string(), false)));
+        ui.handle_input_event(key(Key::Enter));
+        assert_eq!(
+            log.borrow().last(),
+            Some(&("get".to_string(), Some("gamma".to_string()), Some(TARGET)))
+        );
+    }
+}
@@ -3,6 +3,9 @@
 // See README.md and COPYING for details.
 
 use crate::{EvPayload, Event, InputEvent, MButton, Widget};
+use keyboard_types::{Key, Modifiers};
+
+use super::{pan_offset, ModifierTracker};
 
 use crate::style::*;
 
@@ -11,52 +14,83 @@ use crate::rect::*;
 
 use std::sync::{Arc, Mutex};
 
-use hexodsp::wblockdsp::BlockCodeView;
+use crate::blocklang::{BlockCodeView, BlockPos};
+
+/// The maximum number of blocks that are followed for a chain selection.
+const BLOCK_CHAIN_LIMIT: usize = 256;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum BlockPos {
-    Block { id: usize, x: i64, y: i64, row: usize, col: usize, rows: usize },
-    Cell { id: usize, x: i64, y: i64 },
+pub const BLOCK_ZOOM_MIN: f32 = 0.25;
+pub const BLOCK_ZOOM_MAX: f32 = 3.0;
+/// Factor the zoom changes with one step of the mouse wheel.
+pub const BLOCK_ZOOM_STEP: f32 = 1.1;
+pub const BLOCK_SCROLLBAR_W: f32 = 8.0;
+
+/// The ID of the block, that has its origin at `pos`.
+fn block_id_at(code: &dyn BlockCodeView, pos: &BlockPos) -> Option<usize> {
+    let (area, x, y) = pos.block_origin()?;
+    code.block_at(area, x, y).map(|block| block.id())
 }
 
-#[allow(dead_code)]
-impl BlockPos {
-    pub fn area_id(&self) -> usize {
-        match self {
-            BlockPos::Block { id, .. } => *id,
-            BlockPos::Cell { id, .. } => *id,
-        }
-    }
+/// A paste or duplicate operation waiting for the destination,
+/// which is chosen with the mouse.
+struct PastePreview {
+    src: BlockPos,
+    chain: bool,
+    duplicate: bool,
+    /// Position relative to the source block, rows and label of all blocks.
+    ghost: Vec<(i64, i64, usize, String)>,
+}
 
-    pub fn x(&self) -> i64 {
-        match self {
-            BlockPos::Block { x, .. } => *x,
-            BlockPos::Cell { x, .. } => *x,
-        }
-    }
+/// Returns the origins of the block at `x`/`y` and of all blocks that
+/// are connected to its outputs, directly or through other blocks.
+/// These are the blocks of a chain selection in the [BlockCode].
+pub fn block_chain(code: &dyn BlockCodeView, area: usize, x: i64, y: i64) -> Vec<(i64, i64)> {
+    let mut out = vec![(x, y)];
+    let mut i = 0;
 
-    pub fn y(&self) -> i64 {
-        match self {
-            BlockPos::Block { y, .. } => *y,
-            BlockPos::Cell { y, .. } => *y,
-        }
-    }
+    while i < out.len() && out.len() < BLOCK_CHAIN_LIMIT {
+        let (bx, by) = out[i];
+        i += 1;
 
-    pub fn row_info(&self) -> (usize, usize) {
-        match self {
-            BlockPos::Block { rows, row, .. } => (*rows, *row),
-            BlockPos::Cell { .. } => (1, 0),
-        }
-    }
+        let block = if let Some(block) = code.block_at(area, bx, by) {
+            block
+        } else {
+            continue;
+        };
+
+        for row in 0..block.rows() {
+            if !block.has_output(row) {
+                continue;
+            }
 
-    pub fn pos(&self) -> (usize, i64, i64) {
-        match self {
-            BlockPos::Block { id, x, y, .. } => (*id, *x, *y),
-            BlockPos::Cell { id, x, y, .. } => (*id, *x, *y),
+            let (nx, ny) = (bx + 1, by + row as i64);
+            if let Some((ox, oy)) = code.origin_at(area, nx, ny) {
+                let has_input =
+                    code.block_at(area, ox, oy).map(|b| b.has_input((ny - oy) as usize));
+
+                if has_input == Some(true) && !out.contains(&(ox, oy)) {
+                    out.push((ox, oy));
+                }
+            }
         }
     }
+
+    out
 }
 
+/// Displays a [BlockCodeView] and emits [EvPayload::BlockPos] events for
+/// clicks and drags. The widget never changes the code itself, so the
+/// callbacks that apply the changes should also push the matching
+/// [crate::UndoCommand] with [Widget::push_undo].
+///
+/// Ctrl+click selects a block, Ctrl+Shift+click the block with all blocks
+/// connected to its outputs. While focused, Ctrl+C sends a "copy" event for
+/// the selection. Ctrl+V (for the copied blocks) and Ctrl+D (for the
+/// selection) show a preview at the mouse, a left click then sends a
+/// "paste" or "duplicate" event. These events carry an [EvPayload::BlockClip].
+///
+/// The mouse wheel zooms, Shift+wheel scrolls vertically and Ctrl+wheel
+/// horizontally. The view can also be moved with middle-drag and the scrollbars.
 pub struct BlockCode {
     code: Arc<Mutex<dyn BlockCodeView>>,
 
@@ -74,6 +108,26 @@ pub struct BlockCode {
 
     mouse_pos: (f32, f32),
     real_pos: Rect,
+
+    modkeys: ModifierTracker,
+    /// The selected block, whether its chain is selected and the ID of
+    /// the block, to notice when it was moved or removed.
+    selection: Option<(BlockPos, bool, usize)>,
+    clip: Option<(BlockPos, bool, usize)>,
+    /// The [BlockCodeView::generation] the selection and clip were checked at.
+    checked_gen: u64,
+    paste: Option<PastePreview>,
+
+    zoom: f32,
+    dpi_factor: f32,
+    /// Offset from the drawing position to the position in the window.
+    draw_offs: (f32, f32),
+    /// Axis (true for vertical), start mouse position and start shift offset.
+    scroll_drag: Option<(bool, f32, f32)>,
+    /// Set by [BlockCode::set_zoom] and [BlockCode::scroll_into_view],
+    /// which the application may call at any time. The next
+    /// [BlockCode::draw_frame] then requests the redraw.
+    view_changed: bool,
 }
 
 impl BlockCode {
@@ -94,9 +148,328 @@ impl BlockCode {
 
             mouse_pos: (0.0, 0.0),
             real_pos: Rect::from(0.0, 0.0, 0.0, 0.0),
+
+            modkeys: ModifierTracker::new(),
+            selection: None,
+            clip: None,
+            checked_gen: 0,
+            paste: None,
+
+            zoom: 1.0,
+            dpi_factor: 1.0,
+            draw_offs: (0.0, 0.0),
+            scroll_drag: None,
+            view_changed: false,
         }
     }
 
+    fn zoomed_block_size(&self) -> f32 {
+        self.block_size * self.zoom
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Sets the zoom factor, keeping the center of the view in place.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom_at(zoom, (self.real_pos.w * 0.5, self.real_pos.h * 0.5));
+    }
+
+    /// Sets the zoom factor, keeping the point `anchor` relative
+    /// to the widget origin in place.
+    fn zoom_at(&mut self, zoom: f32, anchor: (f32, f32)) {
+        let zoom = zoom.max(BLOCK_ZOOM_MIN).min(BLOCK_ZOOM_MAX);
+        let ratio = zoom / self.zoom;
+
+        self.shift_offs = (
+            anchor.0 - (anchor.0 - self.shift_offs.0) * ratio,
+            anchor.1 - (anchor.1 - self.shift_offs.1) * ratio,
+        );
+        self.zoom = zoom;
+        self.view_changed = true;
+    }
+
+    fn shift(&self) -> (f32, f32) {
+        pan_offset(self.shift_offs, self.tmp_shift_offs)
+    }
+
+    /// Moves the view, so that the block or cell at `pos` is visible.
+    /// Only works after the widget was drawn once.
+    pub fn scroll_into_view(&mut self, pos: BlockPos) {
+        let (area_id, x, y) = pos.block_origin().unwrap_or_else(|| pos.pos());
+        let rows = pos.row_info().0;
+
+        let view = if let Some((_, view)) = self.areas.get(0).and_then(|lvl| lvl.first()) {
+            *view
+        } else {
+            return;
+        };
+        let area = if let Some((_, area)) =
+            self.areas.iter().flat_map(|lvl| lvl.iter()).find(|(id, _)| *id == area_id)
+        {
+            *area
+        } else {
+            return;
+        };
+
+        let block_h = self.dpi_factor * self.zoomed_block_size();
+        let block_w = self.block_w_factor * block_h;
+        let sbw = BLOCK_SCROLLBAR_W * self.dpi_factor;
+
+        let (bx, by) = (area.x + x as f32 * block_w, area.y + y as f32 * block_h);
+        let (bw, bh) = (block_w, rows as f32 * block_h);
+        let (mx, my) = (0.5 * block_w, 0.5 * block_h);
+
+        let (mut sx, mut sy) = self.shift_offs;
+
+        if bx < view.x - sx + mx {
+            sx = view.x - bx + mx;
+        } else if bx + bw > view.x - sx + view.w - sbw - mx {
+            sx = view.x + view.w - sbw - mx - (bx + bw);
+        }
+
+        if by < view.y - sy + my {
+            sy = view.y - by + my;
+        } else if by + bh > view.y - sy + view.h - sbw - my {
+            sy = view.y + view.h - sbw - my - (by + bh);
+        }
+
+        self.shift_offs = (sx, sy);
+        self.view_changed = true;
+    }
+
+    /// Returns the range of the scrollbar as (start, length) and the visible
+    /// part as (start, length) in pixels relative to the top level area origin.
+    /// `None` if everything is visible on that axis.
+    fn scroll_range(&self, vertical: bool) -> Option<((f32, f32), (f32, f32))> {
+        let (area_w, area_h) = self.code.lock().expect("BlockView lockable").area_size(0);
+
+        let block_h = self.dpi_factor * self.zoomed_block_size();
+        let block_w = self.block_w_factor * block_h;
+        let (shift_x, shift_y) = self.shift();
+
+        let (content, view_start, view_len) = if vertical {
+            ((area_h + 1) as f32 * block_h, -shift_y, self.real_pos.h)
+        } else {
+            ((area_w + 1) as f32 * block_w, -shift_x, self.real_pos.w)
+        };
+
+        let start = view_start.min(0.0);
+        let end = content.max(view_start + view_len);
+
+        if end - start <= view_len + 0.5 {
+            None
+        } else {
+            Some(((start, end - start), (view_start, view_len)))
+        }
+    }
+
+    /// Returns the track and thumb of the scrollbar in window coordinates,
+    /// and how many pixels of the range one pixel of the track is.
+    fn scrollbar(&self, vertical: bool) -> Option<(Rect, Rect, f32)> {
+        let ((start, len), (view_start, view_len)) = self.scroll_range(vertical)?;
+
+        let sbw = BLOCK_SCROLLBAR_W * self.dpi_factor;
+        let rp = self.real_pos;
+
+        let track = if vertical {
+            Rect { x: rp.x + rp.w - sbw, y: rp.y, w: sbw, h: rp.h - sbw }
+        } else {
+            Rect { x: rp.x, y: rp.y + rp.h - sbw, w: rp.w - sbw, h: sbw }
+        };
+
+        let track_len = if vertical { track.h } else { track.w };
+        if track_len <= 0.0 {
+            return None;
+        }
+
+        let thumb_len = (view_len / len * track_len).max(2.0 * sbw).min(track_len);
+        let factor = (len - view_len) / (track_len - thumb_len).max(1.0);
+        let thumb_start = (view_start - start) / factor;
+
+        let thumb = if vertical {
+            Rect { x: track.x, y: track.y + thumb_start, w: sbw, h: thumb_len }
+        } else {
+            Rect { x: track.x + thumb_start, y: track.y, w: thumb_len, h: sbw }
+        };
+
+        Some((track, thumb, factor))
+    }
+
+    /// Starts dragging a scrollbar, if the mouse is over one.
+    fn start_scroll_drag(&mut self) -> bool {
+        let (mx, my) = self.mouse_pos;
+
+        for vertical in [false, true] {
+            if let Some((track, thumb, factor)) = self.scrollbar(vertical) {
+                if !track.is_inside(mx, my) {
+                    continue;
+                }
+
+                let (m, thumb_start, thumb_len) =
+                    if vertical { (my, thumb.y, thumb.h) } else { (mx, thumb.x, thumb.w) };
+
+                // Clicking the track moves the thumb center to the mouse:
+                if !thumb.is_inside(mx, my) {
+                    let delta = (m - (thumb_start + 0.5 * thumb_len)) * factor;
+                    if vertical {
+                        self.shift_offs.1 -= delta;
+                    } else {
+                        self.shift_offs.0 -= delta;
+                    }
+                }
+
+                let shift = if vertical { self.shift_offs.1 } else { self.shift_offs.0 };
+                self.scroll_drag = Some((vertical, m, shift));
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn drag_scrollbar(&mut self) {
+        if let Some((vertical, m_start, shift_start)) = self.scroll_drag {
+            if let Some((_, _, factor)) = self.scrollbar(vertical) {
+                if vertical {
+                    self.shift_offs.1 = shift_start - (self.mouse_pos.1 - m_start) * factor;
+                } else {
+                    self.shift_offs.0 = shift_start - (self.mouse_pos.0 - m_start) * factor;
+                }
+            }
+        }
+    }
+
+    /// The selected block and whether the blocks connected to it are
+    /// selected too.
+    pub fn selection(&self) -> Option<(BlockPos, bool)> {
+        self.selection.map(|(pos, chain, _)| (pos, chain))
+    }
+
+    /// Selects the block at `pos`, the selection is dropped if there is none.
+    pub fn set_selection(&mut self, selection: Option<(BlockPos, bool)>) {
+        let code = self.code.lock().expect("BlockView lockable");
+        self.selection =
+            selection.and_then(|(pos, chain)| Some((pos, chain, block_id_at(&*code, &pos)?)));
+    }
+
+    /// The selection and the clip only store the position of the block.
+    /// After the code changed, they are dropped if a different block
+    /// (or none) is at that position now.
+    fn drop_stale_selection(&mut self) {
+        let code = self.code.lock().expect("BlockView lockable");
+        if code.generation() == self.checked_gen {
+            return;
+        }
+        self.checked_gen = code.generation();
+
+        let is_stale = |sel: &Option<(BlockPos, bool, usize)>| match sel {
+            Some((pos, _, id)) => block_id_at(&*code, pos) != Some(*id),
+            None => false,
+        };
+
+        if is_stale(&self.selection) {
+            self.selection = None;
+        }
+        if is_stale(&self.clip) {
+            self.clip = None;
+        }
+    }
+
+    /// The blocks that are selected, as origins in the area of the selection.
+    fn selected_blocks(&self, code: &dyn BlockCodeView) -> Vec<(i64, i64)> {
+        match self.selection.and_then(|(pos, chain, _)| Some((pos.block_origin()?, chain))) {
+            Some(((area, x, y), true)) => block_chain(code, area, x, y),
+            Some(((_, x, y), false)) => vec![(x, y)],
+            None => vec![],
+        }
+    }
+
+    fn start_paste(&mut self, src: BlockPos, chain: bool, duplicate: bool) {
+        let code = self.code.clone();
+        let code = code.lock().expect("BlockView lockable");
+
+        let mut ghost = vec![];
+
+        if let Some((area, ox, oy)) = src.block_origin() {
+            let origins = if chain { block_chain(&*code, area, ox, oy) } else { vec![(ox, oy)] };
+
+            let mut lbl_buf: [u8; 20] = [0; 20];
+            for (x, y) in origins {
+                if let Some(block) = code.block_at(area, x, y) {
+                    let len = block.label(&mut lbl_buf[..]);
+                    let label = std::str::from_utf8(&lbl_buf[0..len]).unwrap_or("").to_string();
+                    ghost.push((x - ox, y - oy, block.rows(), label));
+                }
+            }
+        }
+
+        if ghost.is_empty() {
+            ghost.push((0, 0, 1, String::new()));
+        }
+
+        self.paste = Some(PastePreview { src, chain, duplicate, ghost });
+    }
+
+    fn finish_paste(&mut self, w: &Widget, out_events: &mut Vec<(usize, Event)>) {
+        let (x, y) = self.mouse_pos;
+        let dst = if let Some(dst) = self.find_pos_at_mouse(x, y) { dst } else { return };
+
+        if let Some(paste) = self.paste.take() {
+            let name = if paste.duplicate { "duplicate" } else { "paste" };
+            out_events.push(w.event(
+                name,
+                EvPayload::BlockClip { at: paste.src, chain: paste.chain, to: Some(dst) },
+            ));
+        }
+
+        w.emit_redraw_required();
+    }
+
+    fn handle_clip_key(
+        &mut self,
+        w: &Widget,
+        key: &keyboard_types::KeyboardEvent,
+        out_events: &mut Vec<(usize, Event)>,
+    ) {
+        let ctrl = key.modifiers.contains(Modifiers::CONTROL);
+
+        match &key.key {
+            Key::Escape => {
+                if self.paste.take().is_none() && self.selection.take().is_some() {
+                    out_events.push(w.event("selection", EvPayload::None));
+                }
+            }
+            Key::Enter if self.paste.is_some() => {
+                self.finish_paste(w, out_events);
+            }
+            Key::Character(c) if ctrl => match &c.to_lowercase()[..] {
+                "c" => {
+                    if let Some((at, chain, id)) = self.selection {
+                        self.clip = Some((at, chain, id));
+                        out_events
+                            .push(w.event("copy", EvPayload::BlockClip { at, chain, to: None }));
+                    }
+                }
+                "v" => {
+                    if let Some((at, chain, _)) = self.clip {
+                        self.start_paste(at, chain, false);
+                    }
+                }
+                "d" => {
+                    if let Some((at, chain, _)) = self.selection {
+                        self.start_paste(at, chain, true);
+                    }
+                }
+                _ => return,
+            },
+            _ => return,
+        }
+
+        w.emit_redraw_required();
+    }
+
     pub fn reset_areas(&mut self) {
         for a in self.areas.iter_mut() {
             a.clear();
@@ -112,13 +485,13 @@ impl BlockCode {
     }
 
     fn find_area_at_mouse(&self, x: f32, y: f32) -> Option<(usize, i64, i64, usize)> {
-        let shift_x = (self.shift_offs.0 + self.tmp_shift_offs.map(|o| o.0).unwrap_or(0.0)).round();
-        let shift_y = (self.shift_offs.1 + self.tmp_shift_offs.map(|o| o.1).unwrap_or(0.0)).round();
+        let (shift_x, shift_y) = self.shift();
 
-        let x = x - shift_x;
-        let y = y - shift_y;
+        // The areas are stored in drawing coordinates:
+        let x = x - shift_x - self.draw_offs.0;
+        let y = y - shift_y - self.draw_offs.1;
 
-        let block_h = self.block_size;
+        let block_h = self.dpi_factor * self.zoomed_block_size();
         let block_w = self.block_w_factor * block_h;
 
         for lvl in self.areas.iter().rev() {
@@ -173,15 +546,20 @@ impl BlockCode {
 
         p.clip_region(pos.x, pos.y, pos.w, pos.h);
 
-        let block_h = dpi_f * self.block_size;
+        let block_h = dpi_f * self.zoomed_block_size();
         let block_w = self.block_w_factor * block_h;
 
         let code = self.code.clone();
         let code = code.lock().expect("BlockView lockable");
 
+        let selected = match self.selection.and_then(|(pos, _, _)| pos.block_origin()) {
+            Some((sel_area, _, _)) if sel_area == area_id => self.selected_blocks(&*code),
+            _ => vec![],
+        };
+
         if let Some(s) = code.area_header(area_id) {
             p.label(
-                self.block_size * 0.4,
+                self.zoomed_block_size() * 0.4,
                 -1,
                 style.border_color(),
                 pos.x,
@@ -198,8 +576,7 @@ impl BlockCode {
 
         p.rect_fill(style.bg_color(), pos.x, pos.y, pos.w, pos.h);
 
-        let shift_x = (self.shift_offs.0 + self.tmp_shift_offs.map(|o| o.0).unwrap_or(0.0)).round();
-        let shift_y = (self.shift_offs.1 + self.tmp_shift_offs.map(|o| o.1).unwrap_or(0.0)).round();
+        let (shift_x, shift_y) = self.shift();
 
         let draw_col_offs = if level == 0 { -((shift_x / block_w).round() as i64) } else { 0 };
         let draw_row_offs = if level == 0 { -((shift_y / block_h).round() as i64) } else { 0 };
@@ -263,6 +640,8 @@ impl BlockCode {
                 }
 
                 if let Some(block) = code.block_at(area_id, col, row) {
+                    let has_error = code.block_has_error(area_id, col, row);
+
                     let bg_color = if hover_here {
                         style.block_bg_hover_color()
                     } else {
@@ -270,6 +649,8 @@ impl BlockCode {
                     };
                     let border_color = if hover_here {
                         style.hover_border_color()
+                    } else if has_error {
+                        style.invalid_color()
                     } else {
                         block
                             .custom_color()
@@ -291,12 +672,33 @@ impl BlockCode {
                         h - dpi_f * 4.0,
                     );
 
+                    if selected.contains(&(col, row)) {
+                        p.rect_stroke(
+                            dpi_f * 2.0,
+                            style.selected_color(),
+                            pos.x + x - dpi_f * 1.0,
+                            pos.y + y,
+                            w + dpi_f * 2.0,
+                            h,
+                        );
+                    }
+
+                    if has_error {
+                        let mark = (0.3 * block_h).ceil();
+                        let (mx, my) = (pos.x + x + w, pos.y + y);
+                        p.path_fill(
+                            style.invalid_color(),
+                            &mut [(mx - mark, my), (mx, my), (mx, my + mark)].iter().copied(),
+                            true,
+                        );
+                    }
+
                     let hole_px = (0.6 * block_h).ceil();
 
                     let len = block.label(&mut lbl_buf[..]);
                     let val_s = std::str::from_utf8(&lbl_buf[0..len]).unwrap();
                     p.label(
-                        dpi_f * self.block_size * 0.5,
+                        dpi_f * self.zoomed_block_size() * 0.5,
                         0,
                         style.border_color(),
                         pos.x + x,
@@ -321,7 +723,7 @@ impl BlockCode {
                             let len = block.input_label(i, &mut lbl_buf[..]);
                             let val_s = std::str::from_utf8(&lbl_buf[0..len]).unwrap();
                             p.label(
-                                dpi_f * self.block_size * 0.4,
+                                dpi_f * self.zoomed_block_size() * 0.4,
                                 -1,
                                 style.border_color(),
                                 pos.x + x + dpi_f * 1.0,
@@ -360,7 +762,7 @@ impl BlockCode {
                             let len = block.output_label(i, &mut lbl_buf[..]);
                             let val_s = std::str::from_utf8(&lbl_buf[0..len]).unwrap();
                             p.label(
-                                dpi_f * self.block_size * 0.4,
+                                dpi_f * self.zoomed_block_size() * 0.4,
                                 1,
                                 style.border_color(),
                                 (pos.x + x + (block_w * 0.5) - dpi_f * 1.0).floor(),
@@ -494,6 +896,36 @@ impl BlockCode {
             self.draw_area(style, p, cont_id, apos, level + 1, dbg);
         }
 
+        if let (Some(paste), Some((hover_area, hx, hy, _))) = (&self.paste, self.hover) {
+            if hover_area == area_id {
+                for (dx, dy, rows, label) in paste.ghost.iter() {
+                    let x = (hx + dx) as f32 * block_w;
+                    let y = (hy + dy) as f32 * block_h;
+                    let h = *rows as f32 * block_h;
+
+                    p.rect_stroke(
+                        dpi_f * 2.0,
+                        style.hover_border_color(),
+                        pos.x + x + dpi_f * 1.0,
+                        pos.y + y + dpi_f * 1.0,
+                        block_w - dpi_f * 2.0,
+                        h - dpi_f * 2.0,
+                    );
+                    p.label(
+                        dpi_f * self.zoomed_block_size() * 0.5,
+                        0,
+                        style.hover_border_color(),
+                        pos.x + x,
+                        pos.y + y,
+                        block_w,
+                        h,
+                        label,
+                        dbg.source("paste_ghost"),
+                    );
+                }
+            }
+        }
+
         if level == 0 {
             p.restore();
         }
@@ -504,7 +936,78 @@ impl BlockCode {
 
 impl BlockCode {
     pub fn handle(&mut self, w: &Widget, event: &InputEvent, out_events: &mut Vec<(usize, Event)>) {
+        self.modkeys.handle(event);
+        self.drop_stale_selection();
+
         match event {
+            InputEvent::KeyPressed(key) if w.is_focused() => {
+                self.handle_clip_key(w, key, out_events);
+            }
+            InputEvent::MouseButtonPressed(MButton::Left)
+                if w.is_hovered() && self.start_scroll_drag() =>
+            {
+                w.activate();
+                w.emit_redraw_required();
+            }
+            InputEvent::MousePosition(x, y) if self.scroll_drag.is_some() => {
+                self.mouse_pos = (*x, *y);
+                self.drag_scrollbar();
+                w.emit_redraw_required();
+            }
+            InputEvent::MouseButtonReleased(MButton::Left) if self.scroll_drag.is_some() => {
+                self.scroll_drag = None;
+                w.deactivate();
+                w.emit_redraw_required();
+            }
+            InputEvent::MouseWheel(y) if w.is_hovered() => {
+                let block_h = self.dpi_factor * self.zoomed_block_size();
+
+                if self.modkeys.shift {
+                    self.shift_offs.1 += y.signum() * 2.0 * block_h;
+                } else if self.modkeys.ctrl {
+                    self.shift_offs.0 += y.signum() * 2.0 * block_h;
+                } else {
+                    let zoom = if *y > 0.0 {
+                        self.zoom * BLOCK_ZOOM_STEP
+                    } else {
+                        self.zoom / BLOCK_ZOOM_STEP
+                    };
+                    let anchor =
+                        (self.mouse_pos.0 - self.real_pos.x, self.mouse_pos.1 - self.real_pos.y);
+                    self.zoom_at(zoom, anchor);
+                }
+
+                // Update the hovered cell for the new view:
+                self.hover = self.find_area_at_mouse(self.mouse_pos.0, self.mouse_pos.1);
+                w.emit_redraw_required();
+            }
+            InputEvent::MouseButtonPressed(MButton::Left) if self.paste.is_some() => {
+                if w.is_hovered() {
+                    self.finish_paste(w, out_events);
+                }
+            }
+            InputEvent::MouseButtonPressed(MButton::Right) if self.paste.is_some() => {
+                self.paste = None;
+                w.emit_redraw_required();
+            }
+            InputEvent::MouseButtonPressed(MButton::Left)
+                if self.modkeys.ctrl && w.is_hovered() =>
+            {
+                let (x, y) = self.mouse_pos;
+                let chain = self.modkeys.shift;
+
+                let pos = self.find_pos_at_mouse(x, y);
+                self.set_selection(pos.map(|pos| (pos, chain)));
+
+                if let Some((at, chain, _)) = self.selection {
+                    out_events
+                        .push(w.event("selection", EvPayload::BlockClip { at, chain, to: None }));
+                } else {
+                    out_events.push(w.event("selection", EvPayload::None));
+                }
+
+                w.emit_redraw_required();
+            }
             InputEvent::MouseButtonPressed(btn) => {
                 if !w.is_hovered() {
                     return;
@@ -598,15 +1101,53 @@ impl BlockCode {
         // FIXME: The usage of dpi_f * 1.0 is suspicious, but I currently don't know
         //        why the offsets are there where they are and don't have the time to investigate.
         self.real_pos = real_pos;
+        self.dpi_factor = p.dpi_factor;
+        self.draw_offs = (self.real_pos.x - pos.x, self.real_pos.y - pos.y);
 
         let mut dbg = w.debug_tag();
-        dbg.set_offs((self.real_pos.x - pos.x, self.real_pos.y - pos.y));
+        dbg.set_offs(self.draw_offs);
 
         self.reset_areas();
+        self.drop_stale_selection();
 
         let pos = pos.floor();
         self.store_area_pos(0, 0, pos);
         self.draw_area(style, p, 0, pos, 0, dbg);
+
+        for vertical in [false, true] {
+            if let Some((track, thumb, _)) = self.scrollbar(vertical) {
+                let thumb_clr = if self.scroll_drag.map(|(v, _, _)| v) == Some(vertical) {
+                    style.hover_border_color()
+                } else {
+                    style.inactive_color()
+                };
+
+                p.rect_fill_r(
+                    style.shadow_color(),
+                    track.offs(-self.draw_offs.0, -self.draw_offs.1),
+                );
+                p.rect_fill_r(thumb_clr, thumb.offs(-self.draw_offs.0, -self.draw_offs.1));
+            }
+        }
+    }
+
+    pub fn draw_frame(&mut self, w: &Widget, _style: &DPIStyle, _p: &mut Painter) {
+        if self.view_changed {
+            self.view_changed = false;
+            w.emit_redraw_required();
+        }
+    }
+
+    /// The size of the top level area in pixels, with one block
+    /// of space to the right and bottom.
+    pub(crate) fn export_size(&self) -> (f32, f32) {
+        let code = self.code.lock().expect("BlockView lockable");
+        let (w, h) = code.area_size(0);
+
+        let block_h = self.block_size;
+        let block_w = self.block_w_factor * block_h;
+
+        ((w + 1) as f32 * block_w, (h + 1) as f32 * block_h)
     }
 
     pub fn get_generation(&self) -> u64 {
@@ -668,3 +1209,120 @@ fn draw_markers(
         false,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocklang::{BlockFun, BlockLanguage, BlockType};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn test_code() -> (Arc<Mutex<BlockFun>>, BlockCode) {
+        let mut lang = BlockLanguage::new();
+        lang.define(BlockType::for_test("num", &[], &[""]));
+
+        let fun = Arc::new(Mutex::new(BlockFun::new(Rc::new(RefCell::new(lang)))));
+        let code = BlockCode::new(fun.clone());
+        (fun, code)
+    }
+
+    /// Makes `code` look like it was drawn into a `w` x `h` view.
+    fn fake_draw(code: &mut BlockCode, w: f32, h: f32) {
+        let view = Rect::from(0.0, 0.0, w, h);
+        code.real_pos = view;
+        code.reset_areas();
+        code.store_area_pos(0, 0, view);
+    }
+
+    #[test]
+    fn check_scroll_into_view() {
+        let (_fun, mut code) = test_code();
+        code.scroll_into_view(BlockPos::Cell { id: 0, x: 10, y: 0 });
+        assert_eq!(code.shift_offs, (0.0, 0.0), "not drawn yet");
+
+        fake_draw(&mut code, 300.0, 200.0);
+
+        // Already visible:
+        code.scroll_into_view(BlockPos::Cell { id: 0, x: 1, y: 1 });
+        assert_eq!(code.shift_offs, (0.0, 0.0));
+
+        // Blocks are 75x30, the right edge ends up half a block
+        // before the scrollbar:
+        code.scroll_into_view(BlockPos::Block { id: 0, x: 10, y: 20, row: 0, col: 0, rows: 1 });
+        assert_eq!(code.shift_offs, (300.0 - 8.0 - 37.5 - 825.0, 200.0 - 8.0 - 15.0 - 630.0));
+        assert!(code.view_changed);
+
+        // The left and top edge half a block after the view origin, a
+        // row of a block scrolls to the whole block:
+        code.scroll_into_view(BlockPos::Block { id: 0, x: 0, y: 1, row: 1, col: 0, rows: 2 });
+        assert_eq!(code.shift_offs, (37.5, 15.0));
+
+        // Unknown areas are ignored:
+        code.scroll_into_view(BlockPos::Cell { id: 3, x: 10, y: 10 });
+        assert_eq!(code.shift_offs, (37.5, 15.0));
+    }
+
+    #[test]
+    fn check_scrollbar_geometry() {
+        let (fun, mut code) = test_code();
+        let (area_w, area_h) = fun.lock().unwrap().area_size(0);
+        let content_w = (area_w + 1) as f32 * 75.0;
+
+        // Everything visible:
+        fake_draw(&mut code, content_w + 10.0, (area_h + 1) as f32 * 30.0 + 10.0);
+        assert!(code.scrollbar(false).is_none());
+        assert!(code.scrollbar(true).is_none());
+
+        fake_draw(&mut code, 300.0, 200.0);
+        assert!(content_w > 300.0);
+
+        // The track spans the bottom, without the corner of the vertical one:
+        let (track, thumb, factor) = code.scrollbar(false).unwrap();
+        assert_eq!(track, Rect { x: 0.0, y: 192.0, w: 292.0, h: 8.0 });
+        let thumb_w = 300.0 / content_w * 292.0;
+        assert_eq!(thumb, Rect { x: 0.0, y: 192.0, w: thumb_w, h: 8.0 });
+        assert_eq!(factor, (content_w - 300.0) / (292.0 - thumb_w));
+
+        let (track, _, _) = code.scrollbar(true).unwrap();
+        assert_eq!(track, Rect { x: 292.0, y: 0.0, w: 8.0, h: 192.0 });
+
+        // Scrolled to the end, the thumb is at the end of the track:
+        code.shift_offs.0 = 300.0 - content_w;
+        let (_, thumb, _) = code.scrollbar(false).unwrap();
+        assert!((thumb.x + thumb.w - 292.0).abs() < 0.01);
+
+        // Scrolled before the start, the range grows:
+        code.shift_offs.0 = 100.0;
+        let (_, thumb, _) = code.scrollbar(false).unwrap();
+        assert_eq!(thumb.x, 0.0);
+        assert!(thumb.w < thumb_w);
+    }
+
+    #[test]
+    fn check_selection_dropped_with_its_block() {
+        let (fun, mut code) = test_code();
+        fun.lock().unwrap().instanciate_at(0, 1, 1, "num", None).unwrap();
+        fun.lock().unwrap().instanciate_at(0, 1, 3, "num", None).unwrap();
+
+        code.set_selection(Some((BlockPos::Cell { id: 0, x: 2, y: 2 }, false)));
+        assert_eq!(code.selection(), None);
+
+        let at = BlockPos::Block { id: 0, x: 1, y: 1, row: 0, col: 0, rows: 1 };
+        code.set_selection(Some((at, true)));
+        code.clip = code.selection;
+        assert_eq!(code.selection(), Some((at, true)));
+
+        // Other changes keep the selection:
+        fun.lock().unwrap().instanciate_at(0, 4, 4, "num", None).unwrap();
+        code.drop_stale_selection();
+        assert_eq!(code.selection(), Some((at, true)));
+        assert!(code.clip.is_some());
+
+        // Another block at the same position does not:
+        fun.lock().unwrap().remove_at(0, 1, 1).unwrap();
+        fun.lock().unwrap().move_block_from_to(0, 1, 3, 0, 1, 1).unwrap();
+        code.drop_stale_selection();
+        assert_eq!(code.selection(), None);
+        assert!(code.clip.is_none());
+    }
+}
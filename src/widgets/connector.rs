@@ -2,7 +2,9 @@
 // This file is a part of HexoDSP. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
-use crate::{EvPayload, Event, InputEvent, MButton, Widget};
+use crate::{
+    widget_handle_event, EvKind, EvPayload, Event, InputEvent, MButton, UndoCommand, Widget,
+};
 
 use crate::style::*;
 
@@ -10,7 +12,7 @@ use crate::painter::*;
 use crate::rect::*;
 
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 #[derive(Debug, Clone)]
 pub struct ConnectorData {
@@ -56,6 +58,47 @@ impl ConnectorData {
     }
 }
 
+/// The undo step for a connection change. Undoing it sends the "change"
+/// event again, so that the application can apply the old connection.
+struct ConnectionChange {
+    widget: Weak<RefCell<crate::widget::WidgetImpl>>,
+    data: Rc<RefCell<ConnectorData>>,
+    old: Option<(usize, usize)>,
+    new: Option<(usize, usize)>,
+}
+
+impl ConnectionChange {
+    fn apply(&self, ctx: &mut dyn std::any::Any, con: Option<(usize, usize)>) {
+        {
+            let mut data = self.data.borrow_mut();
+            data.connection = con;
+            data.generation += 1;
+        }
+
+        if let Some(widget) = Widget::from_weak(&self.widget) {
+            widget_handle_event(
+                &widget,
+                ctx,
+                &Event::new(EvKind::Change, EvPayload::SetConnection(con)),
+            );
+        }
+    }
+}
+
+impl UndoCommand for ConnectionChange {
+    fn undo(&mut self, ctx: &mut dyn std::any::Any) {
+        self.apply(ctx, self.old);
+    }
+
+    fn redo(&mut self, ctx: &mut dyn std::any::Any) {
+        self.apply(ctx, self.new);
+    }
+
+    fn describe(&self) -> String {
+        "Change Connection".to_string()
+    }
+}
+
 pub struct Connector {
     data: Rc<RefCell<ConnectorData>>,
 
@@ -190,12 +233,24 @@ impl Connector {
                     return;
                 }
 
+                let old = self.data.borrow().connection;
+
                 if let Some((_drag, con)) = self.get_current_con() {
                     self.data.borrow_mut().connection = Some(con);
                 } else {
                     self.data.borrow_mut().connection = None;
                 }
 
+                let new = self.data.borrow().connection;
+                if old != new {
+                    w.push_undo(ConnectionChange {
+                        widget: w.as_weak(),
+                        data: self.data.clone(),
+                        old,
+                        new,
+                    });
+                }
+
                 out_events.push(
                     w.event("change", EvPayload::SetConnection(self.data.borrow().connection)),
                 );
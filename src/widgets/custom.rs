@@ -0,0 +1,81 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use crate::{Event, InputEvent, Widget};
+
+use crate::style::DPIStyle;
+
+use crate::painter::*;
+use crate::rect::*;
+
+/// A user defined control, which can be put into a widget with
+/// [crate::Control::Custom].
+///
+/// This allows applications to implement their own widgets without the
+/// need to extend the [crate::Control] enum. The methods have the same
+/// signatures the built-in widgets like [crate::HexKnob] or [crate::Scope]
+/// are called with.
+pub trait CustomWidget {
+    /// Draws the widget. `pos` is the drawing position inside the cached
+    /// image of the widget, `real_pos` is the position of the widget
+    /// inside the window. Use `real_pos` for mouse hit testing.
+    fn draw(&mut self, w: &Widget, style: &DPIStyle, pos: Rect, real_pos: Rect, p: &mut Painter);
+
+    /// Called every frame, even if the widget was not redrawn.
+    /// Useful for things that change very often, like the LEDs of
+    /// the [crate::HexGrid].
+    fn draw_frame(&mut self, _w: &Widget, _style: &DPIStyle, _p: &mut Painter) {}
+
+    /// Handles input events. Events for the application callbacks
+    /// are pushed into `out_events`, usually with [Widget::event].
+    fn handle(
+        &mut self,
+        _w: &Widget,
+        _event: &InputEvent,
+        _out_events: &mut Vec<(usize, Event)>,
+    ) {
+    }
+
+    /// Should return the generation counter of the data the widget displays.
+    /// The widget is redrawn if the counter changes.
+    fn get_generation(&mut self) -> u64;
+
+    /// Whether the border, background and shadow from the [crate::Style]
+    /// should be drawn.
+    fn has_default_style(&self) -> bool {
+        true
+    }
+
+    /// Whether the widget can be hovered by the mouse at all.
+    fn can_hover(&self) -> bool {
+        true
+    }
+
+    /// Whether the hover state should be shown by the default style.
+    fn can_show_hover(&self) -> bool {
+        true
+    }
+
+    /// Whether the widget takes the keyboard focus.
+    fn can_focus(&self) -> bool {
+        false
+    }
+
+    /// Whether the widget receives the Tab key while it is focused,
+    /// instead of the focus moving on to the next widget.
+    fn wants_tab(&self) -> bool {
+        false
+    }
+
+    /// Gives the widget a chance to add information about the drop position
+    /// to a "drop" event. See also [crate::HexGrid].
+    fn annotate_drop_event(&mut self, _mouse_pos: (f32, f32), ev: Event) -> Event {
+        ev
+    }
+
+    /// A name used for debugging output and the [crate::TestDriver].
+    fn type_name(&self) -> &str {
+        "Custom"
+    }
+}
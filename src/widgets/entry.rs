@@ -2,8 +2,9 @@
 // This file is a part of HexoTK. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
-use crate::{EvPayload, Event, InputEvent, MButton, Mutable, Widget};
-use keyboard_types::Key;
+use super::{DoubleClickTracker, ModifierTracker};
+use crate::{EvPayload, Event, InputEvent, MButton, Mutable, Validation, Widget};
+use keyboard_types::{Key, Modifiers};
 
 use crate::style::DPIStyle;
 
@@ -16,6 +17,75 @@ use std::rc::Rc;
 pub trait EditableText: Mutable {
     fn update(&self, changed: String);
     fn get(&self) -> String;
+
+    /// Checks the text of an [Entry]. Invalid text is shown with the
+    /// `invalid_color` of the [crate::Style] and can't be committed with Enter.
+    /// A normalized text replaces the input when it is committed.
+    fn validate(&self, _text: &str) -> Validation {
+        Validation::Valid
+    }
+}
+
+/// The numeric mode of an [Entry], see [Entry::set_numeric].
+#[derive(Debug, Clone, Copy)]
+pub struct NumericRange {
+    pub min: f64,
+    pub max: f64,
+    /// The increment for the up/down keys and the mouse wheel.
+    pub step: f64,
+    /// The increment while Shift is held.
+    pub fine_step: f64,
+    /// Number of decimal places the value is formatted with.
+    pub decimals: usize,
+}
+
+impl NumericRange {
+    pub fn new(min: f64, max: f64, step: f64) -> Self {
+        Self { min, max, step, fine_step: step / 10.0, decimals: 2 }
+    }
+
+    pub fn fine_step(mut self, fine_step: f64) -> Self {
+        self.fine_step = fine_step;
+        self
+    }
+
+    pub fn decimals(mut self, decimals: usize) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    pub fn accepts_char(c: char) -> bool {
+        c.is_ascii_digit() || "+-.eE".contains(c)
+    }
+
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        text.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+    }
+
+    pub fn format(&self, v: f64) -> String {
+        format!("{:.*}", self.decimals, v.max(self.min).min(self.max))
+    }
+
+    /// Adds `delta` to the value in `text`, which counts as the
+    /// minimum if it is no number.
+    pub fn step_text(&self, text: &str, delta: f64) -> String {
+        self.format(self.parse(text).unwrap_or(self.min) + delta)
+    }
+
+    /// Numbers are normalized by clamping and formatting them.
+    pub fn validate(&self, text: &str) -> Validation {
+        match self.parse(text) {
+            None => Validation::Invalid,
+            Some(v) => {
+                let norm = self.format(v);
+                if norm == text {
+                    Validation::Valid
+                } else {
+                    Validation::Normalized(norm)
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -52,6 +122,9 @@ impl EditableText for TextField {
     }
 }
 
+/// Maximum number of undo steps an [Entry] keeps.
+const ENTRY_UNDO_LIMIT: usize = 50;
+
 pub struct Entry {
     update_text: Box<dyn EditableText>,
     data: String,
@@ -59,7 +132,23 @@ pub struct Entry {
     pre_string: String,
     post_string: String,
     cursor: usize,
-    mouse_pos: (f32, f32),
+    /// The other end of the selection, the cursor being the one end.
+    anchor: Option<usize>,
+    selecting: bool,
+    /// Screen x position of the first character and the width of
+    /// one character, for mapping the mouse to a cursor position.
+    text_x: f32,
+    char_w: f32,
+    modkeys: ModifierTracker,
+    dbl_click: DoubleClickTracker,
+    /// (text, cursor) states before the edits, for Ctrl+Z/Ctrl+Y.
+    undo: Vec<(String, usize)>,
+    redo: Vec<(String, usize)>,
+    /// Whether the last edit was typing, consecutive typing is
+    /// undone in one step.
+    typing: bool,
+    numeric: Option<NumericRange>,
+    invalid: bool,
 }
 
 impl Entry {
@@ -71,17 +160,39 @@ impl Entry {
             pre_string: String::from(""),
             post_string: String::from(""),
             cursor: 0,
-            mouse_pos: (0.0, 0.0),
+            anchor: None,
+            selecting: false,
+            text_x: 0.0,
+            char_w: 0.0,
+            modkeys: ModifierTracker::new(),
+            dbl_click: DoubleClickTracker::new(),
+            undo: vec![],
+            redo: vec![],
+            typing: false,
+            numeric: None,
+            invalid: false,
         }
     }
 
+    /// Switches the entry into numeric mode: only number characters can
+    /// be typed, the value is clamped and formatted on Enter and can be
+    /// stepped with the up/down keys and the mouse wheel.
+    pub fn set_numeric(&mut self, range: NumericRange) {
+        self.numeric = Some(range);
+        self.invalid = self.validate(&self.data) == Validation::Invalid;
+    }
+
     pub fn get_generation(&mut self) -> u64 {
         let cur_data_gen = self.update_text.get_generation();
 
         if cur_data_gen != self.data_gen {
             self.data = self.update_text.get();
-            self.cursor = self.data.len();
+            self.cursor = self.data.chars().count();
+            self.anchor = None;
+            self.undo.clear();
+            self.redo.clear();
             self.update_cursor();
+            self.invalid = self.validate(&self.data) == Validation::Invalid;
             self.data_gen = cur_data_gen;
         }
 
@@ -93,6 +204,171 @@ impl Entry {
         self.post_string = self.data.chars().skip(self.cursor).collect();
     }
 
+    fn set_cursor(&mut self, cursor: usize, extend: bool) {
+        if extend {
+            if self.anchor.is_none() {
+                self.anchor = Some(self.cursor);
+            }
+        } else {
+            self.anchor = None;
+        }
+
+        self.cursor = cursor.min(self.data.chars().count());
+        self.typing = false;
+        self.update_cursor();
+    }
+
+    /// Returns the selected character range.
+    fn selection(&self) -> Option<(usize, usize)> {
+        let anchor = self.anchor?;
+
+        if anchor == self.cursor {
+            None
+        } else {
+            Some((anchor.min(self.cursor), anchor.max(self.cursor)))
+        }
+    }
+
+    fn byte_offs(&self, idx: usize) -> usize {
+        self.data.char_indices().nth(idx).map(|(i, _)| i).unwrap_or(self.data.len())
+    }
+
+    fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        Some(self.data[self.byte_offs(start)..self.byte_offs(end)].to_string())
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        if let Some((start, end)) = self.selection() {
+            let range = self.byte_offs(start)..self.byte_offs(end);
+            self.data.replace_range(range, "");
+            self.cursor = start;
+            self.anchor = None;
+            self.update_cursor();
+            true
+        } else {
+            self.anchor = None;
+            false
+        }
+    }
+
+    fn delete_range(&mut self, start: usize, end: usize) {
+        self.anchor = Some(start);
+        self.cursor = end;
+        self.delete_selection();
+    }
+
+    fn insert(&mut self, s: &str) {
+        self.delete_selection();
+
+        let offs = self.byte_offs(self.cursor);
+        self.data.insert_str(offs, s);
+        self.cursor += s.chars().count();
+        self.update_cursor();
+    }
+
+    /// Returns the character range of the word at `idx`.
+    fn word_at(&self, idx: usize) -> (usize, usize) {
+        let chars: Vec<char> = self.data.chars().collect();
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+
+        let mut start = idx.min(chars.len());
+        let mut end = start;
+        while start > 0 && is_word_char(chars[start - 1]) {
+            start -= 1;
+        }
+        while end < chars.len() && is_word_char(chars[end]) {
+            end += 1;
+        }
+
+        (start, end)
+    }
+
+    fn index_at(&self, x: f32) -> usize {
+        if self.char_w < 0.1 {
+            return self.cursor;
+        }
+
+        let idx = ((x - self.text_x) / self.char_w).round().max(0.0) as usize;
+        idx.min(self.data.chars().count())
+    }
+
+    /// Remembers the current state before an edit.
+    fn save_undo(&mut self, typing: bool) {
+        if !(typing && self.typing) {
+            self.undo.push((self.data.clone(), self.cursor));
+            if self.undo.len() > ENTRY_UNDO_LIMIT {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+
+        self.typing = typing;
+    }
+
+    /// Runs the numeric mode checks and then the ones of the [EditableText].
+    fn validate(&self, text: &str) -> Validation {
+        match self.numeric.map(|range| range.validate(text)) {
+            Some(Validation::Invalid) => Validation::Invalid,
+            Some(Validation::Normalized(norm)) => match self.update_text.validate(&norm) {
+                Validation::Valid => Validation::Normalized(norm),
+                validation => validation,
+            },
+            _ => self.update_text.validate(text),
+        }
+    }
+
+    fn accepts(&self, text: &str) -> bool {
+        self.numeric.is_none() || text.chars().all(NumericRange::accepts_char)
+    }
+
+    fn replace_text(&mut self, text: String) {
+        self.data = text;
+        self.anchor = None;
+        self.cursor = self.data.chars().count();
+        self.update_cursor();
+    }
+
+    /// Steps the value in numeric mode up (`dir` = 1.0) or down (-1.0).
+    fn step_value(&mut self, dir: f64, fine: bool) -> bool {
+        if let Some(range) = self.numeric {
+            let step = if fine { range.fine_step } else { range.step };
+            self.save_undo(false);
+            self.replace_text(range.step_text(&self.data, dir * step));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn restore(&mut self, state: (String, usize)) {
+        self.data = state.0;
+        self.cursor = state.1;
+        self.anchor = None;
+        self.typing = false;
+        self.update_cursor();
+    }
+
+    fn undo(&mut self) -> bool {
+        if let Some(state) = self.undo.pop() {
+            self.redo.push((self.data.clone(), self.cursor));
+            self.restore(state);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn redo(&mut self) -> bool {
+        if let Some(state) = self.redo.pop() {
+            self.undo.push((self.data.clone(), self.cursor));
+            self.restore(state);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn draw(
         &mut self,
         w: &Widget,
@@ -105,11 +381,13 @@ impl Entry {
 
         p.clip_region(pos.x, pos.y, pos.w, pos.h);
         let is_hovered = w.is_hovered();
-        let is_active = w.is_active();
+        let is_active = w.is_active() || w.is_focused();
 
         let fh = p.font_height(style.font_size(), true);
         let cur_start_x = p.text_width(style.font_size(), true, &self.pre_string[..]);
 
+        let text_color = if self.invalid { style.invalid_color() } else { style.color() };
+
         let color = if is_active {
             style.active_color()
         } else if is_hovered {
@@ -126,12 +404,24 @@ impl Entry {
         }
         xo = xo.round();
 
+        self.text_x = real_pos.x + xo;
+        self.char_w = p.text_width(style.font_size(), true, "0");
+
+        if let Some((start, end)) = self.selection() {
+            let sel_pre: String = self.data.chars().take(start).collect();
+            let sel: String = self.data.chars().skip(start).take(end - start).collect();
+            let sel_x = p.text_width(style.font_size(), true, &sel_pre);
+            let sel_w = p.text_width(style.font_size(), true, &sel);
+
+            p.rect_fill(style.selected_color(), pos.x + xo + sel_x, pos.y + y, sel_w, fh);
+        }
+
         let mut dbg = w.debug_tag();
 
         p.label_mono(
             style.font_size(),
             -1,
-            style.color(),
+            text_color,
             pos.x + xo,
             pos.y + y,
             pos.w,
@@ -149,15 +439,29 @@ impl Entry {
             ],
             false,
         );
+
+        if self.invalid {
+            p.rect_stroke(
+                1.0,
+                style.invalid_color(),
+                pos.x + 0.5,
+                pos.y + 0.5,
+                pos.w - 1.0,
+                pos.h - 1.0,
+            );
+        }
+
         p.reset_clip_region();
     }
 
     pub fn handle(&mut self, w: &Widget, event: &InputEvent, out_events: &mut Vec<(usize, Event)>) {
         let is_hovered = w.is_hovered();
-        let is_active = w.is_active();
+        let is_active = w.is_active() || w.is_focused();
 
         //d// println!("ENTRY EV: {:?}", event);
 
+        self.modkeys.handle(event);
+
         let mut changed = false;
 
         match event {
@@ -168,92 +472,491 @@ impl Entry {
                     return;
                 }
 
+                let ctrl = key.modifiers.contains(Modifiers::CONTROL);
+                let shift = key.modifiers.contains(Modifiers::SHIFT);
+
                 match &key.key {
-                    Key::Character(s) => {
-                        self.data = self.pre_string.clone() + &s + &self.post_string;
+                    Key::Character(s) if ctrl => match &s.to_lowercase()[..] {
+                        "a" => {
+                            self.set_cursor(0, false);
+                            self.set_cursor(len, true);
+                        }
+                        "c" => {
+                            if let (Some(text), Some(clipboard)) =
+                                (self.selected_text(), w.clipboard())
+                            {
+                                clipboard.set_text(&text);
+                            }
+                        }
+                        "x" => {
+                            if let (Some(text), Some(clipboard)) =
+                                (self.selected_text(), w.clipboard())
+                            {
+                                clipboard.set_text(&text);
+                                self.save_undo(false);
+                                self.delete_selection();
+                                changed = true;
+                            }
+                        }
+                        "v" => {
+                            if let Some(text) = w.clipboard().and_then(|c| c.get_text()) {
+                                let text: String =
+                                    text.chars().filter(|c| !c.is_control()).collect();
+                                if self.accepts(&text) {
+                                    self.save_undo(false);
+                                    self.insert(&text);
+                                    changed = true;
+                                }
+                            }
+                        }
+                        "z" if !shift => {
+                            changed = self.undo();
+                        }
+                        "y" | "z" => {
+                            changed = self.redo();
+                        }
+                        _ => {}
+                    },
+                    Key::Character(s) if self.accepts(s) => {
+                        self.save_undo(true);
+                        self.insert(s);
                         changed = true;
-                        self.cursor += 1;
-                        self.update_cursor();
-                        w.emit_redraw_required();
                     }
                     Key::Home => {
-                        self.cursor = 0;
-                        self.update_cursor();
-                        w.emit_redraw_required();
+                        self.set_cursor(0, shift);
                     }
                     Key::End => {
-                        self.cursor = len;
-                        self.update_cursor();
-                        w.emit_redraw_required();
+                        self.set_cursor(len, shift);
                     }
                     Key::Backspace => {
-                        if self.cursor > 0 {
-                            let spre: String =
-                                self.pre_string.chars().take(self.cursor - 1).collect();
+                        if self.selection().is_some() || self.cursor > 0 {
+                            self.save_undo(false);
+                            if !self.delete_selection() {
+                                self.delete_range(self.cursor - 1, self.cursor);
+                            }
                             changed = true;
-                            self.data = spre + &self.post_string;
-                            self.cursor -= 1;
-                            self.update_cursor();
-                            w.emit_redraw_required();
                         }
                     }
                     Key::Delete => {
-                        if self.cursor < len {
-                            let spost: String = self.post_string.chars().skip(1).collect();
+                        if self.selection().is_some() || self.cursor < len {
+                            self.save_undo(false);
+                            if !self.delete_selection() {
+                                self.delete_range(self.cursor, self.cursor + 1);
+                            }
                             changed = true;
-                            self.data = self.pre_string.clone() + &spost;
-                            self.update_cursor();
-                            w.emit_redraw_required();
                         }
                     }
-                    Key::ArrowRight => {
-                        if (self.cursor + 1) <= len {
-                            self.cursor += 1;
-                            self.update_cursor();
-                            w.emit_redraw_required();
-                        }
+                    Key::ArrowRight => match (shift, self.selection()) {
+                        (false, Some((_, end))) => self.set_cursor(end, false),
+                        _ => self.set_cursor(self.cursor + 1, shift),
+                    },
+                    Key::ArrowLeft => match (shift, self.selection()) {
+                        (false, Some((start, _))) => self.set_cursor(start, false),
+                        _ => self.set_cursor(self.cursor.saturating_sub(1), shift),
+                    },
+                    Key::ArrowUp => {
+                        changed = self.step_value(1.0, shift);
                     }
-                    Key::ArrowLeft => {
-                        if self.cursor > 0 {
-                            self.cursor -= 1;
-                            self.update_cursor();
-                            w.emit_redraw_required();
-                        }
-                    }
-                    Key::Enter => {
-                        self.update_text.update(self.data.clone());
-                        out_events.push(w.event("enter", EvPayload::Text(self.data.clone())));
+                    Key::ArrowDown => {
+                        changed = self.step_value(-1.0, shift);
                     }
+                    Key::Enter => match self.validate(&self.data) {
+                        Validation::Invalid => {
+                            self.invalid = true;
+                        }
+                        validation => {
+                            if let Validation::Normalized(text) = validation {
+                                self.save_undo(false);
+                                self.replace_text(text);
+                            }
+
+                            self.invalid = false;
+                            self.update_text.update(self.data.clone());
+                            out_events.push(w.event("enter", EvPayload::Text(self.data.clone())));
+                        }
+                    },
                     _ => {}
                 }
+
+                w.emit_redraw_required();
             }
             InputEvent::MouseButtonPressed(btn) => {
                 if is_hovered {
                     if *btn == MButton::Left {
+                        let idx = self.index_at(self.modkeys.mouse.x);
+
+                        if self.dbl_click.press() {
+                            let (start, end) = self.word_at(idx);
+                            self.set_cursor(start, false);
+                            self.set_cursor(end, true);
+                        } else {
+                            self.set_cursor(idx, self.modkeys.shift);
+                            self.selecting = true;
+                        }
+
                         w.activate();
                         w.emit_redraw_required();
                     }
                 }
             }
-            InputEvent::MouseButtonReleased(_btn) => {
-                //                if w.is_active() {
-                //                    let (x, y) = self.mouse_pos;
-                //
-                //                    if *btn == MButton::Left {
-                ////                        w.deactivate();
-                ////                        w.emit_redraw_required();
-                //                    }
-                //                }
+            InputEvent::MouseButtonReleased(btn) => {
+                if *btn == MButton::Left {
+                    self.selecting = false;
+                }
+            }
+            InputEvent::MousePosition(x, _y) => {
+                if self.selecting {
+                    self.set_cursor(self.index_at(*x), true);
+                    w.emit_redraw_required();
+                }
             }
-            InputEvent::MousePosition(x, y) => {
-                self.mouse_pos = (*x, *y);
+            InputEvent::MouseWheel(y) => {
+                if is_hovered
+                    && self.step_value(if *y > 0.0 { 1.0 } else { -1.0 }, self.modkeys.shift)
+                {
+                    changed = true;
+                    w.emit_redraw_required();
+                }
             }
             _ => {}
         }
 
         if changed {
-            self.update_text.update(self.data.clone());
-            out_events.push(w.event("changed", EvPayload::Text(self.data.clone())));
+            // Invalid input stays in the entry until it is fixed. Out of range
+            // or unformatted input is kept while typing, but only passed on
+            // normalized:
+            let text = match self.validate(&self.data) {
+                Validation::Invalid => None,
+                Validation::Normalized(text) => Some(text),
+                Validation::Valid => Some(self.data.clone()),
+            };
+            self.invalid = text.is_none();
+
+            if let Some(text) = text {
+                self.update_text.update(text.clone());
+                // Our own change must not replace the text being typed:
+                self.data_gen = self.update_text.get_generation();
+                out_events.push(w.event("changed", EvPayload::Text(text)));
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Control, EvKind, UI};
+    use keyboard_types::KeyboardEvent;
+
+    fn key(key: Key) -> InputEvent {
+        InputEvent::KeyPressed(KeyboardEvent { key, ..Default::default() })
+    }
+
+    fn chr(s: &str) -> InputEvent {
+        key(Key::Character(s.to_string()))
+    }
+
+    fn shift(key: Key) -> InputEvent {
+        InputEvent::KeyPressed(KeyboardEvent {
+            key,
+            modifiers: Modifiers::SHIFT,
+            ..Default::default()
+        })
+    }
+
+    fn ctrl(s: &str) -> InputEvent {
+        InputEvent::KeyPressed(KeyboardEvent {
+            key: Key::Character(s.to_string()),
+            modifiers: Modifiers::CONTROL,
+            ..Default::default()
+        })
+    }
+
+    fn type_text(ui: &mut UI, text: &str) {
+        for c in text.chars() {
+            ui.handle_input_event(chr(&c.to_string()));
+        }
+    }
+
+    /// Lays out the characters 10 pixels apart from the left edge of `wid`,
+    /// like drawing would, and returns the mouse x position of the
+    /// character boundary `idx`.
+    fn char_x(wid: &Widget, idx: usize) -> f32 {
+        let x = wid.pos().x;
+        with_entry(wid, |entry| {
+            entry.text_x = x;
+            entry.char_w = 10.0;
+        });
+        x + idx as f32 * 10.0
+    }
+
+    fn mouse_to(ui: &mut UI, wid: &Widget, idx: usize) {
+        let y = wid.pos().y + 5.0;
+        ui.handle_input_event(InputEvent::MousePosition(char_x(wid, idx), y));
+    }
+
+    fn with_entry<R>(wid: &Widget, f: impl FnOnce(&mut Entry) -> R) -> R {
+        wid.with_ctrl(|ctrl| match ctrl {
+            Control::Entry { entry } => f(entry),
+            _ => panic!("no entry"),
+        })
+        .unwrap()
+    }
+
+    fn selected(wid: &Widget) -> Option<String> {
+        with_entry(wid, |entry| entry.selected_text())
+    }
+
+    /// A focused [Entry] for `text` and the log of its "changed" and "enter" events.
+    fn test_entry(
+        text: &TextField,
+        numeric: Option<NumericRange>,
+    ) -> (UI, Widget, Rc<RefCell<Vec<String>>>) {
+        let mut entry = Entry::new(Box::new(text.clone()));
+        if let Some(range) = numeric {
+            entry.set_numeric(range);
+        }
+
+        let (mut ui, mut wids) = UI::for_test(vec![Control::Entry { entry: Box::new(entry) }]);
+        let wid = wids.remove(0);
+        ui.focus(&wid);
+
+        let log = Rc::new(RefCell::new(vec![]));
+        for kind in [EvKind::Changed, EvKind::Enter] {
+            let log = log.clone();
+            wid.reg(kind, move |_ctx, _wid, ev| {
+                if let EvPayload::Text(text) = &ev.data {
+                    log.borrow_mut().push(format!("{}:{}", ev.kind(), text));
+                }
+            });
+        }
+
+        (ui, wid, log)
+    }
+
+    /// The text as shown in the [Entry].
+    fn entry_text(wid: &Widget) -> String {
+        with_entry(wid, |entry| entry.data.clone())
+    }
+
+    #[test]
+    fn check_shift_selection() {
+        let (mut ui, wid, _log) = test_entry(&TextField::new(), None);
+        type_text(&mut ui, "hello world");
+
+        ui.handle_input_event(key(Key::Home));
+        for _ in 0..5 {
+            ui.handle_input_event(shift(Key::ArrowRight));
+        }
+        assert_eq!(selected(&wid).as_deref(), Some("hello"));
+
+        ui.handle_input_event(shift(Key::End));
+        assert_eq!(selected(&wid).as_deref(), Some("hello world"));
+
+        // Moving without Shift collapses the selection to its start:
+        ui.handle_input_event(key(Key::ArrowLeft));
+        assert_eq!(selected(&wid), None);
+        with_entry(&wid, |entry| assert_eq!(entry.cursor, 0));
+
+        // Typing replaces the selection:
+        ui.handle_input_event(shift(Key::End));
+        ui.handle_input_event(shift(Key::ArrowLeft));
+        type_text(&mut ui, "X");
+        assert_eq!(entry_text(&wid), "Xd");
+    }
+
+    #[test]
+    fn check_mouse_selection() {
+        let (mut ui, wid, _log) = test_entry(&TextField::new(), None);
+        type_text(&mut ui, "hello world");
+
+        mouse_to(&mut ui, &wid, 2);
+        ui.handle_input_event(InputEvent::MouseButtonPressed(MButton::Left));
+        with_entry(&wid, |entry| assert_eq!(entry.cursor, 2));
+        assert_eq!(selected(&wid), None);
+
+        mouse_to(&mut ui, &wid, 5);
+        assert_eq!(selected(&wid).as_deref(), Some("llo"));
+
+        ui.handle_input_event(InputEvent::MouseButtonReleased(MButton::Left));
+        mouse_to(&mut ui, &wid, 7);
+        assert_eq!(selected(&wid).as_deref(), Some("llo"));
+
+        // Shift-click extends the selection. The tracker is reset, so the
+        // quick second press does not count as a double click:
+        with_entry(&wid, |entry| entry.dbl_click = DoubleClickTracker::new());
+        ui.handle_input_event(InputEvent::KeyPressed(KeyboardEvent {
+            key: Key::Shift,
+            ..Default::default()
+        }));
+        mouse_to(&mut ui, &wid, 8);
+        ui.handle_input_event(InputEvent::MouseButtonPressed(MButton::Left));
+        ui.handle_input_event(InputEvent::MouseButtonReleased(MButton::Left));
+        assert_eq!(selected(&wid).as_deref(), Some("llo wo"));
+    }
+
+    #[test]
+    fn check_double_click_selects_word() {
+        let (mut ui, wid, _log) = test_entry(&TextField::new(), None);
+        type_text(&mut ui, "hello big_world!");
+
+        mouse_to(&mut ui, &wid, 9);
+        for _ in 0..2 {
+            ui.handle_input_event(InputEvent::MouseButtonPressed(MButton::Left));
+            ui.handle_input_event(InputEvent::MouseButtonReleased(MButton::Left));
+        }
+        assert_eq!(selected(&wid).as_deref(), Some("big_world"));
+        with_entry(&wid, |entry| assert_eq!(entry.cursor, 15));
+    }
+
+    #[test]
+    fn check_clipboard_keys() {
+        let text = TextField::new();
+        let (mut ui, wid, log) = test_entry(&text, None);
+        let clipboard = ui.clipboard();
+        type_text(&mut ui, "abc def");
+        log.borrow_mut().clear();
+
+        // Nothing selected, nothing copied:
+        ui.handle_input_event(ctrl("c"));
+        assert_eq!(clipboard.get_text(), None);
+
+        ui.handle_input_event(ctrl("a"));
+        assert_eq!(selected(&wid).as_deref(), Some("abc def"));
+        ui.handle_input_event(ctrl("c"));
+        assert_eq!(clipboard.get_text().as_deref(), Some("abc def"));
+        assert!(log.borrow().is_empty());
+
+        ui.handle_input_event(key(Key::Home));
+        for _ in 0..3 {
+            ui.handle_input_event(shift(Key::ArrowRight));
+        }
+        ui.handle_input_event(ctrl("x"));
+        assert_eq!(clipboard.get_text().as_deref(), Some("abc"));
+        assert_eq!(entry_text(&wid), " def");
+        assert_eq!(text.get(), " def");
+
+        ui.handle_input_event(key(Key::End));
+        ui.handle_input_event(ctrl("v"));
+        assert_eq!(entry_text(&wid), " defabc");
+        assert_eq!(log.borrow().clone(), vec!["changed: def", "changed: defabc"]);
+
+        // Pasting drops control characters:
+        clipboard.set_text("1\n2");
+        ui.handle_input_event(ctrl("a"));
+        ui.handle_input_event(ctrl("v"));
+        assert_eq!(entry_text(&wid), "12");
+    }
+
+    #[test]
+    fn check_undo_coalesces_typing() {
+        let text = TextField::new();
+        let (mut ui, wid, _log) = test_entry(&text, None);
+
+        type_text(&mut ui, "abc");
+        ui.handle_input_event(ctrl("z"));
+        assert_eq!(entry_text(&wid), "");
+        assert_eq!(text.get(), "");
+
+        // Moving the cursor starts a new undo step:
+        type_text(&mut ui, "ab");
+        ui.handle_input_event(key(Key::ArrowLeft));
+        type_text(&mut ui, "xy");
+        assert_eq!(entry_text(&wid), "axyb");
+        ui.handle_input_event(key(Key::Backspace));
+        assert_eq!(entry_text(&wid), "axb");
+
+        ui.handle_input_event(ctrl("z"));
+        assert_eq!(entry_text(&wid), "axyb");
+        ui.handle_input_event(ctrl("z"));
+        assert_eq!(entry_text(&wid), "ab");
+        ui.handle_input_event(ctrl("z"));
+        assert_eq!(entry_text(&wid), "");
+
+        ui.handle_input_event(ctrl("y"));
+        assert_eq!(entry_text(&wid), "ab");
+        ui.handle_input_event(ctrl("y"));
+        assert_eq!(entry_text(&wid), "axyb");
+        assert_eq!(text.get(), "axyb");
+    }
+
+    #[test]
+    fn check_delete_range() {
+        let mut entry = Entry::new(Box::new(TextField::new()));
+        entry.insert("häll😀o");
+
+        entry.delete_range(1, 3);
+        assert_eq!(entry.data, "hl😀o");
+        assert_eq!(entry.cursor, 1);
+        assert_eq!(entry.pre_string, "h");
+        assert_eq!(entry.anchor, None);
+
+        entry.delete_range(2, 3);
+        assert_eq!(entry.data, "hlo");
+
+        // An empty range deletes nothing:
+        entry.delete_range(1, 1);
+        assert_eq!(entry.data, "hlo");
+        assert_eq!(entry.anchor, None);
+    }
+
+    #[test]
+    fn check_backspace_and_delete() {
+        let (mut ui, wid, _log) = test_entry(&TextField::new(), None);
+        type_text(&mut ui, "aäb");
+
+        ui.handle_input_event(key(Key::ArrowLeft));
+        ui.handle_input_event(key(Key::Backspace));
+        assert_eq!(entry_text(&wid), "ab");
+        ui.handle_input_event(key(Key::Delete));
+        assert_eq!(entry_text(&wid), "a");
+        ui.handle_input_event(key(Key::Delete));
+        assert_eq!(entry_text(&wid), "a");
+
+        ui.handle_input_event(key(Key::Home));
+        ui.handle_input_event(key(Key::Backspace));
+        assert_eq!(entry_text(&wid), "a");
+    }
+
+    #[test]
+    fn check_numeric_typing_emits_normalized() {
+        let text = TextField::new();
+        let range = NumericRange::new(0.0, 1.0, 0.1);
+        let (mut ui, wid, log) = test_entry(&text, Some(range));
+
+        ui.handle_input_event(chr("1"));
+        ui.handle_input_event(chr("2"));
+        ui.handle_input_event(chr("x"));
+        assert_eq!(entry_text(&wid), "12");
+        assert_eq!(text.get(), "1.00");
+        assert_eq!(log.borrow().clone(), vec!["changed:1.00", "changed:1.00"]);
+
+        log.borrow_mut().clear();
+        ui.handle_input_event(key(Key::Enter));
+        assert_eq!(entry_text(&wid), "1.00");
+        assert_eq!(log.borrow().clone(), vec!["enter:1.00"]);
+
+        // Invalid input is not passed on:
+        log.borrow_mut().clear();
+        ui.handle_input_event(chr("-"));
+        assert_eq!(entry_text(&wid), "1.00-");
+        assert!(log.borrow().is_empty());
+        assert_eq!(text.get(), "1.00");
+    }
+
+    #[test]
+    fn check_numeric_range() {
+        let range = NumericRange::new(-1.0, 1.0, 0.1).decimals(3);
+
+        assert_eq!(range.validate("0.500"), Validation::Valid);
+        assert_eq!(range.validate("0.5"), Validation::Normalized("0.500".to_string()));
+        assert_eq!(range.validate("12"), Validation::Normalized("1.000".to_string()));
+        assert_eq!(range.validate("-"), Validation::Invalid);
+        assert_eq!(range.validate("inf"), Validation::Invalid);
+
+        assert_eq!(range.step_text("0.5", 0.1), "0.600");
+        assert_eq!(range.step_text("0.95", 0.1), "1.000");
+        assert_eq!(range.step_text("", -0.01), "-1.000");
+        assert!(!NumericRange::accepts_char('x'));
+    }
+}
@@ -3,6 +3,9 @@
 // See README.md and COPYING for details.
 
 use crate::{EvPayload, Event, InputEvent, MButton, Widget};
+use keyboard_types::{Key, Modifiers};
+
+use super::{pan_offset, ModifierTracker};
 
 use crate::style::*;
 
@@ -10,7 +13,9 @@ use crate::painter::*;
 use crate::rect::*;
 
 use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 pub const UI_GRID_TXT_CENTER_CLR: (f32, f32, f32) = UI_PRIM_CLR;
 pub const UI_GRID_TXT_CENTER_HL_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
@@ -20,11 +25,23 @@ pub const UI_GRID_TXT_EDGE_CLR: (f32, f32, f32) = UI_PRIM_CLR;
 pub const UI_GRID_EMPTY_BORDER_CLR: (f32, f32, f32) = UI_ACCENT_DARK_CLR;
 pub const UI_GRID_HOVER_BORDER_CLR: (f32, f32, f32) = UI_SELECT_CLR;
 pub const UI_GRID_DRAG_BORDER_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
+pub const UI_GRID_SELECT_BORDER_CLR: (f32, f32, f32) = UI_SELECT_CLR;
+pub const UI_GRID_RUBBER_BAND_CLR: (f32, f32, f32) = UI_HLIGHT2_CLR;
+pub const UI_GRID_CURSOR_BORDER_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
+pub const UI_GRID_MARK_BORDER_CLR: (f32, f32, f32) = UI_PRIM2_CLR;
 pub const UI_GRID_BG1_CLR: (f32, f32, f32) = UI_ACCENT_BG1_CLR;
 //pub const UI_GRID_BG2_CLR           : (f32, f32, f32) = UI_ACCENT_BG2_CLR;
 pub const UI_GRID_SIGNAL_OUT_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
 pub const UI_GRID_LED_CLR: (f32, f32, f32) = UI_PRIM_CLR;
+pub const UI_GRID_MINIMAP_BG_CLR: (f32, f32, f32) = UI_ACCENT_BG2_CLR;
+pub const UI_GRID_MINIMAP_BORDER_CLR: (f32, f32, f32) = UI_ACCENT_DARK_CLR;
+pub const UI_GRID_MINIMAP_VIEW_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
+pub const UI_GRID_FLOW_IDLE_CLR: (f32, f32, f32) = UI_ACCENT_DARK_CLR;
+pub const UI_GRID_FLOW_CLR: (f32, f32, f32) = UI_GRID_SIGNAL_OUT_CLR;
+/// Speed of the signal flow dashes on the edges in pixels per second.
+pub const UI_GRID_FLOW_SPEED: f32 = 12.0;
 pub const UI_GRID_LED_R: f32 = 5.0;
+pub const UI_GRID_VIEW_ANIM_MS: u64 = 250;
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub enum HexDir {
@@ -64,6 +81,35 @@ impl HexDir {
     pub fn as_edge(&self) -> u8 {
         *self as u8
     }
+
+    /// The offset to the neighbour tile in this direction. The even
+    /// columns of the grid are shifted up by half a tile, so the
+    /// offset depends on the column `x`.
+    pub fn offs_for_column(&self, x: usize) -> (i32, i32) {
+        let even = x % 2 == 0;
+
+        match self {
+            HexDir::TR => (1, if even { -1 } else { 0 }),
+            HexDir::BR => (1, if even { 0 } else { 1 }),
+            HexDir::B => (0, 1),
+            HexDir::BL => (-1, if even { 0 } else { 1 }),
+            HexDir::TL => (-1, if even { -1 } else { 0 }),
+            HexDir::T => (0, -1),
+        }
+    }
+
+    /// Returns the neighbour of the tile at `x`/`y` in this direction,
+    /// or `None` if it would be outside of a `w` x `h` grid.
+    pub fn neighbour(&self, x: usize, y: usize, w: usize, h: usize) -> Option<(usize, usize)> {
+        let (ox, oy) = self.offs_for_column(x);
+        let (nx, ny) = (x as i32 + ox, y as i32 + oy);
+
+        if nx < 0 || ny < 0 || nx as usize >= w || ny as usize >= h {
+            None
+        } else {
+            Some((nx as usize, ny as usize))
+        }
+    }
 }
 
 use hexodsp::CellDir;
@@ -112,6 +158,11 @@ pub trait HexGridModel {
     fn cell_color(&self, _x: usize, _y: usize) -> u8 {
         0
     }
+    /// Cells that are selected in the application state. They are drawn
+    /// like the cells selected with the mouse in the [HexGrid].
+    fn cell_selected(&self, _x: usize, _y: usize) -> bool {
+        false
+    }
 
     fn cell_led(&self, x: usize, y: usize) -> Option<(f32, f32)>;
     fn cell_label<'a>(&self, x: usize, y: usize, out: &'a mut [u8]) -> Option<HexCell<'a>>; // (&'a str, HexCell, Option<(f32, f32)>)>;
@@ -120,6 +171,17 @@ pub trait HexGridModel {
     //        -> Option<(&'a str, HexEdge)>;
 
     fn cell_edge(&self, x: usize, y: usize, edge: HexDir) -> HexEdge;
+    /// The signal activity (0.0 to 1.0) flowing out of the output edge
+    /// `edge` (one of [HexDir::TR], [HexDir::BR] or [HexDir::B]).
+    /// The [HexGrid] shows it as moving dashes towards the neighbour cell,
+    /// which are animated every frame without redrawing the grid.
+    ///
+    /// Return `None` for edges without a connection. Whether an edge
+    /// is connected is only checked when the grid is redrawn, so a new
+    /// connection should also change [HexGridModel::get_generation].
+    fn cell_edge_activity(&self, _x: usize, _y: usize, _edge: HexDir) -> Option<f32> {
+        None
+    }
     fn cell_edge_label<'a>(
         &self,
         x: usize,
@@ -135,6 +197,36 @@ fn hex_size2wh(size: f32) -> (f32, f32) {
     (2.0_f32 * size, (3.0_f32).sqrt() * size)
 }
 
+/// A smooth transition of the [HexGrid] view between two
+/// scale and shift offset settings.
+struct ViewAnim {
+    from: (f32, (f32, f32)),
+    to: (f32, (f32, f32)),
+    start: Instant,
+}
+
+impl ViewAnim {
+    /// Returns the interpolated (scale, shift_offs) and whether the animation is done.
+    fn at(&self, now: Instant) -> ((f32, (f32, f32)), bool) {
+        let t = now.duration_since(self.start).as_secs_f32()
+            / Duration::from_millis(UI_GRID_VIEW_ANIM_MS).as_secs_f32();
+        if t >= 1.0 {
+            return (self.to, true);
+        }
+
+        let t = t * t * (3.0 - 2.0 * t);
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+        (
+            (
+                lerp(self.from.0, self.to.0),
+                (lerp(self.from.1 .0, self.to.1 .0), lerp(self.from.1 .1, self.to.1 .1)),
+            ),
+            false,
+        )
+    }
+}
+
 enum HexDecorPos {
     Center(f32, f32),
     Top(f32, f32),
@@ -280,10 +372,31 @@ pub struct HexGrid {
 
     led_pos: Option<Vec<((usize, usize), (f32, f32))>>,
     edge_led_pos: Option<Vec<((usize, usize, HexDir), (f32, f32, f32))>>,
+    edge_flow_pos: Option<Vec<((usize, usize, HexDir), (f32, f32, f32))>>,
+    /// The start of the flow dash animation, `None` draws them at rest.
+    flow_start: Option<Instant>,
 
     real_pos: Rect,
     mouse: (f32, f32),
     mouse_state: Option<(MButton, f32, f32)>,
+    modkeys: ModifierTracker,
+
+    selection: BTreeSet<(usize, usize)>,
+    /// Start and end mouse position of the Shift+drag selection.
+    rubber_band: Option<((f32, f32), (f32, f32))>,
+
+    view_anim: Option<ViewAnim>,
+    /// Set when the view was changed outside of the event handling,
+    /// so that [HexGrid::draw_frame] requests a redraw.
+    view_changed: bool,
+
+    minimap: bool,
+    minimap_drag: bool,
+
+    /// The tile of the keyboard cursor, only shown while focused.
+    cursor: Option<(usize, usize)>,
+    /// The tile marked with the keyboard as source for a drag action.
+    mark: Option<(usize, usize)>,
 }
 
 impl HexGrid {
@@ -306,8 +419,19 @@ impl HexGrid {
             real_pos: Rect::from(0.0, 0.0, 0.0, 0.0),
             mouse: (0.0, 0.0),
             mouse_state: None,
+            modkeys: ModifierTracker::new(),
+            selection: BTreeSet::new(),
+            rubber_band: None,
+            view_anim: None,
+            view_changed: false,
+            minimap: false,
+            minimap_drag: false,
+            cursor: None,
+            mark: None,
             led_pos: Some(vec![]),
             edge_led_pos: Some(vec![]),
+            edge_flow_pos: Some(vec![]),
+            flow_start: Some(Instant::now()),
             model,
         }
     }
@@ -342,12 +466,515 @@ impl HexGrid {
     pub fn get_mouse_tile_pos(&self, x: f32, y: f32) -> (i32, i32) {
         let pos = self.real_pos;
 
-        let shift_x = (self.shift_offs.0 + self.tmp_shift_offs.map(|o| o.0).unwrap_or(0.0)).round();
-        let shift_y = (self.shift_offs.1 + self.tmp_shift_offs.map(|o| o.1).unwrap_or(0.0)).round();
+        let (shift_x, shift_y) = self.shift();
 
         self.mouse_to_tile(x - pos.x - shift_x, y - pos.y - shift_y)
     }
 
+    fn shift(&self) -> (f32, f32) {
+        pan_offset(self.shift_offs, self.tmp_shift_offs)
+    }
+
+    /// Returns the center of the tile relative to the unshifted grid origin.
+    fn tile_offset(&self, xi: usize, yi: usize, scale: f32) -> (f32, f32) {
+        let size = self.tile_size * scale * self.dpi_factor;
+        let (w, h) = hex_size2wh(size);
+
+        let y = if xi % 2 == 0 { yi as f32 - 0.5 } else { yi as f32 };
+        let xo = xi as f32 * 0.75 * w + size;
+        let yo = (1.00 + y) * h;
+        let yo = if self.y_offs { yo - 0.5 * h } else { yo };
+
+        (xo, yo)
+    }
+
+    /// Enables the minimap in the bottom right corner, which shows
+    /// the whole grid and the visible part of it. Clicking or dragging
+    /// inside the minimap moves the view.
+    pub fn set_minimap(&mut self, enabled: bool) {
+        self.minimap = enabled;
+        self.view_changed = true;
+    }
+
+    pub fn has_minimap(&self) -> bool {
+        self.minimap
+    }
+
+    /// Stops the flow dash animation, so that every frame draws the
+    /// dashes at the same place. Used by the SVG export.
+    pub(crate) fn stop_flow_animation(&mut self) {
+        self.flow_start = None;
+    }
+
+    /// The offset of the flow dashes at `now`, that move along the edges
+    /// with activity. Always 0.0 after [HexGrid::stop_flow_animation].
+    pub(crate) fn flow_phase(&self, now: Instant, scale: f32, period: f32) -> f32 {
+        if let Some(start) = self.flow_start {
+            (now.saturating_duration_since(start).as_secs_f32() * UI_GRID_FLOW_SPEED * scale)
+                % period
+        } else {
+            0.0
+        }
+    }
+
+    /// The area covered by all tiles at a scale of 1.0, relative to
+    /// the unshifted grid origin.
+    pub(crate) fn grid_extent(&self) -> Rect {
+        let model = self.model.borrow();
+        let (w, h) = hex_size2wh(self.tile_size * self.dpi_factor);
+        let y = if self.y_offs { -0.5 * h } else { 0.0 };
+
+        Rect {
+            x: 0.0,
+            y,
+            w: (model.width() as f32 - 1.0).max(0.0) * 0.75 * w + w,
+            h: (model.height() as f32 + 0.5) * h,
+        }
+    }
+
+    /// Returns the minimap area in window coordinates, the grid extent and
+    /// the factor that maps grid coordinates at a scale of 1.0 into the minimap.
+    fn minimap_geometry(&self) -> Option<(Rect, Rect, f32)> {
+        if !self.minimap {
+            return None;
+        }
+
+        let ext = self.grid_extent();
+        if ext.w <= 0.0 || ext.h <= 0.0 {
+            return None;
+        }
+
+        let factor = (0.25 * self.real_pos.w / ext.w).min(0.25 * self.real_pos.h / ext.h);
+        let (mw, mh) = ((ext.w * factor).round(), (ext.h * factor).round());
+        if mw < 10.0 || mh < 10.0 {
+            return None;
+        }
+
+        let pad = (10.0 * self.dpi_factor).round();
+        let map = Rect {
+            x: self.real_pos.x + self.real_pos.w - mw - pad,
+            y: self.real_pos.y + self.real_pos.h - mh - pad,
+            w: mw,
+            h: mh,
+        };
+
+        Some((map, ext, factor))
+    }
+
+    fn minimap_hit(&self, mouse: (f32, f32)) -> bool {
+        self.minimap_geometry().map(|(map, _, _)| map.is_inside(mouse.0, mouse.1)).unwrap_or(false)
+    }
+
+    /// The part of the grid, that is visible in the view, in the
+    /// coordinates of the minimap `map`.
+    fn minimap_view(&self, map: Rect, ext: Rect, factor: f32) -> Rect {
+        let (shift_x, shift_y) = self.shift();
+        let scale = self.scale.max(0.001);
+
+        Rect {
+            x: map.x + (-shift_x / scale - ext.x) * factor,
+            y: map.y + (-shift_y / scale - ext.y) * factor,
+            w: self.real_pos.w / scale * factor,
+            h: self.real_pos.h / scale * factor,
+        }
+    }
+
+    /// Centers the view on the grid position under the mouse in the minimap.
+    fn pan_to_minimap(&mut self, mouse: (f32, f32)) {
+        if let Some((map, ext, factor)) = self.minimap_geometry() {
+            let mx = mouse.0.max(map.x).min(map.x + map.w);
+            let my = mouse.1.max(map.y).min(map.y + map.h);
+            let gx = (mx - map.x) / factor + ext.x;
+            let gy = (my - map.y) / factor + ext.y;
+
+            let scale = self.scale;
+            self.set_view(
+                scale,
+                (0.5 * self.real_pos.w - gx * scale, 0.5 * self.real_pos.h - gy * scale),
+                false,
+            );
+        }
+    }
+
+    fn draw_minimap(&self, p: &mut Painter, style: &DPIStyle, rp_offset: (f32, f32)) {
+        let (map, ext, factor) = if let Some(geom) = self.minimap_geometry() {
+            geom
+        } else {
+            return;
+        };
+        let map = map.offs(-rp_offset.0, -rp_offset.1);
+
+        p.clip_region(map.x, map.y, map.w, map.h);
+        p.rect_fill_r(UI_GRID_MINIMAP_BG_CLR, map);
+
+        let (w, h) = hex_size2wh(self.tile_size * self.dpi_factor);
+        let (cw, ch) = ((0.75 * w * factor).max(1.0), (h * factor).max(1.0));
+
+        let model = self.model.borrow();
+        for xi in 0..model.width() {
+            for yi in 0..model.height() {
+                if !model.cell_visible(xi, yi) || model.cell_empty(xi, yi) {
+                    continue;
+                }
+
+                let clr = if self.selection.contains(&(xi, yi)) || model.cell_selected(xi, yi) {
+                    UI_GRID_SELECT_BORDER_CLR
+                } else {
+                    style.color_by_idx(model.cell_color(xi, yi) as usize)
+                };
+
+                let (x, y) = self.tile_offset(xi, yi, 1.0);
+                p.rect_fill(
+                    clr,
+                    map.x + (x - ext.x) * factor - 0.5 * cw,
+                    map.y + (y - ext.y) * factor - 0.5 * ch,
+                    cw,
+                    ch,
+                );
+            }
+        }
+
+        let view = self.minimap_view(map, ext, factor);
+        p.rect_stroke(
+            1.0 * self.dpi_factor,
+            UI_GRID_MINIMAP_VIEW_CLR,
+            view.x.round() + 0.5,
+            view.y.round() + 0.5,
+            view.w.round(),
+            view.h.round(),
+        );
+
+        p.reset_clip_region();
+
+        p.rect_stroke(
+            1.0 * self.dpi_factor,
+            UI_GRID_MINIMAP_BORDER_CLR,
+            map.x - 0.5,
+            map.y - 0.5,
+            map.w + 1.0,
+            map.h + 1.0,
+        );
+    }
+
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.cursor
+    }
+
+    /// Places the keyboard cursor, which can be moved with the arrow keys
+    /// while the grid is focused.
+    pub fn set_cursor(&mut self, cursor: Option<(usize, usize)>) {
+        self.cursor = cursor;
+        self.view_changed = true;
+    }
+
+    /// Moves the keyboard cursor to the neighbour tile. If there is no
+    /// cursor yet, it starts at the tile in the center of the view.
+    /// An empty grid has no cursor.
+    fn move_cursor(&mut self, dir: HexDir) {
+        let (w, h) = {
+            let model = self.model.borrow();
+            (model.width(), model.height())
+        };
+
+        if w == 0 || h == 0 {
+            self.cursor = None;
+            return;
+        }
+
+        let cursor = match self.cursor {
+            Some((x, y)) if x < w && y < h => dir.neighbour(x, y, w, h).unwrap_or((x, y)),
+            _ => {
+                // The view center can be outside of the grid, even left of
+                // or above it, so clamp before leaving the signed coordinates:
+                let (x, y) = self.get_mouse_tile_pos(
+                    self.real_pos.x + self.real_pos.w * 0.5,
+                    self.real_pos.y + self.real_pos.h * 0.5,
+                );
+                (x.clamp(0, w as i32 - 1) as usize, y.clamp(0, h as i32 - 1) as usize)
+            }
+        };
+
+        self.cursor = Some(cursor);
+
+        // Keep the cursor inside the view:
+        let (cx, cy) = self.tile_center(cursor.0, cursor.1);
+        let (tw, th) = hex_size2wh(self.tile_size * self.scale * self.dpi_factor);
+        if !self.real_pos.shrink(0.5 * tw, 0.5 * th).is_inside(cx, cy) {
+            self.center_tile(cursor.0, cursor.1, true);
+        }
+    }
+
+    /// Keyboard operation of the cursor, returns true if the key was handled.
+    fn handle_cursor_key(
+        &mut self,
+        w: &Widget,
+        key: &keyboard_types::KeyboardEvent,
+        out_events: &mut Vec<(usize, Event)>,
+    ) -> bool {
+        // Ctrl+Arrows are for panning the view:
+        if key.modifiers.contains(Modifiers::CONTROL) {
+            return false;
+        }
+
+        let shift = key.modifiers.contains(Modifiers::SHIFT);
+
+        let dir = match &key.key {
+            Key::ArrowUp => Some(HexDir::T),
+            Key::ArrowDown => Some(HexDir::B),
+            Key::ArrowLeft if shift => Some(HexDir::BL),
+            Key::ArrowLeft => Some(HexDir::TL),
+            Key::ArrowRight if shift => Some(HexDir::BR),
+            Key::ArrowRight => Some(HexDir::TR),
+            _ => None,
+        };
+
+        if let Some(dir) = dir {
+            self.move_cursor(dir);
+            w.emit_redraw_required();
+            return true;
+        }
+
+        let cursor = match self.cursor {
+            Some((x, y)) if x < self.model.borrow().width() && y < self.model.borrow().height() => {
+                (x, y)
+            }
+            _ => return false,
+        };
+
+        match &key.key {
+            Key::Character(c) if c == "m" => {
+                self.mark = if self.mark == Some(cursor) { None } else { Some(cursor) };
+            }
+            Key::Character(c) if c == " " => self.cursor_action(w, cursor, shift, out_events),
+            Key::Enter => self.cursor_action(w, cursor, shift, out_events),
+            Key::Escape if self.mark.is_some() => {
+                self.mark = None;
+            }
+            _ => return false,
+        }
+
+        w.emit_redraw_required();
+        true
+    }
+
+    /// Emits a "hex_drag" from the marked tile to the cursor, or
+    /// a "click" on the cursor tile. Shift uses the right mouse button.
+    fn cursor_action(
+        &mut self,
+        w: &Widget,
+        cursor: (usize, usize),
+        shift: bool,
+        out_events: &mut Vec<(usize, Event)>,
+    ) {
+        let button = if shift { MButton::Right } else { MButton::Left };
+
+        match self.mark.take() {
+            Some(mark) if mark != cursor => {
+                out_events.push(w.event(
+                    "hex_drag",
+                    EvPayload::HexGridDrag {
+                        x_src: mark.0,
+                        y_src: mark.1,
+                        x_dst: cursor.0,
+                        y_dst: cursor.1,
+                        button,
+                    },
+                ));
+            }
+            _ => {
+                out_events.push(
+                    w.event("click", EvPayload::HexGridClick { x: cursor.0, y: cursor.1, button }),
+                );
+            }
+        }
+    }
+
+    /// Returns the center of the tile in window coordinates.
+    pub fn tile_center(&self, xi: usize, yi: usize) -> (f32, f32) {
+        let (xo, yo) = self.tile_offset(xi, yi, self.scale);
+        let (shift_x, shift_y) = self.shift();
+        (self.real_pos.x + shift_x + xo, self.real_pos.y + shift_y + yo)
+    }
+
+    fn set_view(&mut self, scale: f32, shift_offs: (f32, f32), animate: bool) {
+        self.tmp_shift_offs = None;
+        self.scale_step = ((scale - 1.0) / 0.25).round() as i32;
+
+        if animate {
+            self.view_anim = Some(ViewAnim {
+                from: (self.scale, self.shift_offs),
+                to: (scale, shift_offs),
+                start: Instant::now(),
+            });
+        } else {
+            self.view_anim = None;
+            self.scale = scale;
+            self.shift_offs = shift_offs;
+        }
+
+        self.view_changed = true;
+    }
+
+    /// Returns the view (scale, shift_offs) the view is currently
+    /// animated to, or the current view.
+    fn target_view(&self) -> (f32, (f32, f32)) {
+        self.view_anim.as_ref().map(|anim| anim.to).unwrap_or((self.scale, self.shift_offs))
+    }
+
+    /// Moves the view so that the tile is in the center, keeping the zoom.
+    pub fn center_tile(&mut self, xi: usize, yi: usize, animate: bool) {
+        let scale = self.target_view().0;
+        let (xo, yo) = self.tile_offset(xi, yi, scale);
+
+        self.set_view(scale, (0.5 * self.real_pos.w - xo, 0.5 * self.real_pos.h - yo), animate);
+    }
+
+    /// Zooms and moves the view so that all non empty cells are visible.
+    /// Resets the view if there are none.
+    pub fn zoom_to_fit(&mut self, animate: bool) {
+        let mut bounds: Option<(f32, f32, f32, f32)> = None;
+
+        {
+            let model = self.model.borrow();
+            for xi in 0..model.width() {
+                for yi in 0..model.height() {
+                    if !model.cell_visible(xi, yi) || model.cell_empty(xi, yi) {
+                        continue;
+                    }
+
+                    let (x, y) = self.tile_offset(xi, yi, 1.0);
+                    bounds = Some(match bounds {
+                        None => (x, y, x, y),
+                        Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                    });
+                }
+            }
+        }
+
+        let (x0, y0, x1, y1) = if let Some(bounds) = bounds {
+            bounds
+        } else {
+            self.reset_view(animate);
+            return;
+        };
+
+        // Add the extent of one tile around the tile centers:
+        let (tw, th) = hex_size2wh(self.tile_size * self.dpi_factor);
+        let (bw, bh) = (x1 - x0 + tw, y1 - y0 + th);
+
+        let scale = (0.9 * (self.real_pos.w / bw).min(self.real_pos.h / bh)).max(0.25).min(2.0);
+        let center = (0.5 * (x0 + x1) * scale, 0.5 * (y0 + y1) * scale);
+
+        self.set_view(
+            scale,
+            (0.5 * self.real_pos.w - center.0, 0.5 * self.real_pos.h - center.1),
+            animate,
+        );
+    }
+
+    /// Goes back to the initial zoom and position.
+    pub fn reset_view(&mut self, animate: bool) {
+        self.set_view(1.0, (0.0, 0.0), animate);
+    }
+
+    fn zoom_step(&mut self, zoom_in: bool) {
+        self.view_anim = None;
+
+        if zoom_in {
+            self.scale_step += 1;
+        } else {
+            self.scale_step -= 1;
+        }
+
+        let old_shift = self.shift_offs;
+        let old_shift = (old_shift.0 / self.scale, old_shift.1 / self.scale);
+
+        self.scale = 1.0 + self.scale_step as f32 * 0.25;
+
+        if self.scale <= 0.001 {
+            self.scale = 0.1;
+        }
+
+        self.shift_offs = (old_shift.0 * self.scale, old_shift.1 * self.scale);
+    }
+
+    /// Like [HexGrid::create_center_tile_event], but for the view the
+    /// grid is currently animated to.
+    fn create_target_center_tile_event(&mut self) -> EvPayload {
+        let current = (self.scale, self.shift_offs);
+        (self.scale, self.shift_offs) = self.target_view();
+        let ev = self.create_center_tile_event();
+        (self.scale, self.shift_offs) = current;
+        ev
+    }
+
+    /// Keyboard navigation of the view, returns true if the key was handled.
+    fn handle_view_key(&mut self, key: &keyboard_types::KeyboardEvent) -> bool {
+        let pan = (0.25 * self.real_pos.w, 0.25 * self.real_pos.h);
+
+        if key.modifiers.contains(Modifiers::CONTROL) {
+            let (dx, dy) = match key.key {
+                Key::ArrowLeft => (pan.0, 0.0),
+                Key::ArrowRight => (-pan.0, 0.0),
+                Key::ArrowUp => (0.0, pan.1),
+                Key::ArrowDown => (0.0, -pan.1),
+                _ => return false,
+            };
+
+            let (scale, shift) = self.target_view();
+            self.set_view(scale, (shift.0 + dx, shift.1 + dy), true);
+            return true;
+        }
+
+        match &key.key {
+            Key::Character(c) if c == "+" || c == "=" => self.zoom_step(true),
+            Key::Character(c) if c == "-" => self.zoom_step(false),
+            Key::Character(c) if c == "0" => self.reset_view(true),
+            Key::Home => self.zoom_to_fit(true),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// The selected cells, in column order.
+    pub fn selection(&self) -> Vec<(usize, usize)> {
+        self.selection.iter().copied().collect()
+    }
+
+    pub fn set_selection(&mut self, cells: &[(usize, usize)]) {
+        self.selection = cells.iter().copied().collect();
+    }
+
+    fn emit_selection(&self, w: &Widget, out_events: &mut Vec<(usize, Event)>) {
+        out_events
+            .push(w.event("selection", EvPayload::HexGridSelection { cells: self.selection() }));
+        w.emit_redraw_required();
+    }
+
+    /// Returns the non empty cells with their center inside the
+    /// rectangle spanned by `a` and `b`.
+    fn cells_in_rect(&self, a: (f32, f32), b: (f32, f32)) -> Vec<(usize, usize)> {
+        let rect = Rect::from(a.0.min(b.0), a.1.min(b.1), (a.0 - b.0).abs(), (a.1 - b.1).abs());
+        let model = self.model.borrow();
+
+        let mut cells = vec![];
+        for xi in 0..model.width() {
+            for yi in 0..model.height() {
+                if !model.cell_visible(xi, yi) || model.cell_empty(xi, yi) {
+                    continue;
+                }
+
+                let (cx, cy) = self.tile_center(xi, yi);
+                if rect.is_inside(cx, cy) {
+                    cells.push((xi, yi));
+                }
+            }
+        }
+
+        cells
+    }
+
     pub fn create_center_tile_event(&self) -> EvPayload {
         let tile_pos = self.get_mouse_tile_pos(
             self.real_pos.x + self.real_pos.w * 0.5,
@@ -362,9 +989,66 @@ impl HexGrid {
     pub fn handle(&mut self, w: &Widget, event: &InputEvent, out_events: &mut Vec<(usize, Event)>) {
         let is_hovered = w.is_hovered();
 
+        self.modkeys.handle(event);
+
         match event {
+            InputEvent::MouseButtonPressed(MButton::Left)
+                if is_hovered && self.minimap_hit(self.mouse) =>
+            {
+                self.minimap_drag = true;
+                self.pan_to_minimap(self.mouse);
+                w.activate();
+                w.emit_redraw_required();
+            }
+            InputEvent::MousePosition(x, y) if self.minimap_drag => {
+                self.mouse = (*x, *y);
+                self.pan_to_minimap(self.mouse);
+                w.emit_redraw_required();
+            }
+            InputEvent::MouseButtonReleased(MButton::Left) if self.minimap_drag => {
+                self.minimap_drag = false;
+                out_events.push(w.event("center_pos", self.create_center_tile_event()));
+                w.deactivate();
+            }
+            InputEvent::MouseButtonPressed(MButton::Left) if is_hovered && self.modkeys.shift => {
+                self.rubber_band = Some((self.mouse, self.mouse));
+                w.activate();
+            }
+            InputEvent::MouseButtonReleased(MButton::Left) if self.rubber_band.is_some() => {
+                if let Some((start, end)) = self.rubber_band.take() {
+                    // Ctrl+Shift adds to the selection:
+                    if !self.modkeys.ctrl {
+                        self.selection.clear();
+                    }
+                    self.selection.extend(self.cells_in_rect(start, end));
+                    self.emit_selection(w, out_events);
+                }
+
+                w.deactivate();
+            }
+            InputEvent::KeyPressed(key) => {
+                if !w.is_focused() {
+                    return;
+                }
+
+                if self.handle_cursor_key(w, key, out_events) {
+                    return;
+                }
+
+                if key.key == Key::Escape && !self.selection.is_empty() {
+                    self.selection.clear();
+                    self.emit_selection(w, out_events);
+                } else if self.handle_view_key(key) {
+                    out_events.push(w.event("center_pos", self.create_target_center_tile_event()));
+                    w.emit_redraw_required();
+                }
+            }
             InputEvent::MouseButtonPressed(btn) => {
                 if is_hovered {
+                    if *btn == MButton::Middle {
+                        self.view_anim = None;
+                    }
+
                     self.mouse_state = Some((*btn, self.mouse.0, self.mouse.1));
                     self.start_tile_pos = Some(self.get_mouse_tile_pos(self.mouse.0, self.mouse.1));
 
@@ -395,7 +1079,32 @@ impl HexGrid {
 
                     if let Some(start_tile_pos) = self.start_tile_pos {
                         if cur_tile_pos == start_tile_pos {
-                            if cur_tile_pos.0 >= 0 && cur_tile_pos.1 >= 0 {
+                            let is_cell = cur_tile_pos.0 >= 0 && cur_tile_pos.1 >= 0;
+                            let cell = (cur_tile_pos.0 as usize, cur_tile_pos.1 as usize);
+
+                            if is_cell && *btn == MButton::Left && self.modkeys.ctrl {
+                                // Only the cells of the model can be toggled:
+                                let is_empty = {
+                                    let model = self.model.borrow();
+                                    if cell.0 < model.width() && cell.1 < model.height() {
+                                        Some(model.cell_empty(cell.0, cell.1))
+                                    } else {
+                                        None
+                                    }
+                                };
+
+                                if let Some(is_empty) = is_empty {
+                                    if !self.selection.remove(&cell) && !is_empty {
+                                        self.selection.insert(cell);
+                                    }
+                                    self.emit_selection(w, out_events);
+                                }
+                            } else if is_cell {
+                                if *btn == MButton::Left && !self.selection.is_empty() {
+                                    self.selection.clear();
+                                    self.emit_selection(w, out_events);
+                                }
+
                                 out_events.push(w.event(
                                     "click",
                                     EvPayload::HexGridClick {
@@ -438,14 +1147,22 @@ impl HexGrid {
             InputEvent::MousePosition(x, y) => {
                 self.mouse = (*x, *y);
 
-                if let Some((MButton::Middle, mx, my)) = self.mouse_state {
+                if let Some((start, _)) = self.rubber_band {
+                    self.rubber_band = Some((start, self.mouse));
+                    w.emit_redraw_required();
+                } else if let Some((MButton::Middle, mx, my)) = self.mouse_state {
                     self.tmp_shift_offs = Some((*x - mx, *y - my));
 
                     w.emit_redraw_required();
                 } else {
                     let old_hover_pos = self.hover_pos;
 
-                    self.hover_pos = self.get_mouse_tile_pos(*x, *y);
+                    // No tile is hovered below the minimap:
+                    self.hover_pos = if self.minimap_hit(self.mouse) {
+                        (-1, -1)
+                    } else {
+                        self.get_mouse_tile_pos(*x, *y)
+                    };
 
                     // For left & right mouse clicks:
                     if let Some((_, _mx, _my)) = self.mouse_state {
@@ -467,22 +1184,7 @@ impl HexGrid {
             }
             InputEvent::MouseWheel(y) => {
                 if is_hovered {
-                    if *y < 0.0 {
-                        self.scale_step -= 1;
-                    } else {
-                        self.scale_step += 1;
-                    }
-
-                    let old_shift = self.shift_offs;
-                    let old_shift = (old_shift.0 / self.scale, old_shift.1 / self.scale);
-
-                    self.scale = 1.0 + self.scale_step as f32 * 0.25;
-
-                    if self.scale <= 0.001 {
-                        self.scale = 0.1;
-                    }
-
-                    self.shift_offs = (old_shift.0 * self.scale, old_shift.1 * self.scale);
+                    self.zoom_step(*y >= 0.0);
 
                     out_events.push(w.event("center_pos", self.create_center_tile_event()));
                     w.emit_redraw_required();
@@ -507,6 +1209,7 @@ impl HexGrid {
     ) {
         self.dpi_factor = p.dpi_factor;
         let is_hovered = w.is_hovered();
+        let is_focused = w.is_focused();
 
         let mut dbg = w.debug_tag();
         let rp_offset = (real_pos.x - pos.x, real_pos.y - pos.y);
@@ -534,6 +1237,9 @@ impl HexGrid {
         let mut edge_led_pos = self.edge_led_pos.take();
         edge_led_pos.as_mut().unwrap().clear();
 
+        let mut edge_flow_pos = self.edge_flow_pos.take();
+        edge_flow_pos.as_mut().unwrap().clear();
+
         for xi in 0..nx {
             let x = xi as f32;
 
@@ -549,10 +1255,7 @@ impl HexGrid {
 
                 let test_pos =
                     Rect { x: -0.5 * w, y: -0.5 * h, w: pos.w + 1.0 * w, h: pos.h + 1.0 * h };
-                let shift_x =
-                    (self.shift_offs.0 + self.tmp_shift_offs.map(|o| o.0).unwrap_or(0.0)).round();
-                let shift_y =
-                    (self.shift_offs.1 + self.tmp_shift_offs.map(|o| o.1).unwrap_or(0.0)).round();
+                let (shift_x, shift_y) = self.shift();
 
                 //                let test_pos = test_pos.offs(shift_x, shift_y);
 
@@ -585,13 +1288,22 @@ impl HexGrid {
                 let th2 = p.font_height(self.edge_font_size * self.scale * p.dpi_factor, false);
                 let fs2 = self.edge_font_size * self.scale * p.dpi_factor;
 
+                let selected = self.selection.contains(&(xi, yi)) || model.cell_selected(xi, yi);
+                let is_cursor = is_focused && self.cursor == Some((xi, yi));
+
                 let (line, clr) = if is_hovered
                     && self.hover_pos.0 == (xi as i32)
                     && self.hover_pos.1 == (yi as i32)
                 {
                     (5.0, UI_GRID_HOVER_BORDER_CLR)
+                } else if is_cursor {
+                    (5.0, UI_GRID_CURSOR_BORDER_CLR)
+                } else if self.mark == Some((xi, yi)) {
+                    (4.0, UI_GRID_MARK_BORDER_CLR)
                 } else if Some((xi as i32, yi as i32)) == self.drag_source_pos {
                     (3.0, UI_GRID_DRAG_BORDER_CLR)
+                } else if selected {
+                    (4.0, UI_GRID_SELECT_BORDER_CLR)
                 } else if model.cell_empty(xi, yi) {
                     (3.0, UI_GRID_EMPTY_BORDER_CLR)
                 } else {
@@ -622,6 +1334,11 @@ impl HexGrid {
                                     let led = model.cell_led(xi, yi);
 
                                     let (s, hc) = (cell_vis.label, cell_vis.hlight);
+                                    let hc = if selected && hc != HexHLight::Plain {
+                                        HexHLight::Select
+                                    } else {
+                                        hc
+                                    };
 
                                     let (txt_clr, clr) = match hc {
                                         HexHLight::Normal => (UI_GRID_TXT_CENTER_CLR, clr),
@@ -738,6 +1455,13 @@ impl HexGrid {
                                         (rp_offset.0 + x, rp_offset.1 + y, 90.0),
                                     ));
                                 }
+
+                                if model.cell_edge_activity(xi, yi, HexDir::B).is_some() {
+                                    edge_flow_pos.as_mut().unwrap().push((
+                                        (xi, yi, HexDir::B),
+                                        (rp_offset.0 + x, rp_offset.1 + y, 90.0),
+                                    ));
+                                }
                             }
                             HexDecorPos::TopLeft(x, y) => {
                                 if let Some(s) =
@@ -783,6 +1507,13 @@ impl HexGrid {
                                         (rp_offset.0 + x, rp_offset.1 + y, -30.0),
                                     ));
                                 }
+
+                                if model.cell_edge_activity(xi, yi, HexDir::TR).is_some() {
+                                    edge_flow_pos.as_mut().unwrap().push((
+                                        (xi, yi, HexDir::TR),
+                                        (rp_offset.0 + x, rp_offset.1 + y, -30.0),
+                                    ));
+                                }
                             }
                             HexDecorPos::BotLeft(x, y) => {
                                 if let Some(s) =
@@ -828,6 +1559,13 @@ impl HexGrid {
                                         (rp_offset.0 + x, rp_offset.1 + y, 30.0),
                                     ));
                                 }
+
+                                if model.cell_edge_activity(xi, yi, HexDir::BR).is_some() {
+                                    edge_flow_pos.as_mut().unwrap().push((
+                                        (xi, yi, HexDir::BR),
+                                        (rp_offset.0 + x, rp_offset.1 + y, 30.0),
+                                    ));
+                                }
                             }
                         }
                     },
@@ -839,13 +1577,43 @@ impl HexGrid {
 
         self.led_pos = led_pos;
         self.edge_led_pos = edge_led_pos;
+        self.edge_flow_pos = edge_flow_pos;
+
+        if let Some((start, end)) = self.rubber_band {
+            let (x, y) = (start.0.min(end.0) - rp_offset.0, start.1.min(end.1) - rp_offset.1);
+            p.rect_stroke(
+                1.0 * p.dpi_factor,
+                UI_GRID_RUBBER_BAND_CLR,
+                x.round() + 0.5,
+                y.round() + 0.5,
+                (start.0 - end.0).abs().round(),
+                (start.1 - end.1).abs().round(),
+            );
+        }
+
+        self.draw_minimap(p, style, rp_offset);
 
         p.reset_clip_region();
     }
 
-    pub fn draw_frame(&mut self, _w: &Widget, _style: &DPIStyle, painter: &mut Painter) {
-        let shift_x = (self.shift_offs.0 + self.tmp_shift_offs.map(|o| o.0).unwrap_or(0.0)).round();
-        let shift_y = (self.shift_offs.1 + self.tmp_shift_offs.map(|o| o.1).unwrap_or(0.0)).round();
+    pub fn draw_frame(&mut self, w: &Widget, _style: &DPIStyle, painter: &mut Painter) {
+        if let Some(anim) = &self.view_anim {
+            let ((scale, shift_offs), done) = anim.at(Instant::now());
+            self.scale = scale;
+            self.shift_offs = shift_offs;
+
+            if done {
+                self.view_anim = None;
+            }
+            self.view_changed = true;
+        }
+
+        if self.view_changed {
+            self.view_changed = false;
+            w.emit_redraw_required();
+        }
+
+        let (shift_x, shift_y) = self.shift();
 
         let pos = self.real_pos;
 
@@ -853,6 +1621,47 @@ impl HexGrid {
         painter.translate(shift_x, shift_y);
 
         let model = self.model.borrow();
+
+        let scale = self.scale * painter.dpi_factor;
+        // The gap between the padded hexagons of two neighbour cells:
+        let gap = (3.0_f32).sqrt() * 10.0 * scale;
+        let period = 6.0 * scale;
+        let dash = 3.0 * scale;
+        let phase = self.flow_phase(Instant::now(), scale, period);
+
+        for (edge_pos, pos_angl) in self.edge_flow_pos.as_ref().unwrap().iter() {
+            let activity =
+                if let Some(act) = model.cell_edge_activity(edge_pos.0, edge_pos.1, edge_pos.2) {
+                    act.max(0.0).min(1.0)
+                } else {
+                    continue;
+                };
+
+            let clr = (
+                UI_GRID_FLOW_IDLE_CLR.0 + (UI_GRID_FLOW_CLR.0 - UI_GRID_FLOW_IDLE_CLR.0) * activity,
+                UI_GRID_FLOW_IDLE_CLR.1 + (UI_GRID_FLOW_CLR.1 - UI_GRID_FLOW_IDLE_CLR.1) * activity,
+                UI_GRID_FLOW_IDLE_CLR.2 + (UI_GRID_FLOW_CLR.2 - UI_GRID_FLOW_IDLE_CLR.2) * activity,
+            );
+
+            let (x, y, angle) = *pos_angl;
+            let (dx, dy) = (angle.to_radians().cos(), angle.to_radians().sin());
+
+            // Without activity the dashes stand still:
+            let mut offs = if activity > 0.0 { phase - period } else { 0.0 };
+            while offs < gap {
+                let (a, b) = (offs.max(0.0), (offs + dash).min(gap));
+                if b > a {
+                    painter.stroke(
+                        2.0 * scale,
+                        clr,
+                        &[(x + dx * a, y + dy * a), (x + dx * b, y + dy * b)],
+                        false,
+                    );
+                }
+                offs += period;
+            }
+        }
+
         for (cell_pos, pos) in self.led_pos.as_ref().unwrap().iter() {
             let led = model.cell_led(cell_pos.0, cell_pos.1);
             if let Some(led) = led {
@@ -872,16 +1681,443 @@ impl HexGrid {
     pub fn annotate_drop_event(&mut self, mouse_pos: (f32, f32), ev: Event) -> Event {
         let cur_tile_pos = self.get_mouse_tile_pos(mouse_pos.0, mouse_pos.1);
         if let EvPayload::UserData(data) = ev.data {
-            Event {
-                name: ev.name,
-                data: EvPayload::HexGridDropData {
+            Event::new(
+                &ev.name[..],
+                EvPayload::HexGridDropData {
                     x: cur_tile_pos.0 as usize,
                     y: cur_tile_pos.1 as usize,
                     data,
                 },
-            }
+            )
         } else {
             ev
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Control, EvKind, UI};
+    use morphorm::Units;
+
+    struct TestGrid {
+        w: usize,
+        h: usize,
+        /// The cells that are not empty.
+        filled: Vec<(usize, usize)>,
+    }
+
+    impl HexGridModel for TestGrid {
+        fn width(&self) -> usize {
+            self.w
+        }
+        fn height(&self) -> usize {
+            self.h
+        }
+        fn cell_visible(&self, x: usize, y: usize) -> bool {
+            x < self.w && y < self.h
+        }
+        fn cell_empty(&self, x: usize, y: usize) -> bool {
+            !self.filled.contains(&(x, y))
+        }
+        fn cell_led(&self, _x: usize, _y: usize) -> Option<(f32, f32)> {
+            None
+        }
+        fn cell_label<'a>(&self, _x: usize, _y: usize, _out: &'a mut [u8]) -> Option<HexCell<'a>> {
+            None
+        }
+        fn cell_edge(&self, _x: usize, _y: usize, _edge: HexDir) -> HexEdge {
+            HexEdge::NoArrow
+        }
+        fn cell_edge_label<'a>(
+            &self,
+            _x: usize,
+            _y: usize,
+            _edge: HexDir,
+            _buf: &'a mut [u8],
+        ) -> Option<&'a str> {
+            None
+        }
+        fn get_generation(&self) -> u64 {
+            1
+        }
+    }
+
+    /// A [HexGrid] of `w` x `h` tiles in a 400x300 view.
+    fn test_grid(w: usize, h: usize, filled: &[(usize, usize)]) -> HexGrid {
+        let model = TestGrid { w, h, filled: filled.to_vec() };
+        let mut grid = HexGrid::new(Rc::new(RefCell::new(model)));
+        grid.real_pos = Rect::from(0.0, 0.0, 400.0, 300.0);
+        grid
+    }
+
+    /// The cells of all "selection" events in `out`.
+    fn selection_events(out: &[(usize, Event)]) -> Vec<Vec<(usize, usize)>> {
+        out.iter()
+            .filter_map(|(_, ev)| match &ev.data {
+                EvPayload::HexGridSelection { cells } if ev.is(EvKind::Selection) => {
+                    Some(cells.clone())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn ctrl_click(grid: &mut HexGrid, w: &Widget, cell: (usize, usize)) -> Vec<(usize, Event)> {
+        grid.modkeys.ctrl = true;
+        grid.mouse = grid.tile_center(cell.0, cell.1);
+        grid.mouse_state = Some((MButton::Left, grid.mouse.0, grid.mouse.1));
+        grid.start_tile_pos = Some(grid.get_mouse_tile_pos(grid.mouse.0, grid.mouse.1));
+
+        let mut out = vec![];
+        grid.handle(w, &InputEvent::MouseButtonReleased(MButton::Left), &mut out);
+        out
+    }
+
+    #[test]
+    fn check_selection_cells_in_rect() {
+        let grid = test_grid(4, 4, &[(0, 0), (1, 1), (3, 3)]);
+
+        // Also spans the empty cells (0, 1) and (1, 0):
+        let (a, b) = (grid.tile_center(0, 0), grid.tile_center(1, 1));
+        let (a, b) = ((a.0 - 5.0, a.1 - 5.0), (b.0 + 5.0, b.1 + 5.0));
+        assert_eq!(grid.cells_in_rect(a, b), vec![(0, 0), (1, 1)]);
+        assert_eq!(grid.cells_in_rect(b, a), vec![(0, 0), (1, 1)]);
+
+        // Only the center of a cell counts:
+        let (a, b) = (grid.tile_center(0, 0), grid.tile_center(1, 1));
+        assert_eq!(grid.cells_in_rect((a.0 + 5.0, a.1 + 5.0), b), vec![(1, 1)]);
+        assert_eq!(grid.cells_in_rect((a.0 + 5.0, a.1 + 5.0), (a.0 + 10.0, a.1 + 10.0)), vec![]);
+    }
+
+    #[test]
+    fn check_selection_rubber_band_release() {
+        let w = Widget::new(Rc::new(Style::new()));
+        let mut grid = test_grid(4, 4, &[(0, 0), (1, 1), (3, 3)]);
+        grid.set_selection(&[(3, 3)]);
+
+        let (a, b) = (grid.tile_center(0, 0), grid.tile_center(1, 1));
+        let band = ((a.0 - 5.0, a.1 - 5.0), (b.0 + 5.0, b.1 + 5.0));
+
+        let mut out = vec![];
+        grid.rubber_band = Some(band);
+        grid.handle(&w, &InputEvent::MouseButtonReleased(MButton::Left), &mut out);
+        assert_eq!(selection_events(&out), vec![vec![(0, 0), (1, 1)]]);
+        assert_eq!(grid.selection(), vec![(0, 0), (1, 1)]);
+
+        // With Ctrl the cells are added:
+        grid.set_selection(&[(3, 3)]);
+        grid.modkeys.ctrl = true;
+        let mut out = vec![];
+        grid.rubber_band = Some(band);
+        grid.handle(&w, &InputEvent::MouseButtonReleased(MButton::Left), &mut out);
+        assert_eq!(selection_events(&out), vec![vec![(0, 0), (1, 1), (3, 3)]]);
+    }
+
+    #[test]
+    fn check_selection_ctrl_toggle() {
+        let w = Widget::new(Rc::new(Style::new()));
+        let mut grid = test_grid(4, 4, &[(0, 0), (1, 1)]);
+
+        let out = ctrl_click(&mut grid, &w, (1, 1));
+        assert_eq!(selection_events(&out), vec![vec![(1, 1)]]);
+        assert_eq!(out.len(), 1, "no click event with Ctrl");
+
+        let out = ctrl_click(&mut grid, &w, (0, 0));
+        assert_eq!(selection_events(&out), vec![vec![(0, 0), (1, 1)]]);
+
+        // Empty cells can't be selected:
+        let out = ctrl_click(&mut grid, &w, (2, 2));
+        assert_eq!(selection_events(&out), vec![vec![(0, 0), (1, 1)]]);
+
+        let out = ctrl_click(&mut grid, &w, (1, 1));
+        assert_eq!(selection_events(&out), vec![vec![(0, 0)]]);
+
+        // But they can be deselected, if the selection was set by the application:
+        grid.set_selection(&[(0, 0), (2, 2)]);
+        let out = ctrl_click(&mut grid, &w, (2, 2));
+        assert_eq!(selection_events(&out), vec![vec![(0, 0)]]);
+        let out = ctrl_click(&mut grid, &w, (2, 2));
+        assert_eq!(selection_events(&out), vec![vec![(0, 0)]]);
+    }
+
+    #[test]
+    fn check_selection_ctrl_toggle_outside_grid() {
+        let w = Widget::new(Rc::new(Style::new()));
+        let model = Rc::new(RefCell::new(TestGrid { w: 4, h: 4, filled: vec![(0, 0)] }));
+        let mut grid = HexGrid::new(model.clone());
+        grid.real_pos = Rect::from(0.0, 0.0, 400.0, 300.0);
+
+        // Tiles right of and below the model are no cells, even if the
+        // model does not check the bounds itself:
+        model.borrow_mut().filled.extend([(5, 1), (1, 5)]);
+        for cell in [(5, 1), (1, 5), (4, 4)] {
+            let out = ctrl_click(&mut grid, &w, cell);
+            assert!(out.is_empty(), "{:?}", cell);
+        }
+        assert!(grid.selection().is_empty());
+
+        let out = ctrl_click(&mut grid, &w, (0, 0));
+        assert_eq!(selection_events(&out), vec![vec![(0, 0)]]);
+    }
+
+    /// Compares positions, allowing for the rounding of the shift offset.
+    fn assert_near(a: (f32, f32), b: (f32, f32)) {
+        assert!((a.0 - b.0).abs() <= 1.0 && (a.1 - b.1).abs() <= 1.0, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn check_flow_phase() {
+        let mut grid = test_grid(2, 2, &[]);
+        let start = Instant::now();
+        grid.flow_start = Some(start);
+
+        assert_eq!(grid.flow_phase(start, 1.0, 6.0), 0.0);
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let phase = grid.flow_phase(at(100), 1.0, 6.0);
+        assert!((phase - (0.1 * UI_GRID_FLOW_SPEED) % 6.0).abs() < 0.001);
+        assert!(grid.flow_phase(at(100), 2.0, 12.0) > phase);
+        assert!((0..50).all(|i| grid.flow_phase(at(i * 77), 1.0, 6.0) < 6.0));
+
+        grid.stop_flow_animation();
+        assert_eq!(grid.flow_phase(at(100), 1.0, 6.0), 0.0);
+        assert_eq!(grid.flow_phase(at(5000), 1.0, 6.0), 0.0);
+    }
+
+    #[test]
+    fn check_view_anim_at() {
+        let start = Instant::now();
+        let anim = ViewAnim { from: (1.0, (0.0, 0.0)), to: (2.0, (100.0, -50.0)), start };
+
+        assert_eq!(anim.at(start), ((1.0, (0.0, 0.0)), false));
+        assert_eq!(anim.at(start + Duration::from_millis(125)), ((1.5, (50.0, -25.0)), false));
+
+        let end = start + Duration::from_millis(UI_GRID_VIEW_ANIM_MS);
+        assert_eq!(anim.at(end), ((2.0, (100.0, -50.0)), true));
+        assert_eq!(anim.at(end + Duration::from_secs(1)), ((2.0, (100.0, -50.0)), true));
+    }
+
+    #[test]
+    fn check_zoom_to_fit() {
+        // Fits into the 400x300 view with some margin:
+        let mut grid = test_grid(8, 8, &[(0, 0), (4, 2)]);
+        grid.zoom_to_fit(false);
+        let (tw, _) = hex_size2wh(grid.tile_size);
+        let bounds_w = grid.tile_offset(4, 2, 1.0).0 - grid.tile_offset(0, 0, 1.0).0 + tw;
+        assert!((grid.scale - 0.9 * 400.0 / bounds_w).abs() < 0.001);
+
+        let (a, b) = (grid.tile_center(0, 0), grid.tile_center(4, 2));
+        assert_near((0.5 * (a.0 + b.0), 0.5 * (a.1 + b.1)), (200.0, 150.0));
+
+        // The zoom is clamped for a single cell:
+        let mut grid = test_grid(8, 8, &[(3, 3)]);
+        grid.zoom_to_fit(false);
+        assert_eq!(grid.scale, 2.0);
+        assert_near(grid.tile_center(3, 3), (200.0, 150.0));
+
+        // ... and for a huge grid:
+        let mut grid = test_grid(40, 40, &[(0, 0), (39, 39)]);
+        grid.zoom_to_fit(false);
+        assert_eq!(grid.scale, 0.25);
+
+        // Without any cells the view is reset:
+        let mut grid = test_grid(8, 8, &[]);
+        grid.scale = 2.0;
+        grid.shift_offs = (10.0, 20.0);
+        grid.zoom_to_fit(false);
+        assert_eq!((grid.scale, grid.shift_offs), (1.0, (0.0, 0.0)));
+    }
+
+    #[test]
+    fn check_center_tile() {
+        let mut grid = test_grid(8, 8, &[]);
+        grid.center_tile(5, 3, false);
+        assert_near(grid.tile_center(5, 3), (200.0, 150.0));
+
+        // The zoom is kept:
+        grid.scale = 1.5;
+        grid.center_tile(2, 6, false);
+        assert_eq!(grid.scale, 1.5);
+        assert_near(grid.tile_center(2, 6), (200.0, 150.0));
+
+        // Animated, the view ends up at the same place:
+        let shift_offs = grid.shift_offs;
+        grid.center_tile(5, 3, false);
+        grid.center_tile(2, 6, true);
+        assert_eq!(grid.view_anim.as_ref().map(|anim| anim.to), Some((1.5, shift_offs)));
+    }
+
+    #[test]
+    fn check_cursor_stays_inside_grid() {
+        let mut grid = test_grid(0, 0, &[]);
+        grid.move_cursor(HexDir::B);
+        assert_eq!(grid.cursor(), None);
+
+        // The view center is left of and above the grid:
+        let mut grid = test_grid(8, 8, &[]);
+        grid.shift_offs = (2000.0, 2000.0);
+        grid.move_cursor(HexDir::B);
+        assert_eq!(grid.cursor(), Some((0, 0)));
+
+        // ... and right of and below it:
+        let mut grid = test_grid(8, 8, &[]);
+        grid.shift_offs = (-2000.0, -2000.0);
+        grid.move_cursor(HexDir::B);
+        assert_eq!(grid.cursor(), Some((7, 7)));
+
+        // A cursor outside of a shrunk grid starts over:
+        let mut grid = test_grid(8, 8, &[]);
+        grid.shift_offs = (2000.0, 2000.0);
+        grid.set_cursor(Some((20, 20)));
+        grid.move_cursor(HexDir::T);
+        assert_eq!(grid.cursor(), Some((0, 0)));
+    }
+
+    /// The position of the tile `cell` in the minimap.
+    fn minimap_pos(grid: &HexGrid, cell: (usize, usize)) -> (f32, f32) {
+        let (map, ext, factor) = grid.minimap_geometry().unwrap();
+        let (x, y) = grid.tile_offset(cell.0, cell.1, 1.0);
+        (map.x + (x - ext.x) * factor, map.y + (y - ext.y) * factor)
+    }
+
+    #[test]
+    fn check_minimap_geometry() {
+        let mut grid = test_grid(8, 8, &[]);
+        assert_eq!(grid.minimap_geometry(), None);
+
+        // In the bottom right corner, at most a quarter of the view in size:
+        grid.set_minimap(true);
+        let (map, ext, factor) = grid.minimap_geometry().unwrap();
+        assert_eq!(ext, grid.grid_extent());
+        assert_eq!((map.x + map.w, map.y + map.h), (390.0, 290.0));
+        assert!(map.w <= 100.0 && map.h <= 75.0);
+        assert!(map.w == 100.0 || map.h == 75.0);
+        assert!((map.w - ext.w * factor).abs() <= 0.5);
+        assert!((map.h - ext.h * factor).abs() <= 0.5);
+
+        // Nothing to show for an empty grid or a too small view:
+        assert_eq!(test_grid(0, 0, &[]).minimap_geometry(), None);
+        grid.real_pos = Rect::from(0.0, 0.0, 30.0, 30.0);
+        assert_eq!(grid.minimap_geometry(), None);
+    }
+
+    #[test]
+    fn check_minimap_hit() {
+        let mut grid = test_grid(8, 8, &[]);
+        let mid = (345.0, 250.0);
+        assert!(!grid.minimap_hit(mid));
+
+        grid.set_minimap(true);
+        let (map, _, _) = grid.minimap_geometry().unwrap();
+        assert!(map.is_inside(mid.0, mid.1));
+        assert!(grid.minimap_hit(mid));
+        assert!(grid.minimap_hit((map.x + 1.0, map.y + 1.0)));
+        assert!(!grid.minimap_hit((map.x - 1.0, map.y + 1.0)));
+        assert!(!grid.minimap_hit((map.x + 1.0, map.y + map.h + 1.0)));
+        assert!(!grid.minimap_hit((200.0, 150.0)));
+    }
+
+    #[test]
+    fn check_minimap_pan() {
+        let mut grid = test_grid(8, 8, &[]);
+        grid.set_minimap(true);
+
+        grid.pan_to_minimap(minimap_pos(&grid, (5, 3)));
+        assert_near(grid.tile_center(5, 3), (200.0, 150.0));
+
+        // The zoom is kept:
+        grid.scale = 1.5;
+        let p = minimap_pos(&grid, (2, 6));
+        grid.pan_to_minimap(p);
+        assert_eq!(grid.scale, 1.5);
+        assert_near(grid.tile_center(2, 6), (200.0, 150.0));
+
+        // The view drawn into the minimap is centered on the clicked spot
+        // and shrinks with the zoom:
+        let (map, ext, factor) = grid.minimap_geometry().unwrap();
+        let view = grid.minimap_view(map, ext, factor);
+        assert_near((view.x + 0.5 * view.w, view.y + 0.5 * view.h), p);
+        assert!((view.w - 400.0 / 1.5 * factor).abs() < 0.001);
+
+        // Outside the minimap the position is clamped to its border:
+        grid.pan_to_minimap((1000.0, 1000.0));
+        let (map, ext, factor) = grid.minimap_geometry().unwrap();
+        let view = grid.minimap_view(map, ext, factor);
+        assert_near((view.x + 0.5 * view.w, view.y + 0.5 * view.h), (map.x + map.w, map.y + map.h));
+    }
+
+    fn with_grid<R>(wid: &Widget, f: impl FnOnce(&mut HexGrid) -> R) -> R {
+        wid.with_ctrl(|ctrl| match ctrl {
+            Control::HexGrid { grid } => f(grid),
+            _ => panic!("no grid"),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn check_minimap_click_and_drag() {
+        let model = TestGrid { w: 8, h: 8, filled: vec![] };
+        let grid = HexGrid::new(Rc::new(RefCell::new(model)));
+        let (mut ui, mut wids) = UI::for_test(vec![Control::HexGrid { grid: Box::new(grid) }]);
+        let wid = wids.remove(0);
+
+        wid.change_layout(|l| {
+            l.width = Some(Units::Pixels(400.0));
+            l.height = Some(Units::Pixels(300.0));
+        });
+        ui.relayout();
+        ui.on_layout_changed();
+
+        // Drawing would set the position:
+        let pos = wid.pos();
+        let (from, to) = with_grid(&wid, |grid| {
+            grid.real_pos = pos;
+            grid.set_minimap(true);
+            (minimap_pos(grid, (5, 3)), minimap_pos(grid, (2, 6)))
+        });
+
+        let centers = Rc::new(RefCell::new(vec![]));
+        let c = centers.clone();
+        wid.reg("center_pos", move |_ctx, _wid, ev| {
+            if let EvPayload::HexGridPos { x, y } = ev.data {
+                c.borrow_mut().push((x, y));
+            }
+        });
+
+        ui.handle_input_event(InputEvent::MousePosition(from.0, from.1));
+        ui.handle_input_event(InputEvent::MouseButtonPressed(MButton::Left));
+        assert_near(with_grid(&wid, |grid| grid.tile_center(5, 3)), (200.0, 150.0));
+
+        ui.handle_input_event(InputEvent::MousePosition(to.0, to.1));
+        assert_near(with_grid(&wid, |grid| grid.tile_center(2, 6)), (200.0, 150.0));
+        assert!(centers.borrow().is_empty());
+
+        ui.handle_input_event(InputEvent::MouseButtonReleased(MButton::Left));
+        assert_eq!(centers.borrow().clone(), vec![(2, 6)]);
+    }
+
+    #[test]
+    fn check_hex_dir_neighbour() {
+        // Even column, shifted up by half a tile:
+        assert_eq!(HexDir::TR.neighbour(2, 2, 8, 8), Some((3, 1)));
+        assert_eq!(HexDir::BR.neighbour(2, 2, 8, 8), Some((3, 2)));
+        assert_eq!(HexDir::BL.neighbour(2, 2, 8, 8), Some((1, 2)));
+        assert_eq!(HexDir::TL.neighbour(2, 2, 8, 8), Some((1, 1)));
+
+        // Odd column:
+        assert_eq!(HexDir::TR.neighbour(3, 2, 8, 8), Some((4, 2)));
+        assert_eq!(HexDir::BR.neighbour(3, 2, 8, 8), Some((4, 3)));
+        assert_eq!(HexDir::BL.neighbour(3, 2, 8, 8), Some((2, 3)));
+        assert_eq!(HexDir::TL.neighbour(3, 2, 8, 8), Some((2, 2)));
+
+        assert_eq!(HexDir::T.neighbour(3, 2, 8, 8), Some((3, 1)));
+        assert_eq!(HexDir::B.neighbour(3, 2, 8, 8), Some((3, 3)));
+
+        // Borders:
+        assert_eq!(HexDir::T.neighbour(0, 0, 8, 8), None);
+        assert_eq!(HexDir::TL.neighbour(0, 3, 8, 8), None);
+        assert_eq!(HexDir::BR.neighbour(7, 7, 8, 8), None);
+        assert_eq!(HexDir::TR.neighbour(6, 0, 8, 8), None);
+    }
+}
@@ -2,10 +2,10 @@
 // This file is a part of HexoTK. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
-use crate::{EvPayload, Event, InputEvent, MButton, Widget};
-use keyboard_types::Key;
+use crate::{EvPayload, Event, InputEvent, MButton, UndoCommand, Widget};
+use keyboard_types::{Key, Modifiers};
 
-use super::ModifierTracker;
+use super::{DoubleClickTracker, ModifierTracker, TextBuffer};
 
 use crate::style::*;
 
@@ -26,6 +26,11 @@ pub const UI_FG_KNOB_MODPOS_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
 pub const UI_FG_KNOB_MODNEG_CLR: (f32, f32, f32) = UI_SELECT_CLR;
 pub const UI_TXT_KNOB_CLR: (f32, f32, f32) = UI_PRIM_CLR;
 pub const UI_TXT_KNOB_HOVER_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
+pub const UI_KNOB_LEARN_CLR: (f32, f32, f32) = UI_ACCENT_CLR;
+pub const UI_KNOB_LEARN_ARMED_CLR: (f32, f32, f32) = UI_HLIGHT_CLR;
+pub const UI_KNOB_AUTOMATED_CLR: (f32, f32, f32) = UI_PRIM2_CLR;
+pub const UI_KNOB_RECORDING_CLR: (f32, f32, f32) = UI_SELECT_CLR;
+pub const UI_KNOB_MIDI_MAPPED_CLR: (f32, f32, f32) = UI_HLIGHT2_CLR;
 //pub const UI_TXT_KNOB_MOD_CLR     : (f32, f32, f32) = UI_HLIGHT2_CLR;
 
 fn circle_point(r: f32, angle: f32) -> (f32, f32) {
@@ -220,6 +225,39 @@ impl Knob {
         );
     }
 
+    /// Draws the inline editor for typing in a value over the value label.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_value_edit(
+        &self,
+        p: &mut Painter,
+        x: f32,
+        y: f32,
+        text: &str,
+        cursor: usize,
+        selected: bool,
+        border_color: (f32, f32, f32),
+        dbg: &mut LblDebugTag,
+    ) {
+        let r = self.get_value_rect(false);
+        let (rx, ry) = (x + r.0, y + r.1);
+
+        p.rect_fill(UI_BG_KNOB_STROKE_CLR, rx, ry, r.2, r.3);
+        p.rect_stroke(1.0, border_color, rx + 0.5, ry + 0.5, r.2 - 1.0, r.3 - 1.0);
+
+        let text_w = p.text_width(self.font_size_data, false, text);
+        let text_x = (rx + (r.2 - text_w) * 0.5).round();
+
+        if selected {
+            p.rect_fill(UI_SELECT_CLR, text_x, ry + 1.0, text_w, r.3 - 2.0);
+        }
+
+        p.label(self.font_size_data, 0, UI_TXT_KNOB_HOVER_CLR, rx, ry, r.2, r.3, text, dbg);
+
+        let pre_cursor: String = text.chars().take(cursor).collect();
+        let cx = (text_x + p.text_width(self.font_size_data, false, &pre_cursor)).round() + 0.5;
+        p.stroke(1.0, UI_TXT_KNOB_HOVER_CLR, &[(cx, ry + 2.0), (cx, ry + r.3 - 2.0)], false);
+    }
+
     pub fn draw_mod_arc(
         &self,
         p: &mut Painter,
@@ -278,6 +316,21 @@ impl Knob {
         }
     }
 
+    /// Draws a closed ring just outside of the knob, used for the
+    /// learn mode and automation indicators.
+    pub fn draw_outer_ring(
+        &self,
+        p: &mut Painter,
+        x: f32,
+        y: f32,
+        line_w: f32,
+        color: (f32, f32, f32),
+    ) {
+        let scale = (self.radius + 0.5 * self.line_w + line_w) / self.radius;
+        let mut points = self.s.iter().map(|(px, py)| (x + px * scale, y + py * scale));
+        p.path_stroke(line_w, color, &mut points, true);
+    }
+
     pub fn draw_oct_arc_bg(
         &self,
         p: &mut Painter,
@@ -367,6 +420,19 @@ impl Knob {
     }
 }
 
+/// How the parameter is currently controlled from outside of the UI,
+/// shown by a ring around the [HexKnob].
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AutomationState {
+    None,
+    /// The host plays back automation for the parameter.
+    Automated,
+    /// The host records the changes of the parameter.
+    Recording,
+    /// A MIDI controller was learned for the parameter.
+    MidiMapped,
+}
+
 /// This specifies the granularity or resultion of the change.
 /// The client of this API can then round the given changed values
 /// to a fine/coarse step, or no step at all.
@@ -449,9 +515,45 @@ pub trait ParamModel {
     fn get_denorm(&self) -> f32;
     fn set_denorm(&mut self, v: f32);
 
+    /// Whether [ParamModel::parse_denorm] is implemented. Only then a
+    /// double click on the [HexKnob] opens the inline value editor.
+    fn can_parse_denorm(&self) -> bool {
+        false
+    }
+
+    /// Parses a typed in, denormalized value like it is formatted by
+    /// [ParamModel::fmt] and returns the normalized value for it.
+    /// Used for typing in exact values on a [HexKnob], if
+    /// [ParamModel::can_parse_denorm] returns true. Returns None if
+    /// the text can't be parsed, which is also the default.
+    fn parse_denorm(&self, _text: &str) -> Option<f32> {
+        None
+    }
+
+    /// Identifies the parameter for the "learn_armed" event of the [HexKnob].
+    /// Parameters without an ID can't be armed in learn mode.
+    fn param_id(&self) -> Option<u64> {
+        None
+    }
+
+    /// Should return whether the parameter is automated by the host
+    /// or mapped to a MIDI controller.
+    fn automation_state(&self) -> AutomationState {
+        AutomationState::None
+    }
+
+    /// Resets the parameter to its default, like on a middle click.
+    /// It is not wrapped in [ParamModel::change_start] and
+    /// [ParamModel::change_end], models for plugin hosts send the
+    /// complete edit gesture themselves.
     fn set_default(&mut self);
+
+    /// Starts a user gesture, like a mouse drag. For plugin hosts
+    /// this is the "begin edit" notification.
     fn change_start(&mut self);
+    /// A change within the gesture ("perform edit").
     fn change(&mut self, v: f32, res: ChangeRes);
+    /// Ends the gesture with the final value ("end edit").
     fn change_end(&mut self, v: f32, res: ChangeRes);
 
     /// Should return the generation counter for the internal data.
@@ -498,6 +600,12 @@ impl ParamModel for DummyParamModel {
     fn set_denorm(&mut self, v: f32) {
         self.set(v / 100.0)
     }
+    fn can_parse_denorm(&self) -> bool {
+        true
+    }
+    fn parse_denorm(&self, text: &str) -> Option<f32> {
+        text.trim().parse::<f32>().ok().map(|v| v / 100.0)
+    }
     fn get(&self) -> f32 {
         self.value
     }
@@ -703,6 +811,83 @@ impl HexValueDrag {
     }
 }
 
+fn param_state(model: &dyn ParamModel) -> (f32, Option<f32>) {
+    (model.get(), model.get_mod_amt())
+}
+
+/// Changes the value by `delta` in one undoable step.
+fn step_param(
+    w: &Widget,
+    model_ref: &Rc<RefCell<dyn ParamModel>>,
+    model: &mut dyn ParamModel,
+    delta: f32,
+    res: ChangeRes,
+) {
+    let old = param_state(model);
+    let v = old.0 + delta;
+    model.change_start();
+    model.change(v, res);
+    model.change_end(v, res);
+
+    if let Some(cmd) = ParamChange::if_changed(model_ref, model, old) {
+        w.push_undo(cmd);
+    }
+}
+
+/// The undo step for a value or modulation amount change done with a [HexKnob].
+/// A whole drag gesture from `change_start` to `change_end` is one step.
+struct ParamChange {
+    model: Rc<RefCell<dyn ParamModel>>,
+    old: (f32, Option<f32>),
+    new: (f32, Option<f32>),
+}
+
+impl ParamChange {
+    fn if_changed(
+        model_ref: &Rc<RefCell<dyn ParamModel>>,
+        model: &dyn ParamModel,
+        old: (f32, Option<f32>),
+    ) -> Option<Self> {
+        let new = param_state(model);
+        if new == old {
+            None
+        } else {
+            Some(Self { model: model_ref.clone(), old, new })
+        }
+    }
+
+    fn apply(&self, state: (f32, Option<f32>)) {
+        let mut model = self.model.borrow_mut();
+
+        // A complete gesture, so that plugin hosts record undo and redo as edits:
+        if (model.get() - state.0).abs() > std::f32::EPSILON {
+            model.change_start();
+            model.change(state.0, ChangeRes::Free);
+            model.change_end(state.0, ChangeRes::Free);
+        }
+
+        if model.get_mod_amt() != state.1 {
+            model.set_mod_amt(state.1);
+        }
+    }
+}
+
+impl UndoCommand for ParamChange {
+    fn undo(&mut self, _ctx: &mut dyn std::any::Any) {
+        self.apply(self.old);
+    }
+
+    fn redo(&mut self, _ctx: &mut dyn std::any::Any) {
+        self.apply(self.new);
+    }
+
+    fn describe(&self) -> String {
+        let mut buf = [0; 64];
+        let len = self.model.borrow().fmt_name(&mut buf[..]);
+        format!("Change {}", std::str::from_utf8(&buf[0..len]).unwrap_or("?"))
+    }
+}
+
 pub struct HexKnob {
     lbl_buf: [u8; 15],
     model: Rc<RefCell<dyn ParamModel>>,
@@ -710,6 +895,11 @@ pub struct HexKnob {
     knob: Knob,
     hover: Option<HexKnobZone>,
     drag: Option<HexValueDrag>,
+    undo_start: Option<(f32, Option<f32>)>,
+    /// The inline editor for typing in a value, opened by a double click.
+    edit: Option<TextBuffer>,
+    edit_invalid: bool,
+    dbl_click: DoubleClickTracker,
     real_pos: Rect,
     circle_mid: (f32, f32),
     modkeys: ModifierTracker,
@@ -725,6 +915,10 @@ impl HexKnob {
             knob: Knob::new(28.0, UI_BG_KNOB_STROKE, 12.0, 9.0, UI_ELEM_TXT_H),
             hover: None,
             drag: None,
+            undo_start: None,
+            edit: None,
+            edit_invalid: false,
+            dbl_click: DoubleClickTracker::new(),
             circle_mid: (0.0, 0.0),
             real_pos: Rect::from(0.0, 0.0, 0.0, 0.0),
             modkeys: ModifierTracker::new(),
@@ -755,9 +949,97 @@ impl HexKnob {
         self.model.borrow_mut().get_generation()
     }
 
+    /// Whether the inline value editor is open.
+    pub fn is_editing(&self) -> bool {
+        self.edit.is_some()
+    }
+
+    fn start_edit(&mut self, w: &Widget, text: &str) {
+        let mut edit = TextBuffer::new();
+        edit.set_text(text.trim());
+        edit.select_all();
+
+        self.edit = Some(edit);
+        self.edit_invalid = false;
+
+        w.focus();
+        w.emit_redraw_required();
+    }
+
+    fn commit_edit(&mut self, w: &Widget) {
+        let text = if let Some(edit) = &self.edit { edit.text() } else { return };
+        let mut model = self.model.borrow_mut();
+
+        if let Some(v) = model.parse_denorm(&text) {
+            let old = param_state(&*model);
+            model.change_start();
+            model.change(v, ChangeRes::Free);
+            model.change_end(v, ChangeRes::Free);
+
+            if let Some(cmd) = ParamChange::if_changed(&self.model, &*model, old) {
+                w.push_undo(cmd);
+            }
+
+            self.edit = None;
+        } else {
+            self.edit_invalid = true;
+        }
+    }
+
+    /// Handles the events for the inline value editor. Returns true
+    /// if the event was consumed by it.
+    fn handle_edit(&mut self, w: &Widget, event: &InputEvent) -> bool {
+        if self.edit.is_some() && !w.is_focused() {
+            self.edit = None;
+            w.emit_redraw_required();
+        }
+
+        let edit = if let Some(edit) = &mut self.edit { edit } else { return false };
+
+        match event {
+            InputEvent::KeyPressed(key) => {
+                match &key.key {
+                    Key::Enter => self.commit_edit(w),
+                    Key::Escape => self.edit = None,
+                    _ => {
+                        match &key.key {
+                            Key::Character(s) if !key.modifiers.contains(Modifiers::CONTROL) => {
+                                edit.insert(s)
+                            }
+                            Key::Backspace => edit.delete_back(false),
+                            Key::Delete => edit.delete_forward(false),
+                            Key::ArrowLeft => edit.move_left(false, false),
+                            Key::ArrowRight => edit.move_right(false, false),
+                            Key::Home => edit.move_home(false, false),
+                            Key::End => edit.move_end(false, false),
+                            _ => return false,
+                        }
+
+                        self.edit_invalid = false;
+                    }
+                }
+
+                w.emit_redraw_required();
+                true
+            }
+            InputEvent::MouseButtonPressed(_) => {
+                if !w.is_hovered() {
+                    self.edit = None;
+                    w.emit_redraw_required();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn handle(&mut self, w: &Widget, event: &InputEvent, out_events: &mut Vec<(usize, Event)>) {
         self.modkeys.handle(event);
 
+        if self.handle_edit(w, event) {
+            return;
+        }
+
         let is_hovered = w.is_hovered();
 
         let mut model = self.model.borrow_mut();
@@ -782,10 +1064,30 @@ impl HexKnob {
                     return;
                 }
 
+                if btn == MButton::Left && w.is_learn_mode() {
+                    if let Some(param_id) = model.param_id() {
+                        w.arm_learn();
+                        out_events.push(w.event("learn_armed", EvPayload::ParamLearn { param_id }));
+                    }
+                    return;
+                }
+
+                if btn == MButton::Left
+                    && self.dbl_click.press()
+                    && model.enabled()
+                    && model.can_parse_denorm()
+                {
+                    let text = model.fmt_to_string();
+                    drop(model);
+                    self.start_edit(w, &text);
+                    return;
+                }
+
                 if let Some(zone) = self.cursor_zone(self.modkeys.mouse.x, self.modkeys.mouse.y) {
                     if let Some(mut hvd) =
                         HexValueDrag::from_state(&self.modkeys, &mut *model, btn, zone)
                     {
+                        self.undo_start = Some(param_state(&*model));
                         hvd.start(&mut *model);
                         self.drag = Some(hvd);
 
@@ -803,7 +1105,11 @@ impl HexKnob {
             }
             InputEvent::MouseButtonReleased(MButton::Middle) => {
                 if w.is_active() {
+                    let old = param_state(&*model);
                     model.set_default();
+                    if let Some(cmd) = ParamChange::if_changed(&self.model, &*model, old) {
+                        w.push_undo(cmd);
+                    }
                     w.emit_redraw_required();
 
                     w.deactivate();
@@ -820,6 +1126,12 @@ impl HexKnob {
                 if let Some(mut hvd) = self.drag.take() {
                     hvd.end(&mut *model, self.modkeys.mouse.x, self.modkeys.mouse.y);
 
+                    if let Some(old) = self.undo_start.take() {
+                        if let Some(cmd) = ParamChange::if_changed(&self.model, &*model, old) {
+                            w.push_undo(cmd);
+                        }
+                    }
+
                     w.emit_redraw_required();
                 }
 
@@ -856,9 +1168,14 @@ impl HexKnob {
                     if let Some(mut hvd) =
                         HexValueDrag::from_state(&self.modkeys, &mut *model, MButton::Left, zone)
                     {
+                        let old = param_state(&*model);
                         hvd.start(&mut *model);
                         hvd.wheel(&mut *model, *y);
 
+                        if let Some(cmd) = ParamChange::if_changed(&self.model, &*model, old) {
+                            w.push_undo(cmd);
+                        }
+
                         w.emit_redraw_required();
                     }
                 }
@@ -876,6 +1193,21 @@ impl HexKnob {
                     if let Some(ref mut hvd) = self.drag {
                         hvd.set_fine_res(&mut *model, self.modkeys.mouse.x, self.modkeys.mouse.y);
                     }
+                } else if w.is_focused() && self.drag.is_none() && model.enabled() {
+                    let (coarse, fine) = model.get_ui_steps();
+
+                    let step = match key.key {
+                        Key::ArrowUp | Key::ArrowRight => Some((fine, ChangeRes::Fine)),
+                        Key::ArrowDown | Key::ArrowLeft => Some((-fine, ChangeRes::Fine)),
+                        Key::PageUp => Some((coarse, ChangeRes::Coarse)),
+                        Key::PageDown => Some((-coarse, ChangeRes::Coarse)),
+                        _ => None,
+                    };
+
+                    if let Some((delta, res)) = step {
+                        step_param(w, &self.model, &mut *model, delta, res);
+                        w.emit_redraw_required();
+                    }
                 }
             }
             _ => {}
@@ -885,7 +1217,7 @@ impl HexKnob {
     pub fn draw(
         &mut self,
         w: &Widget,
-        _style: &DPIStyle,
+        style: &DPIStyle,
         pos: Rect,
         real_pos: Rect,
         p: &mut Painter,
@@ -966,6 +1298,24 @@ impl HexKnob {
 
         let value = model.get_ui_range().clamp(0.0, 1.0);
 
+        let ring_w = (dpi_f * 1.5 * factor).round().max(1.0);
+        let ring_color = if w.is_learn_armed() {
+            Some(UI_KNOB_LEARN_ARMED_CLR)
+        } else if w.is_learn_mode() && model.param_id().is_some() {
+            Some(UI_KNOB_LEARN_CLR)
+        } else {
+            match model.automation_state() {
+                AutomationState::None => None,
+                AutomationState::Automated => Some(UI_KNOB_AUTOMATED_CLR),
+                AutomationState::Recording => Some(UI_KNOB_RECORDING_CLR),
+                AutomationState::MidiMapped => Some(UI_KNOB_MIDI_MAPPED_CLR),
+            }
+        };
+
+        if let Some(color) = ring_color {
+            self.knob.draw_outer_ring(p, xo, yo, ring_w, color);
+        }
+
         let zone_hover = if let Some(hvd) = &self.drag {
             Some(hvd.zone)
         } else if is_hovered {
@@ -1051,5 +1401,292 @@ impl HexKnob {
         let val_s = std::str::from_utf8(&self.lbl_buf[0..len]).unwrap();
         // + 2.0 for the marker cube, to space it from the minus sign.
         self.knob.draw_name(p, xo + 2.0, yo, &val_s, dbg.source("name"));
+
+        if let (Some(edit), true) = (&self.edit, w.is_focused()) {
+            let border_color =
+                if self.edit_invalid { style.invalid_color() } else { UI_TXT_KNOB_HOVER_CLR };
+
+            self.knob.draw_value_edit(
+                p,
+                xo,
+                yo,
+                &edit.text(),
+                edit.cursor().1,
+                edit.selection().is_some(),
+                border_color,
+                dbg.source("edit"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Control, UI};
+    use keyboard_types::KeyboardEvent;
+
+    /// Logs the edit gestures, like a model for a plugin host would send them.
+    struct TestParam {
+        value: f32,
+        modamt: Option<f32>,
+        id: Option<u64>,
+        log: Vec<String>,
+    }
+
+    impl ParamModel for TestParam {
+        fn get(&self) -> f32 {
+            self.value
+        }
+        fn set(&mut self, v: f32) {
+            self.log.push(format!("set {:.2}", v));
+            self.value = v;
+        }
+        fn enabled(&self) -> bool {
+            true
+        }
+        fn get_ui_range(&self) -> f32 {
+            self.value
+        }
+        fn get_ui_mod_amt(&self) -> Option<f32> {
+            self.modamt
+        }
+        fn get_mod_amt(&self) -> Option<f32> {
+            self.modamt
+        }
+        fn set_mod_amt(&mut self, amt: Option<f32>) {
+            self.modamt = amt;
+        }
+        fn fmt(&self, buf: &mut [u8]) -> usize {
+            use std::io::Write;
+            let mut bw = std::io::BufWriter::new(buf);
+            match write!(bw, "{:.2}", self.value * 100.0) {
+                Ok(_) => bw.buffer().len(),
+                Err(_) => 0,
+            }
+        }
+        fn fmt_mod(&self, _buf: &mut [u8]) -> usize {
+            0
+        }
+        fn fmt_norm(&self, _buf: &mut [u8]) -> usize {
+            0
+        }
+        fn fmt_name(&self, _buf: &mut [u8]) -> usize {
+            0
+        }
+        fn get_denorm(&self) -> f32 {
+            self.value * 100.0
+        }
+        fn set_denorm(&mut self, v: f32) {
+            self.value = v / 100.0;
+        }
+        fn can_parse_denorm(&self) -> bool {
+            true
+        }
+        fn parse_denorm(&self, text: &str) -> Option<f32> {
+            text.trim().parse::<f32>().ok().map(|v| v / 100.0)
+        }
+        fn param_id(&self) -> Option<u64> {
+            self.id
+        }
+        fn set_default(&mut self) {
+            self.value = 0.5;
+        }
+        fn change_start(&mut self) {
+            self.log.push("start".to_string());
+        }
+        fn change(&mut self, v: f32, _res: ChangeRes) {
+            self.log.push(format!("change {:.2}", v));
+            self.value = v;
+        }
+        fn change_end(&mut self, v: f32, _res: ChangeRes) {
+            self.log.push(format!("end {:.2}", v));
+            self.value = v;
+        }
+        fn get_generation(&mut self) -> u64 {
+            0
+        }
+    }
+
+    fn test_param(value: f32, id: Option<u64>) -> Rc<RefCell<TestParam>> {
+        Rc::new(RefCell::new(TestParam { value, modamt: None, id, log: vec![] }))
+    }
+
+    fn knob_ui(param: &Rc<RefCell<TestParam>>) -> (UI, Widget) {
+        let knob = HexKnob::new(param.clone());
+        let (ui, mut wids) = UI::for_test(vec![Control::HexKnob { knob: Box::new(knob) }]);
+        (ui, wids.remove(0))
+    }
+
+    fn key(key: Key) -> InputEvent {
+        InputEvent::KeyPressed(KeyboardEvent { key, ..Default::default() })
+    }
+
+    fn type_text(ui: &mut UI, text: &str) {
+        for c in text.chars() {
+            ui.handle_input_event(key(Key::Character(c.to_string())));
+        }
+    }
+
+    /// Clicks into `wid`, away from the value drag zones.
+    fn click(ui: &mut UI, wid: &Widget) {
+        let pos = wid.pos();
+        ui.handle_input_event(InputEvent::MousePosition(pos.x + 90.0, pos.y + 45.0));
+        ui.handle_input_event(InputEvent::MouseButtonPressed(MButton::Left));
+        ui.handle_input_event(InputEvent::MouseButtonReleased(MButton::Left));
+    }
+
+    fn is_editing(wid: &Widget) -> bool {
+        wid.with_ctrl(|ctrl| match ctrl {
+            Control::HexKnob { knob } => knob.is_editing(),
+            _ => panic!("no knob"),
+        })
+        .unwrap()
+    }
+
+    fn take_log(param: &Rc<RefCell<TestParam>>) -> Vec<String> {
+        std::mem::take(&mut param.borrow_mut().log)
+    }
+
+    #[test]
+    fn check_keyboard_step() {
+        let param = test_param(0.5, None);
+        let (mut ui, wid) = knob_ui(&param);
+
+        // Only a focused knob is stepped:
+        ui.handle_input_event(key(Key::ArrowUp));
+        assert!(take_log(&param).is_empty());
+
+        ui.focus(&wid);
+        ui.handle_input_event(key(Key::ArrowUp));
+        assert_eq!(take_log(&param), vec!["start", "change 0.51", "end 0.51"]);
+
+        ui.handle_input_event(key(Key::PageDown));
+        assert_eq!(take_log(&param), vec!["start", "change 0.46", "end 0.46"]);
+
+        // Every step is one undo step:
+        assert!(ui.undo());
+        assert!((param.borrow().value - 0.51).abs() < 0.0001);
+        assert!(ui.undo());
+        assert!((param.borrow().value - 0.5).abs() < 0.0001);
+        assert!(!ui.undo());
+    }
+
+    #[test]
+    fn check_inline_editor() {
+        let param = test_param(0.5, None);
+        let (mut ui, wid) = knob_ui(&param);
+
+        // A double click opens the editor with the formatted value selected:
+        click(&mut ui, &wid);
+        click(&mut ui, &wid);
+        assert!(is_editing(&wid));
+        assert_eq!(ui.focused().map(|w| w.unique_id()), Some(wid.unique_id()));
+        take_log(&param);
+
+        // Unparseable text keeps the editor open and changes nothing:
+        type_text(&mut ui, "abc");
+        ui.handle_input_event(key(Key::Enter));
+        assert!(is_editing(&wid));
+        assert!(take_log(&param).is_empty());
+
+        ui.handle_input_event(key(Key::Escape));
+        assert!(!is_editing(&wid));
+        assert!(take_log(&param).is_empty());
+        assert_eq!(param.borrow().value, 0.5);
+        assert!(!ui.undo_stack().can_undo());
+
+        click(&mut ui, &wid);
+        click(&mut ui, &wid);
+        assert!(is_editing(&wid));
+        take_log(&param);
+
+        type_text(&mut ui, "25");
+        ui.handle_input_event(key(Key::Enter));
+        assert!(!is_editing(&wid));
+        assert_eq!(take_log(&param), vec!["start", "change 0.25", "end 0.25"]);
+
+        assert!(ui.undo());
+        assert_eq!(param.borrow().value, 0.5);
+        assert!(!ui.undo());
+    }
+
+    #[test]
+    fn check_undo_sends_edit_gesture() {
+        let param = test_param(0.5, None);
+        let mut cmd = ParamChange { model: param.clone(), old: (0.2, None), new: (0.5, Some(0.1)) };
+
+        cmd.undo(&mut ());
+        assert_eq!(param.borrow().log, vec!["start", "change 0.20", "end 0.20"]);
+        assert_eq!(param.borrow().modamt, None);
+
+        param.borrow_mut().log.clear();
+        cmd.redo(&mut ());
+        assert_eq!(param.borrow().log, vec!["start", "change 0.50", "end 0.50"]);
+        assert_eq!(param.borrow().modamt, Some(0.1));
+
+        // Only the modulation amount changed, no value edit is sent:
+        param.borrow_mut().log.clear();
+        let mut cmd = ParamChange { model: param.clone(), old: (0.5, None), new: (0.5, Some(0.1)) };
+        cmd.undo(&mut ());
+        assert!(param.borrow().log.is_empty());
+        assert_eq!(param.borrow().modamt, None);
+    }
+
+    /// Logs the parameter IDs of the "learn_armed" events of `wid`.
+    fn log_learn(wid: &Widget) -> Rc<RefCell<Vec<u64>>> {
+        let log = Rc::new(RefCell::new(vec![]));
+        let l = log.clone();
+        wid.reg("learn_armed", move |_ctx, _wid, ev| {
+            if let EvPayload::ParamLearn { param_id } = ev.data {
+                l.borrow_mut().push(param_id);
+            }
+        });
+        log
+    }
+
+    #[test]
+    fn check_learn_mode_click_arms() {
+        let param = test_param(0.5, Some(42));
+        let (mut ui, wid) = knob_ui(&param);
+        let log = log_learn(&wid);
+
+        ui.set_learn_mode(true);
+        click(&mut ui, &wid);
+        click(&mut ui, &wid);
+
+        assert_eq!(log.borrow().clone(), vec![42, 42]);
+        assert_eq!(ui.learn_armed().map(|w| w.unique_id()), Some(wid.unique_id()));
+        assert!(wid.is_learn_armed());
+
+        // Neither a value change nor the editor of a double click:
+        assert!(take_log(&param).is_empty());
+        assert_eq!(param.borrow().value, 0.5);
+        assert!(!is_editing(&wid));
+        assert!(!ui.undo_stack().can_undo());
+    }
+
+    #[test]
+    fn check_learn_mode_needs_param_id() {
+        let param = test_param(0.5, None);
+        let (mut ui, wid) = knob_ui(&param);
+        let log = log_learn(&wid);
+
+        ui.set_learn_mode(true);
+        click(&mut ui, &wid);
+
+        assert!(log.borrow().is_empty());
+        assert!(ui.learn_armed().is_none());
+        assert!(!wid.is_learn_armed());
+        assert_eq!(param.borrow().value, 0.5);
+
+        // Outside of learn mode a click does not arm:
+        let param = test_param(0.5, Some(42));
+        let (mut ui, wid) = knob_ui(&param);
+        let log = log_learn(&wid);
+
+        click(&mut ui, &wid);
+        assert!(log.borrow().is_empty());
+        assert!(ui.learn_armed().is_none());
     }
 }
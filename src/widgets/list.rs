@@ -5,6 +5,8 @@
 use super::ModifierTracker;
 use crate::{EvPayload, Event, InputEvent, MButton, Widget};
 
+use keyboard_types::Key;
+
 use crate::style::*;
 
 use crate::painter::*;
@@ -165,6 +167,22 @@ impl List {
         }
     }
 
+    fn select_offs(&mut self, w: &Widget, offs: i32, out_events: &mut Vec<(usize, Event)>) {
+        let item_count = self.model.borrow_mut().len();
+        if item_count == 0 {
+            return;
+        }
+
+        let cur = self.model.borrow_mut().selected_item();
+        let next = match cur {
+            Some(cur) => (cur as i32 + offs).max(0).min(item_count as i32 - 1) as usize,
+            None => 0,
+        };
+
+        self.model.borrow_mut().select(next);
+        out_events.push(w.event("select", EvPayload::ItemSelect { index: next as i32 }));
+    }
+
     fn handle_scroll(&mut self, w: &Widget, zone: i32, out_events: &mut Vec<(usize, Event)>) {
         let item_count = self.model.borrow_mut().len();
         let page_offs = self.shown_item_count / 2;
@@ -285,6 +303,21 @@ impl List {
                 }
                 w.emit_redraw_required();
             },
+            InputEvent::KeyPressed(key) => {
+                if !w.is_focused() {
+                    return;
+                }
+
+                match &key.key {
+                    Key::ArrowUp => self.select_offs(w, -1, out_events),
+                    Key::ArrowDown => self.select_offs(w, 1, out_events),
+                    Key::PageUp => self.handle_scroll(w, -1, out_events),
+                    Key::PageDown => self.handle_scroll(w, -4, out_events),
+                    _ => return,
+                }
+
+                w.emit_redraw_required();
+            }
             InputEvent::MousePosition(_x, _y) => {
                 if !is_hovered {
                     self.hover = None;
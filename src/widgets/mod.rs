@@ -1,5 +1,7 @@
+mod block_palette;
 mod blockcode;
 mod connector;
+mod custom;
 mod entry;
 mod graph;
 mod graph_minmax;
@@ -8,21 +10,25 @@ mod hexknob;
 mod octave_keys;
 mod pattern_editor;
 mod scope;
+mod text_edit;
 mod wichtext;
 mod list;
 
-pub use blockcode::{BlockCode, BlockPos};
+pub use block_palette::{fuzzy_score, BlockPalette};
+pub use blockcode::{block_chain, BlockCode};
 pub use connector::{Connector, ConnectorData};
-pub use entry::{EditableText, Entry, TextField};
+pub use custom::CustomWidget;
+pub use entry::{EditableText, Entry, NumericRange, TextField};
 pub use graph::{Graph, GraphModel, StaticGraphData};
 pub use graph_minmax::{GraphMinMax, GraphMinMaxModel, StaticGraphMinMaxData};
 pub use hexgrid::{HexCell, HexDir, HexEdge, HexGrid, HexGridModel, HexHLight};
-pub use hexknob::{ChangeRes, DummyParamModel, HexKnob, ParamModel};
+pub use hexknob::{AutomationState, ChangeRes, DummyParamModel, HexKnob, ParamModel};
 pub use octave_keys::{DummyOctaveKeysData, OctaveKeys, OctaveKeysModel};
 pub use pattern_editor::{
     PatternData, PatternEditor, PatternEditorFeedback, PatternEditorFeedbackDummy, UIPatternModel,
 };
 pub use scope::{Scope, ScopeModel, StaticScopeData, SCOPE_SAMPLES};
+pub use text_edit::{TextBuffer, TextEdit, TextPos};
 pub use wichtext::{WichText, WichTextData, WichTextSimpleDataStore};
 pub use list::{List, ListModel, ListData, ListScrollMode};
 
@@ -68,3 +74,34 @@ impl ModifierTracker {
         }
     }
 }
+
+/// The offset of a pannable view: The stored `shift_offs` plus the offset
+/// of the middle mouse drag in progress, rounded to whole pixels.
+fn pan_offset(shift_offs: (f32, f32), tmp_shift_offs: Option<(f32, f32)>) -> (f32, f32) {
+    let (tx, ty) = tmp_shift_offs.unwrap_or((0.0, 0.0));
+    ((shift_offs.0 + tx).round(), (shift_offs.1 + ty).round())
+}
+
+/// Detects double clicks by the time between two mouse presses.
+#[derive(Debug)]
+pub struct DoubleClickTracker {
+    last_press: Option<std::time::Instant>,
+}
+
+impl DoubleClickTracker {
+    pub fn new() -> Self {
+        Self { last_press: None }
+    }
+
+    /// To be called on every press, returns true if it completes a double click.
+    pub fn press(&mut self) -> bool {
+        let now = std::time::Instant::now();
+        let is_double = self
+            .last_press
+            .map(|last| now.duration_since(last) < std::time::Duration::from_millis(400))
+            .unwrap_or(false);
+
+        self.last_press = if is_double { None } else { Some(now) };
+        is_double
+    }
+}
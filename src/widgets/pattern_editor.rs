@@ -2,7 +2,7 @@
 // This file is a part of HexoTK. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
-use crate::{Event, InputEvent, MButton, Widget};
+use crate::{Event, InputEvent, MButton, UndoCommand, Widget};
 use keyboard_types::Key;
 
 use super::ModifierTracker;
@@ -46,6 +46,98 @@ impl PatternEditorFeedback for PatternEditorFeedbackDummy {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PatternColType {
+    Note,
+    Step,
+    Gate,
+    Value,
+}
+
+/// The visible state of a pattern, used to undo the edits done
+/// with the [PatternEditor].
+#[derive(Debug, Clone, PartialEq)]
+struct PatternSnapshot {
+    rows: usize,
+    col_types: Vec<PatternColType>,
+    cells: Vec<Option<u16>>,
+}
+
+impl PatternSnapshot {
+    fn take(pat: &dyn UIPatternModel) -> Self {
+        let (rows, cols) = (pat.rows(), pat.cols());
+
+        let mut col_types = Vec::with_capacity(cols);
+        for col in 0..cols {
+            col_types.push(if pat.is_col_note(col) {
+                PatternColType::Note
+            } else if pat.is_col_step(col) {
+                PatternColType::Step
+            } else if pat.is_col_gate(col) {
+                PatternColType::Gate
+            } else {
+                PatternColType::Value
+            });
+        }
+
+        let mut cells = Vec::with_capacity(rows * cols);
+        for row in 0..rows {
+            for col in 0..cols {
+                if pat.get_cell(row, col).is_some() {
+                    cells.push(Some(pat.get_cell_value(row, col)));
+                } else {
+                    cells.push(None);
+                }
+            }
+        }
+
+        Self { rows, col_types, cells }
+    }
+
+    fn restore(&self, pat: &mut dyn UIPatternModel) {
+        pat.set_rows(self.rows);
+
+        for (col, typ) in self.col_types.iter().enumerate() {
+            match typ {
+                PatternColType::Note => pat.set_col_note_type(col),
+                PatternColType::Step => pat.set_col_step_type(col),
+                PatternColType::Gate => pat.set_col_gate_type(col),
+                PatternColType::Value => pat.set_col_value_type(col),
+            }
+        }
+
+        let cols = self.col_types.len();
+        for (i, cell) in self.cells.iter().enumerate() {
+            let (row, col) = (i / cols, i % cols);
+
+            match cell {
+                Some(value) => pat.set_cell_value(row, col, *value),
+                None => pat.clear_cell(row, col),
+            }
+        }
+    }
+}
+
+struct PatternChange {
+    pattern: Arc<Mutex<dyn UIPatternModel>>,
+    before: PatternSnapshot,
+    after: PatternSnapshot,
+}
+
+impl UndoCommand for PatternChange {
+    fn undo(&mut self, _ctx: &mut dyn std::any::Any) {
+        self.before.restore(&mut *self.pattern.lock().expect("Pattern lockable"));
+    }
+
+    fn redo(&mut self, _ctx: &mut dyn std::any::Any) {
+        self.after.restore(&mut *self.pattern.lock().expect("Pattern lockable"));
+    }
+
+    fn describe(&self) -> String {
+        "Edit Pattern".to_string()
+    }
+}
+
 #[derive(Debug)]
 pub struct PatternEditor {
     rows: usize,
@@ -176,8 +268,20 @@ impl PatternEditor {
         }
     }
 
-    fn handle_key_event(&mut self, key: &Key) {
+    /// Handles the key for the cursor, the modes and the pattern edits.
+    /// Returns the pattern as it was before the first edit, if the key
+    /// changed it, for the undo step.
+    fn handle_key_event(&mut self, key: &Key) -> Option<PatternSnapshot> {
         let mut pat = self.pattern.lock().unwrap();
+        let mut before = None;
+
+        // Takes the snapshot for the undo step before the first change:
+        macro_rules! edit {
+            () => {{
+                before.get_or_insert_with(|| PatternSnapshot::take(&*pat));
+                &mut *pat
+            }};
+        }
 
         let mut edit_step = self.edit_step as i16;
 
@@ -204,7 +308,7 @@ impl PatternEditor {
             }
             Key::PageUp => {
                 if self.modkeys.shift {
-                    pat.change_value(self.cursor.0, self.cursor.1, 0x100);
+                    edit!().change_value(self.cursor.0, self.cursor.1, 0x100);
                 } else {
                     advance_cursor(&mut self.cursor, -2 * edit_step as i16, 0, &mut *pat);
                 }
@@ -212,7 +316,7 @@ impl PatternEditor {
             }
             Key::PageDown => {
                 if self.modkeys.shift {
-                    pat.change_value(self.cursor.0, self.cursor.1, -0x100);
+                    edit!().change_value(self.cursor.0, self.cursor.1, -0x100);
                 } else {
                     advance_cursor(&mut self.cursor, 2 * edit_step as i16, 0, &mut *pat);
                 }
@@ -221,13 +325,13 @@ impl PatternEditor {
             Key::ArrowUp => {
                 if self.modkeys.shift {
                     if self.modkeys.ctrl {
-                        pat.change_value(self.cursor.0, self.cursor.1, 0x100);
+                        edit!().change_value(self.cursor.0, self.cursor.1, 0x100);
                     } else {
-                        pat.change_value(self.cursor.0, self.cursor.1, 0x10);
+                        edit!().change_value(self.cursor.0, self.cursor.1, 0x10);
                     }
                 } else if let EnterMode::Rows(_) = self.enter_mode {
                     let rows = pat.rows() + 1;
-                    pat.set_rows(rows);
+                    edit!().set_rows(rows);
                     self.update_info_line = true;
                 } else {
                     advance_cursor(&mut self.cursor, -edit_step as i16, 0, &mut *pat);
@@ -237,14 +341,14 @@ impl PatternEditor {
             Key::ArrowDown => {
                 if self.modkeys.shift {
                     if self.modkeys.ctrl {
-                        pat.change_value(self.cursor.0, self.cursor.1, -0x100);
+                        edit!().change_value(self.cursor.0, self.cursor.1, -0x100);
                     } else {
-                        pat.change_value(self.cursor.0, self.cursor.1, -0x10);
+                        edit!().change_value(self.cursor.0, self.cursor.1, -0x10);
                     }
                 } else if let EnterMode::Rows(_) = self.enter_mode {
                     if pat.rows() > 0 {
                         let rows = pat.rows() - 1;
-                        pat.set_rows(rows);
+                        edit!().set_rows(rows);
                         self.update_info_line = true;
                     }
                 } else {
@@ -254,7 +358,7 @@ impl PatternEditor {
             }
             Key::ArrowLeft => {
                 if self.modkeys.shift {
-                    pat.change_value(self.cursor.0, self.cursor.1, -0x1);
+                    edit!().change_value(self.cursor.0, self.cursor.1, -0x1);
                 } else {
                     advance_cursor(&mut self.cursor, 0, -1, &mut *pat);
                 }
@@ -262,14 +366,14 @@ impl PatternEditor {
             }
             Key::ArrowRight => {
                 if self.modkeys.shift {
-                    pat.change_value(self.cursor.0, self.cursor.1, 0x1);
+                    edit!().change_value(self.cursor.0, self.cursor.1, 0x1);
                 } else {
                     advance_cursor(&mut self.cursor, 0, 1, &mut *pat);
                 }
                 reset_entered_value = true;
             }
             Key::Delete => {
-                pat.clear_cell(self.cursor.0, self.cursor.1);
+                edit!().clear_cell(self.cursor.0, self.cursor.1);
                 advance_cursor(&mut self.cursor, edit_step as i16, 0, &mut *pat);
                 reset_entered_value = true;
             }
@@ -302,7 +406,11 @@ impl PatternEditor {
                 match self.enter_mode {
                     EnterMode::EnterValues(v) => match &c[..] {
                         "." => {
-                            pat.set_cell_value(self.cursor.0, self.cursor.1, self.last_set_value);
+                            edit!().set_cell_value(
+                                self.cursor.0,
+                                self.cursor.1,
+                                self.last_set_value,
+                            );
                             advance_cursor(&mut self.cursor, edit_step as i16, 0, &mut *pat);
                             reset_entered_value = true;
                         }
@@ -314,19 +422,19 @@ impl PatternEditor {
                         }
                         "s" => {
                             let nv = 0x000;
-                            pat.set_cell_value(self.cursor.0, self.cursor.1, nv as u16);
+                            edit!().set_cell_value(self.cursor.0, self.cursor.1, nv as u16);
                             self.last_set_value = nv as u16;
                             advance_cursor(&mut self.cursor, edit_step as i16, 0, &mut *pat);
                         }
                         "g" => {
                             let nv = 0xFFF;
-                            pat.set_cell_value(self.cursor.0, self.cursor.1, nv as u16);
+                            edit!().set_cell_value(self.cursor.0, self.cursor.1, nv as u16);
                             self.last_set_value = nv as u16;
                             advance_cursor(&mut self.cursor, edit_step as i16, 0, &mut *pat);
                         }
                         _ if pat.is_col_note(self.cursor.1) => {
                             if let Some(value) = note_from_char(&c[..], octave) {
-                                pat.set_cell_value(self.cursor.0, self.cursor.1, value as u16);
+                                edit!().set_cell_value(self.cursor.0, self.cursor.1, value as u16);
                                 advance_cursor(&mut self.cursor, edit_step as i16, 0, &mut *pat);
                                 self.last_set_value = value as u16;
                             }
@@ -338,20 +446,32 @@ impl PatternEditor {
                                         let nv = value << 0x8;
                                         self.enter_mode =
                                             EnterMode::EnterValues(EnterValue::One(nv as u16));
-                                        pat.set_cell_value(self.cursor.0, self.cursor.1, nv as u16);
+                                        edit!().set_cell_value(
+                                            self.cursor.0,
+                                            self.cursor.1,
+                                            nv as u16,
+                                        );
                                         self.last_set_value = nv as u16;
                                     }
                                     EnterValue::One(v) => {
                                         let nv = v | (value << 0x4);
                                         self.enter_mode =
                                             EnterMode::EnterValues(EnterValue::Two(nv as u16));
-                                        pat.set_cell_value(self.cursor.0, self.cursor.1, nv as u16);
+                                        edit!().set_cell_value(
+                                            self.cursor.0,
+                                            self.cursor.1,
+                                            nv as u16,
+                                        );
                                         self.last_set_value = nv as u16;
                                     }
                                     EnterValue::Two(v) => {
                                         let nv = v | value;
                                         self.enter_mode = EnterMode::EnterValues(EnterValue::None);
-                                        pat.set_cell_value(self.cursor.0, self.cursor.1, nv as u16);
+                                        edit!().set_cell_value(
+                                            self.cursor.0,
+                                            self.cursor.1,
+                                            nv as u16,
+                                        );
                                         self.last_set_value = nv as u16;
                                         advance_cursor(
                                             &mut self.cursor,
@@ -367,14 +487,14 @@ impl PatternEditor {
                     EnterMode::Rows(v) => match v {
                         EnterValue::None => {
                             if let Some(value) = num_from_char(&c[..]) {
-                                pat.set_rows((value << 4) as usize);
+                                edit!().set_rows((value << 4) as usize);
                                 self.update_info_line = true;
                                 self.enter_mode = EnterMode::Rows(EnterValue::One(value));
                             }
                         }
                         EnterValue::One(v) => {
                             if let Some(value) = num_from_char(&c[..]) {
-                                pat.set_rows((v << 4 | value) as usize);
+                                edit!().set_rows((v << 4 | value) as usize);
                                 self.update_info_line = true;
                                 self.enter_mode = EnterMode::None;
                             }
@@ -406,16 +526,16 @@ impl PatternEditor {
                     EnterMode::ColType => {
                         match &c[..] {
                             "n" => {
-                                pat.set_col_note_type(self.cursor.1);
+                                edit!().set_col_note_type(self.cursor.1);
                             }
                             "s" => {
-                                pat.set_col_step_type(self.cursor.1);
+                                edit!().set_col_step_type(self.cursor.1);
                             }
                             "v" => {
-                                pat.set_col_value_type(self.cursor.1);
+                                edit!().set_col_value_type(self.cursor.1);
                             }
                             "g" => {
-                                pat.set_col_gate_type(self.cursor.1);
+                                edit!().set_col_gate_type(self.cursor.1);
                             }
                             _ => {}
                         }
@@ -425,17 +545,17 @@ impl PatternEditor {
                         match &c[..] {
                             "r" => {
                                 for i in 0..pat.cols() {
-                                    pat.clear_cell(self.cursor.0, i);
+                                    edit!().clear_cell(self.cursor.0, i);
                                 }
                             }
                             "c" => {
                                 for i in 0..pat.rows() {
-                                    pat.clear_cell(i, self.cursor.1);
+                                    edit!().clear_cell(i, self.cursor.1);
                                 }
                             }
                             "s" => {
                                 for i in 0..self.edit_step {
-                                    pat.clear_cell(self.cursor.0 + i, self.cursor.1);
+                                    edit!().clear_cell(self.cursor.0 + i, self.cursor.1);
                                 }
                             }
                             _ => {}
@@ -483,6 +603,8 @@ impl PatternEditor {
                 self.enter_mode = EnterMode::EnterValues(EnterValue::None);
             }
         }
+
+        before
     }
 
     pub fn handle(
@@ -545,8 +667,17 @@ impl PatternEditor {
             InputEvent::KeyPressed(key) => {
                 //d// println!("KEY: {:?}", key);
 
-                if w.is_hovered() || w.is_active() {
-                    self.handle_key_event(&key.key);
+                if w.is_hovered() || w.is_active() || w.is_focused() {
+                    if let Some(before) = self.handle_key_event(&key.key) {
+                        let after = PatternSnapshot::take(&*self.pattern.lock().unwrap());
+                        if before != after {
+                            w.push_undo(PatternChange {
+                                pattern: self.pattern.clone(),
+                                before,
+                                after,
+                            });
+                        }
+                    }
 
                     w.emit_redraw_required();
                 }
@@ -0,0 +1,766 @@
+// Copyright (c) 2022 Weird Constructor <weirdconstructor@gmail.com>
+// This file is a part of HexoTK. Released under GPL-3.0-or-later.
+// See README.md and COPYING for details.
+
+use super::ModifierTracker;
+use crate::{EditableText, EvPayload, Event, InputEvent, MButton, Widget};
+use keyboard_types::Key;
+
+use crate::style::DPIStyle;
+
+use crate::painter::*;
+use crate::rect::*;
+
+/// A position in a [TextBuffer] as (line, column), both counted in characters.
+pub type TextPos = (usize, usize);
+
+fn byte_idx(s: &str, col: usize) -> usize {
+    s.char_indices().nth(col).map(|(i, _)| i).unwrap_or(s.len())
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The lines, the cursor and the selection of a [TextEdit].
+/// All editing operations live here, the widget only maps input
+/// events to them.
+#[derive(Debug, Clone)]
+pub struct TextBuffer {
+    lines: Vec<String>,
+    cursor: TextPos,
+    anchor: Option<TextPos>,
+    /// The column the cursor tries to keep when moving up and down.
+    want_col: usize,
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self { lines: vec![String::new()], cursor: (0, 0), anchor: None, want_col: 0 }
+    }
+
+    /// Replaces the text and keeps the cursor, as far as possible.
+    pub fn set_text(&mut self, text: &str) {
+        self.lines = text.split('\n').map(|l| l.to_string()).collect();
+        self.anchor = None;
+        self.cursor = self.clamp(self.cursor);
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines[..]
+    }
+
+    pub fn cursor(&self) -> TextPos {
+        self.cursor
+    }
+
+    fn line_len(&self, line: usize) -> usize {
+        self.lines[line].chars().count()
+    }
+
+    fn clamp(&self, pos: TextPos) -> TextPos {
+        let line = pos.0.min(self.lines.len() - 1);
+        (line, pos.1.min(self.line_len(line)))
+    }
+
+    /// Moves the cursor. If `extend` is true, the selection is
+    /// extended from the current cursor position.
+    pub fn set_cursor(&mut self, pos: TextPos, extend: bool) {
+        if extend {
+            if self.anchor.is_none() {
+                self.anchor = Some(self.cursor);
+            }
+        } else {
+            self.anchor = None;
+        }
+
+        self.cursor = self.clamp(pos);
+        self.want_col = self.cursor.1;
+    }
+
+    /// Returns the ordered start and end of the selection.
+    pub fn selection(&self) -> Option<(TextPos, TextPos)> {
+        let anchor = self.anchor?;
+
+        if anchor == self.cursor {
+            None
+        } else if anchor < self.cursor {
+            Some((anchor, self.cursor))
+        } else {
+            Some((self.cursor, anchor))
+        }
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+
+        let first = &self.lines[start.0];
+        if start.0 == end.0 {
+            return Some(first[byte_idx(first, start.1)..byte_idx(first, end.1)].to_string());
+        }
+
+        let mut text = first[byte_idx(first, start.1)..].to_string();
+        for line in self.lines[(start.0 + 1)..end.0].iter() {
+            text.push('\n');
+            text += line;
+        }
+        let last = &self.lines[end.0];
+        text.push('\n');
+        text += &last[..byte_idx(last, end.1)];
+
+        Some(text)
+    }
+
+    pub fn select_all(&mut self) {
+        let last = self.lines.len() - 1;
+        self.anchor = Some((0, 0));
+        self.cursor = (last, self.line_len(last));
+    }
+
+    /// Removes the selected text. Returns false if nothing was selected.
+    pub fn delete_selection(&mut self) -> bool {
+        let (start, end) = if let Some(sel) = self.selection() {
+            sel
+        } else {
+            self.anchor = None;
+            return false;
+        };
+
+        let last = &self.lines[end.0];
+        let tail = last[byte_idx(last, end.1)..].to_string();
+
+        let first = &mut self.lines[start.0];
+        first.truncate(byte_idx(first, start.1));
+        first.push_str(&tail);
+
+        self.lines.drain((start.0 + 1)..=end.0);
+
+        self.anchor = None;
+        self.cursor = start;
+        self.want_col = start.1;
+
+        true
+    }
+
+    /// Inserts `text` at the cursor, replacing the selection.
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+
+        let (line, col) = self.cursor;
+        let cur = &self.lines[line];
+        let (head, tail) = cur.split_at(byte_idx(cur, col));
+        let (head, tail) = (head.to_string(), tail.to_string());
+
+        let mut new_lines: Vec<String> = text.split('\n').map(|l| l.to_string()).collect();
+        let last_idx = new_lines.len() - 1;
+        let new_col = new_lines[last_idx].chars().count() + if last_idx == 0 { col } else { 0 };
+
+        let first = head + &new_lines[0];
+        new_lines[0] = first;
+        new_lines[last_idx].push_str(&tail);
+
+        self.lines.splice(line..=line, new_lines);
+
+        self.cursor = (line + last_idx, new_col);
+        self.want_col = new_col;
+    }
+
+    fn delete_range(&mut self, start: TextPos, end: TextPos) {
+        self.anchor = Some(start);
+        self.cursor = end;
+        self.delete_selection();
+    }
+
+    /// Deletes the selection, or the character (or word) before the cursor.
+    pub fn delete_back(&mut self, word: bool) {
+        if !self.delete_selection() {
+            let start =
+                if word { self.prev_word_pos(self.cursor) } else { self.prev_pos(self.cursor) };
+            self.delete_range(start, self.cursor);
+        }
+    }
+
+    /// Deletes the selection, or the character (or word) after the cursor.
+    pub fn delete_forward(&mut self, word: bool) {
+        if !self.delete_selection() {
+            let end =
+                if word { self.next_word_pos(self.cursor) } else { self.next_pos(self.cursor) };
+            self.delete_range(self.cursor, end);
+        }
+    }
+
+    fn prev_pos(&self, pos: TextPos) -> TextPos {
+        if pos.1 > 0 {
+            (pos.0, pos.1 - 1)
+        } else if pos.0 > 0 {
+            (pos.0 - 1, self.line_len(pos.0 - 1))
+        } else {
+            pos
+        }
+    }
+
+    fn next_pos(&self, pos: TextPos) -> TextPos {
+        if pos.1 < self.line_len(pos.0) {
+            (pos.0, pos.1 + 1)
+        } else if (pos.0 + 1) < self.lines.len() {
+            (pos.0 + 1, 0)
+        } else {
+            pos
+        }
+    }
+
+    fn prev_word_pos(&self, pos: TextPos) -> TextPos {
+        if pos.1 == 0 {
+            return self.prev_pos(pos);
+        }
+
+        let chars: Vec<char> = self.lines[pos.0].chars().collect();
+        let mut col = pos.1;
+        while col > 0 && !is_word_char(chars[col - 1]) {
+            col -= 1;
+        }
+        while col > 0 && is_word_char(chars[col - 1]) {
+            col -= 1;
+        }
+
+        (pos.0, col)
+    }
+
+    fn next_word_pos(&self, pos: TextPos) -> TextPos {
+        let chars: Vec<char> = self.lines[pos.0].chars().collect();
+        if pos.1 >= chars.len() {
+            return self.next_pos(pos);
+        }
+
+        let mut col = pos.1;
+        while col < chars.len() && !is_word_char(chars[col]) {
+            col += 1;
+        }
+        while col < chars.len() && is_word_char(chars[col]) {
+            col += 1;
+        }
+
+        (pos.0, col)
+    }
+
+    /// Moves one character or word to the left. Without `extend`
+    /// the cursor jumps to the start of a selection.
+    pub fn move_left(&mut self, word: bool, extend: bool) {
+        if let (false, Some((start, _))) = (extend, self.selection()) {
+            self.set_cursor(start, false);
+            return;
+        }
+
+        let pos = if word { self.prev_word_pos(self.cursor) } else { self.prev_pos(self.cursor) };
+        self.set_cursor(pos, extend);
+    }
+
+    /// Moves one character or word to the right. Without `extend`
+    /// the cursor jumps to the end of a selection.
+    pub fn move_right(&mut self, word: bool, extend: bool) {
+        if let (false, Some((_, end))) = (extend, self.selection()) {
+            self.set_cursor(end, false);
+            return;
+        }
+
+        let pos = if word { self.next_word_pos(self.cursor) } else { self.next_pos(self.cursor) };
+        self.set_cursor(pos, extend);
+    }
+
+    /// Moves `delta` lines up (negative) or down, keeping the column.
+    pub fn move_lines(&mut self, delta: i64, extend: bool) {
+        let line = (self.cursor.0 as i64 + delta).max(0).min(self.lines.len() as i64 - 1);
+        let want_col = self.want_col;
+
+        self.set_cursor((line as usize, want_col), extend);
+        self.want_col = want_col;
+    }
+
+    /// Moves to the start of the line, or of the whole text with `doc`.
+    pub fn move_home(&mut self, doc: bool, extend: bool) {
+        let pos = if doc { (0, 0) } else { (self.cursor.0, 0) };
+        self.set_cursor(pos, extend);
+    }
+
+    /// Moves to the end of the line, or of the whole text with `doc`.
+    pub fn move_end(&mut self, doc: bool, extend: bool) {
+        let line = if doc { self.lines.len() - 1 } else { self.cursor.0 };
+        self.set_cursor((line, self.line_len(line)), extend);
+    }
+}
+
+/// Maximum number of undo steps a [TextEdit] keeps.
+const TEXT_EDIT_UNDO_LIMIT: usize = 100;
+/// What the Tab key inserts.
+const TEXT_EDIT_INDENT: &str = "    ";
+
+/// A multi line text editor. The text is taken from and written back to
+/// an [EditableText], a "changed" event is sent on every edit.
+///
+/// Like the [crate::Entry] it keeps its own undo history for Ctrl+Z and
+/// Ctrl+Y, so the [crate::UndoStack] of the UI is not touched while
+/// typing.
+pub struct TextEdit {
+    model: Box<dyn EditableText>,
+    data_gen: u64,
+    buf: TextBuffer,
+    line_numbers: bool,
+
+    /// First visible line and column.
+    scroll: (usize, usize),
+    /// Number of lines and columns that fit into the widget.
+    visible: (usize, usize),
+    /// Screen position of the text area, for mapping the mouse.
+    text_pos: Rect,
+    char_w: f32,
+    line_h: f32,
+
+    selecting: bool,
+    modkeys: ModifierTracker,
+
+    /// (text, cursor) states before the edits, for Ctrl+Z/Ctrl+Y.
+    undo: Vec<(String, TextPos)>,
+    redo: Vec<(String, TextPos)>,
+    /// Whether the last edit was typing, consecutive typing is
+    /// undone in one step.
+    typing: bool,
+}
+
+impl TextEdit {
+    pub fn new(model: Box<dyn EditableText>) -> Self {
+        Self {
+            model,
+            data_gen: 0,
+            buf: TextBuffer::new(),
+            line_numbers: false,
+            scroll: (0, 0),
+            visible: (0, 0),
+            text_pos: Rect::from(0.0, 0.0, 0.0, 0.0),
+            char_w: 0.0,
+            line_h: 0.0,
+            selecting: false,
+            modkeys: ModifierTracker::new(),
+            undo: vec![],
+            redo: vec![],
+            typing: false,
+        }
+    }
+
+    pub fn set_line_numbers(&mut self, show: bool) {
+        self.line_numbers = show;
+    }
+
+    pub fn buffer(&self) -> &TextBuffer {
+        &self.buf
+    }
+
+    pub fn get_generation(&mut self) -> u64 {
+        let cur_data_gen = self.model.get_generation();
+
+        if cur_data_gen != self.data_gen {
+            self.buf.set_text(&self.model.get());
+            self.undo.clear();
+            self.redo.clear();
+            self.typing = false;
+            self.data_gen = cur_data_gen;
+        }
+
+        cur_data_gen
+    }
+
+    /// Remembers the current state before an edit.
+    fn save_undo(&mut self, typing: bool) {
+        if !(typing && self.typing) {
+            self.undo.push((self.buf.text(), self.buf.cursor()));
+            if self.undo.len() > TEXT_EDIT_UNDO_LIMIT {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+
+        self.typing = typing;
+    }
+
+    fn restore(&mut self, state: (String, TextPos)) {
+        self.buf.set_text(&state.0);
+        self.buf.set_cursor(state.1, false);
+        self.typing = false;
+    }
+
+    fn undo(&mut self) -> bool {
+        if let Some(state) = self.undo.pop() {
+            self.redo.push((self.buf.text(), self.buf.cursor()));
+            self.restore(state);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn redo(&mut self) -> bool {
+        if let Some(state) = self.redo.pop() {
+            self.undo.push((self.buf.text(), self.buf.cursor()));
+            self.restore(state);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn pos_at(&self, x: f32, y: f32) -> Option<TextPos> {
+        if self.line_h < 1.0 || self.char_w < 0.1 {
+            return None;
+        }
+
+        let line = ((y - self.text_pos.y) / self.line_h).floor().max(0.0) as usize;
+        let col = ((x - self.text_pos.x) / self.char_w).round().max(0.0) as usize;
+
+        Some((line + self.scroll.0, col + self.scroll.1))
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        let (lines, cols) = self.visible;
+        let (line, col) = self.buf.cursor();
+
+        if line < self.scroll.0 {
+            self.scroll.0 = line;
+        } else if lines > 0 && line >= self.scroll.0 + lines {
+            self.scroll.0 = line + 1 - lines;
+        }
+
+        if col < self.scroll.1 {
+            self.scroll.1 = col;
+        } else if cols > 0 && col >= self.scroll.1 + cols {
+            self.scroll.1 = col + 1 - cols;
+        }
+    }
+
+    pub fn handle(&mut self, w: &Widget, event: &InputEvent, out_events: &mut Vec<(usize, Event)>) {
+        self.modkeys.handle(event);
+
+        let mut changed = false;
+        let mut moved = false;
+
+        match event {
+            InputEvent::KeyPressed(key) => {
+                if !(w.is_active() || w.is_focused()) {
+                    return;
+                }
+
+                let (ctrl, shift) = (self.modkeys.ctrl, self.modkeys.shift);
+                let page = self.visible.0.max(1) as i64;
+
+                match &key.key {
+                    Key::Character(s) if ctrl => match &s.to_lowercase()[..] {
+                        "a" => {
+                            self.buf.select_all();
+                            moved = true;
+                        }
+                        "z" if !shift => {
+                            changed = self.undo();
+                        }
+                        "y" | "z" => {
+                            changed = self.redo();
+                        }
+                        _ => {}
+                    },
+                    Key::Character(s) => {
+                        self.save_undo(true);
+                        self.buf.insert(s);
+                        changed = true;
+                    }
+                    Key::Enter => {
+                        self.save_undo(false);
+                        self.buf.insert("\n");
+                        changed = true;
+                    }
+                    Key::Tab if !shift => {
+                        self.save_undo(true);
+                        self.buf.insert(TEXT_EDIT_INDENT);
+                        changed = true;
+                    }
+                    Key::Backspace => {
+                        self.save_undo(false);
+                        self.buf.delete_back(ctrl);
+                        changed = true;
+                    }
+                    Key::Delete => {
+                        self.save_undo(false);
+                        self.buf.delete_forward(ctrl);
+                        changed = true;
+                    }
+                    Key::ArrowLeft => {
+                        self.buf.move_left(ctrl, shift);
+                        moved = true;
+                    }
+                    Key::ArrowRight => {
+                        self.buf.move_right(ctrl, shift);
+                        moved = true;
+                    }
+                    Key::ArrowUp => {
+                        self.buf.move_lines(-1, shift);
+                        moved = true;
+                    }
+                    Key::ArrowDown => {
+                        self.buf.move_lines(1, shift);
+                        moved = true;
+                    }
+                    Key::PageUp => {
+                        self.buf.move_lines(-page, shift);
+                        moved = true;
+                    }
+                    Key::PageDown => {
+                        self.buf.move_lines(page, shift);
+                        moved = true;
+                    }
+                    Key::Home => {
+                        self.buf.move_home(ctrl, shift);
+                        moved = true;
+                    }
+                    Key::End => {
+                        self.buf.move_end(ctrl, shift);
+                        moved = true;
+                    }
+                    _ => {}
+                }
+            }
+            InputEvent::MouseButtonPressed(MButton::Left) => {
+                if !w.is_hovered() {
+                    return;
+                }
+
+                if let Some(pos) = self.pos_at(self.modkeys.mouse.x, self.modkeys.mouse.y) {
+                    self.buf.set_cursor(pos, self.modkeys.shift);
+                    moved = true;
+                }
+
+                self.selecting = true;
+                w.activate();
+            }
+            InputEvent::MouseButtonReleased(MButton::Left) => {
+                if self.selecting {
+                    self.selecting = false;
+                    w.deactivate();
+                    w.emit_redraw_required();
+                }
+            }
+            InputEvent::MousePosition(x, y) => {
+                if self.selecting {
+                    if let Some(pos) = self.pos_at(*x, *y) {
+                        self.buf.set_cursor(pos, true);
+                        moved = true;
+                    }
+                }
+            }
+            InputEvent::MouseWheel(y) => {
+                if !w.is_hovered() {
+                    return;
+                }
+
+                let max_scroll = self.buf.lines().len().saturating_sub(self.visible.0);
+                if *y > 0.0 {
+                    self.scroll.0 = self.scroll.0.saturating_sub(3);
+                } else {
+                    self.scroll.0 = (self.scroll.0 + 3).min(max_scroll);
+                }
+
+                w.emit_redraw_required();
+            }
+            _ => {}
+        }
+
+        if moved || changed {
+            self.scroll_to_cursor();
+            w.emit_redraw_required();
+        }
+
+        if changed {
+            let text = self.buf.text();
+            self.model.update(text.clone());
+            // Our own change must not reset the undo history:
+            self.data_gen = self.model.get_generation();
+            out_events.push(w.event("changed", EvPayload::Text(text)));
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        w: &Widget,
+        style: &DPIStyle,
+        pos: Rect,
+        real_pos: Rect,
+        p: &mut Painter,
+    ) {
+        let real_offs = (real_pos.x - pos.x, real_pos.y - pos.y);
+        let has_cursor = w.is_active() || w.is_focused();
+        let mut dbg = w.debug_tag();
+
+        p.clip_region(pos.x, pos.y, pos.w, pos.h);
+
+        let font_size = style.font_size();
+        self.line_h = p.font_height(font_size, true).round().max(1.0);
+        self.char_w = p.text_width(font_size, true, "0").max(1.0);
+
+        let gutter_w = if self.line_numbers {
+            let digits = format!("{}", self.buf.lines().len()).len().max(2);
+            ((digits as f32 + 1.0) * self.char_w).round()
+        } else {
+            0.0
+        };
+        let pad = (2.0 * p.dpi_factor).round();
+
+        let text_area = Rect {
+            x: pos.x + gutter_w + pad,
+            y: pos.y,
+            w: (pos.w - gutter_w - pad).max(0.0),
+            h: pos.h,
+        };
+        self.text_pos = text_area.offs(real_offs.0, real_offs.1);
+        self.visible = (
+            (text_area.h / self.line_h).floor().max(1.0) as usize,
+            (text_area.w / self.char_w).floor().max(1.0) as usize,
+        );
+
+        let (scroll_line, scroll_col) = self.scroll;
+        let selection = self.buf.selection();
+
+        for (i, line) in
+            self.buf.lines().iter().enumerate().skip(scroll_line).take(self.visible.0 + 1)
+        {
+            let y = pos.y + (i - scroll_line) as f32 * self.line_h;
+
+            if self.line_numbers {
+                p.label_mono(
+                    font_size,
+                    1,
+                    style.inactive_color(),
+                    pos.x,
+                    y,
+                    gutter_w - 0.5 * self.char_w,
+                    self.line_h,
+                    &format!("{}", i + 1),
+                    dbg.offs_src(real_offs, "line_nr"),
+                );
+            }
+
+            if let Some((start, end)) = selection {
+                if i >= start.0 && i <= end.0 {
+                    let from = if i == start.0 { start.1 } else { 0 };
+                    // Selected line breaks are shown as one extra column:
+                    let to = if i == end.0 { end.1 } else { line.chars().count() + 1 };
+
+                    let from = from.max(scroll_col);
+                    let to = to.max(from);
+                    if to > from {
+                        p.rect_fill(
+                            style.selected_color(),
+                            text_area.x + (from - scroll_col) as f32 * self.char_w,
+                            y,
+                            (to - from) as f32 * self.char_w,
+                            self.line_h,
+                        );
+                    }
+                }
+            }
+
+            let shown: String = line.chars().skip(scroll_col).take(self.visible.1 + 1).collect();
+            p.label_mono(
+                font_size,
+                -1,
+                style.color(),
+                text_area.x,
+                y,
+                text_area.w,
+                self.line_h,
+                &shown,
+                dbg.offs_src(real_offs, "line"),
+            );
+        }
+
+        let (cur_line, cur_col) = self.buf.cursor();
+        if has_cursor && cur_line >= scroll_line && cur_col >= scroll_col {
+            let x = (text_area.x + (cur_col - scroll_col) as f32 * self.char_w).round() + 0.5;
+            let y = pos.y + (cur_line - scroll_line) as f32 * self.line_h;
+
+            p.stroke(1.0, style.active_color(), &[(x, y), (x, y + self.line_h)], false);
+        }
+
+        p.reset_clip_region();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buf_with(text: &str) -> TextBuffer {
+        let mut buf = TextBuffer::new();
+        buf.set_text(text);
+        buf
+    }
+
+    #[test]
+    fn check_text_buffer_insert_delete() {
+        let mut buf = TextBuffer::new();
+        buf.insert("foo\nbar");
+        assert_eq!(buf.cursor(), (1, 3));
+        buf.move_home(false, false);
+        buf.insert("x\n");
+        assert_eq!(buf.text(), "foo\nx\nbar");
+        assert_eq!(buf.cursor(), (2, 0));
+
+        buf.delete_back(false);
+        assert_eq!(buf.text(), "foo\nxbar");
+        assert_eq!(buf.cursor(), (1, 1));
+
+        buf.move_end(false, false);
+        buf.delete_forward(false);
+        assert_eq!(buf.text(), "foo\nxbar");
+
+        buf.move_home(true, false);
+        buf.delete_forward(false);
+        assert_eq!(buf.text(), "oo\nxbar");
+    }
+
+    #[test]
+    fn check_text_buffer_selection() {
+        let mut buf = buf_with("first line\nsecond\nthird");
+        buf.set_cursor((0, 6), false);
+        buf.move_lines(2, true);
+        assert_eq!(buf.selected_text(), Some("line\nsecond\nthird".to_string()));
+
+        buf.insert("X");
+        assert_eq!(buf.text(), "first X");
+
+        buf.select_all();
+        assert_eq!(buf.selected_text(), Some("first X".to_string()));
+        buf.move_left(false, false);
+        assert_eq!(buf.cursor(), (0, 0));
+        assert_eq!(buf.selection(), None);
+    }
+
+    #[test]
+    fn check_text_buffer_words_and_lines() {
+        let mut buf = buf_with("let foo_bar = 10;\nx");
+        buf.move_right(true, false);
+        assert_eq!(buf.cursor(), (0, 3));
+        buf.move_right(true, false);
+        assert_eq!(buf.cursor(), (0, 11));
+        buf.move_left(true, false);
+        assert_eq!(buf.cursor(), (0, 4));
+
+        buf.move_end(false, false);
+        buf.delete_back(true);
+        assert_eq!(buf.text(), "let foo_bar = ;\nx");
+
+        buf.set_cursor((0, 12), false);
+        buf.move_lines(1, false);
+        assert_eq!(buf.cursor(), (1, 1));
+        buf.move_lines(-1, false);
+        assert_eq!(buf.cursor(), (0, 12));
+    }
+}
@@ -2,9 +2,9 @@
 // This file is a part of HexoTK. Released under GPL-3.0-or-later.
 // See README.md and COPYING for details.
 
-use femtovg::{renderer::OpenGl, Canvas, Color, FontId, ImageId};
+use femtovg::{renderer::OpenGl, Canvas, Color, ImageId};
 
-use crate::painter::{Painter, PersistPainterData};
+use crate::painter::{FemtovgBackend, Painter, PersistPainterData};
 
 use raw_window_handle::RawWindowHandle;
 
@@ -69,9 +69,7 @@ impl FrameTimeMeasurement {
 }
 
 pub struct GUIWindowHandler {
-    canvas: Canvas<OpenGl>,
-    font: FontId,
-    font_mono: FontId,
+    backend: FemtovgBackend,
     img_buf: ImageId,
     //    ftm:        FrameTimeMeasurement,
     //    ftm_redraw: FrameTimeMeasurement,
@@ -157,11 +155,11 @@ impl WindowHandler for GUIWindowHandler {
                     info.logical_size(),
                 );
 
-                self.canvas.set_size(size.width as u32, size.height as u32, 1.0);
-                let (w, h) = (self.canvas.width(), self.canvas.height());
-                self.canvas.delete_image(self.img_buf);
-                self.img_buf = self
-                    .canvas
+                let canvas = &mut self.backend.canvas;
+                canvas.set_size(size.width as u32, size.height as u32, 1.0);
+                let (w, h) = (canvas.width(), canvas.height());
+                canvas.delete_image(self.img_buf);
+                self.img_buf = canvas
                     .create_image_empty(
                         w as usize,
                         h as usize,
@@ -195,49 +193,39 @@ impl WindowHandler for GUIWindowHandler {
 
         self.ui.pre_frame();
 
-        self.canvas.save();
-        self.canvas.set_render_target(femtovg::RenderTarget::Image(self.img_buf));
-        self.painter_data.init_render_targets(femtovg::RenderTarget::Image(self.img_buf));
+        self.backend.canvas.save();
+        self.backend.canvas.set_render_target(femtovg::RenderTarget::Image(self.img_buf));
+        self.backend.init_render_targets(femtovg::RenderTarget::Image(self.img_buf));
 
-        self.canvas.clear_rect(
+        let (w, h) = (self.backend.canvas.width(), self.backend.canvas.height());
+
+        self.backend.canvas.clear_rect(
             0,
             0,
-            self.canvas.width() as u32,
-            self.canvas.height() as u32,
+            w as u32,
+            h as u32,
             Color::rgbf(self.bg_color.0, self.bg_color.1, self.bg_color.2),
         );
 
         {
-            let painter = &mut Painter {
-                canvas: &mut self.canvas,
-                data: &mut self.painter_data,
-                font: self.font,
-                font_mono: self.font_mono,
-                lbl_collect: None,
-                dpi_factor: self.dpi_factor,
-            };
+            let painter =
+                &mut Painter::new(&mut self.backend, &mut self.painter_data, self.dpi_factor);
             self.ui.draw(painter);
         }
 
-        self.painter_data.cleanup(&mut self.canvas);
+        self.painter_data.cleanup(&mut self.backend);
 
-        let img_paint = femtovg::Paint::image(
-            self.img_buf,
-            0.0,
-            0.0,
-            self.canvas.width() as f32,
-            self.canvas.height() as f32,
-            0.0,
-            1.0,
-        );
+        let img_paint =
+            femtovg::Paint::image(self.img_buf, 0.0, 0.0, w as f32, h as f32, 0.0, 1.0);
         let mut path = femtovg::Path::new();
-        path.rect(0.0, 0.0, self.canvas.width() as f32, self.canvas.height() as f32);
+        path.rect(0.0, 0.0, w as f32, h as f32);
 
-        self.canvas.set_render_target(femtovg::RenderTarget::Screen);
-        self.canvas.fill_path(&mut path, &img_paint);
+        let canvas = &mut self.backend.canvas;
+        canvas.set_render_target(femtovg::RenderTarget::Screen);
+        canvas.fill_path(&mut path, &img_paint);
 
-        self.canvas.flush();
-        self.canvas.restore();
+        canvas.flush();
+        canvas.restore();
 
         win.gl_context().unwrap().swap_buffers();
 
@@ -331,9 +319,6 @@ pub fn open_window_ext(
 
         let mut canvas = Canvas::new(renderer).expect("Cannot create canvas");
         canvas.set_size(window_width as u32, window_height as u32, 1.0);
-        let font = canvas.add_font_mem(std::include_bytes!("font.ttf")).expect("can load font");
-        let font_mono =
-            canvas.add_font_mem(std::include_bytes!("font_mono.ttf")).expect("can load font");
         let (w, h) = (canvas.width(), canvas.height());
         let img_buf = canvas
             .create_image_empty(
@@ -359,9 +344,7 @@ pub fn open_window_ext(
             size: (window_width as f32, window_height as f32),
             dpi_factor,
             scale_policy,
-            canvas,
-            font,
-            font_mono,
+            backend: FemtovgBackend::new(canvas),
             img_buf,
             //            ftm:        FrameTimeMeasurement::new("img"),
             //            ftm_redraw: FrameTimeMeasurement::new("redraw"),